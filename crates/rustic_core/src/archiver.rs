@@ -1,9 +1,13 @@
+pub(crate) mod control;
 pub(crate) mod file_archiver;
 pub(crate) mod parent;
 pub(crate) mod tree;
 pub(crate) mod tree_archiver;
 
-use std::path::{Path, PathBuf};
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
 
 use chrono::Local;
 use log::warn;
@@ -11,12 +15,13 @@ use pariter::{scope, IteratorExt};
 
 use crate::{
     archiver::{
-        file_archiver::FileArchiver, parent::Parent, tree::TreeIterator,
+        control::BackupControl, file_archiver::FileArchiver, parent::Parent, tree::TreeIterator,
         tree_archiver::TreeArchiver,
     },
     backend::{decrypt::DecryptWriteBackend, ReadSource, ReadSourceEntry},
     blob::BlobType,
     index::{indexer::Indexer, indexer::SharedIndexer, IndexedBackend},
+    profile::{Phase, ResourceMeter},
     repofile::{configfile::ConfigFile, snapshotfile::SnapshotFile},
     Progress, RusticResult,
 };
@@ -47,6 +52,12 @@ pub struct Archiver<BE: DecryptWriteBackend, I: IndexedBackend> {
 
     /// The SnapshotFile to write to.
     snap: SnapshotFile,
+
+    /// The control to pause, resume and stop the backup.
+    control: BackupControl,
+
+    /// Measures the resources used by the backup.
+    meter: ResourceMeter,
 }
 
 impl<BE: DecryptWriteBackend, I: IndexedBackend> Archiver<BE, I> {
@@ -57,27 +68,53 @@ impl<BE: DecryptWriteBackend, I: IndexedBackend> Archiver<BE, I> {
     /// * `be` - The backend to write to.
     /// * `index` - The index to read from.
     /// * `config` - The config file.
+    /// * `connections` - The number of pack files to upload in parallel.
     /// * `parent` - The parent snapshot to use.
     /// * `snap` - The `SnapshotFile` to write to.
+    /// * `unstable_retries` - How often files which changed while being read are re-read.
+    /// * `skip_unstable` - Whether to skip files which still changed after re-reading them.
+    /// * `control` - The control to pause, resume and stop the backup.
     ///
     /// # Errors
     ///
     /// * [`PackerErrorKind::ZstdError`] - If the zstd compression level is invalid.
     /// * [`PackerErrorKind::SendingCrossbeamMessageFailed`] - If sending the message to the raw packer fails.
     /// * [`PackerErrorKind::IntConversionFailed`] - If converting the data length to u64 fails
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         be: BE,
         index: I,
         config: &ConfigFile,
+        connections: usize,
         parent: Parent,
         mut snap: SnapshotFile,
+        unstable_retries: u32,
+        skip_unstable: bool,
+        control: BackupControl,
     ) -> RusticResult<Self> {
+        let meter = ResourceMeter::start();
         let indexer = Indexer::new(be.clone()).into_shared();
         let mut summary = snap.summary.take().unwrap_or_default();
         summary.backup_start = Local::now();
 
-        let file_archiver = FileArchiver::new(be.clone(), index.clone(), indexer.clone(), config)?;
-        let tree_archiver = TreeArchiver::new(be.clone(), index, indexer.clone(), config, summary)?;
+        let file_archiver = FileArchiver::new(
+            be.clone(),
+            index.clone(),
+            indexer.clone(),
+            config,
+            connections,
+            unstable_retries,
+            skip_unstable,
+            control.clone(),
+        )?;
+        let tree_archiver = TreeArchiver::new(
+            be.clone(),
+            index,
+            indexer.clone(),
+            config,
+            connections,
+            summary,
+        )?;
         Ok(Self {
             file_archiver,
             tree_archiver,
@@ -85,6 +122,8 @@ impl<BE: DecryptWriteBackend, I: IndexedBackend> Archiver<BE, I> {
             indexer,
             be,
             snap,
+            control,
+            meter,
         })
     }
 
@@ -129,10 +168,20 @@ impl<BE: DecryptWriteBackend, I: IndexedBackend> Archiver<BE, I> {
         };
         p.set_title("backing up...");
 
+        // errors which lead to missing entries in the snapshot, except for files which failed
+        let source_errors = AtomicU64::new(0);
+
+        // stop reading the source if the backup is stopped
+        let control = self.control.clone();
+        let mut entries = src.entries();
+        let iter = std::iter::from_fn(move || Phase::Scan.measure(|| entries.next()))
+            .take_while(move |_| !control.is_stopped());
+
         // filter out errors and handle as_path
-        let iter = src.entries().filter_map(|item| match item {
+        let iter = iter.filter_map(|item| match item {
             Err(e) => {
                 warn!("ignoring error {e}\n");
+                _ = source_errors.fetch_add(1, Ordering::Relaxed);
                 None
             }
             Ok(ReadSourceEntry { path, node, open }) => {
@@ -166,6 +215,7 @@ impl<BE: DecryptWriteBackend, I: IndexedBackend> Archiver<BE, I> {
                 Ok(item) => Some(item),
                 Err(err) => {
                     warn!("ignoring error reading parent snapshot: {err:?}");
+                    _ = source_errors.fetch_add(1, Ordering::Relaxed);
                     None
                 }
             })
@@ -183,13 +233,24 @@ impl<BE: DecryptWriteBackend, I: IndexedBackend> Archiver<BE, I> {
         })
         .unwrap()?;
 
+        let files_unstable = self.file_archiver.unstable_files();
+        let failed_paths = self.file_archiver.failed_files();
         let stats = self.file_archiver.finalize()?;
         let (id, mut summary) = self.tree_archiver.finalize(self.parent.tree_id())?;
         stats.apply(&mut summary, BlobType::Data);
+        summary.files_unstable = files_unstable;
+        summary.source_errors = source_errors.into_inner();
+        summary.partial = !failed_paths.is_empty() || summary.source_errors > 0;
+        if self.control.is_stopped() {
+            warn!("backup has been stopped, saving partial snapshot.");
+            summary.partial = true;
+        }
+        summary.failed_paths = failed_paths;
         self.snap.tree = id;
 
         self.indexer.write().unwrap().finalize()?;
 
+        summary.resources = Some(self.meter.finish());
         summary.finalize(self.snap.time)?;
         self.snap.summary = Some(summary);
 
@@ -0,0 +1,155 @@
+use std::{
+    io::{self, Read},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+};
+
+/// Shared state of a [`BackupControl`].
+#[derive(Debug, Default)]
+struct ControlState {
+    /// Whether the backup is paused; allows to check this without locking
+    paused: AtomicBool,
+    /// Whether the backup has been stopped
+    stopped: AtomicBool,
+    /// Lock used together with `resumed`
+    lock: Mutex<()>,
+    /// Notified when the backup is resumed
+    resumed: Condvar,
+}
+
+/// [`BackupControl`] allows to pause, resume and stop a running backup.
+///
+/// All clones share the same state, so a clone can be handed to another thread which controls
+/// the backup. While paused, no more data is read from the backup source. After the backup has
+/// been stopped, no more files are read and the backup is saved as partial snapshot.
+#[derive(Debug, Clone, Default)]
+pub struct BackupControl {
+    state: Arc<ControlState>,
+}
+
+impl BackupControl {
+    /// Pause the backup.
+    pub fn pause(&self) {
+        self.set_paused(true);
+    }
+
+    /// Resume a paused backup.
+    pub fn resume(&self) {
+        self.set_paused(false);
+    }
+
+    /// Returns `true` if the backup is paused.
+    #[must_use]
+    pub fn is_paused(&self) -> bool {
+        self.state.paused.load(Ordering::Acquire)
+    }
+
+    /// Stop the backup.
+    ///
+    /// Files which are currently read are marked as failed and a partial snapshot
+    /// containing all files read so far is saved. Stopping also ends a pause.
+    pub fn stop(&self) {
+        self.state.stopped.store(true, Ordering::Release);
+        self.resume();
+    }
+
+    /// Returns `true` if the backup has been stopped.
+    #[must_use]
+    pub fn is_stopped(&self) -> bool {
+        self.state.stopped.load(Ordering::Acquire)
+    }
+
+    fn set_paused(&self, paused: bool) {
+        self.state.paused.store(paused, Ordering::Release);
+        // notify while holding the lock, so no waiting thread misses the notification
+        let _guard = self.state.lock.lock().unwrap();
+        self.state.resumed.notify_all();
+    }
+
+    /// Block the current thread while the backup is paused.
+    pub(crate) fn wait(&self) {
+        if !self.is_paused() {
+            return;
+        }
+        let guard = self.state.lock.lock().unwrap();
+        let _guard = self
+            .state
+            .resumed
+            .wait_while(guard, |()| self.is_paused())
+            .unwrap();
+    }
+}
+
+/// A reader which blocks while the backup is paused.
+pub(crate) struct PausableReader<R> {
+    /// The wrapped reader
+    inner: R,
+    /// The control to check for pauses
+    control: BackupControl,
+}
+
+impl<R: Read> PausableReader<R> {
+    /// Creates a new `PausableReader`.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The reader to wrap.
+    /// * `control` - The control to check for pauses.
+    pub(crate) const fn new(inner: R, control: BackupControl) -> Self {
+        Self { inner, control }
+    }
+}
+
+impl<R: Read> Read for PausableReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.control.wait();
+        if self.control.is_stopped() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "backup has been stopped",
+            ));
+        }
+        self.inner.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::*;
+
+    #[test]
+    fn pause_blocks_reads_until_resumed() {
+        let control = BackupControl::default();
+        control.pause();
+        assert!(control.is_paused());
+
+        let reader_control = control.clone();
+        let handle = thread::spawn(move || {
+            let mut reader = PausableReader::new(&b"data"[..], reader_control);
+            let mut buf = Vec::new();
+            _ = reader.read_to_end(&mut buf).unwrap();
+            buf
+        });
+
+        thread::sleep(Duration::from_millis(50));
+        assert!(!handle.is_finished());
+        control.resume();
+        assert_eq!(handle.join().unwrap(), b"data");
+    }
+
+    #[test]
+    fn stop_fails_reads() {
+        let control = BackupControl::default();
+        control.pause();
+        control.stop();
+        assert!(!control.is_paused());
+        assert!(control.is_stopped());
+
+        let mut reader = PausableReader::new(&b"data"[..], control);
+        assert!(reader.read_to_end(&mut Vec::new()).is_err());
+    }
+}
@@ -1,7 +1,17 @@
-use std::io::Read;
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use log::warn;
 
 use crate::{
     archiver::{
+        control::{BackupControl, PausableReader},
         parent::{ItemWithParent, ParentResult},
         tree::TreeType,
         tree_archiver::TreeItem,
@@ -21,6 +31,7 @@ use crate::{
     error::ArchiverErrorKind,
     error::RusticResult,
     index::{indexer::SharedIndexer, IndexedBackend},
+    profile::Phase,
     progress::Progress,
     repofile::configfile::ConfigFile,
 };
@@ -37,6 +48,11 @@ pub(crate) struct FileArchiver<BE: DecryptWriteBackend, I: IndexedBackend> {
     index: I,
     data_packer: Packer<BE>,
     rabin: Rabin64,
+    unstable_retries: u32,
+    skip_unstable: bool,
+    unstable_files: Arc<AtomicU64>,
+    failed_files: Arc<Mutex<Vec<PathBuf>>>,
+    control: BackupControl,
 }
 
 impl<BE: DecryptWriteBackend, I: IndexedBackend> FileArchiver<BE, I> {
@@ -53,6 +69,10 @@ impl<BE: DecryptWriteBackend, I: IndexedBackend> FileArchiver<BE, I> {
     /// * `index` - The index to read from.
     /// * `indexer` - The indexer to write to.
     /// * `config` - The config file.
+    /// * `connections` - The number of pack files to upload in parallel.
+    /// * `unstable_retries` - How often files which changed while being read are re-read.
+    /// * `skip_unstable` - Whether to skip files which still changed after re-reading them.
+    /// * `control` - The control to pause and resume reading files.
     ///
     /// # Errors
     ///
@@ -64,6 +84,10 @@ impl<BE: DecryptWriteBackend, I: IndexedBackend> FileArchiver<BE, I> {
         index: I,
         indexer: SharedIndexer<BE>,
         config: &ConfigFile,
+        connections: usize,
+        unstable_retries: u32,
+        skip_unstable: bool,
+        control: BackupControl,
     ) -> RusticResult<Self> {
         let poly = config.poly()?;
 
@@ -73,12 +97,18 @@ impl<BE: DecryptWriteBackend, I: IndexedBackend> FileArchiver<BE, I> {
             indexer,
             config,
             index.total_size(BlobType::Data),
+            connections,
         )?;
         let rabin = Rabin64::new_with_polynom(6, poly);
         Ok(Self {
             index,
             data_packer,
             rabin,
+            unstable_retries,
+            skip_unstable,
+            unstable_files: Arc::new(AtomicU64::new(0)),
+            failed_files: Arc::default(),
+            control,
         })
     }
 
@@ -114,10 +144,14 @@ impl<BE: DecryptWriteBackend, I: IndexedBackend> FileArchiver<BE, I> {
                     p.inc(size);
                     (node, size)
                 } else if node.node_type == NodeType::File {
-                    let r = open
-                        .ok_or(ArchiverErrorKind::UnpackingTreeTypeOptionalFailed)?
-                        .open()?;
-                    self.backup_reader(r, node, p)?
+                    let open = open.ok_or(ArchiverErrorKind::UnpackingTreeTypeOptionalFailed)?;
+                    let file_path = path.join(node.name());
+                    Phase::Chunk
+                        .measure(|| self.backup_file(&path, open, node, p))
+                        .map_err(|err| {
+                            self.failed_files.lock().unwrap().push(file_path);
+                            err
+                        })?
                 } else {
                     (node, 0)
                 };
@@ -126,14 +160,98 @@ impl<BE: DecryptWriteBackend, I: IndexedBackend> FileArchiver<BE, I> {
         })
     }
 
+    /// Backs up a file and checks if it has been changed while being read.
+    ///
+    /// Changed files are re-read up to `unstable_retries` times. If the file is still changing,
+    /// it is either saved as read or skipped, depending on `skip_unstable`.
+    /// Only the bytes of the saved contents are added to the progress.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the directory containing the file.
+    /// * `open` - The source to read the file from.
+    /// * `node` - The node of the file.
+    /// * `p` - The progress tracker.
+    ///
+    /// # Errors
+    ///
+    /// * [`ArchiverErrorKind::UnstableFile`] - If the file is unstable and `skip_unstable` is set.
+    fn backup_file<O: ReadSourceOpen>(
+        &self,
+        path: &Path,
+        open: O,
+        mut node: Node,
+        p: &impl Progress,
+    ) -> RusticResult<(Node, u64)> {
+        let mut open = Some(open);
+        let mut retries = 0;
+        loop {
+            // sources which can't be reopened are only read once
+            let reader = match open.as_ref().and_then(O::reopen) {
+                Some(reader) => reader,
+                None => open
+                    .take()
+                    .ok_or(ArchiverErrorKind::UnpackingTreeTypeOptionalFailed)?
+                    .open(),
+            };
+            let reader = match reader {
+                Ok(reader) => reader,
+                // the content is only given for unchanged files if the parent should be used for unreadable files
+                Err(err) if node.content.is_some() => {
+                    warn!(
+                        "file {:?} can't be read ({err}), using unchanged contents from parent snapshot",
+                        path.join(node.name())
+                    );
+                    let size = node.meta.size;
+                    p.inc(size);
+                    return Ok((node, size));
+                }
+                Err(err) => return Err(err),
+            };
+            let (new_node, filesize) = self.backup_reader(reader, node.clone())?;
+            let (size, mtime) = match open.as_ref().and_then(O::stat) {
+                Some(stat) => stat,
+                None => {
+                    p.inc(filesize);
+                    return Ok((new_node, filesize));
+                }
+            };
+            if size == filesize && size == node.meta.size && mtime == node.meta.mtime {
+                p.inc(filesize);
+                return Ok((new_node, filesize));
+            }
+
+            let path = path.join(node.name());
+            if retries < self.unstable_retries {
+                retries += 1;
+                warn!(
+                    "file {path:?} changed while being read, re-reading it ({retries}/{})",
+                    self.unstable_retries
+                );
+                node.meta.size = size;
+                node.meta.mtime = mtime;
+                // the file has changed, so the parent contents must not be used anymore
+                node.content = None;
+                continue;
+            }
+
+            _ = self.unstable_files.fetch_add(1, Ordering::Relaxed);
+            if self.skip_unstable {
+                return Err(ArchiverErrorKind::UnstableFile(path).into());
+            }
+            warn!("file {path:?} changed while being read, saving possibly inconsistent contents");
+            p.inc(filesize);
+            return Ok((new_node, filesize));
+        }
+    }
+
     fn backup_reader(
         &self,
         r: impl Read + Send + 'static,
         node: Node,
-        p: &impl Progress,
     ) -> RusticResult<(Node, u64)> {
         let chunks: Vec<_> = ChunkIter::new(
-            r,
+            PausableReader::new(r, self.control.clone()),
             usize::try_from(node.meta.size)
                 .map_err(ArchiverErrorKind::ConversionFromU64ToUsizeFailed)?,
             self.rabin.clone(),
@@ -146,7 +264,6 @@ impl<BE: DecryptWriteBackend, I: IndexedBackend> FileArchiver<BE, I> {
             if !self.index.has_data(&id) {
                 self.data_packer.add(chunk.into(), id)?;
             }
-            p.inc(size);
             Ok((id, size))
         })
         .collect::<RusticResult<_>>()?;
@@ -159,6 +276,18 @@ impl<BE: DecryptWriteBackend, I: IndexedBackend> FileArchiver<BE, I> {
         Ok((node, filesize))
     }
 
+    /// Returns the number of files which changed while being read, even after re-reading them.
+    pub(crate) fn unstable_files(&self) -> u64 {
+        self.unstable_files.load(Ordering::Relaxed)
+    }
+
+    /// Returns the sorted paths of the files which could not be backed up.
+    pub(crate) fn failed_files(&self) -> Vec<PathBuf> {
+        let mut files = self.failed_files.lock().unwrap().clone();
+        files.sort_unstable();
+        files
+    }
+
     /// Finalizes the archiver.
     ///
     /// # Returns
@@ -32,6 +32,10 @@ pub struct Parent {
     ignore_ctime: bool,
     /// Ignore inode number when comparing nodes.
     ignore_inode: bool,
+    /// Assume all nodes existing in the parent are unchanged.
+    assume_unchanged: bool,
+    /// Use the parent content for files which can't be read but whose size and mtime are unchanged.
+    use_parent_if_unreadable: bool,
 }
 
 /// The result of a parent search.
@@ -85,11 +89,15 @@ impl Parent {
     /// * `tree_id` - The tree id of the parent tree.
     /// * `ignore_ctime` - Ignore ctime when comparing nodes.
     /// * `ignore_inode` - Ignore inode number when comparing nodes.
+    /// * `assume_unchanged` - Assume all nodes existing in the parent are unchanged.
+    /// * `use_parent_if_unreadable` - Use the parent content for files which can't be read but whose size and mtime are unchanged.
     pub(crate) fn new<BE: IndexedBackend>(
         be: &BE,
         tree_id: Option<Id>,
         ignore_ctime: bool,
         ignore_inode: bool,
+        assume_unchanged: bool,
+        use_parent_if_unreadable: bool,
     ) -> Self {
         // if tree_id is given, try to load tree from backend.
         let tree = tree_id.and_then(|tree_id| match Tree::from_backend(be, tree_id) {
@@ -106,6 +114,8 @@ impl Parent {
             stack: Vec::new(),
             ignore_ctime,
             ignore_inode,
+            assume_unchanged,
+            use_parent_if_unreadable,
         }
     }
 
@@ -159,13 +169,17 @@ impl Parent {
         // use new variables as the mutable borrow is used later
         let ignore_ctime = self.ignore_ctime;
         let ignore_inode = self.ignore_inode;
+        let assume_unchanged = self.assume_unchanged;
 
         self.p_node(name).map_or(ParentResult::NotFound, |p_node| {
             if p_node.node_type == node.node_type
-                && p_node.meta.size == node.meta.size
-                && p_node.meta.mtime == node.meta.mtime
-                && (ignore_ctime || p_node.meta.ctime == node.meta.ctime)
-                && (ignore_inode || p_node.meta.inode == 0 || p_node.meta.inode == node.meta.inode)
+                && (assume_unchanged
+                    || p_node.meta.size == node.meta.size
+                        && p_node.meta.mtime == node.meta.mtime
+                        && (ignore_ctime || p_node.meta.ctime == node.meta.ctime)
+                        && (ignore_inode
+                            || p_node.meta.inode == 0
+                            || p_node.meta.inode == node.meta.inode))
             {
                 ParentResult::Matched(p_node)
             } else {
@@ -261,10 +275,15 @@ impl Parent {
             }
             TreeType::Other((path, mut node, open)) => {
                 let be = be.clone();
+                let assume_unchanged = self.assume_unchanged;
                 let parent = self.is_parent(&node, &node.name());
                 let parent = match parent {
                     ParentResult::Matched(p_node) => {
                         if p_node.content.iter().flatten().all(|id| be.has_data(id)) {
+                            if assume_unchanged {
+                                // the content is taken from the parent, so must be the metadata
+                                node.meta = p_node.meta.clone();
+                            }
                             node.content = Some(p_node.content.iter().flatten().copied().collect());
                             ParentResult::Matched(())
                         } else {
@@ -274,7 +293,22 @@ impl Parent {
                             ParentResult::NotFound
                         }
                     }
-                    parent_result => parent_result.map(|_| ()),
+                    ParentResult::NotMatched => {
+                        if self.use_parent_if_unreadable {
+                            // save the parent content in case the file can't be read; it is only used in this case.
+                            if let Some(p_node) = self.p_node(&node.name()).filter(|p_node| {
+                                p_node.node_type == node.node_type
+                                    && p_node.meta.size == node.meta.size
+                                    && p_node.meta.mtime == node.meta.mtime
+                                    && p_node.content.iter().flatten().all(|id| be.has_data(id))
+                            }) {
+                                node.content =
+                                    Some(p_node.content.iter().flatten().copied().collect());
+                            }
+                        }
+                        ParentResult::NotMatched
+                    }
+                    ParentResult::NotFound => ParentResult::NotFound,
                 };
                 TreeType::Other((path, node, (open, parent)))
             }
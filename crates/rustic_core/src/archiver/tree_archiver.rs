@@ -35,6 +35,8 @@ pub(crate) struct TreeArchiver<BE: DecryptWriteBackend, I: IndexedBackend> {
     tree_packer: Packer<BE>,
     /// The summary of the snapshot.
     summary: SnapshotSummary,
+    /// Whether large trees are sharded
+    shard_trees: bool,
 }
 
 impl<BE: DecryptWriteBackend, I: IndexedBackend> TreeArchiver<BE, I> {
@@ -51,6 +53,7 @@ impl<BE: DecryptWriteBackend, I: IndexedBackend> TreeArchiver<BE, I> {
     /// * `index` - The index to read from.
     /// * `indexer` - The indexer to write to.
     /// * `config` - The config file.
+    /// * `connections` - The number of pack files to upload in parallel.
     /// * `summary` - The summary of the snapshot.
     ///
     /// # Errors
@@ -63,6 +66,7 @@ impl<BE: DecryptWriteBackend, I: IndexedBackend> TreeArchiver<BE, I> {
         index: I,
         indexer: SharedIndexer<BE>,
         config: &ConfigFile,
+        connections: usize,
         summary: SnapshotSummary,
     ) -> RusticResult<Self> {
         let tree_packer = Packer::new(
@@ -71,6 +75,7 @@ impl<BE: DecryptWriteBackend, I: IndexedBackend> TreeArchiver<BE, I> {
             indexer,
             config,
             index.total_size(BlobType::Tree),
+            connections,
         )?;
         Ok(Self {
             tree: Tree::new(),
@@ -78,6 +83,7 @@ impl<BE: DecryptWriteBackend, I: IndexedBackend> TreeArchiver<BE, I> {
             index,
             tree_packer,
             summary,
+            shard_trees: config.shard_trees(),
         })
     }
 
@@ -164,8 +170,9 @@ impl<BE: DecryptWriteBackend, I: IndexedBackend> TreeArchiver<BE, I> {
     ///
     /// The id of the tree.
     fn backup_tree(&mut self, path: &Path, parent: &ParentResult<Id>) -> RusticResult<Id> {
-        let (chunk, id) = self.tree.serialize()?;
-        let dirsize = chunk.len() as u64;
+        let tree = self.tree.serialize(self.shard_trees)?;
+        let id = tree.id;
+        let dirsize = tree.len() as u64;
         let dirsize_bytes = ByteSize(dirsize).to_string_as(true);
 
         self.summary.total_dirs_processed += 1;
@@ -188,7 +195,12 @@ impl<BE: DecryptWriteBackend, I: IndexedBackend> TreeArchiver<BE, I> {
         }
 
         if !self.index.has_tree(&id) {
-            self.tree_packer.add(chunk.into(), id)?;
+            for (chunk, shard_id) in tree.shards {
+                if !self.index.has_tree(&shard_id) {
+                    self.tree_packer.add(chunk.into(), shard_id)?;
+                }
+            }
+            self.tree_packer.add(tree.chunk.into(), id)?;
         }
         Ok(id)
     }
@@ -1,18 +1,36 @@
+#[cfg(feature = "b2")]
+pub(crate) mod b2;
 pub(crate) mod cache;
 pub(crate) mod choose;
 pub(crate) mod decrypt;
 pub(crate) mod dry_run;
+pub(crate) mod fileflags;
 pub(crate) mod hotcold;
+pub(crate) mod http;
 pub(crate) mod ignore;
+#[cfg(feature = "ipfs")]
+pub(crate) mod ipfs;
+pub(crate) mod limit;
 pub(crate) mod local;
+pub(crate) mod mirror;
 pub(crate) mod node;
+pub(crate) mod normalize;
+pub(crate) mod proxy;
 pub(crate) mod rclone;
 pub(crate) mod rest;
+pub(crate) mod restricted;
+pub(crate) mod retry;
+pub(crate) mod route;
+#[cfg(feature = "s3")]
+pub(crate) mod s3;
 pub(crate) mod stdin;
+#[cfg(feature = "webdav")]
+pub(crate) mod webdav;
 
-use std::{io::Read, path::PathBuf};
+use std::{io::Read, path::PathBuf, time::Duration};
 
 use bytes::Bytes;
+use chrono::{DateTime, Local};
 use log::trace;
 use serde::{Deserialize, Serialize};
 
@@ -27,7 +45,7 @@ pub const ALL_FILE_TYPES: [FileType; 4] = [
 ];
 
 /// Type for describing the kind of a file that can occur.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum FileType {
     /// Config file
     #[serde(rename = "config")]
@@ -44,6 +62,15 @@ pub enum FileType {
     /// Data
     #[serde(rename = "pack")]
     Pack,
+    /// Forgotten snapshots which can be undeleted
+    #[serde(rename = "trash")]
+    Trash,
+    /// Files which are going to be removed by `forget` or `prune`
+    #[serde(rename = "journal")]
+    Journal,
+    /// Notes attached to snapshots, packs or keys
+    #[serde(rename = "note")]
+    Note,
 }
 
 impl FileType {
@@ -54,18 +81,35 @@ impl FileType {
             Self::Index => "index",
             Self::Key => "keys",
             Self::Pack => "data",
+            Self::Trash => "trash",
+            Self::Journal => "journal",
+            Self::Note => "notes",
         }
     }
 
     /// Returns if the file type is cacheable.
     const fn is_cacheable(self) -> bool {
         match self {
-            Self::Config | Self::Key | Self::Pack => false,
+            Self::Config | Self::Key | Self::Pack | Self::Trash | Self::Journal | Self::Note => {
+                false
+            }
             Self::Snapshot | Self::Index => true,
         }
     }
 }
 
+/// The state of a file which may need to be thawed, see [`ReadBackend::thaw`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThawState {
+    /// The file can be read
+    Available,
+    /// The file is being thawed and can't be read yet
+    Thawing {
+        /// The size of the file in bytes
+        size: u64,
+    },
+}
+
 /// Trait for backends that can read.
 ///
 /// This trait is implemented by all backends that can read data.
@@ -147,6 +191,29 @@ pub trait ReadBackend: Clone + Send + Sync + 'static {
         length: u32,
     ) -> RusticResult<Bytes>;
 
+    /// Returns the typical duration of thawing if files need to be thawed before they can be read,
+    /// e.g. because they are in an archive storage class. Returns `None` if files can always be read.
+    fn thaw_duration(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Requests thawing of the given file if it is in archive storage.
+    ///
+    /// This can be called repeatedly to poll whether the file has been thawed; thawing is only
+    /// requested once.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    ///
+    /// # Errors
+    ///
+    /// If the state of the file could not be determined or thawing could not be requested.
+    fn thaw(&self, _tpe: FileType, _id: &Id) -> RusticResult<ThawState> {
+        Ok(ThawState::Available)
+    }
+
     /// Finds the id of the file starting with the given string.
     ///
     /// # Type Parameters
@@ -295,6 +362,22 @@ pub trait ReadSourceOpen {
 
     /// Opens the source.
     fn open(self) -> RusticResult<Self::Reader>;
+
+    /// Opens the source without consuming it, such that it can be opened again.
+    ///
+    /// This is used to re-read sources which are changed while being read.
+    /// Returns `None` if the source can only be opened once; such sources are never re-read.
+    fn reopen(&self) -> Option<RusticResult<Self::Reader>> {
+        None
+    }
+
+    /// Returns the current size and modification time of the source.
+    ///
+    /// This is used to detect sources which are changed while being read.
+    /// Returns `None` if this cannot be determined; such sources are never re-read.
+    fn stat(&self) -> Option<(u64, Option<DateTime<Local>>)> {
+        None
+    }
 }
 
 /// Trait for backends that can read from a source.
@@ -0,0 +1,733 @@
+//! Backend using the native API of Backblaze B2
+//!
+//! The account is authorized using the application key given in `B2_ACCOUNT_ID` and `B2_ACCOUNT_KEY`.
+//! Expired authorization tokens are refreshed automatically.
+use std::{
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
+
+use backoff::Error;
+use bytes::Bytes;
+use bytesize::ByteSize;
+use derivative::Derivative;
+use log::{debug, trace, warn};
+use reqwest::{
+    blocking::{RequestBuilder, Response},
+    StatusCode,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use sha1::{Digest, Sha1};
+
+use crate::{
+    backend::{
+        http::{CheckError, HttpClient},
+        FileType, ReadBackend, WriteBackend,
+    },
+    error::{B2ErrorKind, RusticResult},
+    id::Id,
+};
+
+mod consts {
+    /// The url used to authorize the account
+    pub(super) const AUTHORIZE_URL: &str =
+        "https://api.backblazeb2.com/b2api/v2/b2_authorize_account";
+    /// Environment variable containing the application key id
+    pub(super) const ACCOUNT_ID_ENV: &str = "B2_ACCOUNT_ID";
+    /// Environment variable containing the application key
+    pub(super) const ACCOUNT_KEY_ENV: &str = "B2_ACCOUNT_KEY";
+    /// The maximum number of files listed by one request
+    pub(super) const MAX_FILE_COUNT: u32 = 10_000;
+}
+
+/// The authorization of the account as returned by `b2_authorize_account`
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Authorization {
+    /// The id of the account
+    account_id: String,
+    /// The token used to authorize all other requests
+    authorization_token: String,
+    /// The base url of the API
+    api_url: String,
+    /// The base url for downloading files
+    download_url: String,
+    /// The recommended part size for large files
+    recommended_part_size: u64,
+    /// The minimum part size for large files
+    absolute_minimum_part_size: u64,
+}
+
+/// An url to upload a file or a part of a large file to
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UploadUrl {
+    /// The url to upload to
+    upload_url: String,
+    /// The token to use for the upload
+    authorization_token: String,
+}
+
+/// A file or file version as returned by the list operations
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FileInfo {
+    /// The id of the file version
+    file_id: Option<String>,
+    /// The name of the file
+    file_name: String,
+    /// The size of the file
+    content_length: u64,
+    /// The action of the version, i.e. `upload`, `hide`, `start` or `folder`
+    action: String,
+}
+
+/// A list of files as returned by the list operations
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FileList {
+    /// The listed files
+    files: Vec<FileInfo>,
+    /// The name to continue listing with
+    next_file_name: Option<String>,
+    /// The id to continue listing versions with
+    next_file_id: Option<String>,
+}
+
+/// A backend implementation that uses the native API of Backblaze B2.
+#[derive(Clone, Derivative)]
+#[derivative(Debug)]
+pub struct B2Backend {
+    /// The name of the bucket
+    bucket: String,
+    /// The prefix of all file names, either empty or ending with `/`
+    prefix: String,
+    /// The application key id
+    key_id: String,
+    /// The application key
+    #[derivative(Debug = "ignore")]
+    key: String,
+    /// The part size for large files; if not set, the part size recommended by B2 is used
+    part_size: Option<u64>,
+    /// Whether files are hidden instead of deleted, leaving the deletion to the lifecycle rules of the bucket
+    hide_on_delete: bool,
+    /// The HTTP client together with its timeout, proxy and retry settings.
+    http: HttpClient,
+    /// The cached authorization and bucket id
+    #[derivative(Debug = "ignore")]
+    auth: Arc<RwLock<Option<(Authorization, String)>>>,
+}
+
+impl B2Backend {
+    /// Create a new [`B2Backend`] from a given path.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The bucket and an optional prefix, i.e. `bucket[/prefix]`.
+    ///
+    /// # Errors
+    ///
+    /// * [`B2ErrorKind::InvalidPath`] - If the path doesn't contain a bucket.
+    /// * [`B2ErrorKind::NoCredentials`] - If `B2_ACCOUNT_ID` or `B2_ACCOUNT_KEY` is not set.
+    /// * [`HttpErrorKind::BuildingClientFailed`](crate::error::HttpErrorKind::BuildingClientFailed) - If the client could not be built.
+    pub fn new(path: &str) -> RusticResult<Self> {
+        let (bucket, prefix) = path.split_once('/').unwrap_or((path, ""));
+        if bucket.is_empty() {
+            return Err(B2ErrorKind::InvalidPath(path.to_string()).into());
+        }
+        let prefix = prefix.trim_matches('/');
+        let prefix = if prefix.is_empty() {
+            String::new()
+        } else {
+            format!("{prefix}/")
+        };
+        let (key_id, key) = match (
+            std::env::var(consts::ACCOUNT_ID_ENV),
+            std::env::var(consts::ACCOUNT_KEY_ENV),
+        ) {
+            (Ok(key_id), Ok(key)) => (key_id, key),
+            _ => return Err(B2ErrorKind::NoCredentials.into()),
+        };
+
+        Ok(Self {
+            bucket: bucket.to_string(),
+            prefix,
+            key_id,
+            key,
+            part_size: None,
+            hide_on_delete: false,
+            http: HttpClient::new()?,
+            auth: Arc::new(RwLock::new(None)),
+        })
+    }
+
+    /// Returns the authorization and the bucket id, authorizing the account if needed.
+    ///
+    /// # Errors
+    ///
+    /// * [`reqwest::Error`] - If the authorization failed.
+    fn auth(&self) -> Result<(Authorization, String), Error<reqwest::Error>> {
+        if let Ok(cache) = self.auth.read() {
+            if let Some(auth) = cache.as_ref() {
+                return Ok(auth.clone());
+            }
+        }
+
+        debug!("authorizing B2 account");
+        let auth: Authorization = self
+            .http
+            .client()
+            .get(consts::AUTHORIZE_URL)
+            .basic_auth(&self.key_id, Some(&self.key))
+            .send()?
+            .check_error()?
+            .json()?;
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Bucket {
+            bucket_id: String,
+        }
+        #[derive(Deserialize)]
+        struct Buckets {
+            buckets: Vec<Bucket>,
+        }
+        let buckets: Buckets = self
+            .http
+            .client()
+            .post(format!("{}/b2api/v2/b2_list_buckets", auth.api_url))
+            .header("Authorization", &auth.authorization_token)
+            .json(&json!({ "accountId": auth.account_id, "bucketName": self.bucket }))
+            .send()?
+            .check_error()?
+            .json()?;
+        // an unknown bucket is no error, the bucket is created by `create()`
+        let bucket_id = buckets
+            .buckets
+            .into_iter()
+            .next()
+            .map(|bucket| bucket.bucket_id)
+            .unwrap_or_default();
+
+        let auth = (auth, bucket_id);
+        if let Ok(mut cache) = self.auth.write() {
+            *cache = Some(auth.clone());
+        }
+        Ok(auth)
+    }
+
+    /// Check the response for errors.
+    ///
+    /// An expired authorization token is dropped, such that the request is retried with a new token.
+    ///
+    /// # Errors
+    ///
+    /// If the response is an error, it will return an error of type `Error<reqwest::Error>`
+    fn check(&self, response: Response) -> Result<Response, Error<reqwest::Error>> {
+        if response.status() == StatusCode::UNAUTHORIZED {
+            if let Ok(mut cache) = self.auth.write() {
+                *cache = None;
+            }
+            // Note: this always gives an error as the status is a client error
+            let err = response.error_for_status().unwrap_err();
+            return Err(Error::Transient {
+                err,
+                retry_after: None,
+            });
+        }
+        response.check_error()
+    }
+
+    /// Call an operation of the B2 API, retrying transient errors.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - The name of the operation, e.g. `b2_list_file_names`
+    /// * `body` - Returns the JSON body of the request given the bucket id.
+    ///
+    /// # Errors
+    ///
+    /// * [`B2ErrorKind::BackoffError`] - If the backoff failed.
+    fn call<T: DeserializeOwned>(
+        &self,
+        operation: &str,
+        body: impl Fn(&str) -> Value,
+    ) -> RusticResult<T> {
+        trace!("calling {operation}");
+        Ok(self
+            .http
+            .retry(|| {
+                let (auth, bucket_id) = self.auth()?;
+                let response = self
+                    .http
+                    .client()
+                    .post(format!("{}/b2api/v2/{operation}", auth.api_url))
+                    .header("Authorization", &auth.authorization_token)
+                    .json(&body(&bucket_id))
+                    .send()?;
+                Ok(self.check(response)?.json()?)
+            })
+            .map_err(B2ErrorKind::BackoffError)?)
+    }
+
+    /// Returns the name of the given file within the bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    fn file_name(&self, tpe: FileType, id: &Id) -> String {
+        let hex_id = id.to_hex();
+        match tpe {
+            FileType::Config => format!("{}config", self.prefix),
+            FileType::Pack => format!("{}data/{}/{hex_id}", self.prefix, &hex_id[0..2]),
+            _ => format!("{}{}/{hex_id}", self.prefix, tpe.dirname()),
+        }
+    }
+
+    /// Download the given file, retrying transient errors.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the file.
+    /// * `range` - The HTTP range to download, if any.
+    ///
+    /// # Errors
+    ///
+    /// * [`B2ErrorKind::BackoffError`] - If the backoff failed.
+    fn download(&self, name: &str, range: Option<String>) -> RusticResult<Bytes> {
+        Ok(self
+            .http
+            .retry(|| {
+                let (auth, _) = self.auth()?;
+                let url = format!(
+                    "{}/file/{}/{}",
+                    auth.download_url,
+                    encode_name(&self.bucket),
+                    encode_name(name)
+                );
+                let mut request = self
+                    .http
+                    .client()
+                    .get(url)
+                    .header("Authorization", &auth.authorization_token);
+                if let Some(range) = &range {
+                    request = request.header("Range", range);
+                }
+                Ok(self.check(request.send()?)?.bytes()?)
+            })
+            .map_err(B2ErrorKind::BackoffError)?)
+    }
+
+    /// Upload data to an upload url, retrying transient errors with a new upload url.
+    ///
+    /// # Arguments
+    ///
+    /// * `get_url` - The operation and body to get an upload url.
+    /// * `request` - Adds the headers specific to the upload to the request.
+    /// * `buf` - The data to upload.
+    ///
+    /// # Errors
+    ///
+    /// * [`B2ErrorKind::BackoffError`] - If the backoff failed.
+    fn upload(
+        &self,
+        get_url: (&str, &dyn Fn(&str) -> Value),
+        request: impl Fn(RequestBuilder) -> RequestBuilder,
+        buf: &Bytes,
+    ) -> RusticResult<()> {
+        let sha1 = hex::encode(Sha1::digest(buf));
+        Ok(self
+            .http
+            .retry(|| {
+                let (auth, bucket_id) = self.auth()?;
+                let upload_url: UploadUrl = self
+                    .check(
+                        self.http
+                            .client()
+                            .post(format!("{}/b2api/v2/{}", auth.api_url, get_url.0))
+                            .header("Authorization", &auth.authorization_token)
+                            .json(&(get_url.1)(&bucket_id))
+                            .send()?,
+                    )?
+                    .json()?;
+                let response = request(
+                    self.http
+                        .client()
+                        .post(&upload_url.upload_url)
+                        .header("Authorization", &upload_url.authorization_token),
+                )
+                .header("X-Bz-Content-Sha1", &sha1)
+                .body(buf.clone())
+                .send()?;
+                // Note: an expired upload url is no reason to authorize again
+                _ = response.check_error().map_err(|err| match err {
+                    Error::Permanent(err)
+                        if err.status() == Some(StatusCode::UNAUTHORIZED)
+                            || err.status() == Some(StatusCode::REQUEST_TIMEOUT) =>
+                    {
+                        Error::Transient {
+                            err,
+                            retry_after: None,
+                        }
+                    }
+                    err => err,
+                })?;
+                Ok(())
+            })
+            .map_err(B2ErrorKind::BackoffError)?)
+    }
+
+    /// Upload a file using the large file API.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the file.
+    /// * `buf` - The data to upload.
+    /// * `part_size` - The size of the parts.
+    ///
+    /// # Errors
+    ///
+    /// * [`B2ErrorKind::BackoffError`] - If the backoff failed.
+    ///
+    /// # Notes
+    ///
+    /// If the upload fails, the large file is canceled such that no parts are left in the bucket.
+    fn write_large_file(&self, name: &str, buf: &Bytes, part_size: usize) -> RusticResult<()> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct LargeFile {
+            file_id: String,
+        }
+        let file: LargeFile = self.call("b2_start_large_file", |bucket_id| {
+            json!({ "bucketId": bucket_id, "fileName": name, "contentType": "application/octet-stream" })
+        })?;
+        trace!("started large file {} for {name}", file.file_id);
+
+        let upload = || -> RusticResult<()> {
+            let get_url = |_: &str| json!({ "fileId": file.file_id });
+            let mut sha1s = Vec::new();
+            for (number, start) in (0..buf.len())
+                .step_by(part_size)
+                .enumerate()
+                .map(|(i, start)| (i + 1, start))
+            {
+                let part = buf.slice(start..start.saturating_add(part_size).min(buf.len()));
+                self.upload(
+                    ("b2_get_upload_part_url", &get_url),
+                    |request| request.header("X-Bz-Part-Number", number),
+                    &part,
+                )?;
+                sha1s.push(hex::encode(Sha1::digest(&part)));
+            }
+            let _: Value = self.call(
+                "b2_finish_large_file",
+                |_| json!({ "fileId": file.file_id, "partSha1Array": sha1s }),
+            )?;
+            Ok(())
+        };
+
+        let result = upload();
+        if result.is_err() {
+            warn!("canceling upload of large file {name}");
+            if let Err(err) = self.call::<Value>(
+                "b2_cancel_large_file",
+                |_| json!({ "fileId": file.file_id }),
+            ) {
+                warn!("canceling large file {} failed: {err}", file.file_id);
+            }
+        }
+        result
+    }
+}
+
+/// Percent-encode a file name, keeping the `/` separators.
+fn encode_name(name: &str) -> String {
+    name.split('/')
+        .map(|part| {
+            url::form_urlencoded::byte_serialize(part.as_bytes())
+                .collect::<String>()
+                .replace('+', "%20")
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+impl ReadBackend for B2Backend {
+    /// Returns the location of the backend.
+    fn location(&self) -> String {
+        let mut location = "b2:".to_string();
+        location.push_str(&self.bucket);
+        if !self.prefix.is_empty() {
+            location.push('/');
+            location.push_str(self.prefix.trim_end_matches('/'));
+        }
+        location
+    }
+
+    /// Sets an option of the backend.
+    ///
+    /// # Arguments
+    ///
+    /// * `option` - The option to set.
+    /// * `value` - The value to set the option to.
+    ///
+    /// # Errors
+    ///
+    /// * [`B2ErrorKind::InvalidOptionValue`] - If the value is not valid for the option.
+    /// * [`HttpErrorKind::InvalidOptionValue`](crate::error::HttpErrorKind::InvalidOptionValue) - If the value is not valid for a common HTTP option.
+    ///
+    /// # Notes
+    ///
+    /// Currently supported options:
+    /// * `part-size` - The part size of large files. Files larger than this are uploaded in parts. Default is the size recommended by B2.
+    /// * `hide-on-delete` - If `true`, files are hidden instead of deleted, such that the lifecycle rules of the bucket decide when they are deleted. Default is `false`.
+    /// * `retry` - The number of retries to use for transient errors. Default is 5. Set to 0 to disable retries.
+    /// * `timeout` - The timeout to use for requests. Default is 10 minutes. Format is described in [humantime](https://docs.rs/humantime/2.1.0/humantime/fn.parse_duration.html).
+    /// * `proxy` - The proxy URL to use, `system` to use `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY` or `none`. Default is `system`.
+    fn set_option(&mut self, option: &str, value: &str) -> RusticResult<()> {
+        if self.http.set_option(option, value)? {
+            return Ok(());
+        }
+        let invalid = || B2ErrorKind::InvalidOptionValue(option.to_string(), value.to_string());
+        match option {
+            "part-size" => {
+                self.part_size = Some(ByteSize::from_str(value).map_err(|_| invalid())?.as_u64());
+            }
+            "hide-on-delete" => {
+                self.hide_on_delete = bool::from_str(value).map_err(|_| invalid())?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Returns a list of all files of a given type with their size.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the files to list.
+    ///
+    /// # Errors
+    ///
+    /// * [`B2ErrorKind::BackoffError`] - If the backoff failed.
+    ///
+    /// # Notes
+    ///
+    /// Only the latest versions are listed, hidden files are not listed.
+    ///
+    /// # Returns
+    ///
+    /// A vector of tuples containing the id and size of the files.
+    fn list_with_size(&self, tpe: FileType) -> RusticResult<Vec<(Id, u32)>> {
+        trace!("listing tpe: {tpe:?}");
+        let prefix = if tpe == FileType::Config {
+            self.file_name(tpe, &Id::default())
+        } else {
+            format!("{}{}/", self.prefix, tpe.dirname())
+        };
+
+        let mut list = Vec::new();
+        let mut start: Option<String> = None;
+        loop {
+            let files: FileList = self.call("b2_list_file_names", |bucket_id| {
+                let mut body = json!({ "bucketId": bucket_id, "prefix": prefix, "maxFileCount": consts::MAX_FILE_COUNT });
+                if let Some(start) = &start {
+                    body["startFileName"] = json!(start);
+                }
+                body
+            })?;
+            for file in files
+                .files
+                .into_iter()
+                .filter(|file| file.action == "upload")
+            {
+                let size = u32::try_from(file.content_length).unwrap_or(u32::MAX);
+                if tpe == FileType::Config {
+                    if file.file_name == prefix {
+                        list.push((Id::default(), size));
+                    }
+                    continue;
+                }
+                let name = file.file_name.rsplit('/').next().unwrap_or_default();
+                match Id::from_hex(name) {
+                    Ok(id) => list.push((id, size)),
+                    Err(_) => debug!("ignoring file {}", file.file_name),
+                }
+            }
+            start = files.next_file_name;
+            if start.is_none() {
+                break;
+            }
+        }
+        Ok(list)
+    }
+
+    /// Returns the content of a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    ///
+    /// # Errors
+    ///
+    /// * [`B2ErrorKind::BackoffError`] - If the backoff failed.
+    fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
+        trace!("reading tpe: {tpe:?}, id: {id}");
+        self.download(&self.file_name(tpe, id), None)
+    }
+
+    /// Returns a part of the content of a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    /// * `cacheable` - Whether the file is cacheable.
+    /// * `offset` - The offset to read from.
+    /// * `length` - The length to read.
+    ///
+    /// # Errors
+    ///
+    /// * [`B2ErrorKind::BackoffError`] - If the backoff failed.
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        _cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> RusticResult<Bytes> {
+        trace!("reading tpe: {tpe:?}, id: {id}, offset: {offset}, length: {length}");
+        let offset2 = offset + length - 1;
+        self.download(
+            &self.file_name(tpe, id),
+            Some(format!("bytes={offset}-{offset2}")),
+        )
+    }
+}
+
+impl WriteBackend for B2Backend {
+    /// Creates the bucket as private bucket if it doesn't exist yet.
+    ///
+    /// # Errors
+    ///
+    /// * [`B2ErrorKind::BackoffError`] - If the backoff failed.
+    fn create(&self) -> RusticResult<()> {
+        let (auth, bucket_id) = self.auth().map_err(B2ErrorKind::BackoffError)?;
+        if !bucket_id.is_empty() {
+            return Ok(());
+        }
+        debug!("creating bucket {}", self.bucket);
+        let _: Value = self.call("b2_create_bucket", |_| {
+            json!({ "accountId": auth.account_id, "bucketName": self.bucket, "bucketType": "allPrivate" })
+        })?;
+        // get the id of the new bucket
+        if let Ok(mut cache) = self.auth.write() {
+            *cache = None;
+        }
+        Ok(())
+    }
+
+    /// Writes bytes to the given file.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    /// * `cacheable` - Whether the file is cacheable.
+    /// * `buf` - The bytes to write.
+    ///
+    /// # Errors
+    ///
+    /// * [`B2ErrorKind::BackoffError`] - If the backoff failed.
+    ///
+    /// # Notes
+    ///
+    /// Files larger than the part size are uploaded using the large file API.
+    fn write_bytes(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        _cacheable: bool,
+        buf: Bytes,
+    ) -> RusticResult<()> {
+        trace!("writing tpe: {:?}, id: {}", &tpe, &id);
+        let name = self.file_name(tpe, id);
+        let (auth, _) = self.auth().map_err(B2ErrorKind::BackoffError)?;
+        let part_size = self
+            .part_size
+            .unwrap_or(auth.recommended_part_size)
+            .max(auth.absolute_minimum_part_size);
+        // a large file needs at least two parts
+        if buf.len() as u64 > part_size {
+            let part_size = usize::try_from(part_size).unwrap_or(usize::MAX);
+            return self.write_large_file(&name, &buf, part_size);
+        }
+        let encoded_name = encode_name(&name);
+        self.upload(
+            (
+                "b2_get_upload_url",
+                &|bucket_id| json!({ "bucketId": bucket_id }),
+            ),
+            |request| {
+                request
+                    .header("X-Bz-File-Name", &encoded_name)
+                    .header("Content-Type", "application/octet-stream")
+            },
+            &buf,
+        )
+    }
+
+    /// Removes the given file.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    /// * `cacheable` - Whether the file is cacheable.
+    ///
+    /// # Errors
+    ///
+    /// * [`B2ErrorKind::BackoffError`] - If the backoff failed.
+    ///
+    /// # Notes
+    ///
+    /// All versions of the file are deleted. If the option `hide-on-delete` is set, the file
+    /// is hidden instead, such that the lifecycle rules of the bucket decide when it is deleted.
+    fn remove(&self, tpe: FileType, id: &Id, _cacheable: bool) -> RusticResult<()> {
+        trace!("removing tpe: {:?}, id: {}", &tpe, &id);
+        let name = self.file_name(tpe, id);
+        if self.hide_on_delete {
+            let _: Value = self.call(
+                "b2_hide_file",
+                |bucket_id| json!({ "bucketId": bucket_id, "fileName": name }),
+            )?;
+            return Ok(());
+        }
+
+        let (mut start_name, mut start_id) = (Some(name.clone()), None);
+        while start_name.as_ref() == Some(&name) {
+            let versions: FileList = self.call("b2_list_file_versions", |bucket_id| {
+                let mut body =
+                    json!({ "bucketId": bucket_id, "prefix": name, "startFileName": start_name });
+                if let Some(start_id) = &start_id {
+                    body["startFileId"] = json!(start_id);
+                }
+                body
+            })?;
+            for version in versions.files {
+                if let (true, Some(file_id)) = (version.file_name == name, version.file_id) {
+                    let _: Value = self.call(
+                        "b2_delete_file_version",
+                        |_| json!({ "fileName": name, "fileId": file_id }),
+                    )?;
+                }
+            }
+            (start_name, start_id) = (versions.next_file_name, versions.next_file_id);
+        }
+        Ok(())
+    }
+}
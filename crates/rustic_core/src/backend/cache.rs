@@ -430,6 +430,44 @@ impl Cache {
         Ok(())
     }
 
+    /// Returns the path to the super index, i.e. the binary representation of the full index.
+    fn super_index_path(&self) -> PathBuf {
+        self.path.join("index.bin")
+    }
+
+    /// Opens the super index for reading.
+    ///
+    /// # Errors
+    ///
+    /// * [`CacheBackendErrorKind::FromIoError`] - If the file could not be opened.
+    pub(crate) fn open_super_index(&self) -> RusticResult<File> {
+        Ok(File::open(self.super_index_path()).map_err(CacheBackendErrorKind::FromIoError)?)
+    }
+
+    /// Creates a temporary file to write a new super index to.
+    ///
+    /// The new super index replaces the old one when calling [`Cache::persist_super_index`].
+    ///
+    /// # Errors
+    ///
+    /// * [`CacheBackendErrorKind::FromIoError`] - If the file could not be created.
+    pub(crate) fn create_super_index(&self) -> RusticResult<File> {
+        Ok(File::create(self.super_index_path().with_extension("tmp"))
+            .map_err(CacheBackendErrorKind::FromIoError)?)
+    }
+
+    /// Replaces the super index by the one written to the file created by [`Cache::create_super_index`].
+    ///
+    /// # Errors
+    ///
+    /// * [`CacheBackendErrorKind::FromIoError`] - If the file could not be renamed.
+    pub(crate) fn persist_super_index(&self) -> RusticResult<()> {
+        let filename = self.super_index_path();
+        fs::rename(filename.with_extension("tmp"), &filename)
+            .map_err(CacheBackendErrorKind::FromIoError)?;
+        Ok(())
+    }
+
     /// Removes the given file.
     ///
     /// # Arguments
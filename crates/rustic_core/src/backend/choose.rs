@@ -1,9 +1,19 @@
+use std::time::Duration;
+
 use bytes::Bytes;
 
+#[cfg(feature = "b2")]
+use crate::backend::b2::B2Backend;
+#[cfg(feature = "ipfs")]
+use crate::backend::ipfs::IpfsBackend;
+#[cfg(feature = "s3")]
+use crate::backend::s3::S3Backend;
+#[cfg(feature = "webdav")]
+use crate::backend::webdav::WebDavBackend;
 use crate::{
     backend::{
         local::LocalBackend, rclone::RcloneBackend, rest::RestBackend, FileType, ReadBackend,
-        WriteBackend,
+        ThawState, WriteBackend,
     },
     error::BackendErrorKind,
     error::RusticResult,
@@ -19,6 +29,18 @@ pub enum ChooseBackend {
     Rest(RestBackend),
     /// Rclone backend.
     Rclone(RcloneBackend),
+    /// S3 backend.
+    #[cfg(feature = "s3")]
+    S3(S3Backend),
+    /// B2 backend.
+    #[cfg(feature = "b2")]
+    B2(B2Backend),
+    /// `WebDAV` backend.
+    #[cfg(feature = "webdav")]
+    WebDav(WebDavBackend),
+    /// IPFS backend.
+    #[cfg(feature = "ipfs")]
+    Ipfs(IpfsBackend),
 }
 
 impl ChooseBackend {
@@ -31,16 +53,48 @@ impl ChooseBackend {
     /// # Errors
     ///
     /// * [`BackendErrorKind::BackendNotSupported`] - If the backend is not supported.
+    /// * [`BackendErrorKind::BackendNotCompiled`] - If rustic was compiled without the feature of the backend.
     /// * [`LocalErrorKind::DirectoryCreationFailed`] - If the directory could not be created.
     /// * [`RestErrorKind::UrlParsingFailed`] - If the url could not be parsed.
     /// * [`RestErrorKind::BuildingClientFailed`] - If the client could not be built.
+    /// * [`S3ErrorKind::InvalidUrl`] - If the S3 url doesn't contain a bucket.
+    /// * [`B2ErrorKind::NoCredentials`] - If no B2 credentials are given.
+    /// * [`WebDavErrorKind::UrlParsingFailed`] - If the `WebDAV` url could not be parsed.
+    /// * [`IpfsErrorKind::UrlParsingFailed`] - If the IPFS API url could not be parsed.
     pub fn from_url(url: &str) -> RusticResult<Self> {
         Ok(match url.split_once(':') {
+            // UNC paths (`\\server\share`) and extended-length paths (`\\?\C:\repo`)
+            #[cfg(windows)]
+            _ if url.starts_with(r"\\") => Self::Local(LocalBackend::new(url)?),
             #[cfg(windows)]
             Some((drive, _)) if drive.len() == 1 => Self::Local(LocalBackend::new(url)?),
             Some(("rclone", path)) => Self::Rclone(RcloneBackend::new(path)?),
             Some(("rest", path)) => Self::Rest(RestBackend::new(path)?),
+            #[cfg(feature = "s3")]
+            Some(("s3", path)) => Self::S3(S3Backend::new(path)?),
+            #[cfg(feature = "b2")]
+            Some(("b2", path)) => Self::B2(B2Backend::new(path)?),
+            #[cfg(feature = "webdav")]
+            Some(("webdav", path)) => Self::WebDav(WebDavBackend::new(path)?),
+            #[cfg(feature = "ipfs")]
+            Some(("ipfs", path)) => Self::Ipfs(IpfsBackend::new(path)?),
             Some(("local", path)) => Self::Local(LocalBackend::new(path)?),
+            #[cfg(not(feature = "s3"))]
+            Some((backend @ "s3", _)) => {
+                return Err(BackendErrorKind::BackendNotCompiled(backend.to_owned()).into())
+            }
+            #[cfg(not(feature = "b2"))]
+            Some((backend @ "b2", _)) => {
+                return Err(BackendErrorKind::BackendNotCompiled(backend.to_owned()).into())
+            }
+            #[cfg(not(feature = "webdav"))]
+            Some((backend @ "webdav", _)) => {
+                return Err(BackendErrorKind::BackendNotCompiled(backend.to_owned()).into())
+            }
+            #[cfg(not(feature = "ipfs"))]
+            Some((backend @ "ipfs", _)) => {
+                return Err(BackendErrorKind::BackendNotCompiled(backend.to_owned()).into())
+            }
             Some((backend, _)) => {
                 return Err(BackendErrorKind::BackendNotSupported(backend.to_owned()).into())
             }
@@ -56,6 +110,14 @@ impl ReadBackend for ChooseBackend {
             Self::Local(local) => local.location(),
             Self::Rest(rest) => rest.location(),
             Self::Rclone(rclone) => rclone.location(),
+            #[cfg(feature = "s3")]
+            Self::S3(s3) => s3.location(),
+            #[cfg(feature = "b2")]
+            Self::B2(b2) => b2.location(),
+            #[cfg(feature = "webdav")]
+            Self::WebDav(webdav) => webdav.location(),
+            #[cfg(feature = "ipfs")]
+            Self::Ipfs(ipfs) => ipfs.location(),
         }
     }
 
@@ -70,6 +132,14 @@ impl ReadBackend for ChooseBackend {
             Self::Local(local) => local.set_option(option, value),
             Self::Rest(rest) => rest.set_option(option, value),
             Self::Rclone(rclone) => rclone.set_option(option, value),
+            #[cfg(feature = "s3")]
+            Self::S3(s3) => s3.set_option(option, value),
+            #[cfg(feature = "b2")]
+            Self::B2(b2) => b2.set_option(option, value),
+            #[cfg(feature = "webdav")]
+            Self::WebDav(webdav) => webdav.set_option(option, value),
+            #[cfg(feature = "ipfs")]
+            Self::Ipfs(ipfs) => ipfs.set_option(option, value),
         }
     }
 
@@ -91,6 +161,14 @@ impl ReadBackend for ChooseBackend {
             Self::Local(local) => local.list_with_size(tpe),
             Self::Rest(rest) => rest.list_with_size(tpe),
             Self::Rclone(rclone) => rclone.list_with_size(tpe),
+            #[cfg(feature = "s3")]
+            Self::S3(s3) => s3.list_with_size(tpe),
+            #[cfg(feature = "b2")]
+            Self::B2(b2) => b2.list_with_size(tpe),
+            #[cfg(feature = "webdav")]
+            Self::WebDav(webdav) => webdav.list_with_size(tpe),
+            #[cfg(feature = "ipfs")]
+            Self::Ipfs(ipfs) => ipfs.list_with_size(tpe),
         }
     }
 
@@ -115,6 +193,14 @@ impl ReadBackend for ChooseBackend {
             Self::Local(local) => local.read_full(tpe, id),
             Self::Rest(rest) => rest.read_full(tpe, id),
             Self::Rclone(rclone) => rclone.read_full(tpe, id),
+            #[cfg(feature = "s3")]
+            Self::S3(s3) => s3.read_full(tpe, id),
+            #[cfg(feature = "b2")]
+            Self::B2(b2) => b2.read_full(tpe, id),
+            #[cfg(feature = "webdav")]
+            Self::WebDav(webdav) => webdav.read_full(tpe, id),
+            #[cfg(feature = "ipfs")]
+            Self::Ipfs(ipfs) => ipfs.read_full(tpe, id),
         }
     }
 
@@ -143,6 +229,57 @@ impl ReadBackend for ChooseBackend {
             Self::Local(local) => local.read_partial(tpe, id, cacheable, offset, length),
             Self::Rest(rest) => rest.read_partial(tpe, id, cacheable, offset, length),
             Self::Rclone(rclone) => rclone.read_partial(tpe, id, cacheable, offset, length),
+            #[cfg(feature = "s3")]
+            Self::S3(s3) => s3.read_partial(tpe, id, cacheable, offset, length),
+            #[cfg(feature = "b2")]
+            Self::B2(b2) => b2.read_partial(tpe, id, cacheable, offset, length),
+            #[cfg(feature = "webdav")]
+            Self::WebDav(webdav) => webdav.read_partial(tpe, id, cacheable, offset, length),
+            #[cfg(feature = "ipfs")]
+            Self::Ipfs(ipfs) => ipfs.read_partial(tpe, id, cacheable, offset, length),
+        }
+    }
+
+    /// Returns the typical duration of thawing, if files need to be thawed before reading.
+    fn thaw_duration(&self) -> Option<Duration> {
+        match self {
+            Self::Local(local) => local.thaw_duration(),
+            Self::Rest(rest) => rest.thaw_duration(),
+            Self::Rclone(rclone) => rclone.thaw_duration(),
+            #[cfg(feature = "s3")]
+            Self::S3(s3) => s3.thaw_duration(),
+            #[cfg(feature = "b2")]
+            Self::B2(b2) => b2.thaw_duration(),
+            #[cfg(feature = "webdav")]
+            Self::WebDav(webdav) => webdav.thaw_duration(),
+            #[cfg(feature = "ipfs")]
+            Self::Ipfs(ipfs) => ipfs.thaw_duration(),
+        }
+    }
+
+    /// Requests thawing of the given file.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    ///
+    /// # Errors
+    ///
+    /// If the state of the file could not be determined or thawing could not be requested.
+    fn thaw(&self, tpe: FileType, id: &Id) -> RusticResult<ThawState> {
+        match self {
+            Self::Local(local) => local.thaw(tpe, id),
+            Self::Rest(rest) => rest.thaw(tpe, id),
+            Self::Rclone(rclone) => rclone.thaw(tpe, id),
+            #[cfg(feature = "s3")]
+            Self::S3(s3) => s3.thaw(tpe, id),
+            #[cfg(feature = "b2")]
+            Self::B2(b2) => b2.thaw(tpe, id),
+            #[cfg(feature = "webdav")]
+            Self::WebDav(webdav) => webdav.thaw(tpe, id),
+            #[cfg(feature = "ipfs")]
+            Self::Ipfs(ipfs) => ipfs.thaw(tpe, id),
         }
     }
 }
@@ -154,6 +291,14 @@ impl WriteBackend for ChooseBackend {
             Self::Local(local) => local.create(),
             Self::Rest(rest) => rest.create(),
             Self::Rclone(rclone) => rclone.create(),
+            #[cfg(feature = "s3")]
+            Self::S3(s3) => s3.create(),
+            #[cfg(feature = "b2")]
+            Self::B2(b2) => b2.create(),
+            #[cfg(feature = "webdav")]
+            Self::WebDav(webdav) => webdav.create(),
+            #[cfg(feature = "ipfs")]
+            Self::Ipfs(ipfs) => ipfs.create(),
         }
     }
 
@@ -170,6 +315,14 @@ impl WriteBackend for ChooseBackend {
             Self::Local(local) => local.write_bytes(tpe, id, cacheable, buf),
             Self::Rest(rest) => rest.write_bytes(tpe, id, cacheable, buf),
             Self::Rclone(rclone) => rclone.write_bytes(tpe, id, cacheable, buf),
+            #[cfg(feature = "s3")]
+            Self::S3(s3) => s3.write_bytes(tpe, id, cacheable, buf),
+            #[cfg(feature = "b2")]
+            Self::B2(b2) => b2.write_bytes(tpe, id, cacheable, buf),
+            #[cfg(feature = "webdav")]
+            Self::WebDav(webdav) => webdav.write_bytes(tpe, id, cacheable, buf),
+            #[cfg(feature = "ipfs")]
+            Self::Ipfs(ipfs) => ipfs.write_bytes(tpe, id, cacheable, buf),
         }
     }
 
@@ -185,6 +338,14 @@ impl WriteBackend for ChooseBackend {
             Self::Local(local) => local.remove(tpe, id, cacheable),
             Self::Rest(rest) => rest.remove(tpe, id, cacheable),
             Self::Rclone(rclone) => rclone.remove(tpe, id, cacheable),
+            #[cfg(feature = "s3")]
+            Self::S3(s3) => s3.remove(tpe, id, cacheable),
+            #[cfg(feature = "b2")]
+            Self::B2(b2) => b2.remove(tpe, id, cacheable),
+            #[cfg(feature = "webdav")]
+            Self::WebDav(webdav) => webdav.remove(tpe, id, cacheable),
+            #[cfg(feature = "ipfs")]
+            Self::Ipfs(ipfs) => ipfs.remove(tpe, id, cacheable),
         }
     }
 }
@@ -0,0 +1,155 @@
+//! Reading and setting file flags, i.e. `chattr` attributes on Linux and `chflags` flags on macOS
+use std::{fs::Metadata, io, path::Path};
+
+use crate::backend::node::FileFlag;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod platform {
+    use std::{
+        fs::{File, Metadata, OpenOptions},
+        io,
+        os::unix::fs::OpenOptionsExt,
+        path::Path,
+    };
+
+    use nix::libc::{O_NOFOLLOW, O_NONBLOCK};
+    use rustix::fs::{ioctl_getflags, ioctl_setflags, IFlags};
+
+    use crate::backend::node::FileFlag;
+
+    /// The flags which are saved and restored
+    const FLAGS: [(IFlags, FileFlag); 3] = [
+        (IFlags::IMMUTABLE, FileFlag::Immutable),
+        (IFlags::APPEND, FileFlag::AppendOnly),
+        (IFlags::NODUMP, FileFlag::NoDump),
+    ];
+
+    /// Open a file or directory to use the flag ioctls on it, without blocking on special files
+    fn open(path: &Path) -> io::Result<File> {
+        OpenOptions::new()
+            .read(true)
+            .custom_flags(O_NONBLOCK | O_NOFOLLOW)
+            .open(path)
+    }
+
+    pub(super) fn get_flags(path: &Path, _meta: &Metadata) -> io::Result<Vec<FileFlag>> {
+        let flags = ioctl_getflags(open(path)?)?;
+        Ok(FLAGS
+            .into_iter()
+            .filter(|(iflag, _)| flags.contains(*iflag))
+            .map(|(_, flag)| flag)
+            .collect())
+    }
+
+    pub(super) fn set_flags(path: &Path, flags: &[FileFlag]) -> io::Result<()> {
+        let file = open(path)?;
+        let current = ioctl_getflags(&file)?;
+        let mut new = current;
+        for (iflag, flag) in FLAGS {
+            new.set(iflag, flags.contains(&flag));
+        }
+        if new != current {
+            ioctl_setflags(&file, new)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::{
+        fs::{symlink_metadata, Metadata},
+        io,
+        os::macos::fs::MetadataExt,
+        path::Path,
+    };
+
+    use nix::{sys::stat::FileFlag as Flags, unistd::chflags};
+
+    use crate::backend::node::FileFlag;
+
+    /// The flags which are saved and restored, see chflags(2). Both user and system flags are mapped.
+    const FLAGS: [(u32, FileFlag); 5] = [
+        (0x0000_0001, FileFlag::NoDump),     // UF_NODUMP
+        (0x0000_0002, FileFlag::Immutable),  // UF_IMMUTABLE
+        (0x0000_0004, FileFlag::AppendOnly), // UF_APPEND
+        (0x0002_0000, FileFlag::Immutable),  // SF_IMMUTABLE
+        (0x0004_0000, FileFlag::AppendOnly), // SF_APPEND
+    ];
+
+    pub(super) fn get_flags(_path: &Path, meta: &Metadata) -> io::Result<Vec<FileFlag>> {
+        let flags = meta.st_flags();
+        let mut result: Vec<_> = FLAGS
+            .into_iter()
+            .filter(|(bits, _)| flags & bits != 0)
+            .map(|(_, flag)| flag)
+            .collect();
+        result.dedup();
+        Ok(result)
+    }
+
+    pub(super) fn set_flags(path: &Path, flags: &[FileFlag]) -> io::Result<()> {
+        let current = symlink_metadata(path)?.st_flags();
+        // only the user flags can be set by the owner; system flags are kept if they are already set
+        let mut new = current;
+        for (bits, flag) in &FLAGS[..3] {
+            if flags.contains(flag) {
+                new |= bits;
+            } else {
+                new &= !bits;
+            }
+        }
+        if new != current {
+            chflags(path, Flags::from_bits_truncate(new))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+mod platform {
+    use std::{fs::Metadata, io, path::Path};
+
+    use crate::backend::node::FileFlag;
+
+    pub(super) fn get_flags(_path: &Path, _meta: &Metadata) -> io::Result<Vec<FileFlag>> {
+        Ok(Vec::new())
+    }
+
+    pub(super) fn set_flags(_path: &Path, _flags: &[FileFlag]) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Get the file flags of a regular file or directory.
+///
+/// On platforms which don't support file flags, no flags are returned.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file or directory
+/// * `meta` - The metadata of the file or directory
+///
+/// # Errors
+///
+/// If the flags couldn't be read, e.g. because the filesystem doesn't support them
+pub(crate) fn get_flags(path: &Path, meta: &Metadata) -> io::Result<Vec<FileFlag>> {
+    platform::get_flags(path, meta)
+}
+
+/// Set the file flags of a regular file or directory.
+///
+/// Only the flags known as [`FileFlag`] are changed, all other flags are kept.
+/// On platforms which don't support file flags, this does nothing.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file or directory
+/// * `flags` - The flags to set
+///
+/// # Errors
+///
+/// If the flags couldn't be set, e.g. because of missing permissions
+pub(crate) fn set_flags(path: &Path, flags: &[FileFlag]) -> io::Result<()> {
+    platform::set_flags(path, flags)
+}
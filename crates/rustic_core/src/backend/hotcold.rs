@@ -1,6 +1,12 @@
+use std::time::Duration;
+
 use bytes::Bytes;
 
-use crate::{backend::FileType, backend::ReadBackend, backend::WriteBackend, id::Id, RusticResult};
+use crate::{
+    backend::{FileType, ReadBackend, ThawState, WriteBackend},
+    id::Id,
+    RusticResult,
+};
 
 /// A hot/cold backend implementation.
 ///
@@ -65,6 +71,14 @@ impl<BE: WriteBackend> ReadBackend for HotColdBackend<BE> {
             (Some(be), true) => be.read_partial(tpe, id, cacheable, offset, length),
         }
     }
+
+    fn thaw_duration(&self) -> Option<Duration> {
+        self.be.thaw_duration()
+    }
+
+    fn thaw(&self, tpe: FileType, id: &Id) -> RusticResult<ThawState> {
+        self.be.thaw(tpe, id)
+    }
 }
 
 impl<BE: WriteBackend> WriteBackend for HotColdBackend<BE> {
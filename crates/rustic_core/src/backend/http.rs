@@ -0,0 +1,401 @@
+//! Plumbing shared by the HTTP based backends
+//!
+//! Failed requests are retried using [`LimitRetryBackoff`], where errors are classified by
+//! [`CheckError`]. The backends S3, B2, `WebDAV` and IPFS use one [`HttpClient`] each, which
+//! holds the client and handles the options `retry`, `timeout` and `proxy` the same way for all
+//! of them. Responses in XML are read using [`XmlElement`].
+#[cfg(any(feature = "s3", feature = "b2", feature = "webdav", feature = "ipfs"))]
+use std::str::FromStr;
+use std::time::Duration;
+
+use backoff::{backoff::Backoff, Error, ExponentialBackoff, ExponentialBackoffBuilder};
+use log::warn;
+#[cfg(any(feature = "s3", feature = "webdav"))]
+use quick_xml::{events::Event, Reader};
+use reqwest::blocking::Response;
+#[cfg(any(feature = "s3", feature = "b2", feature = "webdav", feature = "ipfs"))]
+use reqwest::{
+    blocking::{Client, ClientBuilder},
+    header::{HeaderMap, HeaderValue},
+};
+
+#[cfg(any(feature = "s3", feature = "b2", feature = "webdav", feature = "ipfs"))]
+use crate::{
+    backend::proxy::ProxyOption,
+    error::{HttpErrorKind, RusticResult},
+};
+
+mod consts {
+    use std::time::Duration;
+
+    /// Default number of retries
+    pub(super) const DEFAULT_RETRY: usize = 5;
+    /// Default timeout, 10 minutes as we can have *large* packfiles
+    #[cfg(any(feature = "s3", feature = "b2", feature = "webdav", feature = "ipfs"))]
+    pub(super) const DEFAULT_TIMEOUT: Duration = Duration::from_secs(600);
+}
+
+// trait CheckError to add user-defined method check_error on Response
+pub(crate) trait CheckError {
+    /// Check reqwest Response for error and treat errors as permanent or transient
+    fn check_error(self) -> Result<Response, Error<reqwest::Error>>;
+}
+
+impl CheckError for Response {
+    /// Check reqwest Response for error and treat errors as permanent or transient
+    ///
+    /// # Errors
+    ///
+    /// If the response is an error, it will return an error of type Error<reqwest::Error>
+    ///
+    /// # Returns
+    ///
+    /// The response if it is not an error
+    fn check_error(self) -> Result<Response, Error<reqwest::Error>> {
+        match self.error_for_status() {
+            Ok(t) => Ok(t),
+            // Note: status() always give Some(_) as it is called from a Response
+            Err(err) if err.status().unwrap().is_client_error() => Err(Error::Permanent(err)),
+            Err(err) => Err(Error::Transient {
+                err,
+                retry_after: None,
+            }),
+        }
+    }
+}
+
+/// A backoff implementation that limits the number of retries
+#[derive(Clone, Debug)]
+pub(crate) struct LimitRetryBackoff {
+    /// The maximum number of retries
+    pub(crate) max_retries: usize,
+    /// The current number of retries
+    retries: usize,
+    /// The exponential backoff
+    exp: ExponentialBackoff,
+}
+
+impl LimitRetryBackoff {
+    /// Creates a new [`LimitRetryBackoff`].
+    ///
+    /// # Arguments
+    ///
+    /// * `max_retries` - The maximum number of retries
+    /// * `exp` - The exponential backoff giving the delays between the retries
+    pub(crate) const fn new(max_retries: usize, exp: ExponentialBackoff) -> Self {
+        Self {
+            max_retries,
+            retries: 0,
+            exp,
+        }
+    }
+}
+
+impl Default for LimitRetryBackoff {
+    fn default() -> Self {
+        Self {
+            max_retries: consts::DEFAULT_RETRY,
+            retries: 0,
+            exp: ExponentialBackoffBuilder::new()
+                .with_max_elapsed_time(None) // no maximum elapsed time; we count number of retires
+                .build(),
+        }
+    }
+}
+
+impl Backoff for LimitRetryBackoff {
+    /// Returns the next backoff duration.
+    ///
+    /// # Notes
+    ///
+    /// If the number of retries exceeds the maximum number of retries, it returns None.
+    fn next_backoff(&mut self) -> Option<Duration> {
+        self.retries += 1;
+        if self.retries > self.max_retries {
+            None
+        } else {
+            self.exp.next_backoff()
+        }
+    }
+
+    /// Resets the backoff to the initial state.
+    fn reset(&mut self) {
+        self.retries = 0;
+        self.exp.reset();
+    }
+}
+
+/// Notify function for backoff in case of error
+///
+/// # Arguments
+///
+/// * `err` - The error that occurred
+/// * `duration` - The duration of the backoff
+pub(crate) fn notify(err: reqwest::Error, duration: Duration) {
+    warn!("Error {err} at {duration:?}, retrying");
+}
+
+/// The HTTP client of a backend together with its timeout, proxy and retry settings
+#[cfg(any(feature = "s3", feature = "b2", feature = "webdav", feature = "ipfs"))]
+#[derive(Clone, Debug)]
+pub(crate) struct HttpClient {
+    /// The client to use.
+    client: Client,
+    /// The timeout for requests.
+    timeout: Duration,
+    /// The proxy to use for requests.
+    proxy: ProxyOption,
+    /// The backoff implementation to use.
+    backoff: LimitRetryBackoff,
+}
+
+#[cfg(any(feature = "s3", feature = "b2", feature = "webdav", feature = "ipfs"))]
+impl HttpClient {
+    /// Create a new [`HttpClient`] with the default timeout, proxy and retry settings.
+    ///
+    /// # Errors
+    ///
+    /// * [`HttpErrorKind::BuildingClientFailed`] - If the client could not be built.
+    pub(crate) fn new() -> RusticResult<Self> {
+        let proxy = ProxyOption::default();
+        Ok(Self {
+            client: Self::build(consts::DEFAULT_TIMEOUT, &proxy)?,
+            timeout: consts::DEFAULT_TIMEOUT,
+            proxy,
+            backoff: LimitRetryBackoff::default(),
+        })
+    }
+
+    /// Build the client using the given timeout and proxy.
+    ///
+    /// # Errors
+    ///
+    /// * [`HttpErrorKind::BuildingClientFailed`] - If the client could not be built.
+    fn build(timeout: Duration, proxy: &ProxyOption) -> RusticResult<Client> {
+        let mut headers = HeaderMap::new();
+        _ = headers.insert("User-Agent", HeaderValue::from_static("rustic"));
+        let builder = ClientBuilder::new()
+            .default_headers(headers)
+            .timeout(timeout);
+        Ok(proxy
+            .apply(builder)
+            .and_then(ClientBuilder::build)
+            .map_err(HttpErrorKind::BuildingClientFailed)?)
+    }
+
+    /// Returns the client to send requests with.
+    pub(crate) const fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Sets one of the options common to all HTTP based backends.
+    ///
+    /// # Arguments
+    ///
+    /// * `option` - The option to set.
+    /// * `value` - The value to set the option to.
+    ///
+    /// # Errors
+    ///
+    /// * [`HttpErrorKind::InvalidOptionValue`] - If the value is not valid for the option.
+    /// * [`HttpErrorKind::CouldNotParseDuration`] - If the timeout could not be parsed.
+    /// * [`HttpErrorKind::BuildingClientFailed`] - If the client could not be built.
+    ///
+    /// # Notes
+    ///
+    /// The common options are:
+    /// * `retry` - The number of retries to use for transient errors. Default is 5. Set to 0 to disable retries.
+    /// * `timeout` - The timeout to use for requests. Default is 10 minutes. Format is described in [humantime](https://docs.rs/humantime/2.1.0/humantime/fn.parse_duration.html).
+    /// * `proxy` - The proxy URL to use, `system` to use `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY` or `none`. Default is `system`.
+    ///
+    /// # Returns
+    ///
+    /// Whether the option is one of the common options.
+    pub(crate) fn set_option(&mut self, option: &str, value: &str) -> RusticResult<bool> {
+        match option {
+            "retry" => {
+                self.backoff.max_retries = match value {
+                    "false" | "off" => 0,
+                    "default" => LimitRetryBackoff::default().max_retries,
+                    _ => usize::from_str(value).map_err(|_| {
+                        HttpErrorKind::InvalidOptionValue(option.to_string(), value.to_string())
+                    })?,
+                };
+            }
+            "timeout" => {
+                let timeout = humantime::Duration::from_str(value)
+                    .map_err(HttpErrorKind::CouldNotParseDuration)?;
+                self.timeout = *timeout;
+                self.client = Self::build(self.timeout, &self.proxy)?;
+            }
+            "proxy" => {
+                self.proxy = ProxyOption::from_value(value);
+                self.client = Self::build(self.timeout, &self.proxy)?;
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Run an operation, retrying transient errors.
+    ///
+    /// # Arguments
+    ///
+    /// * `op` - The operation to run.
+    ///
+    /// # Errors
+    ///
+    /// The permanent error or the last transient error of the operation.
+    pub(crate) fn retry<T>(
+        &self,
+        op: impl FnMut() -> Result<T, Error<reqwest::Error>>,
+    ) -> Result<T, Error<reqwest::Error>> {
+        backoff::retry_notify(self.backoff.clone(), op, notify)
+    }
+}
+
+/// An element of a XML document, as returned by S3 or `WebDAV` servers
+///
+/// Namespaces are ignored, i.e. elements are identified by their local name only.
+#[cfg(any(feature = "s3", feature = "webdav"))]
+#[derive(Debug, Default)]
+pub(crate) struct XmlElement {
+    /// The local name of the element
+    name: String,
+    /// The unescaped text of the element, without the text of child elements
+    text: String,
+    /// The child elements
+    children: Vec<XmlElement>,
+}
+
+#[cfg(any(feature = "s3", feature = "webdav"))]
+impl XmlElement {
+    /// Create an empty element with the local name of the given tag.
+    fn new(name: &[u8]) -> Self {
+        Self {
+            name: String::from_utf8_lossy(name).to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Parse a XML document.
+    ///
+    /// # Arguments
+    ///
+    /// * `xml` - The XML document.
+    ///
+    /// # Errors
+    ///
+    /// * [`quick_xml::Error`] - If the document is not well-formed or has no root element.
+    ///
+    /// # Returns
+    ///
+    /// The root element of the document.
+    pub(crate) fn parse(xml: &str) -> Result<Self, quick_xml::Error> {
+        let mut reader = Reader::from_str(xml);
+        // the first element of the stack collects the root element
+        let mut stack = vec![Self::default()];
+        loop {
+            match reader.read_event()? {
+                Event::Start(start) => stack.push(Self::new(start.local_name().as_ref())),
+                Event::Empty(start) => {
+                    let element = Self::new(start.local_name().as_ref());
+                    if let Some(parent) = stack.last_mut() {
+                        parent.children.push(element);
+                    }
+                }
+                Event::End(_) if stack.len() > 1 => {
+                    // Note: the stack contains at least two elements, so these always match
+                    if let (Some(element), Some(parent)) = (stack.pop(), stack.last_mut()) {
+                        parent.children.push(element);
+                    }
+                }
+                Event::Text(text) => {
+                    if let Some(element) = stack.last_mut() {
+                        element.text.push_str(&text.unescape()?);
+                    }
+                }
+                Event::CData(data) => {
+                    if let Some(element) = stack.last_mut() {
+                        element.text.push_str(&String::from_utf8_lossy(&data));
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        if stack.len() > 1 {
+            return Err(quick_xml::Error::UnexpectedEof(format!(
+                "element {}",
+                stack
+                    .last()
+                    .map(|element| element.name.as_str())
+                    .unwrap_or_default()
+            )));
+        }
+        stack
+            .pop()
+            .and_then(|document| document.children.into_iter().next())
+            .ok_or_else(|| quick_xml::Error::UnexpectedEof("root element".to_string()))
+    }
+
+    /// Returns the local name of the element.
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the unescaped text of the element.
+    pub(crate) fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns the first child element with the given local name.
+    pub(crate) fn child(&self, name: &str) -> Option<&Self> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    /// Returns the text of the first child element with the given local name.
+    pub(crate) fn child_text(&self, name: &str) -> Option<&str> {
+        self.child(name).map(Self::text)
+    }
+
+    /// Returns all child elements with the given local name.
+    pub(crate) fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Self> + 'a {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+}
+
+#[cfg(all(test, any(feature = "s3", feature = "webdav")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_xml_with_namespaces_and_entities() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<d:multistatus xmlns:d="DAV:">
+  <d:response><d:href>/a%20b/&lt;c&gt;&amp;d</d:href><d:resourcetype><d:collection/></d:resourcetype></d:response>
+  <d:response><d:href><![CDATA[/x<y>]]></d:href><d:resourcetype/></d:response>
+</d:multistatus>"#;
+        let root = XmlElement::parse(xml).unwrap();
+        assert_eq!(root.name(), "multistatus");
+        let responses: Vec<_> = root.children("response").collect();
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0].child_text("href"), Some("/a%20b/<c>&d"));
+        assert!(responses[0]
+            .child("resourcetype")
+            .and_then(|tpe| tpe.child("collection"))
+            .is_some());
+        assert_eq!(responses[1].child_text("href"), Some("/x<y>"));
+        assert!(responses[1]
+            .child("resourcetype")
+            .and_then(|tpe| tpe.child("collection"))
+            .is_none());
+    }
+
+    #[test]
+    fn parse_invalid_xml() {
+        assert!(XmlElement::parse("<a><b></a>").is_err());
+        assert!(XmlElement::parse("<a><b>").is_err());
+        assert!(XmlElement::parse("no xml").is_err());
+    }
+}
@@ -2,8 +2,10 @@
 use std::os::unix::fs::{FileTypeExt, MetadataExt};
 
 use std::{
-    fs::{read_link, File},
-    path::{Path, PathBuf},
+    collections::HashMap,
+    ffi::{OsStr, OsString},
+    fs::{read_dir, read_link, File},
+    path::{Component, Path, PathBuf},
 };
 
 use serde_with::{serde_as, DisplayFromStr};
@@ -16,7 +18,7 @@ use chrono::TimeZone;
 use chrono::{DateTime, Local, Utc};
 use derive_setters::Setters;
 use ignore::{overrides::OverrideBuilder, DirEntry, Walk, WalkBuilder};
-use log::warn;
+use log::{debug, warn};
 #[cfg(not(windows))]
 use nix::unistd::{Gid, Group, Uid, User};
 
@@ -25,7 +27,9 @@ use crate::backend::node::ExtendedAttribute;
 
 use crate::{
     backend::{
-        node::{Metadata, Node, NodeType},
+        fileflags,
+        node::{FileFlag, Metadata, Node, NodeType},
+        normalize::UnicodeNormalization,
         ReadSource, ReadSourceEntry, ReadSourceOpen,
     },
     error::{IgnoreErrorKind, RusticResult},
@@ -41,6 +45,8 @@ pub struct LocalSource {
     walker: Walk,
     /// The save options to use.
     save_opts: LocalSourceSaveOptions,
+    /// The unicode normalization of names and paths.
+    normalizer: NameNormalizer,
 }
 
 #[serde_as]
@@ -60,6 +66,11 @@ pub struct LocalSourceSaveOptions {
     #[cfg_attr(feature = "clap", clap(long))]
     #[cfg_attr(feature = "merge", merge(strategy = merge::bool::overwrite_false))]
     pub ignore_devid: bool,
+
+    /// Normalize unicode file names to the given form when saving them
+    /// (names colliding with another entry after normalization are kept as they are)
+    #[cfg_attr(feature = "clap", clap(long, value_name = "FORM", value_enum))]
+    pub normalize_unicode: Option<UnicodeNormalization>,
 }
 
 #[serde_as]
@@ -110,10 +121,42 @@ pub struct LocalSourceFilterOptions {
     #[cfg_attr(feature = "merge", merge(strategy = merge::bool::overwrite_false))]
     pub one_file_system: bool,
 
+    /// With --one-file-system, still include mounted filesystems of these types (comma-separated, e.g. ext4,xfs; Linux only)
+    #[cfg_attr(
+        feature = "clap",
+        clap(
+            long,
+            value_name = "FSTYPE",
+            value_delimiter = ',',
+            requires = "one_file_system"
+        )
+    )]
+    #[cfg_attr(feature = "merge", merge(strategy = merge::vec::overwrite_empty))]
+    pub allow_filesystem: Vec<String>,
+
+    /// With --one-file-system, still include the filesystem mounted at this path (can be specified multiple times)
+    #[cfg_attr(
+        feature = "clap",
+        clap(long, value_name = "PATH", requires = "one_file_system")
+    )]
+    #[cfg_attr(feature = "merge", merge(strategy = merge::vec::overwrite_empty))]
+    pub allow_mountpoint: Vec<PathBuf>,
+
     /// Maximum size of files to be backed up. Larger files will be excluded.
     #[cfg_attr(feature = "clap", clap(long, value_name = "SIZE"))]
     #[serde_as(as = "Option<DisplayFromStr>")]
     pub exclude_larger_than: Option<ByteSize>,
+
+    /// Don't exclude files and directories which have the nodump flag set (using `chattr +d` or `chflags nodump`)
+    #[cfg_attr(feature = "clap", clap(long))]
+    #[cfg_attr(feature = "merge", merge(strategy = merge::bool::overwrite_false))]
+    pub ignore_nodump: bool,
+
+    /// Paths which are always excluded, e.g. the repository if it is within the backup paths
+    #[cfg_attr(feature = "clap", clap(skip))]
+    #[cfg_attr(feature = "merge", merge(skip))]
+    #[serde(skip)]
+    pub excluded_paths: Vec<PathBuf>,
 }
 
 impl LocalSource {
@@ -190,7 +233,6 @@ impl LocalSource {
             .git_ignore(filter_opts.git_ignore)
             .require_git(!filter_opts.no_require_git)
             .sort_by_file_path(Path::cmp)
-            .same_file_system(filter_opts.one_file_system)
             .max_filesize(filter_opts.exclude_larger_than.map(|s| s.as_u64()))
             .overrides(
                 override_builder
@@ -198,10 +240,35 @@ impl LocalSource {
                     .map_err(IgnoreErrorKind::GenericError)?,
             );
 
+        // with allowed filesystems, crossing filesystem boundaries is handled by the entry filter
+        let mount_filter = MountFilter::from_options(filter_opts);
+        _ = walk_builder.same_file_system(filter_opts.one_file_system && mount_filter.is_none());
+
         let exclude_if_present = filter_opts.exclude_if_present.clone();
-        if !filter_opts.exclude_if_present.is_empty() {
+        let excluded_paths = filter_opts.excluded_paths.clone();
+        let exclude_nodump = !filter_opts.ignore_nodump && cfg!(not(windows));
+        if exclude_nodump
+            || !filter_opts.exclude_if_present.is_empty()
+            || !excluded_paths.is_empty()
+            || mount_filter.is_some()
+        {
             _ = walk_builder.filter_entry(move |entry| match entry.file_type() {
+                _ if excluded_paths.iter().any(|path| path == entry.path()) => {
+                    debug!("excluding {:?}", entry.path());
+                    false
+                }
+                Some(tpe)
+                    if exclude_nodump && (tpe.is_dir() || tpe.is_file()) && is_nodump(entry) =>
+                {
+                    debug!("excluding {:?} which has the nodump flag set", entry.path());
+                    false
+                }
                 Some(tpe) if tpe.is_dir() => {
+                    if let Some(mount_filter) = &mount_filter {
+                        if !mount_filter.allows(entry) {
+                            return false;
+                        }
+                    }
                     for file in &exclude_if_present {
                         if entry.path().join(file).exists() {
                             return false;
@@ -219,11 +286,132 @@ impl LocalSource {
         Ok(Self {
             builder,
             walker,
+            normalizer: NameNormalizer::new(save_opts.normalize_unicode),
             save_opts,
         })
     }
 }
 
+/// Returns `true` if the nodump flag is set for the directory entry.
+///
+/// # Arguments
+///
+/// * `entry` - The directory entry to check.
+fn is_nodump(entry: &DirEntry) -> bool {
+    entry
+        .metadata()
+        .ok()
+        .and_then(|meta| fileflags::get_flags(entry.path(), &meta).ok())
+        .map_or(false, |flags| flags.contains(&FileFlag::NoDump))
+}
+
+/// [`MountFilter`] decides which mounted filesystems are included when not crossing filesystem boundaries.
+#[derive(Debug)]
+struct MountFilter {
+    /// Filesystem types to include
+    filesystems: Vec<String>,
+    /// Mountpoints to include
+    mountpoints: Vec<PathBuf>,
+    /// The filesystem types of all mountpoints
+    types: HashMap<PathBuf, String>,
+}
+
+impl MountFilter {
+    /// Create a [`MountFilter`] if other filesystems are excluded, but some of them are allowed.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter_opts` - The [`LocalSourceFilterOptions`] to use.
+    ///
+    /// # Returns
+    ///
+    /// The [`MountFilter`] or `None` if no filter is needed or supported on this platform.
+    fn from_options(filter_opts: &LocalSourceFilterOptions) -> Option<Self> {
+        if !filter_opts.one_file_system
+            || (filter_opts.allow_filesystem.is_empty() && filter_opts.allow_mountpoint.is_empty())
+        {
+            return None;
+        }
+        if cfg!(windows) {
+            warn!("allowing other filesystems is not supported on Windows, excluding all other filesystems.");
+            return None;
+        }
+        let types = mount_types();
+        if !filter_opts.allow_filesystem.is_empty() && types.is_empty() {
+            warn!("cannot determine filesystem types of mountpoints, only allowed mountpoints are included.");
+        }
+        Some(Self {
+            filesystems: filter_opts.allow_filesystem.clone(),
+            mountpoints: filter_opts.allow_mountpoint.clone(),
+            types,
+        })
+    }
+
+    /// Returns `true` if the directory entry is on the same filesystem as its parent or is an allowed mountpoint.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The directory entry to check.
+    #[cfg(not(windows))]
+    fn allows(&self, entry: &DirEntry) -> bool {
+        if entry.depth() == 0 {
+            return true;
+        }
+        let parent_dev = entry
+            .path()
+            .parent()
+            .and_then(|parent| std::fs::symlink_metadata(parent).ok())
+            .map(|meta| meta.dev());
+        let dev = entry.metadata().ok().map(|meta| meta.dev());
+        if parent_dev.is_none() || dev == parent_dev {
+            return true;
+        }
+
+        let path = entry.path();
+        let allowed = self.mountpoints.iter().any(|mountpoint| mountpoint == path)
+            || self
+                .types
+                .get(path)
+                .map_or(false, |tpe| self.filesystems.contains(tpe));
+        if !allowed {
+            debug!("excluding other filesystem mounted at {path:?}");
+        }
+        allowed
+    }
+
+    #[cfg(windows)]
+    fn allows(&self, _entry: &DirEntry) -> bool {
+        true
+    }
+}
+
+/// Get the filesystem types of all mountpoints from `/proc/self/mounts`.
+///
+/// # Returns
+///
+/// The filesystem types by mountpoint; empty if they cannot be determined.
+fn mount_types() -> HashMap<PathBuf, String> {
+    let mounts = match std::fs::read_to_string("/proc/self/mounts") {
+        Ok(mounts) => mounts,
+        Err(_) => return HashMap::new(),
+    };
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace().skip(1);
+            let mountpoint = fields.next()?;
+            let tpe = fields.next()?;
+            // special characters in mountpoints are octal-escaped
+            let mountpoint = mountpoint
+                .replace("\\040", " ")
+                .replace("\\011", "\t")
+                .replace("\\012", "\n")
+                .replace("\\134", "\\");
+            Some((PathBuf::from(mountpoint), tpe.to_string()))
+        })
+        .collect()
+}
+
 #[derive(Debug)]
 /// Describes an open file from the local backend.
 pub struct OpenFile(PathBuf);
@@ -244,6 +432,33 @@ impl ReadSourceOpen for OpenFile {
         let path = self.0;
         File::open(path).map_err(|err| IgnoreErrorKind::UnableToOpenFile(err).into())
     }
+
+    /// Open the file from the local backend without consuming it.
+    ///
+    /// # Returns
+    ///
+    /// The read handle to the file from the local backend.
+    ///
+    /// # Errors
+    ///
+    /// * [`IgnoreErrorKind::UnableToOpenFile`] - If the file could not be opened.
+    fn reopen(&self) -> Option<RusticResult<Self::Reader>> {
+        Some(File::open(&self.0).map_err(|err| IgnoreErrorKind::UnableToOpenFile(err).into()))
+    }
+
+    /// Get the current size and modification time of the file.
+    ///
+    /// # Returns
+    ///
+    /// The size and modification time or `None` if the metadata could not be read.
+    fn stat(&self) -> Option<(u64, Option<DateTime<Local>>)> {
+        let m = self.0.metadata().ok()?;
+        let mtime = m
+            .modified()
+            .ok()
+            .map(|t| DateTime::<Utc>::from(t).with_timezone(&Local));
+        Some((m.len(), mtime))
+    }
 }
 
 impl ReadSource for LocalSource {
@@ -297,12 +512,95 @@ impl Iterator for LocalSource {
                 e.map_err(IgnoreErrorKind::GenericError)?,
                 self.save_opts.with_atime,
                 self.save_opts.ignore_devid,
+                &mut self.normalizer,
             )
             .map_err(std::convert::Into::into)
         })
     }
 }
 
+/// Applies the unicode normalization to the names and paths of a local source.
+///
+/// A name which is changed by the normalization keeps its original bytes if another entry of the
+/// same directory is normalized to the same name, as both entries would collide otherwise.
+#[derive(Debug)]
+struct NameNormalizer {
+    /// The unicode normalization to apply, if any.
+    form: Option<UnicodeNormalization>,
+    /// The names of all entries changed by the normalization, by their original paths.
+    names: HashMap<PathBuf, OsString>,
+}
+
+impl NameNormalizer {
+    /// Create a new [`NameNormalizer`] applying the given normalization.
+    fn new(form: Option<UnicodeNormalization>) -> Self {
+        Self {
+            form,
+            names: HashMap::new(),
+        }
+    }
+
+    /// Returns the name to save for the entry with the given path.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The original path of the entry.
+    /// * `name` - The original name of the entry.
+    fn name(&mut self, path: &Path, name: &OsStr) -> OsString {
+        let form = match self.form {
+            Some(form) => form,
+            None => return name.to_os_string(),
+        };
+        let normalized = form.normalize_name(name);
+        if normalized == name {
+            return normalized;
+        }
+        if let Some(name) = self.names.get(path) {
+            return name.clone();
+        }
+
+        let collides = path.parent().map_or(false, |parent| {
+            read_dir(parent).map_or(false, |entries| {
+                entries.filter_map(Result::ok).any(|entry| {
+                    let other = entry.file_name();
+                    other != name && form.normalize_name(&other) == normalized
+                })
+            })
+        });
+        let name = if collides {
+            warn!("{path:?} collides with another entry after unicode normalization, keeping its original name.");
+            name.to_os_string()
+        } else {
+            normalized
+        };
+        _ = self.names.insert(path.to_path_buf(), name.clone());
+        name
+    }
+
+    /// Returns the path to save for the entry with the given path.
+    ///
+    /// All components are normalized like the names of the entries they refer to.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The original path of the entry.
+    fn path(&mut self, path: &Path) -> PathBuf {
+        if self.form.is_none() {
+            return path.to_path_buf();
+        }
+        let mut original = PathBuf::new();
+        let mut result = PathBuf::new();
+        for comp in path.components() {
+            original.push(comp);
+            match comp {
+                Component::Normal(name) => result.push(self.name(&original, name)),
+                comp => result.push(comp),
+            }
+        }
+        result
+    }
+}
+
 /// Maps a [`DirEntry`] to a [`ReadSourceEntry`].
 ///
 /// # Arguments
@@ -310,6 +608,7 @@ impl Iterator for LocalSource {
 /// * `entry` - The [`DirEntry`] to map.
 /// * `with_atime` - Whether to save access time for files and directories.
 /// * `ignore_devid` - Whether to save device ID for files and directories.
+/// * `normalizer` - The unicode normalization to apply to names and paths.
 ///
 /// # Errors
 ///
@@ -320,8 +619,9 @@ fn map_entry(
     entry: DirEntry,
     with_atime: bool,
     _ignore_devid: bool,
+    normalizer: &mut NameNormalizer,
 ) -> RusticResult<ReadSourceEntry<OpenFile>> {
-    let name = entry.file_name();
+    let name = normalizer.name(entry.path(), entry.file_name());
     let m = entry.metadata().map_err(IgnoreErrorKind::GenericError)?;
 
     // TODO: Set them to suitable values
@@ -367,20 +667,22 @@ fn map_entry(
         device_id,
         links,
         extended_attributes: Vec::new(),
+        flags: Vec::new(),
     };
 
     let node = if m.is_dir() {
-        Node::new_node(name, NodeType::Dir, meta)
+        Node::new_node(&name, NodeType::Dir, meta)
     } else if m.is_symlink() {
         let target = read_link(entry.path()).map_err(IgnoreErrorKind::FromIoError)?;
         let node_type = NodeType::from_link(&target);
-        Node::new_node(name, node_type, meta)
+        Node::new_node(&name, node_type, meta)
     } else {
-        Node::new_node(name, NodeType::File, meta)
+        Node::new_node(&name, NodeType::File, meta)
     };
 
     let path = entry.into_path();
     let open = Some(OpenFile(path.clone()));
+    let path = normalizer.path(&path);
     Ok(ReadSourceEntry { path, node, open })
 }
 
@@ -435,6 +737,7 @@ fn get_group_by_gid(gid: u32) -> Option<String> {
 /// * `entry` - The [`DirEntry`] to map.
 /// * `with_atime` - Whether to save access time for files and directories.
 /// * `ignore_devid` - Whether to save device ID for files and directories.
+/// * `normalizer` - The unicode normalization to apply to names and paths.
 ///
 /// # Errors
 ///
@@ -446,8 +749,9 @@ fn map_entry(
     entry: DirEntry,
     with_atime: bool,
     ignore_devid: bool,
+    normalizer: &mut NameNormalizer,
 ) -> RusticResult<ReadSourceEntry<OpenFile>> {
-    let name = entry.file_name();
+    let name = normalizer.name(entry.path(), entry.file_name());
     let m = entry.metadata().map_err(IgnoreErrorKind::GenericError)?;
 
     let uid = m.uid();
@@ -502,6 +806,15 @@ fn map_entry(
             .collect::<RusticResult<_>>()?
     };
 
+    let flags = if m.is_dir() || m.is_file() {
+        fileflags::get_flags(entry.path(), &m).unwrap_or_else(|err| {
+            debug!("cannot read file flags of {:?}: {err}", entry.path());
+            Vec::new()
+        })
+    } else {
+        Vec::new()
+    };
+
     let meta = Metadata {
         size,
         mtime,
@@ -516,30 +829,32 @@ fn map_entry(
         device_id,
         links,
         extended_attributes,
+        flags,
     };
     let filetype = m.file_type();
 
     let node = if m.is_dir() {
-        Node::new_node(name, NodeType::Dir, meta)
+        Node::new_node(&name, NodeType::Dir, meta)
     } else if m.is_symlink() {
         let target = read_link(entry.path()).map_err(IgnoreErrorKind::FromIoError)?;
         let node_type = NodeType::from_link(&target);
-        Node::new_node(name, node_type, meta)
+        Node::new_node(&name, node_type, meta)
     } else if filetype.is_block_device() {
         let node_type = NodeType::Dev { device: m.rdev() };
-        Node::new_node(name, node_type, meta)
+        Node::new_node(&name, node_type, meta)
     } else if filetype.is_char_device() {
         let node_type = NodeType::Chardev { device: m.rdev() };
-        Node::new_node(name, node_type, meta)
+        Node::new_node(&name, node_type, meta)
     } else if filetype.is_fifo() {
-        Node::new_node(name, NodeType::Fifo, meta)
+        Node::new_node(&name, NodeType::Fifo, meta)
     } else if filetype.is_socket() {
-        Node::new_node(name, NodeType::Socket, meta)
+        Node::new_node(&name, NodeType::Socket, meta)
     } else {
-        Node::new_node(name, NodeType::File, meta)
+        Node::new_node(&name, NodeType::File, meta)
     };
     let path = entry.into_path();
     let open = Some(OpenFile(path.clone()));
+    let path = normalizer.path(&path);
     Ok(ReadSourceEntry { path, node, open })
 }
 
@@ -640,3 +955,39 @@ pub mod mapper {
         mode
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Some filesystems (e.g. APFS) don't allow names which only differ in their normalization
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn normalizer_keeps_colliding_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let decomposed = OsStr::new("cafe\u{301}");
+        let composed = OsStr::new("caf\u{e9}");
+        let single = OsStr::new("na\u{308}ive");
+        for name in [decomposed, composed, single] {
+            File::create(dir.path().join(name)).unwrap();
+        }
+
+        let mut normalizer = NameNormalizer::new(Some(UnicodeNormalization::Nfc));
+        assert_eq!(
+            normalizer.name(&dir.path().join(decomposed), decomposed),
+            decomposed
+        );
+        assert_eq!(
+            normalizer.name(&dir.path().join(composed), composed),
+            composed
+        );
+        assert_eq!(
+            normalizer.name(&dir.path().join(single), single),
+            OsStr::new("n\u{e4}ive")
+        );
+        assert_eq!(
+            normalizer.path(&dir.path().join(single)),
+            dir.path().join("n\u{e4}ive")
+        );
+    }
+}
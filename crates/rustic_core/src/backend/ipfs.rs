@@ -0,0 +1,552 @@
+//! Experimental backend storing the repository in the mutable file system (MFS) of an IPFS node
+//!
+//! The node is accessed using its HTTP API, e.g. of Kubo. The repository files are saved as
+//! MFS paths like `<path>/data/<xx>/<id>`; if an IPNS key is given, the root CID of the repository
+//! is published under this key whenever snapshots are changed.
+use bytes::Bytes;
+use log::{debug, trace};
+use rand::{thread_rng, RngCore};
+use reqwest::{
+    blocking::{RequestBuilder, Response},
+    header::CONTENT_TYPE,
+    StatusCode, Url,
+};
+use serde::Deserialize;
+
+use crate::{
+    backend::{
+        http::{CheckError, HttpClient},
+        FileType, ReadBackend, WriteBackend,
+    },
+    error::{IpfsErrorKind, RusticResult},
+    id::Id,
+};
+
+mod consts {
+    /// Environment variable containing the url of the API if the path doesn't contain one
+    pub(super) const API_ENV: &str = "IPFS_API";
+    /// The default url of the API of a local node
+    pub(super) const DEFAULT_API: &str = "http://127.0.0.1:5001";
+}
+
+/// An error returned by the API
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ApiError {
+    /// The error message
+    message: String,
+}
+
+/// An entry of a MFS directory as returned by `files/ls`
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct MfsEntry {
+    /// The name of the entry
+    name: String,
+    /// The type of the entry, 0 for files and 1 for directories
+    #[serde(rename = "Type")]
+    tpe: u8,
+    /// The size of the entry
+    size: u64,
+}
+
+/// A listing of a MFS directory as returned by `files/ls`
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct MfsListing {
+    /// The entries of the directory; `null` if the directory is empty
+    entries: Option<Vec<MfsEntry>>,
+}
+
+/// The status of a MFS file or directory as returned by `files/stat`
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct MfsStat {
+    /// The CID of the file or directory
+    hash: String,
+    /// The size of the file
+    size: u64,
+}
+
+/// Build a `multipart/form-data` body containing the given data as single file.
+///
+/// # Arguments
+///
+/// * `boundary` - The boundary separating the parts.
+/// * `data` - The contents of the file.
+fn multipart(boundary: &str, data: &[u8]) -> Bytes {
+    let mut body = format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"file\"\r\nContent-Type: application/octet-stream\r\n\r\n"
+    )
+    .into_bytes();
+    body.extend_from_slice(data);
+    body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+    body.into()
+}
+
+/// A backend implementation that uses the MFS of an IPFS node.
+#[derive(Clone, Debug)]
+pub struct IpfsBackend {
+    /// The url of the API commands, ending with `/api/v0/`.
+    api: Url,
+    /// The MFS path of the repository, starting with `/` and without trailing `/`.
+    path: String,
+    /// The IPNS key to publish the repository under, if any.
+    ipns_key: Option<String>,
+    /// The HTTP client together with its timeout, proxy and retry settings.
+    http: HttpClient,
+}
+
+impl IpfsBackend {
+    /// Create a new [`IpfsBackend`] from a given path.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The MFS path of the repository, i.e. `[http(s)://host:port]/path`. If no
+    ///            API url is given, `IPFS_API` or `http://127.0.0.1:5001` is used.
+    ///
+    /// # Errors
+    ///
+    /// * [`IpfsErrorKind::UrlParsingFailed`] - If the url could not be parsed.
+    /// * [`HttpErrorKind::BuildingClientFailed`](crate::error::HttpErrorKind::BuildingClientFailed) - If the client could not be built.
+    pub fn new(path: &str) -> RusticResult<Self> {
+        let (api, path) = if path.starts_with("http://") || path.starts_with("https://") {
+            let url = Url::parse(path).map_err(IpfsErrorKind::UrlParsingFailed)?;
+            let path = url.path().to_string();
+            (url, path)
+        } else {
+            let api = std::env::var(consts::API_ENV).unwrap_or_else(|_| consts::DEFAULT_API.into());
+            (
+                Url::parse(&api).map_err(IpfsErrorKind::UrlParsingFailed)?,
+                path.to_string(),
+            )
+        };
+        let api = api
+            .join("/api/v0/")
+            .map_err(IpfsErrorKind::UrlParsingFailed)?;
+        let path = format!("/{}", path.trim_matches('/'));
+
+        Ok(Self {
+            api,
+            path,
+            ipns_key: None,
+            http: HttpClient::new()?,
+        })
+    }
+
+    /// Returns the MFS path of the given directory of the repository.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory relative to the repository, empty for the repository itself.
+    fn dir(&self, dir: &str) -> String {
+        if dir.is_empty() {
+            self.path.clone()
+        } else {
+            format!("{}/{dir}", self.path.trim_end_matches('/'))
+        }
+    }
+
+    /// Returns the MFS path of the given file.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    fn path(&self, tpe: FileType, id: &Id) -> String {
+        let hex_id = id.to_hex();
+        match tpe {
+            FileType::Config => self.dir("config"),
+            FileType::Pack => self.dir(&format!("data/{}/{hex_id}", &hex_id[0..2])),
+            _ => self.dir(&format!("{}/{hex_id}", tpe.dirname())),
+        }
+    }
+
+    /// Call an API command, retrying transient errors.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command to call, e.g. `files/read`.
+    /// * `args` - The arguments of the command.
+    /// * `request` - Adds headers and body to the request.
+    /// * `read` - Reads the result from the response.
+    ///
+    /// # Errors
+    ///
+    /// * [`IpfsErrorKind::BackoffError`] - If the request failed, even after retrying.
+    /// * [`IpfsErrorKind::ApiError`] - If the command failed.
+    ///
+    /// # Returns
+    ///
+    /// The result or `None` if the command failed because the path doesn't exist.
+    fn call<T>(
+        &self,
+        command: &str,
+        args: &[(&str, &str)],
+        request: impl Fn(RequestBuilder) -> RequestBuilder,
+        read: impl Fn(Response) -> reqwest::Result<T>,
+    ) -> RusticResult<Option<T>> {
+        trace!("calling {command} {args:?}");
+        let url = self
+            .api
+            .join(command)
+            .map_err(IpfsErrorKind::UrlParsingFailed)?;
+        let result = self
+            .http
+            .retry(|| {
+                let response = request(self.http.client().post(url.clone()).query(args)).send()?;
+                if response.status() == StatusCode::INTERNAL_SERVER_ERROR {
+                    // the API reports failed commands, e.g. for missing files, as internal server error
+                    if let Ok(error) = response.json::<ApiError>() {
+                        return Ok(Err(error.message));
+                    }
+                    return Ok(Err("unknown error".to_string()));
+                }
+                Ok(Ok(read(response.check_error()?)?))
+            })
+            .map_err(IpfsErrorKind::BackoffError)?;
+
+        match result {
+            Ok(result) => Ok(Some(result)),
+            Err(message) if message.contains("does not exist") => Ok(None),
+            Err(message) => Err(IpfsErrorKind::ApiError(message).into()),
+        }
+    }
+
+    /// List the entries of a MFS directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory relative to the repository.
+    ///
+    /// # Errors
+    ///
+    /// If the API call failed.
+    ///
+    /// # Returns
+    ///
+    /// The entries of the directory; empty if the directory doesn't exist.
+    fn ls(&self, dir: &str) -> RusticResult<Vec<MfsEntry>> {
+        let listing = self.call(
+            "files/ls",
+            &[("arg", &self.dir(dir)), ("long", "true"), ("U", "true")],
+            |request| request,
+            Response::json::<MfsListing>,
+        )?;
+        Ok(listing
+            .and_then(|listing| listing.entries)
+            .unwrap_or_default())
+    }
+
+    /// Create a MFS directory including its parents, if it doesn't exist yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory relative to the repository, empty for the repository itself.
+    ///
+    /// # Errors
+    ///
+    /// If the API call failed.
+    fn mkdir(&self, dir: &str) -> RusticResult<()> {
+        _ = self.call(
+            "files/mkdir",
+            &[("arg", &self.dir(dir)), ("parents", "true")],
+            |request| request,
+            |_| Ok(()),
+        )?;
+        Ok(())
+    }
+
+    /// Publish the root CID of the repository under the IPNS key, if one is given.
+    ///
+    /// # Errors
+    ///
+    /// If the API calls failed.
+    fn publish(&self) -> RusticResult<()> {
+        let key = match &self.ipns_key {
+            Some(key) => key,
+            None => return Ok(()),
+        };
+        let stat = self
+            .call(
+                "files/stat",
+                &[("arg", &self.path)],
+                |request| request,
+                Response::json::<MfsStat>,
+            )?
+            .ok_or_else(|| IpfsErrorKind::FileNotFound(self.path.clone()))?;
+        debug!("publishing /ipfs/{} under IPNS key {key}", stat.hash);
+        _ = self.call(
+            "name/publish",
+            &[("arg", &format!("/ipfs/{}", stat.hash)), ("key", key)],
+            |request| request,
+            |_| Ok(()),
+        )?;
+        Ok(())
+    }
+}
+
+impl ReadBackend for IpfsBackend {
+    /// Returns the location of the backend.
+    fn location(&self) -> String {
+        let mut location = "ipfs:".to_string();
+        location.push_str(&self.api[..url::Position::BeforePath]);
+        location.push_str(&self.path);
+        location
+    }
+
+    /// Sets an option of the backend.
+    ///
+    /// # Arguments
+    ///
+    /// * `option` - The option to set.
+    /// * `value` - The value to set the option to.
+    ///
+    /// # Errors
+    ///
+    /// * [`HttpErrorKind::InvalidOptionValue`](crate::error::HttpErrorKind::InvalidOptionValue) - If the value is not valid for a common HTTP option.
+    ///
+    /// # Notes
+    ///
+    /// Currently supported options:
+    /// * `ipns-key` - The name of the key to publish the repository under using IPNS. Default is not to publish.
+    /// * `retry` - The number of retries to use for transient errors. Default is 5. Set to 0 to disable retries.
+    /// * `timeout` - The timeout to use for requests. Default is 10 minutes. Format is described in [humantime](https://docs.rs/humantime/2.1.0/humantime/fn.parse_duration.html).
+    /// * `proxy` - The proxy URL to use, `system` to use `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY` or `none`. Default is `system`.
+    fn set_option(&mut self, option: &str, value: &str) -> RusticResult<()> {
+        if self.http.set_option(option, value)? {
+            return Ok(());
+        }
+        if option == "ipns-key" {
+            self.ipns_key = (!value.is_empty()).then(|| value.to_string());
+        }
+        Ok(())
+    }
+
+    /// Returns a list of all files of a given type with their size.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the files to list.
+    ///
+    /// # Errors
+    ///
+    /// * [`IpfsErrorKind::BackoffError`] - If the backoff failed.
+    /// * [`IpfsErrorKind::ApiError`] - If listing failed.
+    ///
+    /// # Returns
+    ///
+    /// A vector of tuples containing the id and size of the files.
+    fn list_with_size(&self, tpe: FileType) -> RusticResult<Vec<(Id, u32)>> {
+        trace!("listing tpe: {tpe:?}");
+        if tpe == FileType::Config {
+            let stat = self.call(
+                "files/stat",
+                &[("arg", &self.dir("config"))],
+                |request| request,
+                Response::json::<MfsStat>,
+            )?;
+            return stat.map_or_else(
+                || Ok(Vec::new()),
+                |stat| {
+                    Ok(vec![(
+                        Id::default(),
+                        stat.size
+                            .try_into()
+                            .map_err(IpfsErrorKind::FromTryIntError)?,
+                    )])
+                },
+            );
+        }
+
+        let dirs = if tpe == FileType::Pack {
+            self.ls("data")?
+                .into_iter()
+                .filter(|entry| entry.tpe == 1)
+                .map(|entry| format!("data/{}", entry.name))
+                .collect()
+        } else {
+            vec![tpe.dirname().to_string()]
+        };
+
+        let mut list = Vec::new();
+        for dir in dirs {
+            for entry in self.ls(&dir)? {
+                if entry.tpe == 1 {
+                    continue;
+                }
+                match Id::from_hex(&entry.name) {
+                    Ok(id) => list.push((
+                        id,
+                        entry
+                            .size
+                            .try_into()
+                            .map_err(IpfsErrorKind::FromTryIntError)?,
+                    )),
+                    Err(_) => debug!("ignoring file {dir}/{}", entry.name),
+                }
+            }
+        }
+        Ok(list)
+    }
+
+    /// Returns the content of a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    ///
+    /// # Errors
+    ///
+    /// * [`IpfsErrorKind::BackoffError`] - If the backoff failed.
+    /// * [`IpfsErrorKind::FileNotFound`] - If the file doesn't exist.
+    fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
+        trace!("reading tpe: {tpe:?}, id: {id}");
+        let path = self.path(tpe, id);
+        self.call(
+            "files/read",
+            &[("arg", &path)],
+            |request| request,
+            Response::bytes,
+        )?
+        .ok_or_else(|| IpfsErrorKind::FileNotFound(path).into())
+    }
+
+    /// Returns a part of the content of a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    /// * `cacheable` - Whether the file is cacheable.
+    /// * `offset` - The offset to read from.
+    /// * `length` - The length to read.
+    ///
+    /// # Errors
+    ///
+    /// * [`IpfsErrorKind::BackoffError`] - If the backoff failed.
+    /// * [`IpfsErrorKind::FileNotFound`] - If the file doesn't exist.
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        _cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> RusticResult<Bytes> {
+        trace!("reading tpe: {tpe:?}, id: {id}, offset: {offset}, length: {length}");
+        let path = self.path(tpe, id);
+        self.call(
+            "files/read",
+            &[
+                ("arg", &path),
+                ("offset", &offset.to_string()),
+                ("count", &length.to_string()),
+            ],
+            |request| request,
+            Response::bytes,
+        )?
+        .ok_or_else(|| IpfsErrorKind::FileNotFound(path).into())
+    }
+}
+
+impl WriteBackend for IpfsBackend {
+    /// Creates the repository directory and the directories for all file types.
+    ///
+    /// # Errors
+    ///
+    /// * [`IpfsErrorKind::BackoffError`] - If the backoff failed.
+    /// * [`IpfsErrorKind::ApiError`] - If a directory could not be created.
+    fn create(&self) -> RusticResult<()> {
+        self.mkdir("")?;
+        for tpe in [
+            FileType::Index,
+            FileType::Key,
+            FileType::Snapshot,
+            FileType::Pack,
+        ] {
+            self.mkdir(tpe.dirname())?;
+        }
+        for i in 0u8..=255 {
+            self.mkdir(&format!("data/{}", hex::encode([i])))?;
+        }
+        Ok(())
+    }
+
+    /// Writes bytes to the given file.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    /// * `cacheable` - Whether the file is cacheable.
+    /// * `buf` - The bytes to write.
+    ///
+    /// # Errors
+    ///
+    /// * [`IpfsErrorKind::BackoffError`] - If the backoff failed.
+    /// * [`IpfsErrorKind::ApiError`] - If the file could not be written.
+    fn write_bytes(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        _cacheable: bool,
+        buf: Bytes,
+    ) -> RusticResult<()> {
+        trace!("writing tpe: {:?}, id: {}", &tpe, &id);
+        let mut boundary = [0; 16];
+        thread_rng().fill_bytes(&mut boundary);
+        let boundary = hex::encode(boundary);
+        let body = multipart(&boundary, &buf);
+        _ = self.call(
+            "files/write",
+            &[
+                ("arg", &self.path(tpe, id)),
+                ("create", "true"),
+                ("parents", "true"),
+                ("truncate", "true"),
+            ],
+            |request| {
+                request
+                    .header(
+                        CONTENT_TYPE,
+                        format!("multipart/form-data; boundary={boundary}"),
+                    )
+                    .body(body.clone())
+            },
+            |_| Ok(()),
+        )?;
+        if tpe == FileType::Snapshot {
+            self.publish()?;
+        }
+        Ok(())
+    }
+
+    /// Removes the given file.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    /// * `cacheable` - Whether the file is cacheable.
+    ///
+    /// # Errors
+    ///
+    /// * [`IpfsErrorKind::BackoffError`] - If the backoff failed.
+    /// * [`IpfsErrorKind::FileNotFound`] - If the file doesn't exist.
+    fn remove(&self, tpe: FileType, id: &Id, _cacheable: bool) -> RusticResult<()> {
+        trace!("removing tpe: {:?}, id: {}", &tpe, &id);
+        let path = self.path(tpe, id);
+        if self
+            .call("files/rm", &[("arg", &path)], |request| request, |_| Ok(()))?
+            .is_none()
+        {
+            return Err(IpfsErrorKind::FileNotFound(path).into());
+        }
+        if tpe == FileType::Snapshot {
+            self.publish()?;
+        }
+        Ok(())
+    }
+}
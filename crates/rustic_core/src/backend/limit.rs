@@ -0,0 +1,117 @@
+use std::{sync::Arc, time::Duration};
+
+use bytes::Bytes;
+
+use crate::{
+    backend::{FileType, ReadBackend, ThawState, WriteBackend},
+    error::RusticResult,
+    id::Id,
+    throttle::Throttle,
+};
+
+/// A backend implementation which limits the bandwidth used by the wrapped backend.
+///
+/// The limits are shared by all clones of the backend, i.e. by all parallel connections.
+///
+/// # Type Parameters
+///
+/// * `BE` - The backend to use.
+#[derive(Clone, Debug)]
+pub struct LimitBackend<BE: WriteBackend> {
+    /// The backend to use.
+    be: BE,
+    /// The limit of data written to the backend.
+    upload: Option<Arc<Throttle>>,
+    /// The limit of data read from the backend.
+    download: Option<Arc<Throttle>>,
+}
+
+impl<BE: WriteBackend> LimitBackend<BE> {
+    /// Creates a new `LimitBackend`.
+    ///
+    /// # Type Parameters
+    ///
+    /// * `BE` - The backend to use.
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The backend to use.
+    /// * `upload` - The limit of data written to the backend in bytes per second, if any.
+    /// * `download` - The limit of data read from the backend in bytes per second, if any.
+    pub fn new(be: BE, upload: Option<u64>, download: Option<u64>) -> Self {
+        Self {
+            be,
+            upload: upload.map(|rate| Arc::new(Throttle::new(rate))),
+            download: download.map(|rate| Arc::new(Throttle::new(rate))),
+        }
+    }
+
+    /// Waits until `bytes` may be read from the backend.
+    fn wait_download(&self, bytes: usize) {
+        if let Some(throttle) = &self.download {
+            throttle.wait(bytes as u64);
+        }
+    }
+}
+
+impl<BE: WriteBackend> ReadBackend for LimitBackend<BE> {
+    fn location(&self) -> String {
+        self.be.location()
+    }
+
+    fn set_option(&mut self, option: &str, value: &str) -> RusticResult<()> {
+        self.be.set_option(option, value)
+    }
+
+    fn list_with_size(&self, tpe: FileType) -> RusticResult<Vec<(Id, u32)>> {
+        self.be.list_with_size(tpe)
+    }
+
+    fn list(&self, tpe: FileType) -> RusticResult<Vec<Id>> {
+        self.be.list(tpe)
+    }
+
+    fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
+        // the size is only known after reading, so the following reads wait for this one
+        let data = self.be.read_full(tpe, id)?;
+        self.wait_download(data.len());
+        Ok(data)
+    }
+
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> RusticResult<Bytes> {
+        self.wait_download(length as usize);
+        self.be.read_partial(tpe, id, cacheable, offset, length)
+    }
+
+    fn thaw_duration(&self) -> Option<Duration> {
+        self.be.thaw_duration()
+    }
+
+    fn thaw(&self, tpe: FileType, id: &Id) -> RusticResult<ThawState> {
+        self.be.thaw(tpe, id)
+    }
+}
+
+impl<BE: WriteBackend> WriteBackend for LimitBackend<BE> {
+    fn create(&self) -> RusticResult<()> {
+        self.be.create()
+    }
+
+    fn write_bytes(&self, tpe: FileType, id: &Id, cacheable: bool, buf: Bytes) -> RusticResult<()> {
+        if let Some(throttle) = &self.upload {
+            throttle.wait(buf.len() as u64);
+        }
+        self.be.write_bytes(tpe, id, cacheable, buf)
+    }
+
+    fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> RusticResult<()> {
+        self.be.remove(tpe, id, cacheable)
+    }
+}
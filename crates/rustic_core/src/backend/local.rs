@@ -1,9 +1,11 @@
 #[cfg(not(windows))]
-use std::os::unix::fs::{symlink, PermissionsExt};
+use std::os::unix::fs::{symlink, FileExt, PermissionsExt};
 
+#[cfg(windows)]
+use std::io::Read;
 use std::{
     fs::{self, File, OpenOptions},
-    io::{Read, Seek, SeekFrom, Write},
+    io::{Seek, SeekFrom, Write},
     path::{Path, PathBuf},
     process::Command,
 };
@@ -15,7 +17,11 @@ use log::{debug, trace, warn};
 #[cfg(not(windows))]
 use nix::sys::stat::{mknod, Mode, SFlag};
 #[cfg(not(windows))]
-use nix::unistd::{fchownat, FchownatFlags, Gid, Group, Uid, User};
+use nix::sys::statvfs::statvfs;
+#[cfg(not(windows))]
+use nix::unistd::{
+    access, fchownat, pathconf, AccessFlags, FchownatFlags, Gid, Group, PathconfVar, Uid, User,
+};
 use shell_words::split;
 use walkdir::WalkDir;
 
@@ -26,6 +32,7 @@ use crate::backend::node::NodeType;
 
 use crate::{
     backend::{
+        fileflags,
         node::{ExtendedAttribute, Metadata, Node},
         FileType, ReadBackend, WriteBackend, ALL_FILE_TYPES,
     },
@@ -70,6 +77,23 @@ pub struct LocalBackend {
     post_create_command: Option<String>,
     /// The command to call after a file was deleted.
     post_delete_command: Option<String>,
+    /// Which data is synced to the storage device after writing or removing files.
+    sync: SyncStrategy,
+    /// Whether files are written to a temporary file which is then renamed.
+    atomic_write: bool,
+}
+
+/// Which data the [`LocalBackend`] syncs to the storage device after writing or removing files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum SyncStrategy {
+    /// Don't sync, leave it to the operating system
+    None,
+    /// Sync the contents and metadata of written files
+    #[default]
+    File,
+    /// Sync written files and their parent directories, such that creating or removing the
+    /// directory entry is also persisted
+    Full,
 }
 
 impl LocalBackend {
@@ -82,17 +106,35 @@ impl LocalBackend {
     /// # Errors
     ///
     /// * [`LocalErrorKind::DirectoryCreationFailed`] - If the directory could not be created.
+    /// * [`LocalErrorKind::CanonicalizingPathFailed`] - If the path could not be made absolute (only on Windows).
+    ///
+    /// # Notes
+    ///
+    /// On Windows, the path is converted into an absolute extended-length path (`\\?\C:\...`
+    /// or `\\?\UNC\server\share\...`). This resolves drive-relative paths like `C:repo` and
+    /// allows to access files whose path exceeds `MAX_PATH`, e.g. on SMB shares.
     // TODO: We should use `impl Into<Path/PathBuf>` here. we even use it in the body!
     pub fn new(path: &str) -> RusticResult<Self> {
-        let path = path.into();
+        #[cfg(windows)]
+        let path = &extended_length_path(path);
+        let path: PathBuf = path.into();
         fs::create_dir_all(&path).map_err(LocalErrorKind::DirectoryCreationFailed)?;
+        #[cfg(windows)]
+        let path = fs::canonicalize(&path).map_err(LocalErrorKind::CanonicalizingPathFailed)?;
         Ok(Self {
             path,
             post_create_command: None,
             post_delete_command: None,
+            sync: SyncStrategy::default(),
+            atomic_write: false,
         })
     }
 
+    /// The base path of the backend.
+    pub(crate) fn base_path(&self) -> &Path {
+        &self.path
+    }
+
     /// Path to the given file type and id.
     ///
     /// If the file type is `FileType::Pack`, the id will be used to determine the subdirectory.
@@ -114,6 +156,36 @@ impl LocalBackend {
         }
     }
 
+    /// Sync the directory containing the given file to the storage device, if the
+    /// [`SyncStrategy`] requires it.
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The path to the file whose parent directory should be synced.
+    ///
+    /// # Errors
+    ///
+    /// * [`LocalErrorKind::FileDoesNotHaveParent`] - If the file has no parent directory.
+    /// * [`LocalErrorKind::OpeningFileFailed`] - If the directory could not be opened.
+    /// * [`LocalErrorKind::SyncingOfOsMetadataFailed`] - If the directory could not be synced.
+    ///
+    /// # Notes
+    ///
+    /// On Windows, directories can't be synced, so this does nothing.
+    fn sync_dir(&self, filename: &Path) -> RusticResult<()> {
+        if self.sync != SyncStrategy::Full || cfg!(windows) {
+            return Ok(());
+        }
+        let dir = filename
+            .parent()
+            .ok_or_else(|| LocalErrorKind::FileDoesNotHaveParent(filename.to_path_buf()))?;
+        File::open(dir)
+            .map_err(LocalErrorKind::OpeningFileFailed)?
+            .sync_all()
+            .map_err(LocalErrorKind::SyncingOfOsMetadataFailed)?;
+        Ok(())
+    }
+
     /// Call the given command.
     ///
     /// # Arguments
@@ -161,6 +233,33 @@ impl LocalBackend {
     }
 }
 
+/// Converts an absolute path into an extended-length path (`\\?\C:\...` or `\\?\UNC\server\share\...`).
+///
+/// This allows to create the base directory even if its path exceeds `MAX_PATH`. Relative and
+/// drive-relative paths are returned unchanged.
+///
+/// # Arguments
+///
+/// * `path` - The path given by the user
+#[cfg(windows)]
+fn extended_length_path(path: &str) -> String {
+    // `/` is no separator in extended-length paths
+    let path = path.replace('/', r"\");
+    let bytes = path.as_bytes();
+    if path.split('\\').any(|c| c == "." || c == "..") {
+        // `.` and `..` are not resolved in extended-length paths
+        path
+    } else if path.starts_with(r"\\?\") {
+        path
+    } else if let Some(unc) = path.strip_prefix(r"\\") {
+        format!(r"\\?\UNC\{unc}")
+    } else if bytes.len() > 2 && bytes[0].is_ascii_alphabetic() && &bytes[1..3] == br":\" {
+        format!(r"\\?\{path}")
+    } else {
+        path
+    }
+}
+
 impl ReadBackend for LocalBackend {
     /// Returns the location of the backend.
     ///
@@ -178,12 +277,21 @@ impl ReadBackend for LocalBackend {
     /// * `option` - The option to set.
     /// * `value` - The value to set the option to.
     ///
+    /// # Errors
+    ///
+    /// * [`LocalErrorKind::InvalidOptionValue`] - If the value is not valid for the option.
+    ///
     /// # Notes
     ///
     /// The following options are supported:
     /// * `post-create-command` - The command to call after a file was created.
     /// * `post-delete-command` - The command to call after a file was deleted.
+    /// * `sync` - What to sync to the storage device: `none`, `file` (written files) or `full` (written files
+    ///   and their parent directories, also after removing files). Default is `file`.
+    /// * `atomic-write` - If `true`, files are written to a temporary file which is renamed when complete,
+    ///   such that interrupted writes don't leave truncated files. Default is `false`.
     fn set_option(&mut self, option: &str, value: &str) -> RusticResult<()> {
+        let invalid = || LocalErrorKind::InvalidOptionValue(option.to_string(), value.to_string());
         match option {
             "post-create-command" => {
                 self.post_create_command = Some(value.to_string());
@@ -191,6 +299,21 @@ impl ReadBackend for LocalBackend {
             "post-delete-command" => {
                 self.post_delete_command = Some(value.to_string());
             }
+            "sync" => {
+                self.sync = match value {
+                    "none" | "off" => SyncStrategy::None,
+                    "file" => SyncStrategy::File,
+                    "full" => SyncStrategy::Full,
+                    _ => return Err(invalid().into()),
+                }
+            }
+            "atomic-write" => {
+                self.atomic_write = match value {
+                    "true" | "on" => true,
+                    "false" | "off" => false,
+                    _ => return Err(invalid().into()),
+                }
+            }
             opt => {
                 warn!("Option {opt} is not supported! Ignoring it.");
             }
@@ -322,19 +445,45 @@ impl ReadBackend for LocalBackend {
         length: u32,
     ) -> RusticResult<Bytes> {
         trace!("reading tpe: {tpe:?}, id: {id}, offset: {offset}, length: {length}");
-        let mut file = File::open(self.path(tpe, id)).map_err(LocalErrorKind::OpeningFileFailed)?;
+        let file = File::open(self.path(tpe, id)).map_err(LocalErrorKind::OpeningFileFailed)?;
+        read_exact_at(file, offset.into(), length.into())
+    }
+}
+
+/// Read exactly `length` bytes starting at `offset` from the given file.
+///
+/// On unix, this uses a positional read (`pread`) such that only the requested range is read
+/// without the need to seek. On other platforms, the file is seeked to `offset` and read from there.
+///
+/// # Arguments
+///
+/// * `file` - The file to read from
+/// * `offset` - The offset to read from
+/// * `length` - The length to read
+///
+/// # Errors
+///
+/// * [`LocalErrorKind::CouldNotSeekToPositionInFile`] - If the file could not be seeked to the given position.
+/// * [`LocalErrorKind::FromTryIntError`] - If the length could not be converted to usize.
+/// * [`LocalErrorKind::ReadingExactLengthOfFileFailed`] - If the length of the file could not be read.
+fn read_exact_at(file: File, offset: u64, length: u64) -> RusticResult<Bytes> {
+    let mut vec = vec![0; length.try_into().map_err(LocalErrorKind::FromTryIntError)?];
+
+    #[cfg(not(windows))]
+    file.read_exact_at(&mut vec, offset)
+        .map_err(LocalErrorKind::ReadingExactLengthOfFileFailed)?;
+
+    #[cfg(windows)]
+    {
+        let mut file = file;
         _ = file
-            .seek(SeekFrom::Start(
-                offset
-                    .try_into()
-                    .expect("offset conversion should never fail."),
-            ))
+            .seek(SeekFrom::Start(offset))
             .map_err(LocalErrorKind::CouldNotSeekToPositionInFile)?;
-        let mut vec = vec![0; length.try_into().map_err(LocalErrorKind::FromTryIntError)?];
         file.read_exact(&mut vec)
             .map_err(LocalErrorKind::ReadingExactLengthOfFileFailed)?;
-        Ok(vec.into())
     }
+
+    Ok(vec.into())
 }
 
 impl WriteBackend for LocalBackend {
@@ -373,6 +522,7 @@ impl WriteBackend for LocalBackend {
     /// * [`LocalErrorKind::SettingFileLengthFailed`] - If the length of the file could not be set.
     /// * [`LocalErrorKind::CouldNotWriteToBuffer`] - If the bytes could not be written to the file.
     /// * [`LocalErrorKind::SyncingOfOsMetadataFailed`] - If the metadata of the file could not be synced.
+    /// * [`LocalErrorKind::RenamingFileFailed`] - If the temporary file could not be renamed.
     fn write_bytes(
         &self,
         tpe: FileType,
@@ -382,10 +532,21 @@ impl WriteBackend for LocalBackend {
     ) -> RusticResult<()> {
         trace!("writing tpe: {:?}, id: {}", &tpe, &id);
         let filename = self.path(tpe, id);
+        if matches!(tpe, FileType::Trash | FileType::Journal | FileType::Note) {
+            // these dirs are not created by `create` for compatibility with existing repositories
+            fs::create_dir_all(self.path.join(tpe.dirname()))
+                .map_err(LocalErrorKind::DirectoryCreationFailed)?;
+        }
+        // the temporary file name is no valid id, so it is ignored when listing files
+        let write_filename = if self.atomic_write {
+            filename.with_extension("tmp")
+        } else {
+            filename.clone()
+        };
         let mut file = fs::OpenOptions::new()
             .create(true)
             .write(true)
-            .open(&filename)
+            .open(&write_filename)
             .map_err(LocalErrorKind::OpeningFileFailed)?;
         file.set_len(
             buf.len()
@@ -395,8 +556,15 @@ impl WriteBackend for LocalBackend {
         .map_err(LocalErrorKind::SettingFileLengthFailed)?;
         file.write_all(&buf)
             .map_err(LocalErrorKind::CouldNotWriteToBuffer)?;
-        file.sync_all()
-            .map_err(LocalErrorKind::SyncingOfOsMetadataFailed)?;
+        if self.sync != SyncStrategy::None {
+            file.sync_all()
+                .map_err(LocalErrorKind::SyncingOfOsMetadataFailed)?;
+        }
+        drop(file);
+        if self.atomic_write {
+            fs::rename(&write_filename, &filename).map_err(LocalErrorKind::RenamingFileFailed)?;
+        }
+        self.sync_dir(&filename)?;
         if let Some(command) = &self.post_create_command {
             if let Err(err) = Self::call_command(tpe, id, &filename, command) {
                 warn!("post-create: {err}");
@@ -416,10 +584,12 @@ impl WriteBackend for LocalBackend {
     /// # Errors
     ///
     /// * [`LocalErrorKind::FileRemovalFailed`] - If the file could not be removed.
+    /// * [`LocalErrorKind::SyncingOfOsMetadataFailed`] - If the parent directory could not be synced.
     fn remove(&self, tpe: FileType, id: &Id, _cacheable: bool) -> RusticResult<()> {
         trace!("removing tpe: {:?}, id: {}", &tpe, &id);
         let filename = self.path(tpe, id);
         fs::remove_file(&filename).map_err(LocalErrorKind::FileRemovalFailed)?;
+        self.sync_dir(&filename)?;
         if let Some(command) = &self.post_delete_command {
             if let Err(err) = Self::call_command(tpe, id, &filename, command) {
                 warn!("post-delete: {err}");
@@ -429,6 +599,22 @@ impl WriteBackend for LocalBackend {
     }
 }
 
+#[derive(Clone, Copy, Debug, Default)]
+#[non_exhaustive]
+/// Limits of the filesystem a [`LocalDestination`] is located in.
+///
+/// Limits which cannot be determined are `None`.
+pub struct DestinationLimits {
+    /// The space available for writing
+    pub free_space: Option<u64>,
+    /// The maximum length of a file name in bytes
+    pub max_name_len: Option<usize>,
+    /// The maximum length of a path in bytes
+    pub max_path_len: Option<usize>,
+    /// Whether the destination can be written to
+    pub writable: bool,
+}
+
 #[derive(Clone, Debug)]
 /// Local destination, used when restoring.
 pub struct LocalDestination {
@@ -624,6 +810,123 @@ impl LocalDestination {
         Ok(())
     }
 
+    /// The existing directory the destination is (or will be) located in.
+    fn existing_dir(&self) -> Option<&Path> {
+        let mut dir = if self.is_file {
+            self.path.parent()?
+        } else {
+            &self.path
+        };
+        loop {
+            if dir.as_os_str().is_empty() {
+                return Some(Path::new("."));
+            }
+            if dir.is_dir() {
+                return Some(dir);
+            }
+            dir = dir.parent()?;
+        }
+    }
+
+    #[cfg(windows)]
+    // TODO: Windows support
+    /// Query the limits of the filesystem the destination is located in
+    #[must_use]
+    pub fn limits(&self) -> DestinationLimits {
+        DestinationLimits {
+            writable: self.existing_dir().is_some(),
+            ..Default::default()
+        }
+    }
+
+    #[cfg(not(windows))]
+    /// Query the limits of the filesystem the destination is located in
+    #[must_use]
+    pub fn limits(&self) -> DestinationLimits {
+        let dir = match self.existing_dir() {
+            Some(dir) => dir,
+            None => return DestinationLimits::default(),
+        };
+
+        let free_space = statvfs(dir).ok().map(|stat| {
+            // the types used by statvfs are platform-dependent
+            #[allow(clippy::unnecessary_cast, clippy::cast_lossless)]
+            let (blocks, size) = (stat.blocks_available() as u64, stat.fragment_size() as u64);
+            blocks.saturating_mul(size)
+        });
+        let limit = |var| {
+            pathconf(dir, var)
+                .ok()
+                .flatten()
+                .and_then(|len| usize::try_from(len).ok())
+        };
+
+        DestinationLimits {
+            free_space,
+            max_name_len: limit(PathconfVar::NAME_MAX),
+            max_path_len: limit(PathconfVar::PATH_MAX),
+            writable: access(dir, AccessFlags::W_OK).is_ok(),
+        }
+    }
+
+    #[cfg(windows)]
+    // TODO: Windows support
+    /// Check whether the owner given in the metadata can be set for restored entries
+    #[must_use]
+    pub fn can_set_owner(_meta: &Metadata, _numeric_id: bool) -> bool {
+        true
+    }
+
+    #[cfg(not(windows))]
+    /// Check whether the owner given in the metadata can be set for restored entries
+    ///
+    /// Only root can give files away, other users can only restore their own files.
+    ///
+    /// # Arguments
+    ///
+    /// * `meta` - The metadata to get the owner from
+    /// * `numeric_id` - Whether the numeric uid is used instead of the user name
+    #[must_use]
+    pub fn can_set_owner(meta: &Metadata, numeric_id: bool) -> bool {
+        let euid = Uid::effective();
+        if euid.is_root() {
+            return true;
+        }
+        let user = if numeric_id {
+            None
+        } else {
+            meta.user
+                .as_ref()
+                .and_then(|name| User::from_name(name).ok().flatten())
+        };
+        let uid = user.map(|u| u.uid).or_else(|| meta.uid.map(Uid::from_raw));
+        uid.map_or(true, |uid| uid == euid)
+    }
+
+    #[cfg(windows)]
+    // TODO: Windows support
+    /// Check whether the given special file can be created
+    #[must_use]
+    pub fn can_create_special(_node: &Node) -> bool {
+        true
+    }
+
+    #[cfg(not(windows))]
+    /// Check whether the given special file can be created
+    ///
+    /// Only root is allowed to create device files.
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - The node to get the type from
+    #[must_use]
+    pub fn can_create_special(node: &Node) -> bool {
+        !matches!(
+            node.node_type,
+            NodeType::Dev { .. } | NodeType::Chardev { .. }
+        ) || Uid::effective().is_root()
+    }
+
     #[cfg(windows)]
     // TODO: Windows support
     /// Set uid/gid for `item` (relative to the base path) utilizing the file metadata
@@ -794,6 +1097,40 @@ impl LocalDestination {
         Ok(())
     }
 
+    /// Set file flags like immutable or append-only for `item` (relative to the base path)
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - The item to set the file flags for
+    /// * `node` - The node to get the file flags from
+    ///
+    /// # Errors
+    ///
+    /// * [`LocalErrorKind::SettingFileFlagsFailed`] - If the file flags could not be set.
+    ///
+    /// # Notes
+    ///
+    /// This must be called after all other metadata has been set, as immutable
+    /// or append-only files cannot be changed anymore.
+    pub fn set_flags(&self, item: impl AsRef<Path>, node: &Node) -> RusticResult<()> {
+        if !(node.is_file() || node.is_dir()) {
+            return Ok(());
+        }
+
+        let filename = self.path(item);
+        // don't touch the flags if there is nothing to set and none is set
+        if node.meta.flags.is_empty()
+            && fs::symlink_metadata(&filename)
+                .and_then(|meta| fileflags::get_flags(&filename, &meta))
+                .map_or(true, |flags| flags.is_empty())
+        {
+            return Ok(());
+        }
+        fileflags::set_flags(&filename, &node.meta.flags)
+            .map_err(|err| LocalErrorKind::SettingFileFlagsFailed(err, filename))?;
+        Ok(())
+    }
+
     /// Set length of `item` (relative to the base path)
     ///
     /// # Arguments
@@ -918,14 +1255,8 @@ impl LocalDestination {
     /// * [`LocalErrorKind::ReadingExactLengthOfFileFailed`] - If the length of the file could not be read.
     pub fn read_at(&self, item: impl AsRef<Path>, offset: u64, length: u64) -> RusticResult<Bytes> {
         let filename = self.path(item);
-        let mut file = File::open(filename).map_err(LocalErrorKind::OpeningFileFailed)?;
-        _ = file
-            .seek(SeekFrom::Start(offset))
-            .map_err(LocalErrorKind::CouldNotSeekToPositionInFile)?;
-        let mut vec = vec![0; length.try_into().map_err(LocalErrorKind::FromTryIntError)?];
-        file.read_exact(&mut vec)
-            .map_err(LocalErrorKind::ReadingExactLengthOfFileFailed)?;
-        Ok(vec.into())
+        let file = File::open(filename).map_err(LocalErrorKind::OpeningFileFailed)?;
+        read_exact_at(file, offset, length)
     }
 
     /// Check if a matching file exists.
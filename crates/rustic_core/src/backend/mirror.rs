@@ -0,0 +1,315 @@
+use std::{collections::HashMap, time::Duration};
+
+use bytes::Bytes;
+use log::{debug, info, warn};
+use serde::Serialize;
+
+use crate::{
+    backend::{FileType, ReadBackend, ThawState, WriteBackend},
+    error::RusticResult,
+    id::Id,
+    progress::Progress,
+};
+
+/// All [`FileType`]s which are synchronized to the mirrors
+const MIRRORED_FILE_TYPES: [FileType; 8] = [
+    FileType::Config,
+    FileType::Key,
+    FileType::Snapshot,
+    FileType::Index,
+    FileType::Pack,
+    FileType::Trash,
+    FileType::Journal,
+    FileType::Note,
+];
+
+/// A backend writing all files to several backends and reading from the first one which is accessible.
+///
+/// Writes and removals succeed if they succeed for the primary backend or, if a quorum is set, for at
+/// least this number of backends; failures of the other backends are logged. Listings contain the files
+/// of all accessible backends. Backends which missed changes can be synchronized using [`MirrorBackend::resync`].
+///
+/// # Type Parameters
+///
+/// * `BE` - The backend to use.
+#[derive(Clone, Debug)]
+pub struct MirrorBackend<BE: WriteBackend> {
+    /// The backends; the first one is the primary backend.
+    backends: Vec<BE>,
+    /// The number of backends writes and removals must succeed for. If not set, they must succeed for the primary backend.
+    quorum: Option<usize>,
+}
+
+/// Statistics about synchronizing a mirror
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct MirrorResyncStats {
+    /// The location of the mirror
+    pub location: String,
+    /// Number of files copied to the mirror
+    pub copied: u64,
+    /// Number of bytes copied to the mirror
+    pub copied_bytes: u64,
+}
+
+impl<BE: WriteBackend> MirrorBackend<BE> {
+    /// Creates a new `MirrorBackend`.
+    ///
+    /// # Type Parameters
+    ///
+    /// * `BE` - The backend to use.
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The primary backend.
+    /// * `mirrors` - The backends mirroring the primary backend.
+    pub fn new(be: BE, mirrors: Vec<BE>) -> Self {
+        let mut backends = vec![be];
+        backends.extend(mirrors);
+        Self {
+            backends,
+            quorum: None,
+        }
+    }
+
+    /// Sets the number of backends writes and removals must succeed for.
+    ///
+    /// # Arguments
+    ///
+    /// * `quorum` - The number of backends (including the primary backend). If `None`, writes and
+    ///   removals must succeed for the primary backend. If larger than the number of backends, they
+    ///   must succeed for all backends.
+    pub fn set_quorum(&mut self, quorum: Option<usize>) {
+        self.quorum = quorum;
+    }
+
+    /// Returns the backends; the first one is the primary backend.
+    pub(crate) fn backends(&self) -> &[BE] {
+        &self.backends
+    }
+
+    /// Run the given operation on the backends in their order until it succeeds.
+    ///
+    /// # Errors
+    ///
+    /// The error of the primary backend if the operation failed on all backends.
+    fn first_ok<T>(&self, tpe: FileType, op: impl Fn(&BE) -> RusticResult<T>) -> RusticResult<T> {
+        if self.backends.len() == 1 {
+            return op(&self.backends[0]);
+        }
+        let mut first_err = None;
+        for be in &self.backends {
+            match op(be) {
+                Ok(result) => return Ok(result),
+                Err(err) => {
+                    warn!("{tpe:?} not accessible in {}: {err}", be.location());
+                    first_err = first_err.or(Some(err));
+                }
+            }
+        }
+        // Note: there is always at least one backend, so there is an error
+        Err(first_err.unwrap())
+    }
+
+    /// Run the given operation on all backends.
+    ///
+    /// # Errors
+    ///
+    /// The first error if the operation failed on the primary backend or, if a quorum is set,
+    /// on too many backends.
+    fn all_ok(&self, op: &str, f: impl Fn(&BE) -> RusticResult<()>) -> RusticResult<()> {
+        if self.backends.len() == 1 {
+            return f(&self.backends[0]);
+        }
+        let mut first_err = None;
+        let mut succeeded = 0;
+        let mut primary_ok = false;
+        for (i, be) in self.backends.iter().enumerate() {
+            match f(be) {
+                Ok(()) => {
+                    succeeded += 1;
+                    primary_ok |= i == 0;
+                }
+                Err(err) => {
+                    warn!(
+                        "{op} failed in {}: {err}. Use `repair mirrors` to synchronize the mirrors.",
+                        be.location()
+                    );
+                    first_err = first_err.or(Some(err));
+                }
+            }
+        }
+        let success = match self.quorum {
+            Some(quorum) => succeeded >= quorum.min(self.backends.len()),
+            None => primary_ok,
+        };
+        match first_err {
+            Some(err) if !success => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    /// Synchronizes the primary backend and all mirrors.
+    ///
+    /// Files missing in a backend or having a different size are copied from the first backend
+    /// containing them, so files are copied in both directions. Files are never removed: Files
+    /// which have only been removed from some backends, e.g. by `forget` or `prune` while a mirror
+    /// was not accessible, are copied back and are removed again by the next `forget` or `prune`.
+    ///
+    /// # Arguments
+    ///
+    /// * `dry_run` - If true, only determine what would be done
+    /// * `p` - The progress bar counting the processed file types
+    ///
+    /// # Errors
+    ///
+    /// If listing, reading or writing files failed.
+    ///
+    /// # Returns
+    ///
+    /// The statistics for each backend, starting with the primary backend.
+    pub fn resync(&self, dry_run: bool, p: &impl Progress) -> RusticResult<Vec<MirrorResyncStats>> {
+        let mut stats: Vec<_> = self
+            .backends
+            .iter()
+            .map(|be| MirrorResyncStats {
+                location: be.location(),
+                ..Default::default()
+            })
+            .collect();
+
+        p.set_length(MIRRORED_FILE_TYPES.len() as u64);
+        for tpe in MIRRORED_FILE_TYPES {
+            let lists: Vec<HashMap<_, _>> = self
+                .backends
+                .iter()
+                .map(|be| Ok(be.list_with_size(tpe)?.into_iter().collect()))
+                .collect::<RusticResult<_>>()?;
+            // files are copied from the first backend containing them
+            let mut sources = HashMap::new();
+            for (i, list) in lists.iter().enumerate() {
+                for (id, size) in list {
+                    _ = sources.entry(*id).or_insert((i, *size));
+                }
+            }
+
+            for (id, (source, size)) in sources {
+                let mut data = None;
+                for ((be, list), stats) in self.backends.iter().zip(&lists).zip(stats.iter_mut()) {
+                    if list.get(&id) == Some(&size) {
+                        continue;
+                    }
+                    debug!("copying {tpe:?} {id} to {}", stats.location);
+                    stats.copied += 1;
+                    stats.copied_bytes += u64::from(size);
+                    if dry_run {
+                        continue;
+                    }
+                    if data.is_none() {
+                        data = Some(self.backends[source].read_full(tpe, &id)?);
+                    }
+                    if let Some(data) = &data {
+                        // cloning `Bytes` is cheap
+                        be.write_bytes(tpe, &id, tpe.is_cacheable(), data.clone())?;
+                    }
+                }
+            }
+            p.inc(1);
+        }
+        p.finish();
+
+        let copy = if dry_run { "would copy" } else { "copied" };
+        for stats in &stats {
+            info!(
+                "{}: {copy} {} files ({} bytes)",
+                stats.location, stats.copied, stats.copied_bytes
+            );
+        }
+        Ok(stats)
+    }
+}
+
+impl<BE: WriteBackend> ReadBackend for MirrorBackend<BE> {
+    fn location(&self) -> String {
+        self.backends[0].location()
+    }
+
+    fn set_option(&mut self, option: &str, value: &str) -> RusticResult<()> {
+        for be in &mut self.backends {
+            be.set_option(option, value)?;
+        }
+        Ok(())
+    }
+
+    fn list_with_size(&self, tpe: FileType) -> RusticResult<Vec<(Id, u32)>> {
+        if self.backends.len() == 1 {
+            return self.backends[0].list_with_size(tpe);
+        }
+        // files which are missing in some backends, e.g. because writing them failed, are still listed
+        let mut files = HashMap::new();
+        let mut first_err = None;
+        let mut success = false;
+        for be in &self.backends {
+            match be.list_with_size(tpe) {
+                Ok(list) => {
+                    success = true;
+                    for (id, size) in list {
+                        _ = files.entry(id).or_insert(size);
+                    }
+                }
+                Err(err) => {
+                    warn!("{tpe:?} not accessible in {}: {err}", be.location());
+                    first_err = first_err.or(Some(err));
+                }
+            }
+        }
+        match first_err {
+            Some(err) if !success => Err(err),
+            _ => Ok(files.into_iter().collect()),
+        }
+    }
+
+    fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
+        self.first_ok(tpe, |be| be.read_full(tpe, id))
+    }
+
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> RusticResult<Bytes> {
+        self.first_ok(tpe, |be| {
+            be.read_partial(tpe, id, cacheable, offset, length)
+        })
+    }
+
+    fn thaw_duration(&self) -> Option<Duration> {
+        self.backends[0].thaw_duration()
+    }
+
+    fn thaw(&self, tpe: FileType, id: &Id) -> RusticResult<ThawState> {
+        self.first_ok(tpe, |be| be.thaw(tpe, id))
+    }
+}
+
+impl<BE: WriteBackend> WriteBackend for MirrorBackend<BE> {
+    fn create(&self) -> RusticResult<()> {
+        for be in &self.backends {
+            be.create()?;
+        }
+        Ok(())
+    }
+
+    fn write_bytes(&self, tpe: FileType, id: &Id, cacheable: bool, buf: Bytes) -> RusticResult<()> {
+        // cloning `Bytes` is cheap
+        self.all_ok("writing", |be| {
+            be.write_bytes(tpe, id, cacheable, buf.clone())
+        })
+    }
+
+    fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> RusticResult<()> {
+        self.all_ok("removing", |be| be.remove(tpe, id, cacheable))
+    }
+}
@@ -210,6 +210,9 @@ pub struct Metadata {
     /// Extended attributes of the node
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub extended_attributes: Vec<ExtendedAttribute>,
+    /// File flags of the node, e.g. set by `chattr` or `chflags`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flags: Vec<FileFlag>,
 }
 
 // Deserialize a Base64-encoded value into Vec<u8>.
@@ -250,6 +253,18 @@ pub struct ExtendedAttribute {
     pub(crate) value: Vec<u8>,
 }
 
+/// File flag of a [`Node`], as set by `chattr` on Linux or `chflags` on macOS
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileFlag {
+    /// The file cannot be modified, deleted or renamed
+    Immutable,
+    /// The file can only be opened for appending
+    AppendOnly,
+    /// The file should not be backed up
+    NoDump,
+}
+
 pub(crate) fn is_default<T: Default + PartialEq>(t: &T) -> bool {
     t == &T::default()
 }
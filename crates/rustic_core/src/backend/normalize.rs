@@ -0,0 +1,338 @@
+use std::{
+    ffi::{OsStr, OsString},
+    fmt::{self, Display},
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+
+use derive_setters::Setters;
+use serde_with::{serde_as, DisplayFromStr};
+use unicode_normalization::UnicodeNormalization as _;
+
+use crate::{error::PathTranslationErrorKind, RusticError, RusticResult};
+
+/// The platform name saved in snapshots created on Windows
+pub const WINDOWS_PLATFORM: &str = "windows";
+
+/// Characters which are not allowed in file names on Windows
+const WINDOWS_INVALID_CHARS: &[char] = &['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+/// Unicode normalization form to apply to file names.
+pub enum UnicodeNormalization {
+    /// Canonical composition (as used by Linux and Windows)
+    Nfc,
+    /// Canonical decomposition (as used by macOS HFS+)
+    Nfd,
+}
+
+impl UnicodeNormalization {
+    /// Normalize a file name.
+    ///
+    /// Names which are not valid UTF-8 are returned unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name to normalize
+    #[must_use]
+    pub fn normalize_name(self, name: &OsStr) -> OsString {
+        name.to_str().map_or_else(
+            || name.to_os_string(),
+            |s| match self {
+                Self::Nfc => s.nfc().collect::<String>().into(),
+                Self::Nfd => s.nfd().collect::<String>().into(),
+            },
+        )
+    }
+
+    /// Normalize all normal components of a path.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to normalize
+    #[must_use]
+    pub fn normalize_path(self, path: &Path) -> PathBuf {
+        map_components(path, |name| self.normalize_name(name))
+    }
+}
+
+#[cfg_attr(feature = "clap", derive(clap::Parser))]
+#[cfg_attr(feature = "merge", derive(merge::Merge))]
+#[derive(serde::Deserialize, serde::Serialize, Default, Clone, Copy, Debug, Setters)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+#[setters(into)]
+/// [`PathNormalizationOptions`] describe how paths are normalized before they are compared.
+pub struct PathNormalizationOptions {
+    /// Normalize unicode file names to the given form before comparing
+    #[cfg_attr(feature = "clap", clap(long, value_name = "FORM", value_enum))]
+    pub normalize_unicode: Option<UnicodeNormalization>,
+
+    /// Compare file names case-insensitively
+    #[cfg_attr(feature = "clap", clap(long))]
+    #[cfg_attr(feature = "merge", merge(strategy = merge::bool::overwrite_false))]
+    pub ignore_case: bool,
+}
+
+impl PathNormalizationOptions {
+    /// Returns `true` if any normalization is configured.
+    #[must_use]
+    pub const fn is_active(&self) -> bool {
+        self.normalize_unicode.is_some() || self.ignore_case
+    }
+
+    /// Normalize a file name according to these options.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name to normalize
+    #[must_use]
+    pub fn normalize_name(&self, name: &OsStr) -> OsString {
+        let name = self
+            .normalize_unicode
+            .map_or_else(|| name.to_os_string(), |form| form.normalize_name(name));
+        if !self.ignore_case {
+            return name;
+        }
+        match name.to_str() {
+            // normalize to NFC afterwards as case folding may decompose characters
+            Some(s) => s.to_lowercase().nfc().collect::<String>().into(),
+            None => name,
+        }
+    }
+
+    /// Normalize all normal components of a path according to these options.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to normalize
+    #[must_use]
+    pub fn normalize_path(&self, path: &Path) -> PathBuf {
+        if !self.is_active() {
+            return path.to_path_buf();
+        }
+        map_components(path, |name| self.normalize_name(name))
+    }
+}
+
+/// Convert a path given in the notation of the platform a snapshot was created on into a platform-neutral path.
+///
+/// Within trees, paths are always saved component-wise and a Windows drive `C:` is saved as directory `C`.
+/// This converts a Windows path like `C:\\Users\\me` into `C/Users/me` such that it can be used on any platform.
+/// Paths of other platforms and all paths on Windows itself are returned unchanged.
+///
+/// # Arguments
+///
+/// * `path` - The path to convert
+/// * `platform` - The platform the snapshot was created on, see [`crate::repofile::SnapshotFile::platform`]
+#[must_use]
+pub fn neutral_path(path: &str, platform: &str) -> PathBuf {
+    if platform != WINDOWS_PLATFORM || cfg!(windows) {
+        return PathBuf::from(path);
+    }
+    let mut chars = path.chars();
+    let path = match (chars.next(), chars.next()) {
+        (Some(drive), Some(':')) if drive.is_ascii_alphabetic() => {
+            format!("{drive}/{}", chars.as_str())
+        }
+        _ => path.to_string(),
+    };
+    path.split(['\\', '/']).filter(|s| !s.is_empty()).collect()
+}
+
+/// A mapping of a Windows drive letter to a path used when restoring.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DriveMapping {
+    /// The drive letter
+    pub drive: char,
+    /// The path (relative to the restore destination) the drive is restored to
+    pub path: PathBuf,
+}
+
+impl FromStr for DriveMapping {
+    type Err = RusticError;
+    fn from_str(s: &str) -> RusticResult<Self> {
+        let (drive, path) = s
+            .split_once('=')
+            .ok_or_else(|| PathTranslationErrorKind::InvalidDriveMapping(s.to_string()))?;
+        let mut chars = drive.trim_end_matches(':').chars();
+        match (chars.next(), chars.next()) {
+            (Some(drive), None) if drive.is_ascii_alphabetic() && !path.is_empty() => Ok(Self {
+                drive: drive.to_ascii_uppercase(),
+                path: PathBuf::from(path),
+            }),
+            _ => Err(PathTranslationErrorKind::InvalidDriveMapping(s.to_string()).into()),
+        }
+    }
+}
+
+impl Display for DriveMapping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.drive, self.path.display())
+    }
+}
+
+#[serde_as]
+#[cfg_attr(feature = "clap", derive(clap::Parser))]
+#[cfg_attr(feature = "merge", derive(merge::Merge))]
+#[derive(serde::Deserialize, serde::Serialize, Default, Clone, Debug, Setters)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+#[setters(into)]
+/// [`PathTranslationOptions`] describe how paths of snapshots created on another platform are translated when restoring.
+pub struct PathTranslationOptions {
+    /// Restore the Windows drive LETTER to PATH (relative to the destination), e.g. `C=c-drive` (can be specified multiple times)
+    #[cfg_attr(feature = "clap", clap(long, value_name = "LETTER=PATH"))]
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    #[cfg_attr(feature = "merge", merge(strategy = merge::vec::overwrite_empty))]
+    pub map_drive: Vec<DriveMapping>,
+
+    /// Replace characters which are not allowed in file names on this platform by `_`
+    #[cfg_attr(feature = "clap", clap(long))]
+    #[cfg_attr(feature = "merge", merge(strategy = merge::bool::overwrite_false))]
+    pub sanitize_names: bool,
+}
+
+impl PathTranslationOptions {
+    /// Returns `true` if any translation is configured.
+    #[must_use]
+    pub fn is_active(&self) -> bool {
+        !self.map_drive.is_empty() || self.sanitize_names
+    }
+
+    /// Translate a path (relative to the restored node) according to these options.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to translate
+    ///
+    /// # Notes
+    ///
+    /// Drive mappings only apply to the first component, i.e. when restoring a complete snapshot.
+    #[must_use]
+    pub fn translate_path(&self, path: &Path) -> PathBuf {
+        let mut components = path.components();
+        let mut translated = PathBuf::new();
+        if let Some(Component::Normal(first)) = path.components().next() {
+            let mapping = self.map_drive.iter().find(|mapping| {
+                first.to_str().map_or(false, |first| {
+                    first.eq_ignore_ascii_case(&mapping.drive.to_string())
+                })
+            });
+            if let Some(mapping) = mapping {
+                translated.push(&mapping.path);
+                _ = components.next();
+            }
+        }
+        for comp in components {
+            match comp {
+                Component::Normal(name) if self.sanitize_names => {
+                    translated.push(sanitize_name(name));
+                }
+                comp => translated.push(comp),
+            }
+        }
+        translated
+    }
+}
+
+/// Replace characters which are not allowed in file names on this platform by `_`.
+///
+/// Names which are not valid UTF-8 are returned unchanged.
+///
+/// # Arguments
+///
+/// * `name` - The name to sanitize
+fn sanitize_name(name: &OsStr) -> OsString {
+    name.to_str().map_or_else(
+        || name.to_os_string(),
+        |name| {
+            if cfg!(windows) {
+                let name: String = name
+                    .chars()
+                    .map(|c| {
+                        if c.is_control() || WINDOWS_INVALID_CHARS.contains(&c) {
+                            '_'
+                        } else {
+                            c
+                        }
+                    })
+                    .collect();
+                // Windows silently strips trailing dots and spaces
+                let trimmed = name.trim_end_matches(['.', ' ']);
+                let suffix = "_".repeat(name.len() - trimmed.len());
+                (trimmed.to_string() + &suffix).into()
+            } else {
+                name.replace('\0', "_").into()
+            }
+        },
+    )
+}
+
+/// Apply `f` to all normal components of `path`, keeping all other components.
+fn map_components(path: &Path, f: impl Fn(&OsStr) -> OsString) -> PathBuf {
+    path.components()
+        .map(|comp| match comp {
+            Component::Normal(name) => f(name),
+            comp => comp.as_os_str().to_os_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_unicode_forms() {
+        let composed = Path::new("dir/caf\u{e9}");
+        let decomposed = Path::new("dir/cafe\u{301}");
+        assert_eq!(
+            UnicodeNormalization::Nfc.normalize_path(decomposed),
+            composed
+        );
+        assert_eq!(
+            UnicodeNormalization::Nfd.normalize_path(composed),
+            decomposed
+        );
+    }
+
+    #[test]
+    fn normalize_ignore_case() {
+        let opts = PathNormalizationOptions::default()
+            .normalize_unicode(UnicodeNormalization::Nfc)
+            .ignore_case(true);
+        assert_eq!(
+            opts.normalize_path(Path::new("/Dir/CAFE\u{301}")),
+            opts.normalize_path(Path::new("/dir/caf\u{e9}"))
+        );
+        assert_eq!(
+            PathNormalizationOptions::default().normalize_path(Path::new("/Dir")),
+            Path::new("/Dir")
+        );
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn neutral_windows_paths() {
+        assert_eq!(
+            neutral_path("C:\\Users\\me", WINDOWS_PLATFORM),
+            Path::new("C/Users/me")
+        );
+        assert_eq!(neutral_path("dir\\file", "linux"), Path::new("dir\\file"));
+    }
+
+    #[test]
+    fn translate_drive_mapping() {
+        let opts = PathTranslationOptions::default().map_drive(vec!["c:=win/c".parse().unwrap()]);
+        assert_eq!(
+            opts.translate_path(Path::new("C/Users/me")),
+            Path::new("win/c/Users/me")
+        );
+        assert_eq!(
+            opts.translate_path(Path::new("D/data")),
+            Path::new("D/data")
+        );
+        assert!("CD=win".parse::<DriveMapping>().is_err());
+    }
+}
@@ -2,7 +2,7 @@ use std::{
     io::{BufRead, BufReader},
     process::{Child, Command, Stdio},
     str,
-    sync::Arc,
+    sync::{Arc, Mutex},
 };
 
 use bytes::Bytes;
@@ -31,7 +31,26 @@ impl Drop for ChildToKill {
     /// Kill the child process.
     fn drop(&mut self) {
         debug!("killing rclone.");
-        self.0.kill().unwrap();
+        // rclone may have already exited; wait to not leave a zombie process
+        _ = self.0.kill();
+        _ = self.0.wait();
+    }
+}
+
+/// Log a line of the rclone output; errors of rclone are logged as warnings.
+///
+/// # Arguments
+///
+/// * `line` - The line output by rclone
+fn log_output(line: &str) {
+    let line = line.trim_end();
+    if line.is_empty() {
+        return;
+    }
+    if line.contains("ERROR") || line.contains("CRITICAL") {
+        warn!("rclone: {line}");
+    } else {
+        info!("rclone output: {line}");
     }
 }
 
@@ -43,7 +62,7 @@ pub struct RcloneBackend {
     /// The url of the backend.
     url: String,
     /// The child data contains the child process and is used to kill the child process when the backend is dropped.
-    _child_data: Arc<ChildToKill>,
+    child: Arc<Mutex<ChildToKill>>,
 }
 
 /// Get the rclone version.
@@ -157,8 +176,7 @@ impl RcloneBackend {
                         break url.trim_end().trim_matches(brackets).to_string();
                     }
                 }
-                None if !line.is_empty() => info!("rclone output: {line}"),
-                _ => {}
+                None => log_output(&line),
             }
         };
 
@@ -167,9 +185,7 @@ impl RcloneBackend {
             if stderr.read_line(&mut line).unwrap() == 0 {
                 break;
             }
-            if !line.is_empty() {
-                info!("rclone output: {line}");
-            }
+            log_output(&line);
         });
 
         if !rest_url.starts_with("http://") {
@@ -180,13 +196,36 @@ impl RcloneBackend {
             "http://".to_string() + user.as_str() + ":" + password.as_str() + "@" + &rest_url[7..];
 
         debug!("using REST backend with url {url}.");
-        let rest = RestBackend::new(&rest_url)?;
+        let mut rest = RestBackend::new(&rest_url)?;
+        // rclone is accessed locally, so never use a proxy
+        rest.set_option("proxy", "none")?;
         Ok(Self {
-            _child_data: Arc::new(ChildToKill(child)),
+            child: Arc::new(Mutex::new(ChildToKill(child))),
             url: url.to_string(),
             rest,
         })
     }
+
+    /// Checks whether rclone is still running if an operation failed.
+    ///
+    /// If rclone has exited, the failed operation can't succeed and the exit status of rclone is returned
+    /// instead of the error of the REST backend.
+    ///
+    /// # Arguments
+    ///
+    /// * `result` - The result of the operation
+    ///
+    /// # Errors
+    ///
+    /// * [`ProviderErrorKind::RCloneExitWithBadStatus`] - If rclone has exited.
+    fn check_exited<T>(&self, result: RusticResult<T>) -> RusticResult<T> {
+        if result.is_err() {
+            if let Ok(Some(status)) = self.child.lock().unwrap().0.try_wait() {
+                return Err(ProviderErrorKind::RCloneExitWithBadStatus(status).into());
+            }
+        }
+        result
+    }
 }
 
 impl ReadBackend for RcloneBackend {
@@ -208,6 +247,11 @@ impl ReadBackend for RcloneBackend {
     ///
     /// If the option is not supported.
     fn set_option(&mut self, option: &str, value: &str) -> RusticResult<()> {
+        if option == "proxy" {
+            // rclone uses the proxy given by the environment for its own connections
+            warn!("Option proxy is not supported for rclone! Ignoring it.");
+            return Ok(());
+        }
         self.rest.set_option(option, value)
     }
 
@@ -221,7 +265,7 @@ impl ReadBackend for RcloneBackend {
     ///
     /// If the size could not be determined.
     fn list_with_size(&self, tpe: FileType) -> RusticResult<Vec<(Id, u32)>> {
-        self.rest.list_with_size(tpe)
+        self.check_exited(self.rest.list_with_size(tpe))
     }
 
     /// Reads full data of the given file.
@@ -239,7 +283,7 @@ impl ReadBackend for RcloneBackend {
     ///
     /// The data read.
     fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
-        self.rest.read_full(tpe, id)
+        self.check_exited(self.rest.read_full(tpe, id))
     }
 
     /// Reads partial data of the given file.
@@ -267,7 +311,7 @@ impl ReadBackend for RcloneBackend {
         offset: u32,
         length: u32,
     ) -> RusticResult<Bytes> {
-        self.rest.read_partial(tpe, id, cacheable, offset, length)
+        self.check_exited(self.rest.read_partial(tpe, id, cacheable, offset, length))
     }
 }
 
@@ -278,7 +322,7 @@ impl WriteBackend for RcloneBackend {
     ///
     /// * [`RestErrorKind::BackoffError`] - If the backoff failed.
     fn create(&self) -> RusticResult<()> {
-        self.rest.create()
+        self.check_exited(self.rest.create())
     }
 
     /// Writes bytes to the given file.
@@ -294,7 +338,7 @@ impl WriteBackend for RcloneBackend {
     ///
     /// * [`RestErrorKind::BackoffError`] - If the backoff failed.
     fn write_bytes(&self, tpe: FileType, id: &Id, cacheable: bool, buf: Bytes) -> RusticResult<()> {
-        self.rest.write_bytes(tpe, id, cacheable, buf)
+        self.check_exited(self.rest.write_bytes(tpe, id, cacheable, buf))
     }
 
     /// Removes the given file.
@@ -309,6 +353,6 @@ impl WriteBackend for RcloneBackend {
     ///
     /// * [`RestErrorKind::BackoffError`] - If the backoff failed.
     fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> RusticResult<()> {
-        self.rest.remove(tpe, id, cacheable)
+        self.check_exited(self.rest.remove(tpe, id, cacheable))
     }
 }
@@ -1,102 +1,39 @@
+use std::fs;
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::time::Duration;
 
-use backoff::{backoff::Backoff, Error, ExponentialBackoff, ExponentialBackoffBuilder};
 use bytes::Bytes;
-use log::{trace, warn};
+use log::{debug, trace};
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
 use reqwest::{
-    blocking::{Client, ClientBuilder, Response},
-    header::{HeaderMap, HeaderValue},
-    Url,
+    blocking::{Client, ClientBuilder},
+    header::{HeaderMap, HeaderValue, CONTENT_LENGTH, CONTENT_RANGE},
+    Certificate, Identity, StatusCode, Url,
 };
 use serde::Deserialize;
 
 use crate::{
-    backend::{FileType, ReadBackend, WriteBackend},
+    backend::{
+        http::{notify, CheckError, LimitRetryBackoff},
+        proxy::ProxyOption,
+        FileType, ReadBackend, WriteBackend,
+    },
     error::{RestErrorKind, RusticResult},
     id::Id,
 };
 
 mod consts {
-    /// Default number of retries
-    pub(super) const DEFAULT_RETRY: usize = 5;
-}
-
-// trait CheckError to add user-defined method check_error on Response
-pub(crate) trait CheckError {
-    /// Check reqwest Response for error and treat errors as permanent or transient
-    fn check_error(self) -> Result<Response, Error<reqwest::Error>>;
-}
-
-impl CheckError for Response {
-    /// Check reqwest Response for error and treat errors as permanent or transient
-    ///
-    /// # Errors
-    ///
-    /// If the response is an error, it will return an error of type Error<reqwest::Error>
-    ///
-    /// # Returns
-    ///
-    /// The response if it is not an error
-    fn check_error(self) -> Result<Response, Error<reqwest::Error>> {
-        match self.error_for_status() {
-            Ok(t) => Ok(t),
-            // Note: status() always give Some(_) as it is called from a Response
-            Err(err) if err.status().unwrap().is_client_error() => Err(Error::Permanent(err)),
-            Err(err) => Err(Error::Transient {
-                err,
-                retry_after: None,
-            }),
-        }
-    }
-}
-
-/// A backoff implementation that limits the number of retries
-#[derive(Clone, Debug)]
-struct LimitRetryBackoff {
-    /// The maximum number of retries
-    max_retries: usize,
-    /// The current number of retries
-    retries: usize,
-    /// The exponential backoff
-    exp: ExponentialBackoff,
-}
-
-impl Default for LimitRetryBackoff {
-    fn default() -> Self {
-        Self {
-            max_retries: consts::DEFAULT_RETRY,
-            retries: 0,
-            exp: ExponentialBackoffBuilder::new()
-                .with_max_elapsed_time(None) // no maximum elapsed time; we count number of retires
-                .build(),
-        }
-    }
-}
-
-impl Backoff for LimitRetryBackoff {
-    /// Returns the next backoff duration.
-    ///
-    /// # Notes
-    ///
-    /// If the number of retries exceeds the maximum number of retries, it returns None.
-    fn next_backoff(&mut self) -> Option<Duration> {
-        self.retries += 1;
-        if self.retries > self.max_retries {
-            None
-        } else {
-            self.exp.next_backoff()
-        }
-    }
+    use std::time::Duration;
 
-    /// Resets the backoff to the initial state.
-    fn reset(&mut self) {
-        self.retries = 0;
-        self.exp.reset();
-    }
+    /// Default timeout, 10 minutes as we can have *large* packfiles
+    pub(super) const DEFAULT_TIMEOUT: Duration = Duration::from_secs(600);
 }
 
 /// A backend implementation that uses REST to access the backend.
+///
+/// It speaks the REST protocol of restic's rest-server. Basic auth credentials are taken from the url.
+/// All requests share one client, so connections are reused and HTTP/2 is used if the server offers it via TLS.
 #[derive(Clone, Debug)]
 pub struct RestBackend {
     /// The url of the backend.
@@ -105,16 +42,14 @@ pub struct RestBackend {
     client: Client,
     /// The backoff implementation to use.
     backoff: LimitRetryBackoff,
-}
-
-/// Notify function for backoff in case of error
-///
-/// # Arguments
-///
-/// * `err` - The error that occurred
-/// * `duration` - The duration of the backoff
-fn notify(err: reqwest::Error, duration: Duration) {
-    warn!("Error {err} at {duration:?}, retrying");
+    /// The timeout for requests.
+    timeout: Duration,
+    /// A PEM file with an additional root certificate to trust, e.g. a self-signed certificate of the server.
+    cacert: Option<PathBuf>,
+    /// A PEM file with the certificate and private key to authenticate at the server.
+    tls_client_cert: Option<PathBuf>,
+    /// The proxy to use for requests.
+    proxy: ProxyOption,
 }
 
 impl RestBackend {
@@ -138,20 +73,113 @@ impl RestBackend {
             Url::parse(&url).map_err(RestErrorKind::UrlParsingFailed)?
         };
 
+        Ok(Self {
+            url,
+            client: Self::build_client(
+                consts::DEFAULT_TIMEOUT,
+                None,
+                None,
+                &ProxyOption::default(),
+            )?,
+            backoff: LimitRetryBackoff::default(),
+            timeout: consts::DEFAULT_TIMEOUT,
+            cacert: None,
+            tls_client_cert: None,
+            proxy: ProxyOption::default(),
+        })
+    }
+
+    /// Builds the client.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - The timeout for requests.
+    /// * `cacert` - A PEM file with an additional root certificate to trust.
+    /// * `tls_client_cert` - A PEM file with the certificate and private key to authenticate at the server.
+    /// * `proxy` - The proxy to use.
+    ///
+    /// # Errors
+    ///
+    /// * [`RestErrorKind::ReadingFileFailed`] - If a certificate file could not be read.
+    /// * [`RestErrorKind::ParsingCertificateFailed`] - If a certificate file could not be parsed.
+    /// * [`RestErrorKind::BuildingClientFailed`] - If the client could not be built.
+    fn build_client(
+        timeout: Duration,
+        cacert: Option<&PathBuf>,
+        tls_client_cert: Option<&PathBuf>,
+        proxy: &ProxyOption,
+    ) -> RusticResult<Client> {
         let mut headers = HeaderMap::new();
         _ = headers.insert("User-Agent", HeaderValue::from_static("rustic"));
 
-        let client = ClientBuilder::new()
+        let mut builder = ClientBuilder::new()
             .default_headers(headers)
-            .timeout(Duration::from_secs(600)) // set default timeout to 10 minutes (we can have *large* packfiles)
-            .build()
-            .map_err(RestErrorKind::BuildingClientFailed)?;
+            .timeout(timeout);
 
-        Ok(Self {
-            url,
-            client,
-            backoff: LimitRetryBackoff::default(),
-        })
+        if let Some(path) = cacert {
+            let pem = fs::read(path)
+                .map_err(|err| RestErrorKind::ReadingFileFailed(path.clone(), err))?;
+            let cert = Certificate::from_pem(&pem)
+                .map_err(|err| RestErrorKind::ParsingCertificateFailed(path.clone(), err))?;
+            builder = builder.add_root_certificate(cert);
+        }
+        if let Some(path) = tls_client_cert {
+            let pem = fs::read(path)
+                .map_err(|err| RestErrorKind::ReadingFileFailed(path.clone(), err))?;
+            let identity = Identity::from_pem(&pem)
+                .map_err(|err| RestErrorKind::ParsingCertificateFailed(path.clone(), err))?;
+            builder = builder.identity(identity);
+        }
+
+        Ok(proxy
+            .apply(builder)
+            .and_then(ClientBuilder::build)
+            .map_err(RestErrorKind::BuildingClientFailed)?)
+    }
+
+    /// Rebuilds the client after the timeout, TLS or proxy settings have been changed.
+    ///
+    /// # Errors
+    ///
+    /// See [`RestBackend::build_client`].
+    fn rebuild_client(&mut self) -> RusticResult<()> {
+        self.client = Self::build_client(
+            self.timeout,
+            self.cacert.as_ref(),
+            self.tls_client_cert.as_ref(),
+            &self.proxy,
+        )?;
+        Ok(())
+    }
+
+    /// Returns the size of the file at the given url using a `HEAD` request.
+    ///
+    /// This is only needed for REST servers which don't support API v2 which lists files together with their size.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The url of the file.
+    ///
+    /// # Errors
+    ///
+    /// * [`RestErrorKind::BackoffError`] - If the backoff failed.
+    /// * [`RestErrorKind::MissingContentLength`] - If the response contains no valid content length.
+    fn size(&self, url: &Url) -> RusticResult<u32> {
+        trace!("getting size of {url}");
+        let size = backoff::retry_notify(
+            self.backoff.clone(),
+            || {
+                let response = self.client.head(url.clone()).send()?.check_error()?;
+                Ok(response
+                    .headers()
+                    .get(CONTENT_LENGTH)
+                    .and_then(|len| len.to_str().ok())
+                    .and_then(|len| len.parse().ok()))
+            },
+            notify,
+        )
+        .map_err(RestErrorKind::BackoffError)?;
+        Ok(size.ok_or_else(|| RestErrorKind::MissingContentLength(url.clone()))?)
     }
 
     /// Returns the url for a given type and id.
@@ -209,11 +237,14 @@ impl ReadBackend for RestBackend {
     /// Currently supported options:
     /// * `retry` - The number of retries to use for transient errors. Default is 5. Set to 0 to disable retries.
     /// * `timeout` - The timeout to use for requests. Default is 10 minutes. Format is described in [humantime](https://docs.rs/humantime/2.1.0/humantime/fn.parse_duration.html).
+    /// * `cacert` - A PEM file with an additional root certificate to trust, e.g. for a self-signed server certificate.
+    /// * `tls-client-cert` - A PEM file containing the certificate and private key to authenticate at the server.
+    /// * `proxy` - The proxy URL to use, `system` to use `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY` or `none`. Default is `system`.
     fn set_option(&mut self, option: &str, value: &str) -> RusticResult<()> {
         if option == "retry" {
             let max_retries = match value {
                 "false" | "off" => 0,
-                "default" => consts::DEFAULT_RETRY,
+                "default" => LimitRetryBackoff::default().max_retries,
                 _ => usize::from_str(value)
                     .map_err(|_| RestErrorKind::NotSupportedForRetry(value.into()))?,
             };
@@ -223,10 +254,17 @@ impl ReadBackend for RestBackend {
                 Ok(val) => val,
                 Err(e) => return Err(RestErrorKind::CouldNotParseDuration(e).into()),
             };
-            self.client = match ClientBuilder::new().timeout(*timeout).build() {
-                Ok(val) => val,
-                Err(err) => return Err(RestErrorKind::BuildingClientFailed(err).into()),
-            };
+            self.timeout = *timeout;
+            self.rebuild_client()?;
+        } else if option == "cacert" {
+            self.cacert = Some(PathBuf::from(value));
+            self.rebuild_client()?;
+        } else if option == "tls-client-cert" {
+            self.tls_client_cert = Some(PathBuf::from(value));
+            self.rebuild_client()?;
+        } else if option == "proxy" {
+            self.proxy = ProxyOption::from_value(value);
+            self.rebuild_client()?;
         }
         Ok(())
     }
@@ -241,12 +279,16 @@ impl ReadBackend for RestBackend {
     ///
     /// * [`RestErrorKind::JoiningUrlFailed`] - If the url could not be created.
     /// * [`RestErrorKind::BackoffError`] - If the backoff failed.
+    /// * [`RestErrorKind::MissingContentLength`] - If the size of a file could not be retrieved.
     /// * [`IdErrorKind::HexError`] - If the string is not a valid hexadecimal string
     ///
     /// # Notes
     ///
     /// The returned list is sorted by id.
     ///
+    /// REST API v2 is requested which returns the file sizes within the listing. If the server only supports
+    /// API v1, the sizes are retrieved by separate `HEAD` requests which are sent in parallel.
+    ///
     /// # Returns
     ///
     /// A vector of tuples containing the id and size of the files.
@@ -267,42 +309,59 @@ impl ReadBackend for RestBackend {
         match backoff::retry_notify(
             self.backoff.clone(),
             || {
-                // format which is delivered by the REST-service
+                // format which is delivered by the REST-service:
+                // API v2 returns names together with sizes, API v1 only returns names
                 #[derive(Deserialize)]
-                struct ListEntry {
-                    name: String,
-                    size: u32,
+                #[serde(untagged)]
+                enum ListEntry {
+                    V2 { name: String, size: u32 },
+                    V1(String),
                 }
 
                 if tpe == FileType::Config {
                     return Ok(
                         if self.client.head(url.clone()).send()?.status().is_success() {
-                            vec![(Id::default(), 0)]
+                            vec![(Id::default(), Some(0))]
                         } else {
                             Vec::new()
                         },
                     );
                 }
 
-                let list = self
+                let response = self
                     .client
                     .get(url.clone())
                     .header("Accept", "application/vnd.x.restic.rest.v2")
-                    .send()?
+                    .send()?;
+                // servers which don't support a file type, e.g. `trash` or `journal`, don't have any such files
+                if response.status() == StatusCode::NOT_FOUND {
+                    return Ok(Vec::new());
+                }
+                let list = response
                     .check_error()?
                     .json::<Option<Vec<ListEntry>>>()? // use Option to be handle null json value
                     .unwrap_or_default();
                 Ok(list
                     .into_iter()
-                    .filter_map(|i| match Id::from_hex(&i.name) {
-                        Ok(id) => Some((id, i.size)),
-                        Err(_) => None,
+                    .filter_map(|i| {
+                        let (name, size) = match i {
+                            ListEntry::V2 { name, size } => (name, Some(size)),
+                            ListEntry::V1(name) => (name, None),
+                        };
+                        Id::from_hex(&name).ok().map(|id| (id, size))
                     })
                     .collect())
             },
             notify,
         ) {
-            Ok(val) => Ok(val),
+            Ok(list) => list
+                .into_par_iter()
+                .map(|(id, size)| match size {
+                    Some(size) => Ok((id, size)),
+                    // server only supports API v1 => get size of file separately
+                    None => Ok((id, self.size(&self.url(tpe, &id)?)?)),
+                })
+                .collect(),
             Err(e) => Err(RestErrorKind::BackoffError(e).into()),
         }
     }
@@ -349,6 +408,12 @@ impl ReadBackend for RestBackend {
     /// # Errors
     ///
     /// * [`RestErrorKind::BackoffError`] - If the backoff failed.
+    /// * [`RestErrorKind::RangeMismatch`] - If the server didn't return exactly the requested range.
+    ///
+    /// # Notes
+    ///
+    /// Only the requested range is fetched using a HTTP range request. If the server doesn't
+    /// support range requests and answers with the full file, the requested range is cut out of it.
     fn read_partial(
         &self,
         tpe: FileType,
@@ -361,20 +426,59 @@ impl ReadBackend for RestBackend {
         let offset2 = offset + length - 1;
         let header_value = format!("bytes={offset}-{offset2}");
         let url = self.url(tpe, id)?;
-        Ok(backoff::retry_notify(
+        let (is_partial, content_range, data) = backoff::retry_notify(
             self.backoff.clone(),
             || {
-                Ok(self
+                let response = self
                     .client
                     .get(url.clone())
                     .header("Range", header_value.clone())
                     .send()?
-                    .check_error()?
-                    .bytes()?)
+                    .check_error()?;
+                let is_partial = response.status() == StatusCode::PARTIAL_CONTENT;
+                let content_range = response
+                    .headers()
+                    .get(CONTENT_RANGE)
+                    .and_then(|value| value.to_str().ok())
+                    .map(ToString::to_string);
+                Ok((is_partial, content_range, response.bytes()?))
             },
             notify,
         )
-        .map_err(RestErrorKind::BackoffError)?)
+        .map_err(RestErrorKind::BackoffError)?;
+
+        let start = offset as usize;
+        let end = start + length as usize;
+        let data = if is_partial {
+            // the server must return exactly the requested range
+            if let Some(content_range) = content_range {
+                if !content_range.starts_with(&format!("bytes {offset}-{offset2}/")) {
+                    return Err(RestErrorKind::RangeMismatch(
+                        tpe,
+                        *id,
+                        header_value,
+                        content_range,
+                    )
+                    .into());
+                }
+            }
+            data
+        } else if data.len() >= end {
+            debug!("server ignored range request for {tpe:?} {id}, got full file.");
+            data.slice(start..end)
+        } else {
+            data
+        };
+        if data.len() != length as usize {
+            return Err(RestErrorKind::RangeMismatch(
+                tpe,
+                *id,
+                header_value,
+                format!("{} bytes", data.len()),
+            )
+            .into());
+        }
+        Ok(data)
     }
 }
 
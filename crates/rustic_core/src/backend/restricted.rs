@@ -0,0 +1,160 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use crate::{
+    backend::{FileType, ReadBackend, ThawState, WriteBackend},
+    error::{BackendErrorKind, RusticResult},
+    id::Id,
+    repofile::KeyRestriction,
+};
+
+/// A backend implementation which only allows the operations permitted by the restriction
+/// of the key used to open the repository.
+///
+/// # Notes
+///
+/// This is a client-side guard and no access control: The restriction is saved in the key file,
+/// which is not authenticated, and is only enforced by this client. Anybody with write access to
+/// the storage can remove the restriction or use another client. It protects against accidental
+/// or scripted misuse of a restricted key, but not against a compromised host.
+///
+/// No restricted key can write key files or the repository config, so it can't be used to add
+/// a less restricted key.
+///
+/// # Type Parameters
+///
+/// * `BE` - The backend to use.
+#[derive(Clone, Debug)]
+pub struct RestrictedBackend<BE: WriteBackend> {
+    /// The backend to use.
+    be: BE,
+    /// The restriction to enforce.
+    restriction: Option<KeyRestriction>,
+}
+
+impl<BE: WriteBackend> RestrictedBackend<BE> {
+    /// Creates a new `RestrictedBackend` without any restriction.
+    ///
+    /// # Type Parameters
+    ///
+    /// * `BE` - The backend to use.
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The backend to use.
+    pub const fn new(be: BE) -> Self {
+        Self {
+            be,
+            restriction: None,
+        }
+    }
+
+    /// Sets the restriction to enforce.
+    ///
+    /// # Arguments
+    ///
+    /// * `restriction` - The restriction to enforce.
+    pub fn set_restriction(&mut self, restriction: Option<KeyRestriction>) {
+        self.restriction = restriction;
+    }
+
+    /// Returns the enforced restriction.
+    pub const fn restriction(&self) -> Option<KeyRestriction> {
+        self.restriction
+    }
+
+    /// Check if the operation is allowed for the given file type.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - The operation, i.e. `writing` or `removing`.
+    /// * `tpe` - The type of the file.
+    /// * `is_allowed` - Whether the operation is allowed for the enforced restriction.
+    ///
+    /// # Errors
+    ///
+    /// * [`BackendErrorKind::NotAllowedByKeyRestriction`] - If the operation is not allowed.
+    fn check(
+        &self,
+        operation: &'static str,
+        tpe: FileType,
+        is_allowed: impl FnOnce(KeyRestriction) -> bool,
+    ) -> RusticResult<()> {
+        match self.restriction {
+            Some(restriction) if !is_allowed(restriction) => Err(
+                BackendErrorKind::NotAllowedByKeyRestriction(operation, tpe, restriction).into(),
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<BE: WriteBackend> ReadBackend for RestrictedBackend<BE> {
+    fn location(&self) -> String {
+        self.be.location()
+    }
+
+    fn set_option(&mut self, option: &str, value: &str) -> RusticResult<()> {
+        self.be.set_option(option, value)
+    }
+
+    fn list_with_size(&self, tpe: FileType) -> RusticResult<Vec<(Id, u32)>> {
+        self.be.list_with_size(tpe)
+    }
+
+    fn list(&self, tpe: FileType) -> RusticResult<Vec<Id>> {
+        self.be.list(tpe)
+    }
+
+    fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
+        self.be.read_full(tpe, id)
+    }
+
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> RusticResult<Bytes> {
+        self.be.read_partial(tpe, id, cacheable, offset, length)
+    }
+
+    fn thaw_duration(&self) -> Option<Duration> {
+        self.be.thaw_duration()
+    }
+
+    fn thaw(&self, tpe: FileType, id: &Id) -> RusticResult<ThawState> {
+        self.be.thaw(tpe, id)
+    }
+}
+
+impl<BE: WriteBackend> WriteBackend for RestrictedBackend<BE> {
+    fn create(&self) -> RusticResult<()> {
+        self.be.create()
+    }
+
+    fn write_bytes(&self, tpe: FileType, id: &Id, cacheable: bool, buf: Bytes) -> RusticResult<()> {
+        self.check("writing", tpe, |restriction| match restriction {
+            KeyRestriction::ReadOnly => false,
+            // only allow writing the files needed by a backup
+            KeyRestriction::AppendOnly => {
+                matches!(tpe, FileType::Snapshot | FileType::Index | FileType::Pack)
+            }
+            // trash and journal files are only written to remove files. Writing key files or the
+            // config would allow to add an unrestricted key or to change the repository.
+            KeyRestriction::NoDelete => !matches!(
+                tpe,
+                FileType::Trash | FileType::Journal | FileType::Key | FileType::Config
+            ),
+        })?;
+        self.be.write_bytes(tpe, id, cacheable, buf)
+    }
+
+    fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> RusticResult<()> {
+        self.check("removing", tpe, |_| false)?;
+        self.be.remove(tpe, id, cacheable)
+    }
+}
@@ -0,0 +1,221 @@
+use std::time::Duration;
+
+use backoff::{Error, ExponentialBackoffBuilder};
+use bytes::Bytes;
+use derivative::Derivative;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+
+use crate::{
+    backend::{http::LimitRetryBackoff, FileType, ReadBackend, ThawState, WriteBackend},
+    error::{BackendErrorKind, RusticError, RusticErrorKind, RusticResult},
+    id::Id,
+};
+
+/// Classes of errors of backend operations, used to decide which errors are retried
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorClass {
+    /// I/O errors, e.g. of the local backend on a network mount
+    Io,
+    /// Errors of HTTP based backends (rest, rclone, s3, b2, webdav, ipfs) which persisted after their own retries
+    Http,
+    /// All other errors
+    Other,
+}
+
+impl ErrorClass {
+    /// Determines the class of an error.
+    ///
+    /// # Arguments
+    ///
+    /// * `err` - The error to classify
+    fn of(err: &RusticError) -> Self {
+        match err.kind() {
+            RusticErrorKind::Local(_)
+            | RusticErrorKind::Backend(
+                BackendErrorKind::FromIoError(_) | BackendErrorKind::FromLocalError(_),
+            ) => Self::Io,
+            RusticErrorKind::Rest(_)
+            | RusticErrorKind::Provider(_)
+            | RusticErrorKind::S3(_)
+            | RusticErrorKind::B2(_)
+            | RusticErrorKind::WebDav(_)
+            | RusticErrorKind::Ipfs(_)
+            | RusticErrorKind::Backend(
+                BackendErrorKind::RestApiError(_) | BackendErrorKind::FromProviderError(_),
+            ) => Self::Http,
+            _ => Self::Other,
+        }
+    }
+}
+
+#[serde_as]
+#[derive(Clone, Debug, Serialize, Deserialize, Derivative)]
+#[derivative(Default)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+/// Options to retry failed backend operations
+pub struct RetryOptions {
+    /// Number of retries of a failed backend operation. Default: 0, i.e. no retries
+    pub max_retries: usize,
+
+    /// Delay before the first retry; the delay is doubled with every retry
+    #[derivative(Default(value = "Duration::from_secs(1).into()"))]
+    #[serde_as(as = "DisplayFromStr")]
+    pub initial_delay: humantime::Duration,
+
+    /// Maximum delay between two retries
+    #[derivative(Default(value = "Duration::from_secs(60).into()"))]
+    #[serde_as(as = "DisplayFromStr")]
+    pub max_delay: humantime::Duration,
+
+    /// Random variation of the delays (between 0 and 1), so clients don't retry at the same time
+    #[derivative(Default(value = "0.5"))]
+    pub jitter: f64,
+
+    /// Classes of errors which are retried
+    #[derivative(Default(value = "vec![ErrorClass::Io, ErrorClass::Http]"))]
+    pub retry_on: Vec<ErrorClass>,
+}
+
+impl RetryOptions {
+    /// Returns whether failed operations are retried at all.
+    fn is_active(&self) -> bool {
+        self.max_retries > 0 && !self.retry_on.is_empty()
+    }
+
+    /// Returns the backoff to use for an operation.
+    fn backoff(&self) -> LimitRetryBackoff {
+        LimitRetryBackoff::new(
+            self.max_retries,
+            ExponentialBackoffBuilder::new()
+                .with_initial_interval(*self.initial_delay)
+                .with_max_interval(*self.max_delay)
+                .with_randomization_factor(self.jitter.clamp(0.0, 1.0))
+                .with_max_elapsed_time(None)
+                .build(),
+        )
+    }
+}
+
+/// A backend implementation which retries failed operations of the wrapped backend.
+///
+/// # Type Parameters
+///
+/// * `BE` - The backend to use.
+#[derive(Clone, Debug)]
+pub struct RetryBackend<BE: WriteBackend> {
+    /// The backend to use.
+    be: BE,
+    /// The options when to retry.
+    opts: RetryOptions,
+}
+
+impl<BE: WriteBackend> RetryBackend<BE> {
+    /// Creates a new `RetryBackend`.
+    ///
+    /// # Type Parameters
+    ///
+    /// * `BE` - The backend to use.
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The backend to use.
+    /// * `opts` - The options when to retry.
+    pub fn new(be: BE, opts: RetryOptions) -> Self {
+        Self { be, opts }
+    }
+
+    /// Runs an operation and retries it according to the options.
+    ///
+    /// # Arguments
+    ///
+    /// * `op` - The operation, used for logging.
+    /// * `f` - The operation to run.
+    ///
+    /// # Errors
+    ///
+    /// The error of the last try, if all tries failed.
+    fn retry<T>(&self, op: &str, f: impl Fn() -> RusticResult<T>) -> RusticResult<T> {
+        if !self.opts.is_active() {
+            return f();
+        }
+        backoff::retry_notify(
+            self.opts.backoff(),
+            || {
+                f().map_err(|err| {
+                    if self.opts.retry_on.contains(&ErrorClass::of(&err)) {
+                        Error::transient(err)
+                    } else {
+                        Error::permanent(err)
+                    }
+                })
+            },
+            |err, duration: Duration| warn!("{op} failed: {err}, retrying in {duration:?}"),
+        )
+        .map_err(|err| match err {
+            Error::Permanent(err) | Error::Transient { err, .. } => err,
+        })
+    }
+}
+
+impl<BE: WriteBackend> ReadBackend for RetryBackend<BE> {
+    fn location(&self) -> String {
+        self.be.location()
+    }
+
+    fn set_option(&mut self, option: &str, value: &str) -> RusticResult<()> {
+        self.be.set_option(option, value)
+    }
+
+    fn list_with_size(&self, tpe: FileType) -> RusticResult<Vec<(Id, u32)>> {
+        self.retry("listing", || self.be.list_with_size(tpe))
+    }
+
+    fn list(&self, tpe: FileType) -> RusticResult<Vec<Id>> {
+        self.retry("listing", || self.be.list(tpe))
+    }
+
+    fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
+        self.retry("reading", || self.be.read_full(tpe, id))
+    }
+
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> RusticResult<Bytes> {
+        self.retry("reading", || {
+            self.be.read_partial(tpe, id, cacheable, offset, length)
+        })
+    }
+
+    fn thaw_duration(&self) -> Option<Duration> {
+        self.be.thaw_duration()
+    }
+
+    fn thaw(&self, tpe: FileType, id: &Id) -> RusticResult<ThawState> {
+        self.retry("thawing", || self.be.thaw(tpe, id))
+    }
+}
+
+impl<BE: WriteBackend> WriteBackend for RetryBackend<BE> {
+    fn create(&self) -> RusticResult<()> {
+        self.retry("creating repository", || self.be.create())
+    }
+
+    fn write_bytes(&self, tpe: FileType, id: &Id, cacheable: bool, buf: Bytes) -> RusticResult<()> {
+        // cloning `Bytes` is cheap
+        self.retry("writing", || {
+            self.be.write_bytes(tpe, id, cacheable, buf.clone())
+        })
+    }
+
+    fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> RusticResult<()> {
+        self.retry("removing", || self.be.remove(tpe, id, cacheable))
+    }
+}
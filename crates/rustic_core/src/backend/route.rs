@@ -0,0 +1,205 @@
+use std::fmt::Debug;
+
+use bytes::Bytes;
+use log::debug;
+
+use crate::{
+    backend::{FileType, ReadBackend, WriteBackend},
+    blob::BlobType,
+    id::Id,
+    RusticResult,
+};
+
+/// Decides which of several backends a file is saved to.
+///
+/// This allows layouts like saving metadata and small packs on fast storage and large data packs
+/// on cheap object storage.
+pub trait BackendRouter: Clone + Debug + Send + Sync + 'static {
+    /// Returns the index of the backend the given file is saved to.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `blob_type` - For pack files, the type of the blobs contained in the pack, if known.
+    /// * `size` - The size of the file; `None` when reading or removing the file.
+    ///
+    /// # Notes
+    ///
+    /// An index without backend selects the first backend. When reading, the selected backend
+    /// is tried first and the other backends are used as fallback.
+    fn route(&self, tpe: FileType, blob_type: Option<BlobType>, size: Option<u64>) -> usize;
+}
+
+/// A router saving all files to the first backend except data packs of at least the given size,
+/// which are saved to the second backend.
+#[derive(Clone, Copy, Debug)]
+pub struct SizeRouter {
+    /// Data packs of at least this size are saved to the second backend
+    pub threshold: u64,
+}
+
+impl BackendRouter for SizeRouter {
+    fn route(&self, tpe: FileType, blob_type: Option<BlobType>, size: Option<u64>) -> usize {
+        match (tpe, blob_type, size) {
+            (FileType::Pack, Some(BlobType::Data), Some(size)) if size < self.threshold => 0,
+            (FileType::Pack, Some(BlobType::Data) | None, _) => 1,
+            _ => 0,
+        }
+    }
+}
+
+/// A backend routing files to one of several backends.
+///
+/// # Type Parameters
+///
+/// * `BE` - The backend to use.
+/// * `R` - The router deciding which backend is used for a file.
+#[derive(Clone, Debug)]
+pub struct RoutingBackend<BE: WriteBackend, R: BackendRouter> {
+    /// The backends; the first one is the default backend.
+    backends: Vec<BE>,
+    /// The router to use.
+    router: R,
+}
+
+impl<BE: WriteBackend, R: BackendRouter> RoutingBackend<BE, R> {
+    /// Creates a new `RoutingBackend`.
+    ///
+    /// # Type Parameters
+    ///
+    /// * `BE` - The backend to use.
+    /// * `R` - The router to use.
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The default backend, i.e. the backend with index 0.
+    /// * `others` - The other backends, having the indices 1, 2, ...
+    /// * `router` - The router deciding which backend is used for a file.
+    pub fn new(be: BE, others: Vec<BE>, router: R) -> Self {
+        let mut backends = vec![be];
+        backends.extend(others);
+        Self { backends, router }
+    }
+
+    /// Returns the backends in the order they are tried for the given file.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `blob_type` - For pack files, the type of the blobs contained in the pack, if known.
+    fn ordered(&self, tpe: FileType, blob_type: Option<BlobType>) -> impl Iterator<Item = &BE> {
+        let first = self.index(tpe, blob_type, None);
+        std::iter::once(&self.backends[first]).chain(
+            self.backends
+                .iter()
+                .enumerate()
+                .filter(move |(i, _)| *i != first)
+                .map(|(_, be)| be),
+        )
+    }
+
+    /// Returns the index of the backend selected by the router, using the default backend for invalid indices.
+    fn index(&self, tpe: FileType, blob_type: Option<BlobType>, size: Option<u64>) -> usize {
+        let index = self.router.route(tpe, blob_type, size);
+        if index < self.backends.len() {
+            index
+        } else {
+            0
+        }
+    }
+
+    /// Run the given operation on the backends in the order given by the router until it succeeds.
+    ///
+    /// # Errors
+    ///
+    /// The error of the selected backend if the operation failed on all backends.
+    fn try_all<T>(
+        &self,
+        tpe: FileType,
+        blob_type: Option<BlobType>,
+        op: impl Fn(&BE) -> RusticResult<T>,
+    ) -> RusticResult<T> {
+        let mut first_err = None;
+        for be in self.ordered(tpe, blob_type) {
+            match op(be) {
+                Ok(result) => return Ok(result),
+                Err(err) => {
+                    debug!("{tpe:?} not accessible in {}: {err}", be.location());
+                    first_err = first_err.or(Some(err));
+                }
+            }
+        }
+        // Note: there is always at least one backend, so there is an error
+        Err(first_err.unwrap())
+    }
+}
+
+/// Returns the blob type of a pack file given its cacheability.
+fn blob_type(tpe: FileType, cacheable: bool) -> Option<BlobType> {
+    (tpe == FileType::Pack).then_some(if cacheable {
+        BlobType::Tree
+    } else {
+        BlobType::Data
+    })
+}
+
+impl<BE: WriteBackend, R: BackendRouter> ReadBackend for RoutingBackend<BE, R> {
+    fn location(&self) -> String {
+        self.backends[0].location()
+    }
+
+    fn set_option(&mut self, option: &str, value: &str) -> RusticResult<()> {
+        for be in &mut self.backends {
+            be.set_option(option, value)?;
+        }
+        Ok(())
+    }
+
+    /// Lists the files of all backends; files saved in several backends are only listed once.
+    fn list_with_size(&self, tpe: FileType) -> RusticResult<Vec<(Id, u32)>> {
+        let mut list = Vec::new();
+        for be in &self.backends {
+            list.extend(be.list_with_size(tpe)?);
+        }
+        list.sort_unstable_by_key(|(id, _)| *id);
+        list.dedup_by_key(|(id, _)| *id);
+        Ok(list)
+    }
+
+    fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
+        self.try_all(tpe, None, |be| be.read_full(tpe, id))
+    }
+
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> RusticResult<Bytes> {
+        self.try_all(tpe, blob_type(tpe, cacheable), |be| {
+            be.read_partial(tpe, id, cacheable, offset, length)
+        })
+    }
+}
+
+impl<BE: WriteBackend, R: BackendRouter> WriteBackend for RoutingBackend<BE, R> {
+    fn create(&self) -> RusticResult<()> {
+        for be in &self.backends {
+            be.create()?;
+        }
+        Ok(())
+    }
+
+    fn write_bytes(&self, tpe: FileType, id: &Id, cacheable: bool, buf: Bytes) -> RusticResult<()> {
+        let index = self.index(tpe, blob_type(tpe, cacheable), Some(buf.len() as u64));
+        self.backends[index].write_bytes(tpe, id, cacheable, buf)
+    }
+
+    fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> RusticResult<()> {
+        self.try_all(tpe, blob_type(tpe, cacheable), |be| {
+            be.remove(tpe, id, cacheable)
+        })
+    }
+}
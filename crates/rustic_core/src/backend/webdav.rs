@@ -0,0 +1,697 @@
+//! Backend using a `WebDAV` server like Nextcloud
+//!
+//! Basic and digest authentication are supported; the credentials are given within the url.
+//! If the url only contains a user, the password is taken from `WEBDAV_PASSWORD`.
+use std::{
+    io::Cursor,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, RwLock,
+    },
+};
+
+use backoff::Error;
+use bytes::Bytes;
+use derivative::Derivative;
+use log::{debug, trace};
+use md5::{Digest, Md5};
+use rand::{thread_rng, RngCore};
+use reqwest::{
+    blocking::{Body, RequestBuilder, Response},
+    header::{CONTENT_LENGTH, WWW_AUTHENTICATE},
+    Method, StatusCode, Url,
+};
+
+use crate::{
+    backend::{
+        http::{CheckError, HttpClient, XmlElement},
+        FileType, ReadBackend, WriteBackend,
+    },
+    error::{RusticResult, WebDavErrorKind},
+    id::Id,
+};
+
+mod consts {
+    /// Environment variable containing the password if the url doesn't contain one
+    pub(super) const PASSWORD_ENV: &str = "WEBDAV_PASSWORD";
+    /// The body of a `PROPFIND` request asking for the properties needed for listing
+    pub(super) const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?><d:propfind xmlns:d="DAV:"><d:prop><d:resourcetype/><d:getcontentlength/></d:prop></d:propfind>"#;
+}
+
+/// A digest challenge sent by the server
+#[derive(Clone, Debug, Default)]
+struct DigestChallenge {
+    /// The realm
+    realm: String,
+    /// The nonce chosen by the server
+    nonce: String,
+    /// The opaque value which must be sent back, if any
+    opaque: Option<String>,
+    /// Whether the quality of protection `auth` is used
+    qop_auth: bool,
+}
+
+impl DigestChallenge {
+    /// Parse a digest challenge from a `WWW-Authenticate` header.
+    ///
+    /// # Arguments
+    ///
+    /// * `header` - The value of the header.
+    ///
+    /// # Returns
+    ///
+    /// The challenge or `None` if the header doesn't contain a digest challenge.
+    fn parse(header: &str) -> Option<Self> {
+        let params = header.trim().strip_prefix("Digest ")?;
+        let mut challenge = Self::default();
+        let mut rest = params;
+        while let Some((key, value)) = rest.split_once('=') {
+            let key = key.trim().trim_start_matches(',').trim();
+            let value = value.trim_start();
+            let (value, remaining) = match value.strip_prefix('"') {
+                Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+                None => value.split_once(',').unwrap_or((value, "")),
+            };
+            match key.to_ascii_lowercase().as_str() {
+                "realm" => challenge.realm = value.to_string(),
+                "nonce" => challenge.nonce = value.to_string(),
+                "opaque" => challenge.opaque = Some(value.to_string()),
+                "qop" => challenge.qop_auth = value.split(',').any(|qop| qop.trim() == "auth"),
+                _ => {}
+            }
+            rest = remaining;
+        }
+        Some(challenge)
+    }
+}
+
+/// Compute the hex-encoded MD5 hash of the given string.
+fn md5_hex(data: &str) -> String {
+    hex::encode(Md5::digest(data.as_bytes()))
+}
+
+/// Decode the percent-encoded characters of the given string.
+fn percent_decode(s: &str) -> String {
+    url::form_urlencoded::parse(format!("x={}", s.replace('+', "%2B")).as_bytes())
+        .next()
+        .map(|(_, value)| value.to_string())
+        .unwrap_or_default()
+}
+
+/// A found entry of a `PROPFIND` listing
+struct DavEntry {
+    /// The last segment of the path of the entry
+    name: String,
+    /// Whether the entry is a collection, i.e. a directory
+    is_dir: bool,
+    /// The size of the entry
+    size: u32,
+}
+
+/// Parse the entries of a `PROPFIND` response.
+///
+/// # Arguments
+///
+/// * `xml` - The multistatus response.
+///
+/// # Errors
+///
+/// * [`WebDavErrorKind::UnexpectedResponse`] - If the response is no multistatus response.
+fn parse_propfind(xml: &str) -> RusticResult<Vec<DavEntry>> {
+    let root = XmlElement::parse(xml)
+        .map_err(|err| WebDavErrorKind::UnexpectedResponse(format!("{err}: {xml}")))?;
+    if root.name() != "multistatus" {
+        return Err(WebDavErrorKind::UnexpectedResponse(format!(
+            "expected multistatus, got {}: {xml}",
+            root.name()
+        ))
+        .into());
+    }
+    Ok(root
+        .children("response")
+        .filter_map(|response| {
+            let href = response.child_text("href")?.trim();
+            let name = percent_decode(
+                href.trim_end_matches('/')
+                    .rsplit('/')
+                    .next()
+                    .unwrap_or_default(),
+            );
+            // properties are grouped by their status, missing properties are reported with 404
+            let props: Vec<_> = response
+                .children("propstat")
+                .filter_map(|propstat| propstat.child("prop"))
+                .collect();
+            let is_dir = props.iter().any(|prop| {
+                prop.child("resourcetype")
+                    .and_then(|tpe| tpe.child("collection"))
+                    .is_some()
+            });
+            let size = props
+                .iter()
+                .find_map(|prop| prop.child_text("getcontentlength"))
+                .and_then(|size| size.trim().parse().ok())
+                .unwrap_or_default();
+            Some(DavEntry { name, is_dir, size })
+        })
+        .collect())
+}
+
+/// A backend implementation that uses a `WebDAV` server.
+#[derive(Clone, Derivative)]
+#[derivative(Debug)]
+pub struct WebDavBackend {
+    /// The url of the repository, always ending with `/` and without credentials.
+    url: Url,
+    /// The user to authenticate with, if any
+    user: Option<String>,
+    /// The password to authenticate with
+    #[derivative(Debug = "ignore")]
+    password: String,
+    /// Whether files are uploaded using chunked transfer encoding
+    chunked: bool,
+    /// The HTTP client together with its timeout, proxy and retry settings.
+    http: HttpClient,
+    /// The digest challenge of the server, if digest authentication is used
+    #[derivative(Debug = "ignore")]
+    digest: Arc<RwLock<Option<DigestChallenge>>>,
+    /// The nonce count for digest authentication
+    #[derivative(Debug = "ignore")]
+    nonce_count: Arc<AtomicU32>,
+}
+
+impl WebDavBackend {
+    /// Create a new [`WebDavBackend`] from a given url.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The url of the form `http(s)://[user[:password]@]host[:port]/path`.
+    ///
+    /// # Errors
+    ///
+    /// * [`WebDavErrorKind::UrlParsingFailed`] - If the url could not be parsed.
+    /// * [`HttpErrorKind::BuildingClientFailed`](crate::error::HttpErrorKind::BuildingClientFailed) - If the client could not be built.
+    pub fn new(url: &str) -> RusticResult<Self> {
+        let mut url = Url::parse(url).map_err(WebDavErrorKind::UrlParsingFailed)?;
+        if !url.path().ends_with('/') {
+            let path = format!("{}/", url.path());
+            url.set_path(&path);
+        }
+        let user = (!url.username().is_empty()).then(|| percent_decode(url.username()));
+        let password = url.password().map_or_else(
+            || std::env::var(consts::PASSWORD_ENV).unwrap_or_default(),
+            percent_decode,
+        );
+        // Note: setting empty credentials never fails for http(s) urls
+        _ = url.set_username("");
+        _ = url.set_password(None);
+
+        Ok(Self {
+            url,
+            user,
+            password,
+            chunked: false,
+            http: HttpClient::new()?,
+            digest: Arc::new(RwLock::new(None)),
+            nonce_count: Arc::new(AtomicU32::new(0)),
+        })
+    }
+
+    /// Returns the path of the given file relative to the repository url.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    fn path(tpe: FileType, id: &Id) -> String {
+        let hex_id = id.to_hex();
+        match tpe {
+            FileType::Config => "config".to_string(),
+            FileType::Pack => format!("data/{}/{hex_id}", &hex_id[0..2]),
+            _ => format!("{}/{hex_id}", tpe.dirname()),
+        }
+    }
+
+    /// Add the authorization to the request.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The request to authorize.
+    /// * `method` - The method of the request.
+    /// * `url` - The url of the request.
+    fn authorize(&self, request: RequestBuilder, method: &Method, url: &Url) -> RequestBuilder {
+        let user = match &self.user {
+            Some(user) => user,
+            None => return request,
+        };
+        let challenge = match self.digest.read().ok().and_then(|digest| digest.clone()) {
+            Some(challenge) => challenge,
+            None => return request.basic_auth(user, Some(&self.password)),
+        };
+
+        let uri = &url[url::Position::BeforePath..];
+        let ha1 = md5_hex(&format!("{user}:{}:{}", challenge.realm, self.password));
+        let ha2 = md5_hex(&format!("{method}:{uri}"));
+        let mut header = format!(
+            r#"Digest username="{user}", realm="{}", nonce="{}", uri="{uri}""#,
+            challenge.realm, challenge.nonce
+        );
+        if challenge.qop_auth {
+            let nc = format!(
+                "{:08x}",
+                self.nonce_count.fetch_add(1, Ordering::Relaxed) + 1
+            );
+            let mut cnonce = [0; 8];
+            thread_rng().fill_bytes(&mut cnonce);
+            let cnonce = hex::encode(cnonce);
+            let response = md5_hex(&format!(
+                "{ha1}:{}:{nc}:{cnonce}:auth:{ha2}",
+                challenge.nonce
+            ));
+            header.push_str(&format!(
+                r#", qop=auth, nc={nc}, cnonce="{cnonce}", response="{response}""#
+            ));
+        } else {
+            let response = md5_hex(&format!("{ha1}:{}:{ha2}", challenge.nonce));
+            header.push_str(&format!(r#", response="{response}""#));
+        }
+        if let Some(opaque) = &challenge.opaque {
+            header.push_str(&format!(r#", opaque="{opaque}""#));
+        }
+        request.header("Authorization", header)
+    }
+
+    /// Returns the url of the given path relative to the repository url.
+    ///
+    /// # Errors
+    ///
+    /// * [`WebDavErrorKind::UrlParsingFailed`] - If the url could not be created.
+    fn url(&self, path: &str) -> RusticResult<Url> {
+        Ok(self
+            .url
+            .join(path)
+            .map_err(WebDavErrorKind::UrlParsingFailed)?)
+    }
+
+    /// Send a request and read the response, retrying transient errors.
+    ///
+    /// If the server asks for digest authentication, the request is repeated using the given challenge.
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - The method of the request.
+    /// * `url` - The url of the request.
+    /// * `request` - Adds headers and body to the request.
+    /// * `read` - Reads the result from the response.
+    ///
+    /// # Errors
+    ///
+    /// If the request failed, even after retrying, it will return an error of type `Error<reqwest::Error>`
+    fn send<T>(
+        &self,
+        method: &Method,
+        url: &Url,
+        request: impl Fn(RequestBuilder) -> RequestBuilder,
+        read: impl Fn(Response) -> reqwest::Result<T>,
+    ) -> Result<T, Error<reqwest::Error>> {
+        self.http.retry(|| {
+            let builder = self.http.client().request(method.clone(), url.clone());
+            let mut response = request(self.authorize(builder, method, url)).send()?;
+            if response.status() == StatusCode::UNAUTHORIZED {
+                let challenge = response
+                    .headers()
+                    .get_all(WWW_AUTHENTICATE)
+                    .iter()
+                    .filter_map(|header| header.to_str().ok())
+                    .find_map(DigestChallenge::parse);
+                if let (Some(challenge), Ok(mut digest)) = (challenge, self.digest.write()) {
+                    debug!("using digest authentication for realm {}", challenge.realm);
+                    *digest = Some(challenge);
+                    self.nonce_count.store(0, Ordering::Relaxed);
+                    drop(digest);
+                    let builder = self.http.client().request(method.clone(), url.clone());
+                    response = request(self.authorize(builder, method, url)).send()?;
+                }
+            }
+            Ok(read(response.check_error()?)?)
+        })
+    }
+
+    /// List the entries of a collection.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the collection relative to the repository url, ending with `/`.
+    ///
+    /// # Errors
+    ///
+    /// * [`WebDavErrorKind::BackoffError`] - If the backoff failed.
+    /// * [`WebDavErrorKind::UnexpectedResponse`] - If the response could not be parsed.
+    ///
+    /// # Returns
+    ///
+    /// The entries of the collection; empty if the collection doesn't exist.
+    fn propfind(&self, path: &str) -> RusticResult<Vec<DavEntry>> {
+        trace!("listing {path}");
+        // Note: method names only fail to parse if they contain invalid characters
+        let method = Method::from_bytes(b"PROPFIND").unwrap();
+        let xml = match self.send(
+            &method,
+            &self.url(path)?,
+            |request| {
+                request
+                    .header("Depth", "1")
+                    .header("Content-Type", "application/xml; charset=utf-8")
+                    .body(consts::PROPFIND_BODY)
+            },
+            Response::text,
+        ) {
+            Ok(xml) => xml,
+            Err(Error::Permanent(err)) if err.status() == Some(StatusCode::NOT_FOUND) => {
+                return Ok(Vec::new())
+            }
+            Err(err) => return Err(WebDavErrorKind::BackoffError(err).into()),
+        };
+        let name = path
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .unwrap_or_default();
+        // the first entry is the collection itself
+        Ok(parse_propfind(&xml)?
+            .into_iter()
+            .skip_while(|entry| entry.is_dir && entry.name == name)
+            .collect())
+    }
+
+    /// Create a collection, if it doesn't exist yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the collection relative to the repository url, ending with `/`.
+    ///
+    /// # Errors
+    ///
+    /// * [`WebDavErrorKind::BackoffError`] - If the backoff failed.
+    fn mkcol(&self, path: &str) -> RusticResult<()> {
+        trace!("creating {path}");
+        let method = Method::from_bytes(b"MKCOL").unwrap();
+        match self.send(&method, &self.url(path)?, |request| request, |_| Ok(())) {
+            // 405 Method Not Allowed means that the collection already exists
+            Err(Error::Permanent(err)) if err.status() == Some(StatusCode::METHOD_NOT_ALLOWED) => {
+                Ok(())
+            }
+            result => Ok(result.map_err(WebDavErrorKind::BackoffError)?),
+        }
+    }
+}
+
+impl ReadBackend for WebDavBackend {
+    /// Returns the location of the backend.
+    fn location(&self) -> String {
+        let mut location = "webdav:".to_string();
+        let mut url = self.url.clone();
+        if let Some(user) = &self.user {
+            // Note: setting credentials never fails for http(s) urls
+            _ = url.set_username(user);
+        }
+        location.push_str(url.as_str());
+        location
+    }
+
+    /// Sets an option of the backend.
+    ///
+    /// # Arguments
+    ///
+    /// * `option` - The option to set.
+    /// * `value` - The value to set the option to.
+    ///
+    /// # Errors
+    ///
+    /// * [`WebDavErrorKind::InvalidOptionValue`] - If the value is not valid for the option.
+    /// * [`HttpErrorKind::InvalidOptionValue`](crate::error::HttpErrorKind::InvalidOptionValue) - If the value is not valid for a common HTTP option.
+    ///
+    /// # Notes
+    ///
+    /// Currently supported options:
+    /// * `chunked` - If `true`, files are uploaded using chunked transfer encoding. Default is `false`.
+    /// * `retry` - The number of retries to use for transient errors. Default is 5. Set to 0 to disable retries.
+    /// * `timeout` - The timeout to use for requests. Default is 10 minutes. Format is described in [humantime](https://docs.rs/humantime/2.1.0/humantime/fn.parse_duration.html).
+    /// * `proxy` - The proxy URL to use, `system` to use `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY` or `none`. Default is `system`.
+    fn set_option(&mut self, option: &str, value: &str) -> RusticResult<()> {
+        if self.http.set_option(option, value)? {
+            return Ok(());
+        }
+        let invalid = || WebDavErrorKind::InvalidOptionValue(option.to_string(), value.to_string());
+        match option {
+            "chunked" => {
+                self.chunked = bool::from_str(value).map_err(|_| invalid())?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Returns a list of all files of a given type with their size.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the files to list.
+    ///
+    /// # Errors
+    ///
+    /// * [`WebDavErrorKind::BackoffError`] - If the backoff failed.
+    ///
+    /// # Notes
+    ///
+    /// Pack files are listed by one `PROPFIND` request per sub-directory of `data/`, as many
+    /// servers don't allow listings of infinite depth.
+    ///
+    /// # Returns
+    ///
+    /// A vector of tuples containing the id and size of the files.
+    fn list_with_size(&self, tpe: FileType) -> RusticResult<Vec<(Id, u32)>> {
+        trace!("listing tpe: {tpe:?}");
+        if tpe == FileType::Config {
+            let size = |response: Response| {
+                Ok(response
+                    .headers()
+                    .get(CONTENT_LENGTH)
+                    .and_then(|len| len.to_str().ok())
+                    .and_then(|len| len.parse().ok())
+                    .unwrap_or_default())
+            };
+            return match self.send(&Method::HEAD, &self.url("config")?, |request| request, size) {
+                Ok(size) => Ok(vec![(Id::default(), size)]),
+                Err(Error::Permanent(err)) if err.status() == Some(StatusCode::NOT_FOUND) => {
+                    Ok(Vec::new())
+                }
+                Err(err) => Err(WebDavErrorKind::BackoffError(err).into()),
+            };
+        }
+
+        let dirs = if tpe == FileType::Pack {
+            self.propfind("data/")?
+                .into_iter()
+                .filter(|entry| entry.is_dir)
+                .map(|entry| format!("data/{}/", entry.name))
+                .collect()
+        } else {
+            vec![format!("{}/", tpe.dirname())]
+        };
+
+        let mut list = Vec::new();
+        for dir in dirs {
+            for entry in self.propfind(&dir)? {
+                if entry.is_dir {
+                    continue;
+                }
+                match Id::from_hex(&entry.name) {
+                    Ok(id) => list.push((id, entry.size)),
+                    Err(_) => debug!("ignoring file {dir}{}", entry.name),
+                }
+            }
+        }
+        Ok(list)
+    }
+
+    /// Returns the content of a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    ///
+    /// # Errors
+    ///
+    /// * [`WebDavErrorKind::BackoffError`] - If the backoff failed.
+    fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
+        trace!("reading tpe: {tpe:?}, id: {id}");
+        let url = self.url(&Self::path(tpe, id))?;
+        Ok(self
+            .send(&Method::GET, &url, |request| request, Response::bytes)
+            .map_err(WebDavErrorKind::BackoffError)?)
+    }
+
+    /// Returns a part of the content of a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    /// * `cacheable` - Whether the file is cacheable.
+    /// * `offset` - The offset to read from.
+    /// * `length` - The length to read.
+    ///
+    /// # Errors
+    ///
+    /// * [`WebDavErrorKind::BackoffError`] - If the backoff failed.
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        _cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> RusticResult<Bytes> {
+        trace!("reading tpe: {tpe:?}, id: {id}, offset: {offset}, length: {length}");
+        let offset2 = offset + length - 1;
+        let url = self.url(&Self::path(tpe, id))?;
+        Ok(self
+            .send(
+                &Method::GET,
+                &url,
+                |request| request.header("Range", format!("bytes={offset}-{offset2}")),
+                Response::bytes,
+            )
+            .map_err(WebDavErrorKind::BackoffError)?)
+    }
+}
+
+impl WriteBackend for WebDavBackend {
+    /// Creates the repository directory and the directories for all file types.
+    ///
+    /// # Errors
+    ///
+    /// * [`WebDavErrorKind::BackoffError`] - If the backoff failed.
+    ///
+    /// # Notes
+    ///
+    /// The parent of the repository directory must already exist.
+    fn create(&self) -> RusticResult<()> {
+        self.mkcol("")?;
+        for tpe in [
+            FileType::Index,
+            FileType::Key,
+            FileType::Snapshot,
+            FileType::Pack,
+        ] {
+            self.mkcol(&format!("{}/", tpe.dirname()))?;
+        }
+        for i in 0u8..=255 {
+            self.mkcol(&format!("data/{}/", hex::encode([i])))?;
+        }
+        Ok(())
+    }
+
+    /// Writes bytes to the given file.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    /// * `cacheable` - Whether the file is cacheable.
+    /// * `buf` - The bytes to write.
+    ///
+    /// # Errors
+    ///
+    /// * [`WebDavErrorKind::BackoffError`] - If the backoff failed.
+    fn write_bytes(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        _cacheable: bool,
+        buf: Bytes,
+    ) -> RusticResult<()> {
+        trace!("writing tpe: {:?}, id: {}", &tpe, &id);
+        let url = self.url(&Self::path(tpe, id))?;
+        Ok(self
+            .send(
+                &Method::PUT,
+                &url,
+                |request| {
+                    if self.chunked {
+                        // a body of unknown length is sent using chunked transfer encoding
+                        request.body(Body::new(Cursor::new(buf.clone())))
+                    } else {
+                        request.body(buf.clone())
+                    }
+                },
+                |_| Ok(()),
+            )
+            .map_err(WebDavErrorKind::BackoffError)?)
+    }
+
+    /// Removes the given file.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    /// * `cacheable` - Whether the file is cacheable.
+    ///
+    /// # Errors
+    ///
+    /// * [`WebDavErrorKind::BackoffError`] - If the backoff failed.
+    fn remove(&self, tpe: FileType, id: &Id, _cacheable: bool) -> RusticResult<()> {
+        trace!("removing tpe: {:?}, id: {}", &tpe, &id);
+        let url = self.url(&Self::path(tpe, id))?;
+        Ok(self
+            .send(&Method::DELETE, &url, |request| request, |_| Ok(()))
+            .map_err(WebDavErrorKind::BackoffError)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_propfind_listing() {
+        let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:multistatus xmlns:d="DAV:" xmlns:s="http://sabredav.org/ns">
+  <d:response>
+    <d:href>/remote.php/dav/files/user/repo/data/</d:href>
+    <d:propstat>
+      <d:prop><d:resourcetype><d:collection/></d:resourcetype></d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+    <d:propstat>
+      <d:prop><d:getcontentlength/></d:prop>
+      <d:status>HTTP/1.1 404 Not Found</d:status>
+    </d:propstat>
+  </d:response>
+  <d:response>
+    <d:href>/remote.php/dav/files/user/repo/data/a%20b&amp;c</d:href>
+    <d:propstat>
+      <d:prop><d:resourcetype/><d:getcontentlength>1234</d:getcontentlength></d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+  </d:response>
+</d:multistatus>"#;
+        let entries = parse_propfind(xml).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name, "data");
+        assert!(entries[0].is_dir);
+        assert_eq!(entries[1].name, "a b&c");
+        assert!(!entries[1].is_dir);
+        assert_eq!(entries[1].size, 1234);
+    }
+
+    #[test]
+    fn parse_invalid_propfind() {
+        assert!(parse_propfind("<d:error xmlns:d=\"DAV:\"/>").is_err());
+        assert!(parse_propfind("<d:multistatus><d:response>").is_err());
+    }
+}
@@ -10,6 +10,7 @@ use bytes::{Bytes, BytesMut};
 use chrono::Local;
 use crossbeam_channel::{bounded, Receiver, Sender};
 use pariter::{scope, IteratorExt};
+use rand::{thread_rng, RngCore};
 use zstd::encode_all;
 
 use crate::{
@@ -20,6 +21,7 @@ use crate::{
     error::RusticResult,
     id::Id,
     index::indexer::SharedIndexer,
+    profile::Phase,
     repofile::{
         configfile::ConfigFile, indexfile::IndexBlob, indexfile::IndexPack,
         packfile::PackHeaderLength, packfile::PackHeaderRef, snapshotfile::SnapshotSummary,
@@ -156,6 +158,7 @@ impl<BE: DecryptWriteBackend> Packer<BE> {
     /// * `indexer` - The indexer to write to.
     /// * `config` - The config file.
     /// * `total_size` - The total size of the pack file.
+    /// * `connections` - The number of pack files to upload in parallel.
     ///
     /// # Errors
     ///
@@ -168,6 +171,7 @@ impl<BE: DecryptWriteBackend> Packer<BE> {
         indexer: SharedIndexer<BE>,
         config: &ConfigFile,
         total_size: u64,
+        connections: usize,
     ) -> RusticResult<Self> {
         let key = be.key().clone();
         let raw_packer = Arc::new(RwLock::new(RawPacker::new(
@@ -176,6 +180,7 @@ impl<BE: DecryptWriteBackend> Packer<BE> {
             indexer.clone(),
             config,
             total_size,
+            connections,
         )));
         let zstd = config.zstd()?;
 
@@ -384,6 +389,8 @@ pub(crate) struct RawPacker<BE: DecryptWriteBackend> {
     file_writer: Option<Actor>,
     /// The pack sizer
     pack_sizer: PackSizer,
+    /// Pack files are padded to a multiple of this size, if set
+    padding: Option<u32>,
     /// The packer stats
     stats: PackerStats,
 }
@@ -402,12 +409,14 @@ impl<BE: DecryptWriteBackend> RawPacker<BE> {
     /// * `indexer` - The indexer to write to.
     /// * `config` - The config file.
     /// * `total_size` - The total size of the pack file.
+    /// * `connections` - The number of pack files to upload in parallel.
     fn new(
         be: BE,
         blob_type: BlobType,
         indexer: SharedIndexer<BE>,
         config: &ConfigFile,
         total_size: u64,
+        connections: usize,
     ) -> Self {
         let file_writer = Some(Actor::new(
             FileWriterHandle {
@@ -416,7 +425,7 @@ impl<BE: DecryptWriteBackend> RawPacker<BE> {
                 cacheable: blob_type.is_cacheable(),
             },
             1,
-            1,
+            connections,
         ));
 
         let pack_sizer = PackSizer::from_config(config, blob_type, total_size);
@@ -431,6 +440,7 @@ impl<BE: DecryptWriteBackend> RawPacker<BE> {
             index: IndexPack::default(),
             file_writer,
             pack_sizer,
+            padding: config.pack_padding.filter(|padding| *padding > 0),
             stats: PackerStats::default(),
         }
     }
@@ -522,6 +532,9 @@ impl<BE: DecryptWriteBackend> RawPacker<BE> {
 
     /// Writes header and length of header to packfile
     ///
+    /// If padding is configured, random bytes are written before the header such that the size of the
+    /// packfile is a multiple of the padding size. The actual pack size is then saved in the index.
+    ///
     /// # Errors
     ///
     /// * [`PackerErrorKind::IntConversionFailed`] - If converting the header length to u32 fails
@@ -537,6 +550,22 @@ impl<BE: DecryptWriteBackend> RawPacker<BE> {
             .len()
             .try_into()
             .map_err(PackerErrorKind::IntConversionFailed)?;
+
+        if let Some(padding) = self.padding {
+            // size of the pack without padding, i.e. including header and header length
+            let size = PackHeaderRef::from_index_pack(&self.index).pack_size();
+            let padded_size = (u64::from(size) + u64::from(padding) - 1) / u64::from(padding)
+                * u64::from(padding);
+            let padded_size: u32 = padded_size
+                .try_into()
+                .map_err(PackerErrorKind::IntConversionFailed)?;
+            if padded_size > size {
+                let mut pad = vec![0; (padded_size - size) as usize];
+                thread_rng().fill_bytes(&mut pad);
+                _ = self.write_data(&pad)?;
+                self.index.size = Some(padded_size);
+            }
+        }
         _ = self.write_data(&data)?;
 
         // finally write length of header unencrypted to pack file
@@ -597,8 +626,10 @@ impl<BE: DecryptWriteBackend> FileWriterHandle<BE> {
     fn process(&self, load: (Bytes, Id, IndexPack)) -> RusticResult<IndexPack> {
         let (file, id, mut index) = load;
         index.id = id;
-        self.be
-            .write_bytes(FileType::Pack, &id, self.cacheable, file)?;
+        Phase::Upload.measure(|| {
+            self.be
+                .write_bytes(FileType::Pack, &id, self.cacheable, file)
+        })?;
         index.time = Some(Local::now());
         Ok(index)
     }
@@ -628,11 +659,11 @@ impl Actor {
     ///
     /// * `fwh` - The file writer handle.
     /// * `queue_len` - The length of the queue.
-    /// * `par` - The number of parallel threads.
+    /// * `par` - The number of files to upload in parallel.
     fn new<BE: DecryptWriteBackend>(
         fwh: FileWriterHandle<BE>,
         queue_len: usize,
-        _par: usize,
+        par: usize,
     ) -> Self {
         let (tx, rx) = bounded(queue_len);
         let (finish_tx, finish_rx) = bounded::<RusticResult<()>>(0);
@@ -647,7 +678,11 @@ impl Actor {
                         (file, id, index)
                     })
                     .readahead_scoped(scope)
-                    .map(|load| fwh.process(load))
+                    .parallel_map_scoped_custom(
+                        scope,
+                        |options| options.threads(par),
+                        |load| fwh.process(load),
+                    )
                     .readahead_scoped(scope)
                     .try_for_each(|index| fwh.index(index?));
                 _ = finish_tx.send(status);
@@ -722,6 +757,7 @@ impl<BE: DecryptFullBackend> Repacker<BE> {
     /// * `indexer` - The indexer to write to.
     /// * `config` - The config file.
     /// * `total_size` - The total size of the pack file.
+    /// * `connections` - The number of pack files to upload in parallel.
     ///
     /// # Errors
     ///
@@ -732,8 +768,16 @@ impl<BE: DecryptFullBackend> Repacker<BE> {
         indexer: SharedIndexer<BE>,
         config: &ConfigFile,
         total_size: u64,
+        connections: usize,
     ) -> RusticResult<Self> {
-        let packer = Packer::new(be.clone(), blob_type, indexer, config, total_size)?;
+        let packer = Packer::new(
+            be.clone(),
+            blob_type,
+            indexer,
+            config,
+            total_size,
+            connections,
+        )?;
         let size_limit = PackSizer::from_config(config, blob_type, total_size).pack_size();
         Ok(Self {
             be,
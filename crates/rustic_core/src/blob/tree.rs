@@ -29,6 +29,8 @@ use crate::{
 pub(super) mod constants {
     /// The maximum number of trees that are loaded in parallel
     pub(super) const MAX_TREE_LOADER: usize = 4;
+    /// The maximum number of nodes saved in a single tree blob; larger trees are sharded
+    pub(super) const MAX_TREE_NODES: usize = 50_000;
 }
 
 pub(crate) type TreeStreamItem = RusticResult<(PathBuf, Tree)>;
@@ -42,6 +44,35 @@ pub struct Tree {
     ///
     /// This is usually sorted by `Node.name()`, i.e. by the node name as `OsString`
     pub nodes: Vec<Node>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    /// The ids of the tree blobs containing the remaining nodes of a large directory, in order.
+    ///
+    /// When reading a tree from the backend, the nodes of all shards are appended to `nodes`.
+    pub shards: Vec<Id>,
+}
+
+/// A serialized [`Tree`], which may be sharded into several tree blobs
+#[derive(Debug)]
+pub(crate) struct SerializedTree {
+    /// The serialized root tree blob
+    pub(crate) chunk: Vec<u8>,
+    /// The id of the root tree blob
+    pub(crate) id: Id,
+    /// The serialized shards and their ids; empty if the tree is not sharded
+    pub(crate) shards: Vec<(Vec<u8>, Id)>,
+}
+
+impl SerializedTree {
+    /// The total size of all tree blobs
+    pub(crate) fn len(&self) -> usize {
+        self.chunk.len()
+            + self
+                .shards
+                .iter()
+                .map(|(chunk, _)| chunk.len())
+                .sum::<usize>()
+    }
 }
 
 /// Deserializes `Option<T>` as `T::default()` if the value is `null`
@@ -58,7 +89,10 @@ impl Tree {
     /// Creates a new `Tree` with no nodes.
     #[must_use]
     pub(crate) const fn new() -> Self {
-        Self { nodes: Vec::new() }
+        Self {
+            nodes: Vec::new(),
+            shards: Vec::new(),
+        }
     }
 
     /// Adds a node to the tree.
@@ -70,18 +104,93 @@ impl Tree {
         self.nodes.push(node);
     }
 
-    /// Serializes the tree.
+    /// Serializes the tree into a single blob.
     ///
     /// # Returns
     ///
     /// A tuple of the serialized tree as `Vec<u8>` and the tree's ID
-    pub(crate) fn serialize(&self) -> RusticResult<(Vec<u8>, Id)> {
+    fn serialize_blob(&self) -> RusticResult<(Vec<u8>, Id)> {
         let mut chunk = serde_json::to_vec(&self).map_err(TreeErrorKind::SerializingTreeFailed)?;
         chunk.push(b'\n'); // for whatever reason, restic adds a newline, so to be compatible...
         let id = hash(&chunk);
         Ok((chunk, id))
     }
 
+    /// Serializes the tree.
+    ///
+    /// # Arguments
+    ///
+    /// * `shard` - Whether large trees may be sharded, see [`ConfigFile::shard_trees`]
+    ///
+    /// # Returns
+    ///
+    /// The serialized tree. If `shard` is set, trees with more than `MAX_TREE_NODES` nodes are
+    /// sharded: The root blob contains the first nodes and the ids of the shards which contain the
+    /// remaining nodes.
+    ///
+    /// # Notes
+    ///
+    /// Sharded trees are a rustic extension; restic only sees the nodes saved in the root blob.
+    ///
+    /// [`ConfigFile::shard_trees`]: crate::repofile::configfile::ConfigFile::shard_trees
+    pub(crate) fn serialize(&self, shard: bool) -> RusticResult<SerializedTree> {
+        if !shard || self.nodes.len() <= constants::MAX_TREE_NODES {
+            // the nodes of read shards are contained in `nodes`, so the shards must not be saved again
+            let (chunk, id) = if self.shards.is_empty() {
+                self.serialize_blob()?
+            } else {
+                Self {
+                    nodes: self.nodes.clone(),
+                    shards: Vec::new(),
+                }
+                .serialize_blob()?
+            };
+            return Ok(SerializedTree {
+                chunk,
+                id,
+                shards: Vec::new(),
+            });
+        }
+
+        let mut chunks = self.nodes.chunks(constants::MAX_TREE_NODES);
+        let first = chunks.next().unwrap_or_default();
+        let shards = chunks
+            .map(|nodes| {
+                Self {
+                    nodes: nodes.to_vec(),
+                    shards: Vec::new(),
+                }
+                .serialize_blob()
+            })
+            .collect::<RusticResult<Vec<_>>>()?;
+        let root = Self {
+            nodes: first.to_vec(),
+            shards: shards.iter().map(|(_, id)| *id).collect(),
+        };
+        let (chunk, id) = root.serialize_blob()?;
+        Ok(SerializedTree { chunk, id, shards })
+    }
+
+    /// Deserializes a single tree blob from the backend.
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The backend to read from.
+    /// * `id` - The ID of the tree blob to deserialize.
+    ///
+    /// # Errors
+    ///
+    /// * [`TreeErrorKind::BlobIdNotFound`] - If the tree ID is not found in the backend.
+    /// * [`TreeErrorKind::DeserializingTreeFailed`] - If deserialization fails.
+    fn blob_from_backend(be: &impl IndexedBackend, id: Id) -> RusticResult<Self> {
+        let data = be
+            .get_tree(&id)
+            .ok_or_else(|| TreeErrorKind::BlobIdNotFound(id))?
+            .read_data(be.be())?;
+
+        Ok(serde_json::from_slice(&data).map_err(TreeErrorKind::DeserializingTreeFailed)?)
+    }
+
     /// Deserializes a tree from the backend.
     ///
     /// # Arguments
@@ -96,14 +205,24 @@ impl Tree {
     ///
     /// # Returns
     ///
-    /// The deserialized tree.
+    /// The deserialized tree. If the tree is sharded, the nodes of all shards are read.
     pub(crate) fn from_backend(be: &impl IndexedBackend, id: Id) -> RusticResult<Self> {
-        let data = be
-            .get_tree(&id)
-            .ok_or_else(|| TreeErrorKind::BlobIdNotFound(id))?
-            .read_data(be.be())?;
+        Self::from_blobs(id, |id| Self::blob_from_backend(be, id))
+    }
 
-        Ok(serde_json::from_slice(&data).map_err(TreeErrorKind::DeserializingTreeFailed)?)
+    /// Deserializes a tree, reading the nodes of all shards.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the tree to deserialize.
+    /// * `read_blob` - Reads and deserializes a single tree blob.
+    fn from_blobs(id: Id, read_blob: impl Fn(Id) -> RusticResult<Self>) -> RusticResult<Self> {
+        let mut tree = read_blob(id)?;
+        for shard in &tree.shards {
+            let shard = read_blob(*shard)?;
+            tree.nodes.extend(shard.nodes);
+        }
+        Ok(tree)
     }
 
     /// Creates a new node from a path.
@@ -691,3 +810,57 @@ pub(crate) fn merge_nodes(
     }
     Ok(node)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn sharded_tree_roundtrip() {
+        let mut tree = Tree::new();
+        for i in 0..=2 * constants::MAX_TREE_NODES {
+            tree.add(Node::new_node(
+                OsStr::new(&format!("file{i:06}")),
+                NodeType::File,
+                Metadata::default(),
+            ));
+        }
+
+        let serialized = tree.serialize(true).unwrap();
+        assert_eq!(serialized.shards.len(), 2);
+        let blobs: HashMap<_, _> = serialized
+            .shards
+            .iter()
+            .map(|(chunk, id)| (*id, chunk.clone()))
+            .chain([(serialized.id, serialized.chunk.clone())])
+            .collect();
+        for (id, chunk) in &blobs {
+            assert_eq!(*id, hash(chunk));
+        }
+
+        let read = Tree::from_blobs(serialized.id, |id| {
+            Ok(serde_json::from_slice(&blobs[&id]).unwrap())
+        })
+        .unwrap();
+        assert_eq!(read.nodes, tree.nodes);
+        assert_eq!(
+            read.shards,
+            serialized
+                .shards
+                .iter()
+                .map(|(_, id)| *id)
+                .collect::<Vec<_>>()
+        );
+
+        // saving a read tree again must not change it
+        let resaved = read.serialize(true).unwrap();
+        assert_eq!(resaved.id, serialized.id);
+
+        // without sharding, all nodes are saved in a single blob
+        let unsharded = read.serialize(false).unwrap();
+        assert!(unsharded.shards.is_empty());
+        assert_eq!(unsharded.id, tree.serialize(false).unwrap().id);
+    }
+}
@@ -1,3 +1,5 @@
+/// The `backend check` command.
+pub mod backend_check;
 pub mod backup;
 /// The `cat` command.
 pub mod cat;
@@ -8,13 +10,25 @@ pub mod copy;
 /// The `dump` command.
 pub mod dump;
 pub mod forget;
+/// The `index` command.
+pub mod index;
 pub mod init;
+/// Journaled removal of repository files.
+pub mod journal;
 pub mod key;
 pub mod merge;
+/// Notes attached to snapshots, packs or keys.
+pub mod note;
 pub mod prune;
+/// The `rekey` command.
+pub mod rekey;
 /// The `repair` command.
 pub mod repair;
 /// The `repoinfo` command.
 pub mod repoinfo;
 pub mod restore;
 pub mod snapshots;
+/// The `sync` command.
+pub mod sync;
+/// Moving snapshots to the trash and undeleting them.
+pub mod trash;
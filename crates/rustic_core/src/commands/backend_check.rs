@@ -0,0 +1,268 @@
+//! `backend check` command
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use bytesize::ByteSize;
+use derive_setters::Setters;
+use log::{debug, info};
+use rand::{thread_rng, RngCore};
+use serde::Serialize;
+
+use crate::{
+    backend::{FileType, ReadBackend, WriteBackend},
+    error::RusticResult,
+    id::Id,
+    repository::Repository,
+};
+
+#[cfg_attr(feature = "clap", derive(clap::Parser))]
+#[derive(Clone, Copy, Debug, Setters)]
+#[setters(into)]
+/// Options for the `backend check` command
+pub struct BackendCheckOptions {
+    /// Size of the probe file used to measure the throughput
+    #[cfg_attr(
+        feature = "clap",
+        clap(long, value_name = "SIZE", default_value = "4MiB")
+    )]
+    pub size: ByteSize,
+}
+
+impl Default for BackendCheckOptions {
+    fn default() -> Self {
+        Self {
+            size: ByteSize::mib(4),
+        }
+    }
+}
+
+/// A single operation of the backend check
+#[derive(Clone, Debug, Serialize)]
+pub struct BackendCheckStep {
+    /// Description of the operation
+    pub name: String,
+    /// Duration of the operation
+    pub duration: Duration,
+    /// Number of bytes transferred, if the throughput is relevant
+    pub bytes: Option<u64>,
+    /// Error message, if the operation failed
+    pub error: Option<String>,
+}
+
+impl BackendCheckStep {
+    /// Returns the throughput in bytes per second, if bytes have been transferred
+    pub fn throughput(&self) -> Option<f64> {
+        self.bytes
+            .filter(|_| !self.duration.is_zero())
+            .map(|bytes| bytes as f64 / self.duration.as_secs_f64())
+    }
+}
+
+/// Results of checking a single backend
+#[derive(Clone, Debug, Serialize)]
+pub struct BackendCheckResult {
+    /// The location of the backend
+    pub location: String,
+    /// The performed operations
+    pub steps: Vec<BackendCheckStep>,
+    /// Whether partial reads return exactly the requested range; `None` if this could not be determined
+    pub ranged_reads: Option<bool>,
+    /// Whether existing files can be overwritten, e.g. `false` for append-only servers; `None` if this could not be determined
+    pub overwrite: Option<bool>,
+    /// Whether files need to be restored from an archive tier before reading them
+    pub needs_thaw: bool,
+}
+
+impl BackendCheckResult {
+    /// Returns whether all operations were successful
+    pub fn is_ok(&self) -> bool {
+        self.steps.iter().all(|step| step.error.is_none())
+    }
+}
+
+/// Collects the steps of a check
+struct Steps(Vec<BackendCheckStep>);
+
+impl Steps {
+    /// Run and measure an operation
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Description of the operation
+    /// * `bytes` - Number of bytes transferred by the operation
+    /// * `op` - The operation
+    ///
+    /// # Returns
+    ///
+    /// The result of the operation, if it was successful
+    fn run<T>(
+        &mut self,
+        name: &str,
+        bytes: Option<u64>,
+        op: impl FnOnce() -> RusticResult<T>,
+    ) -> Option<T> {
+        debug!("backend check: {name}");
+        let start = Instant::now();
+        let result = op();
+        let duration = start.elapsed();
+        let (result, error) = match result {
+            Ok(result) => (Some(result), None),
+            Err(err) => (None, Some(err.to_string())),
+        };
+        self.0.push(BackendCheckStep {
+            name: name.to_string(),
+            duration,
+            bytes,
+            error,
+        });
+        result
+    }
+
+    /// Add a failed verification of a previous operation
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Description of the verification
+    /// * `error` - What went wrong
+    fn fail(&mut self, name: &str, error: &str) {
+        self.0.push(BackendCheckStep {
+            name: name.to_string(),
+            duration: Duration::ZERO,
+            bytes: None,
+            error: Some(error.to_string()),
+        });
+    }
+}
+
+/// Check all backends of the repository, i.e. the repository backend, its mirrors and the hot backend.
+///
+/// # Type Parameters
+///
+/// * `P` - The progress bar type.
+/// * `S` - The state the repository is in.
+///
+/// # Arguments
+///
+/// * `repo` - The repository whose backends are checked
+/// * `opts` - The check options to use
+///
+/// # Returns
+///
+/// The results for each backend
+pub(crate) fn check_backends<P, S>(
+    repo: &Repository<P, S>,
+    opts: &BackendCheckOptions,
+) -> Vec<BackendCheckResult> {
+    repo.be_mirror
+        .backends()
+        .iter()
+        .chain(repo.be_hot.iter())
+        .map(|be| check_backend(be, opts))
+        .collect()
+}
+
+/// Check a backend by writing, listing, reading and removing a probe file.
+///
+/// The probe file is saved as pack file with a random id, so a left-over probe file is removed by `prune`.
+///
+/// # Arguments
+///
+/// * `be` - The backend to check
+/// * `opts` - The check options to use
+pub(crate) fn check_backend(
+    be: &impl WriteBackend,
+    opts: &BackendCheckOptions,
+) -> BackendCheckResult {
+    let location = be.location();
+    info!("checking backend {location}...");
+    let mut steps = Steps(Vec::new());
+    let mut result = BackendCheckResult {
+        location,
+        steps: Vec::new(),
+        ranged_reads: None,
+        overwrite: None,
+        needs_thaw: be.thaw_duration().is_some(),
+    };
+
+    let id = Id::random();
+    // the size of repository files is limited to `u32`
+    let size = u32::try_from(opts.size.as_u64())
+        .unwrap_or(u32::MAX)
+        .max(64);
+    let mut data = vec![0; size as usize];
+    thread_rng().fill_bytes(&mut data);
+    let data = Bytes::from(data);
+
+    if steps
+        .run("write probe file", Some(size.into()), || {
+            be.write_bytes(FileType::Pack, &id, false, data.clone())
+        })
+        .is_none()
+    {
+        // without a probe file, no further operation can be checked
+        result.steps = steps.0;
+        return result;
+    }
+
+    if let Some(list) = steps.run("list pack files", None, || {
+        be.list_with_size(FileType::Pack)
+    }) {
+        match list.iter().find(|(listed, _)| *listed == id) {
+            None => steps.fail("find probe file in listing", "probe file is not listed"),
+            Some((_, listed_size)) if *listed_size != size => steps.fail(
+                "find probe file in listing",
+                &format!("listed size {listed_size} differs from written size {size}"),
+            ),
+            Some(_) => {}
+        }
+    }
+
+    if let Some(read) = steps.run("read probe file", Some(size.into()), || {
+        be.read_full(FileType::Pack, &id)
+    }) {
+        if read != data {
+            steps.fail(
+                "verify probe file",
+                "read contents differ from written contents",
+            );
+        }
+    }
+
+    // a small partial read shows the latency of the backend
+    let offset = size / 2;
+    if let Some(read) = steps.run("read 16 bytes of probe file (latency)", None, || {
+        be.read_partial(FileType::Pack, &id, false, offset, 16)
+    }) {
+        // some servers ignore the requested range and return the complete file
+        let start = offset as usize;
+        result.ranged_reads = Some(read == data.slice(start..start + 16));
+    }
+
+    // failing to overwrite is a property of the backend, e.g. for append-only servers, not an error
+    result.overwrite = steps.run("overwrite probe file", None, || {
+        Ok(be
+            .write_bytes(
+                FileType::Pack,
+                &id,
+                false,
+                Bytes::from_static(b"overwritten probe file"),
+            )
+            .is_ok())
+    });
+
+    if steps
+        .run("remove probe file", None, || {
+            be.remove(FileType::Pack, &id, false)
+        })
+        .is_some()
+        && be.read_full(FileType::Pack, &id).is_ok()
+    {
+        steps.fail(
+            "verify removal",
+            "probe file can still be read after removing it",
+        );
+    }
+
+    result.steps = steps.0;
+    result
+}
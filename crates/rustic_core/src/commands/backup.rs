@@ -1,25 +1,28 @@
 //! `backup` subcommand
 use derive_setters::Setters;
-use log::info;
+use log::{info, warn};
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use path_dedot::ParseDot;
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
 
 use crate::{
-    archiver::{parent::Parent, Archiver},
+    archiver::{control::BackupControl, parent::Parent, Archiver},
     backend::ignore::{LocalSource, LocalSourceFilterOptions, LocalSourceSaveOptions},
-    backend::{dry_run::DryRunBackend, stdin::StdinSource},
+    backend::{choose::ChooseBackend, dry_run::DryRunBackend, stdin::StdinSource},
     error::RusticResult,
     id::Id,
     progress::ProgressBars,
     repofile::snapshotfile::{SnapshotGroup, SnapshotGroupCriterion},
     repofile::{PathList, SnapshotFile},
-    repository::{IndexedIds, IndexedTree, Repository},
+    repository::{IndexedIds, IndexedTree, Open, Repository},
 };
 
+/// Default number of times files which changed while being read are re-read
+const DEFAULT_UNSTABLE_RETRIES: u32 = 2;
+
 /// `backup` subcommand
 #[serde_as]
 #[cfg_attr(feature = "clap", derive(clap::Parser))]
@@ -57,6 +60,16 @@ pub struct ParentOptions {
     #[cfg_attr(feature = "clap", clap(long, conflicts_with = "force",))]
     #[cfg_attr(feature = "merge", merge(strategy = merge::bool::overwrite_false))]
     pub ignore_inode: bool,
+
+    /// Assume all files existing in the parent snapshot are unchanged and only read new files
+    #[cfg_attr(feature = "clap", clap(long, conflicts_with = "force",))]
+    #[cfg_attr(feature = "merge", merge(strategy = merge::bool::overwrite_false))]
+    pub assume_unchanged: bool,
+
+    /// Use the contents of the parent snapshot for files which can't be read but whose size and mtime are unchanged
+    #[cfg_attr(feature = "clap", clap(long, conflicts_with = "force",))]
+    #[cfg_attr(feature = "merge", merge(strategy = merge::bool::overwrite_false))]
+    pub use_parent_if_unreadable: bool,
 }
 
 impl ParentOptions {
@@ -106,6 +119,8 @@ impl ParentOptions {
                 parent_tree,
                 self.ignore_ctime,
                 self.ignore_inode,
+                self.assume_unchanged,
+                self.use_parent_if_unreadable,
             ),
         )
     }
@@ -136,6 +151,20 @@ pub struct BackupOptions {
     #[cfg_attr(feature = "merge", merge(strategy = merge::bool::overwrite_false))]
     pub dry_run: bool,
 
+    /// Number of times to re-read files which changed while being read [default: 2]
+    #[cfg_attr(feature = "clap", clap(long, value_name = "N"))]
+    pub unstable_retries: Option<u32>,
+
+    /// Don't save files which still change after re-reading them
+    #[cfg_attr(feature = "clap", clap(long))]
+    #[cfg_attr(feature = "merge", merge(strategy = merge::bool::overwrite_false))]
+    pub skip_unstable: bool,
+
+    /// Don't exclude the repository and its cache if they are within the backup paths
+    #[cfg_attr(feature = "clap", clap(long))]
+    #[cfg_attr(feature = "merge", merge(strategy = merge::bool::overwrite_false))]
+    pub include_repository: bool,
+
     #[cfg_attr(feature = "clap", clap(flatten))]
     #[serde(flatten)]
     /// Options how to use a parent snapshot
@@ -150,6 +179,12 @@ pub struct BackupOptions {
     #[serde(flatten)]
     /// Options how to filter from a local source
     pub ignore_filter_opts: LocalSourceFilterOptions,
+
+    /// Control to pause and resume the backup while it is running
+    #[cfg_attr(feature = "clap", clap(skip))]
+    #[cfg_attr(feature = "merge", merge(skip))]
+    #[serde(skip)]
+    pub control: BackupControl,
 }
 
 /// Backup data, create a snapshot.
@@ -217,7 +252,17 @@ pub(crate) fn backup<P: ProgressBars, S: IndexedIds>(
 
     let be = DryRunBackend::new(repo.dbe().clone(), opts.dry_run);
     info!("starting to backup {source}...");
-    let archiver = Archiver::new(be, index.clone(), repo.config(), parent, snap)?;
+    let archiver = Archiver::new(
+        be,
+        index.clone(),
+        repo.config(),
+        repo.upload_connections(),
+        parent,
+        snap,
+        opts.unstable_retries.unwrap_or(DEFAULT_UNSTABLE_RETRIES),
+        opts.skip_unstable,
+        opts.control.clone(),
+    )?;
     let p = repo.pb.progress_bytes("determining size...");
 
     let snap = if backup_stdin {
@@ -225,13 +270,66 @@ pub(crate) fn backup<P: ProgressBars, S: IndexedIds>(
         let src = StdinSource::new(path.clone())?;
         archiver.archive(repo.index(), src, path, as_path.as_ref(), &p)?
     } else {
-        let src = LocalSource::new(
-            opts.ignore_save_opts,
-            &opts.ignore_filter_opts,
-            &backup_path,
-        )?;
+        let mut filter_opts = opts.ignore_filter_opts.clone();
+        if !opts.include_repository {
+            filter_opts
+                .excluded_paths
+                .extend(repository_paths_within(repo, &backup_path));
+        }
+        let src = LocalSource::new(opts.ignore_save_opts, &filter_opts, &backup_path)?;
         archiver.archive(repo.index(), src, &backup_path[0], as_path.as_ref(), &p)?
     };
 
     Ok(snap)
 }
+
+/// Find the local directories of the repository which are within the backup paths.
+///
+/// These are the directories of local backends (including the hot repository and mirrors)
+/// and the cache directory. Backing them up would let the repository grow with each backup.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+/// * `backup_paths` - The paths to backup
+///
+/// # Returns
+///
+/// The repository directories as paths within the backup paths, i.e. as found when traversing the backup paths.
+fn repository_paths_within<P, S: Open>(
+    repo: &Repository<P, S>,
+    backup_paths: &[PathBuf],
+) -> Vec<PathBuf> {
+    let mut repo_dirs: Vec<&Path> = repo
+        .be_mirror
+        .backends()
+        .iter()
+        .chain(repo.be_hot.as_ref())
+        .filter_map(|be| match be {
+            ChooseBackend::Local(local) => Some(local.base_path()),
+            _ => None,
+        })
+        .collect();
+    if let Some(cache) = repo.cache() {
+        repo_dirs.push(Path::new(cache.location()));
+    }
+
+    let backup_paths: Vec<_> = backup_paths
+        .iter()
+        .filter_map(|path| Some((path, path.canonicalize().ok()?)))
+        .collect();
+    let mut excluded = Vec::new();
+    for dir in repo_dirs.iter().filter_map(|dir| dir.canonicalize().ok()) {
+        for (path, canonical_path) in &backup_paths {
+            if let Ok(relative) = dir.strip_prefix(canonical_path) {
+                warn!(
+                    "excluding {} which is within the backup path {}, use --include-repository to back it up.",
+                    dir.display(),
+                    path.display()
+                );
+                excluded.push(path.join(relative));
+            }
+        }
+    }
+    excluded
+}
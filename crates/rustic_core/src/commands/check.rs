@@ -1,11 +1,12 @@
 //! `check` subcommand
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Mutex};
 
 use bytes::Bytes;
 use derive_setters::Setters;
 use itertools::Itertools;
 use log::{debug, error, warn};
 use rayon::prelude::{IntoParallelIterator, ParallelBridge, ParallelIterator};
+use serde::Serialize;
 use zstd::stream::decode_all;
 
 use crate::{
@@ -38,6 +39,156 @@ pub struct CheckOptions {
     pub read_data: bool,
 }
 
+/// Codes of the findings of the `check` command
+///
+/// The codes are stable and can be used by automation to triage problems of the repository.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[non_exhaustive]
+pub enum CheckFindingCode {
+    /// A file in the cache could not be read
+    CacheReadError,
+    /// A file in the cache differs from the file in the repository
+    CacheMismatch,
+    /// A file of the repository could not be read
+    ReadError,
+    /// A file in the hot repository does not exist in the repository
+    ExtraHotFile,
+    /// A file of the repository is missing in the hot repository
+    MissingHotFile,
+    /// A file in the hot repository has a different size than in the repository
+    HotFileSizeMismatch,
+    /// A pack marked for deletion has no time set
+    MissingPackTime,
+    /// A blob has a different type than its pack
+    BlobTypeMismatch,
+    /// The offsets of the blobs of a pack in the index are not contiguous
+    BlobOffsetMismatch,
+    /// A pack is not referenced by the index
+    ExtraPack,
+    /// A pack referenced by the index is missing
+    MissingPack,
+    /// The size of a pack differs from the size computed by the index
+    PackSizeMismatch,
+    /// A file in a tree has no content
+    MissingContent,
+    /// A file in a tree references a null blob id
+    NullBlob,
+    /// A file in a tree references a blob which is missing in the index
+    MissingBlob,
+    /// A directory in a tree has no subtree
+    MissingSubtree,
+    /// A directory in a tree references a null subtree id
+    NullSubtree,
+    /// The data of a pack doesn't match the index or is corrupted
+    BrokenPack,
+    /// The data of a blob is corrupted
+    BrokenBlob,
+}
+
+impl CheckFindingCode {
+    /// Returns whether the finding is only a warning, i.e. doesn't indicate a problem of the repository.
+    #[must_use]
+    pub const fn is_warning(self) -> bool {
+        matches!(self, Self::ExtraPack)
+    }
+
+    /// Returns the suggested command to remedy the finding, if any.
+    #[must_use]
+    pub const fn remediation(self) -> Option<&'static str> {
+        match self {
+            Self::CacheReadError | Self::CacheMismatch => {
+                Some("remove the cache directory of the repository")
+            }
+            Self::MissingPackTime => Some("rustic prune"),
+            Self::ExtraPack
+            | Self::MissingPack
+            | Self::PackSizeMismatch
+            | Self::BlobTypeMismatch
+            | Self::BlobOffsetMismatch => Some("rustic repair index"),
+            Self::MissingContent
+            | Self::NullBlob
+            | Self::MissingBlob
+            | Self::MissingSubtree
+            | Self::NullSubtree => Some("rustic repair snapshots"),
+            Self::BrokenPack | Self::BrokenBlob => {
+                Some("remove the pack, then run rustic repair index and rustic repair snapshots")
+            }
+            Self::ReadError
+            | Self::ExtraHotFile
+            | Self::MissingHotFile
+            | Self::HotFileSizeMismatch => None,
+        }
+    }
+}
+
+/// A problem of the repository found by the `check` command
+#[derive(Clone, Debug, Serialize)]
+#[non_exhaustive]
+pub struct CheckFinding {
+    /// The code of the finding
+    pub code: CheckFindingCode,
+    /// Whether the finding is only a warning
+    pub warning: bool,
+    /// The description of the finding
+    pub message: String,
+    /// The ids of the affected files or blobs
+    pub ids: Vec<Id>,
+    /// The suggested command to remedy the finding
+    pub remediation: Option<&'static str>,
+}
+
+/// The results of the `check` command
+#[derive(Clone, Debug, Default, Serialize)]
+#[non_exhaustive]
+pub struct CheckResults {
+    /// The findings in the order they have been found
+    pub findings: Vec<CheckFinding>,
+}
+
+impl CheckResults {
+    /// Returns the number of findings which are errors, i.e. not only warnings.
+    #[must_use]
+    pub fn errors(&self) -> usize {
+        self.findings.iter().filter(|f| !f.warning).count()
+    }
+}
+
+/// Collects the findings of the `check` command, possibly from several threads.
+#[derive(Debug, Default)]
+struct Findings(Mutex<Vec<CheckFinding>>);
+
+impl Findings {
+    /// Log and save a finding.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The code of the finding
+    /// * `ids` - The ids of the affected files or blobs
+    /// * `message` - The description of the finding
+    fn add(&self, code: CheckFindingCode, ids: &[Id], message: String) {
+        if code.is_warning() {
+            warn!("{message}");
+        } else {
+            error!("{message}");
+        }
+        self.0.lock().unwrap().push(CheckFinding {
+            code,
+            warning: code.is_warning(),
+            message,
+            ids: ids.to_vec(),
+            remediation: code.remediation(),
+        });
+    }
+
+    /// Returns the collected findings.
+    fn into_results(self) -> CheckResults {
+        CheckResults {
+            findings: self.0.into_inner().unwrap(),
+        }
+    }
+}
+
 impl CheckOptions {
     /// Runs the `check` command
     ///
@@ -53,7 +204,15 @@ impl CheckOptions {
     /// # Errors
     ///
     /// If the repository is corrupted
-    pub(crate) fn run<P: ProgressBars, S: Open>(self, repo: &Repository<P, S>) -> RusticResult<()> {
+    ///
+    /// # Returns
+    ///
+    /// The findings of the check
+    pub(crate) fn run<P: ProgressBars, S: Open>(
+        self,
+        repo: &Repository<P, S>,
+    ) -> RusticResult<CheckResults> {
+        let findings = Findings::default();
         let be = repo.dbe();
         let cache = repo.cache();
         let hot_be = &repo.be_hot;
@@ -70,18 +229,18 @@ impl CheckOptions {
 
                     let p = pb.progress_bytes(format!("checking {file_type:?} in cache..."));
                     // TODO: Make concurrency (20) customizable
-                    check_cache_files(20, cache, raw_be, file_type, &p)?;
+                    check_cache_files(20, cache, raw_be, file_type, &p, &findings)?;
                 }
             }
         }
 
         if let Some(hot_be) = hot_be {
             for file_type in [FileType::Snapshot, FileType::Index] {
-                check_hot_files(raw_be, hot_be, file_type, pb)?;
+                check_hot_files(raw_be, hot_be, file_type, pb, &findings)?;
             }
         }
 
-        let index_collector = check_packs(be, hot_be, self.read_data, pb)?;
+        let index_collector = check_packs(be, hot_be, self.read_data, pb, &findings)?;
 
         if let Some(cache) = &cache {
             let p = pb.progress_spinner("cleaning up packs from cache...");
@@ -91,7 +250,7 @@ impl CheckOptions {
             if !self.trust_cache {
                 let p = pb.progress_bytes("checking packs in cache...");
                 // TODO: Make concurrency (5) customizable
-                check_cache_files(5, cache, raw_be, FileType::Pack, &p)?;
+                check_cache_files(5, cache, raw_be, FileType::Pack, &p, &findings)?;
             }
         }
 
@@ -106,9 +265,20 @@ impl CheckOptions {
                 .map(|(_, size)| u64::from(*size))
                 .sum::<u64>();
 
+        if self.read_data {
+            // packs in archive storage need to be thawed before they can be read
+            let packs: Vec<_> = index_collector
+                .data_packs()
+                .iter()
+                .chain(index_collector.tree_packs())
+                .map(|(id, _)| *id)
+                .collect();
+            repo.warm_up_wait(packs.into_iter())?;
+        }
+
         let index_be = IndexBackend::new_from_index(be, index_collector.into_index());
 
-        check_snapshots(&index_be, pb)?;
+        check_snapshots(&index_be, pb, &findings)?;
 
         if self.read_data {
             let p = pb.progress_bytes("reading pack data...");
@@ -121,14 +291,18 @@ impl CheckOptions {
                 .for_each_with((be.clone(), p.clone()), |(be, p), pack| {
                     let id = pack.id;
                     let data = be.read_full(FileType::Pack, &id).unwrap();
-                    match check_pack(be, pack, data, p) {
+                    match check_pack(be, pack, data, p, &findings) {
                         Ok(()) => {}
-                        Err(err) => error!("Error reading pack {id} : {err}",),
+                        Err(err) => findings.add(
+                            CheckFindingCode::ReadError,
+                            &[id],
+                            format!("Error reading pack {id} : {err}"),
+                        ),
                     }
                 });
             p.finish();
         }
-        Ok(())
+        Ok(findings.into_results())
     }
 }
 
@@ -140,6 +314,7 @@ impl CheckOptions {
 /// * `be_hot` - The hot backend to check
 /// * `file_type` - The type of the files to check
 /// * `pb` - The progress bar to use
+/// * `findings` - The findings to add to
 ///
 /// # Errors
 ///
@@ -149,6 +324,7 @@ fn check_hot_files(
     be_hot: &impl ReadBackend,
     file_type: FileType,
     pb: &impl ProgressBars,
+    findings: &Findings,
 ) -> RusticResult<()> {
     let p = pb.progress_spinner(format!("checking {file_type:?} in hot repo..."));
     let mut files = be
@@ -160,17 +336,26 @@ fn check_hot_files(
 
     for (id, size_hot) in files_hot {
         match files.remove(&id) {
-            None => error!("hot file Type: {file_type:?}, Id: {id} does not exist in repo"),
-            Some(size) if size != size_hot => {
-                // TODO: This should be an actual error not a log entry
-                error!("Type: {file_type:?}, Id: {id}: hot size: {size_hot}, actual size: {size}");
-            }
+            None => findings.add(
+                CheckFindingCode::ExtraHotFile,
+                &[id],
+                format!("hot file Type: {file_type:?}, Id: {id} does not exist in repo"),
+            ),
+            Some(size) if size != size_hot => findings.add(
+                CheckFindingCode::HotFileSizeMismatch,
+                &[id],
+                format!("Type: {file_type:?}, Id: {id}: hot size: {size_hot}, actual size: {size}"),
+            ),
             _ => {} //everything ok
         }
     }
 
     for (id, _) in files {
-        error!("hot file Type: {file_type:?}, Id: {id} is missing!",);
+        findings.add(
+            CheckFindingCode::MissingHotFile,
+            &[id],
+            format!("hot file Type: {file_type:?}, Id: {id} is missing!"),
+        );
     }
     p.finish();
 
@@ -186,6 +371,7 @@ fn check_hot_files(
 /// * `be` - The backend to check
 /// * `file_type` - The type of the files to check
 /// * `p` - The progress bar to use
+/// * `findings` - The findings to add to
 ///
 /// # Errors
 ///
@@ -196,6 +382,7 @@ fn check_cache_files(
     be: &impl ReadBackend,
     file_type: FileType,
     p: &impl Progress,
+    findings: &Findings,
 ) -> RusticResult<()> {
     let files = cache.list_with_size(file_type)?;
 
@@ -214,17 +401,23 @@ fn check_cache_files(
                 cache.read_full(file_type, &id),
                 be.read_full(file_type, &id),
             ) {
-                (Err(err), _) => {
-                    error!("Error reading cached file Type: {file_type:?}, Id: {id} : {err}");
-                }
-                (_, Err(err)) => {
-                    error!("Error reading file Type: {file_type:?}, Id: {id} : {err}");
-                }
-                (Ok(data_cached), Ok(data)) if data_cached != data => {
-                    error!(
+                (Err(err), _) => findings.add(
+                    CheckFindingCode::CacheReadError,
+                    &[id],
+                    format!("Error reading cached file Type: {file_type:?}, Id: {id} : {err}"),
+                ),
+                (_, Err(err)) => findings.add(
+                    CheckFindingCode::ReadError,
+                    &[id],
+                    format!("Error reading file Type: {file_type:?}, Id: {id} : {err}"),
+                ),
+                (Ok(data_cached), Ok(data)) if data_cached != data => findings.add(
+                    CheckFindingCode::CacheMismatch,
+                    &[id],
+                    format!(
                         "Cached file Type: {file_type:?}, Id: {id} is not identical to backend!"
-                    );
-                }
+                    ),
+                ),
                 (Ok(_), Ok(_)) => {} // everything ok
             }
 
@@ -243,6 +436,7 @@ fn check_cache_files(
 /// * `hot_be` - The hot backend to check
 /// * `read_data` - Whether to read the data of the packs
 /// * `pb` - The progress bar to use
+/// * `findings` - The findings to add to
 ///
 /// # Errors
 ///
@@ -256,6 +450,7 @@ fn check_packs(
     hot_be: &Option<impl ReadBackend>,
     read_data: bool,
     pb: &impl ProgressBars,
+    findings: &Findings,
 ) -> RusticResult<IndexCollector> {
     let mut packs = HashMap::new();
     let mut tree_packs = HashMap::new();
@@ -275,7 +470,11 @@ fn check_packs(
 
         // Check if time is set _
         if check_time && p.time.is_none() {
-            error!("pack {}: No time is set! Run prune to correct this!", p.id);
+            findings.add(
+                CheckFindingCode::MissingPackTime,
+                &[p.id],
+                format!("pack {}: No time is set! Run prune to correct this!", p.id),
+            );
         }
 
         // check offsests in index
@@ -284,16 +483,24 @@ fn check_packs(
         blobs.sort_unstable();
         for blob in blobs {
             if blob.tpe != blob_type {
-                error!(
-                    "pack {}: blob {} blob type does not match: type: {:?}, expected: {:?}",
-                    p.id, blob.id, blob.tpe, blob_type
+                findings.add(
+                    CheckFindingCode::BlobTypeMismatch,
+                    &[p.id, blob.id],
+                    format!(
+                        "pack {}: blob {} blob type does not match: type: {:?}, expected: {:?}",
+                        p.id, blob.id, blob.tpe, blob_type
+                    ),
                 );
             }
 
             if blob.offset != expected_offset {
-                error!(
-                    "pack {}: blob {} offset in index: {}, expected: {}",
-                    p.id, blob.id, blob.offset, expected_offset
+                findings.add(
+                    CheckFindingCode::BlobOffsetMismatch,
+                    &[p.id, blob.id],
+                    format!(
+                        "pack {}: blob {} offset in index: {}, expected: {}",
+                        p.id, blob.id, blob.offset, expected_offset
+                    ),
                 );
             }
             expected_offset += blob.length;
@@ -316,12 +523,12 @@ fn check_packs(
 
     if let Some(hot_be) = hot_be {
         let p = pb.progress_spinner("listing packs in hot repo...");
-        check_packs_list(hot_be, tree_packs)?;
+        check_packs_list(hot_be, tree_packs, findings)?;
         p.finish();
     }
 
     let p = pb.progress_spinner("listing packs...");
-    check_packs_list(be, packs)?;
+    check_packs_list(be, packs, findings)?;
     p.finish();
 
     Ok(index_collector)
@@ -334,23 +541,26 @@ fn check_packs(
 ///
 /// * `be` - The backend to check
 /// * `packs` - The packs to check
+/// * `findings` - The findings to add to
 ///
 /// # Errors
 ///
 /// If a pack is missing or has a different size
-fn check_packs_list(be: &impl ReadBackend, mut packs: HashMap<Id, u32>) -> RusticResult<()> {
+fn check_packs_list(
+    be: &impl ReadBackend,
+    mut packs: HashMap<Id, u32>,
+    findings: &Findings,
+) -> RusticResult<()> {
     for (id, size) in be.list_with_size(FileType::Pack)? {
         match packs.remove(&id) {
-            None => warn!("pack {id} not referenced in index. Can be a parallel backup job. To repair: 'rustic repair index'."),
-            Some(index_size) if index_size != size => {
-                error!("pack {id}: size computed by index: {index_size}, actual size: {size}. To repair: 'rustic repair index'.");
-            }
+            None => findings.add(CheckFindingCode::ExtraPack, &[id], format!("pack {id} not referenced in index. Can be a parallel backup job. To repair: 'rustic repair index'.")),
+            Some(index_size) if index_size != size => findings.add(CheckFindingCode::PackSizeMismatch, &[id], format!("pack {id}: size computed by index: {index_size}, actual size: {size}. To repair: 'rustic repair index'.")),
             _ => {} //everything ok
         }
     }
 
     for (id, _) in packs {
-        error!("pack {id} is referenced by the index but not present! To repair: 'rustic repair index'.",);
+        findings.add(CheckFindingCode::MissingPack, &[id], format!("pack {id} is referenced by the index but not present! To repair: 'rustic repair index'."));
     }
     Ok(())
 }
@@ -361,11 +571,16 @@ fn check_packs_list(be: &impl ReadBackend, mut packs: HashMap<Id, u32>) -> Rusti
 ///
 /// * `index` - The index to check
 /// * `pb` - The progress bar to use
+/// * `findings` - The findings to add to
 ///
 /// # Errors
 ///
 /// If a snapshot or tree is missing or has a different size
-fn check_snapshots(index: &impl IndexedBackend, pb: &impl ProgressBars) -> RusticResult<()> {
+fn check_snapshots(
+    index: &impl IndexedBackend,
+    pb: &impl ProgressBars,
+    findings: &Findings,
+) -> RusticResult<()> {
     let p = pb.progress_counter("reading snapshots...");
     let snap_trees: Vec<_> = index
         .be()
@@ -383,19 +598,35 @@ fn check_snapshots(index: &impl IndexedBackend, pb: &impl ProgressBars) -> Rusti
             match node.node_type {
                 NodeType::File => node.content.as_ref().map_or_else(
                     || {
-                        error!("file {:?} doesn't have a content", path.join(node.name()));
+                        findings.add(
+                            CheckFindingCode::MissingContent,
+                            &[],
+                            format!("file {:?} doesn't have a content", path.join(node.name())),
+                        );
                     },
                     |content| {
                         for (i, id) in content.iter().enumerate() {
                             if id.is_null() {
-                                error!("file {:?} blob {} has null ID", path.join(node.name()), i);
+                                findings.add(
+                                    CheckFindingCode::NullBlob,
+                                    &[],
+                                    format!(
+                                        "file {:?} blob {} has null ID",
+                                        path.join(node.name()),
+                                        i
+                                    ),
+                                );
                             }
 
                             if !index.has_data(id) {
-                                error!(
-                                    "file {:?} blob {} is missing in index",
-                                    path.join(node.name()),
-                                    id
+                                findings.add(
+                                    CheckFindingCode::MissingBlob,
+                                    &[*id],
+                                    format!(
+                                        "file {:?} blob {} is missing in index",
+                                        path.join(node.name()),
+                                        id
+                                    ),
                                 );
                             }
                         }
@@ -404,12 +635,16 @@ fn check_snapshots(index: &impl IndexedBackend, pb: &impl ProgressBars) -> Rusti
 
                 NodeType::Dir => {
                     match node.subtree {
-                        None => {
-                            error!("dir {:?} subtree does not exist", path.join(node.name()));
-                        }
-                        Some(tree) if tree.is_null() => {
-                            error!("dir {:?} subtree has null ID", path.join(node.name()));
-                        }
+                        None => findings.add(
+                            CheckFindingCode::MissingSubtree,
+                            &[],
+                            format!("dir {:?} subtree does not exist", path.join(node.name())),
+                        ),
+                        Some(tree) if tree.is_null() => findings.add(
+                            CheckFindingCode::NullSubtree,
+                            &[],
+                            format!("dir {:?} subtree has null ID", path.join(node.name())),
+                        ),
                         _ => {} // subtree is ok
                     }
                 }
@@ -430,6 +665,7 @@ fn check_snapshots(index: &impl IndexedBackend, pb: &impl ProgressBars) -> Rusti
 /// * `index_pack` - The pack to check
 /// * `data` - The data of the pack
 /// * `p` - The progress bar to use
+/// * `findings` - The findings to add to
 ///
 /// # Errors
 ///
@@ -443,20 +679,29 @@ fn check_pack(
     index_pack: IndexPack,
     mut data: Bytes,
     p: &impl Progress,
+    findings: &Findings,
 ) -> RusticResult<()> {
     let id = index_pack.id;
     let size = index_pack.pack_size();
     if data.len() != size as usize {
-        error!(
-            "pack {id}: data size does not match expected size. Read: {} bytes, expected: {size} bytes",
-            data.len()
+        findings.add(
+            CheckFindingCode::BrokenPack,
+            &[id],
+            format!(
+                "pack {id}: data size does not match expected size. Read: {} bytes, expected: {size} bytes",
+                data.len()
+            ),
         );
         return Ok(());
     }
 
     let comp_id = hash(&data);
     if id != comp_id {
-        error!("pack {id}: Hash mismatch. Computed hash: {comp_id}");
+        findings.add(
+            CheckFindingCode::BrokenPack,
+            &[id],
+            format!("pack {id}: Hash mismatch. Computed hash: {comp_id}"),
+        );
         return Ok(());
     }
 
@@ -464,7 +709,7 @@ fn check_pack(
     let header_len = PackHeaderRef::from_index_pack(&index_pack).size();
     let pack_header_len = PackHeaderLength::from_binary(&data.split_off(data.len() - 4))?.to_u32();
     if pack_header_len != header_len {
-        error!("pack {id}: Header length in pack file doesn't match index. In pack: {pack_header_len}, calculated: {header_len}");
+        findings.add(CheckFindingCode::BrokenPack, &[id], format!("pack {id}: Header length in pack file doesn't match index. In pack: {pack_header_len}, calculated: {header_len}"));
         return Ok(());
     }
 
@@ -475,7 +720,11 @@ fn check_pack(
     let mut blobs = index_pack.blobs;
     blobs.sort_unstable_by_key(|b| b.offset);
     if pack_blobs != blobs {
-        error!("pack {id}: Header from pack file does not match the index");
+        findings.add(
+            CheckFindingCode::BrokenPack,
+            &[id],
+            format!("pack {id}: Header from pack file does not match the index"),
+        );
         debug!("pack file header: {pack_blobs:?}");
         debug!("index: {:?}", blobs);
         return Ok(());
@@ -491,14 +740,18 @@ fn check_pack(
         if let Some(length) = blob.uncompressed_length {
             blob_data = decode_all(&*blob_data).unwrap();
             if blob_data.len() != length.get() as usize {
-                error!("pack {id}, blob {blob_id}: Actual uncompressed length does not fit saved uncompressed length");
+                findings.add(CheckFindingCode::BrokenBlob, &[id, blob_id], format!("pack {id}, blob {blob_id}: Actual uncompressed length does not fit saved uncompressed length"));
                 return Ok(());
             }
         }
 
         let comp_id = hash(&blob_data);
         if blob.id != comp_id {
-            error!("pack {id}, blob {blob_id}: Hash mismatch. Computed hash: {comp_id}");
+            findings.add(
+                CheckFindingCode::BrokenBlob,
+                &[id, blob_id],
+                format!("pack {id}, blob {blob_id}: Hash mismatch. Computed hash: {comp_id}"),
+            );
             return Ok(());
         }
         p.inc(blob.length.into());
@@ -1,10 +1,14 @@
 //! `config` subcommand
+use std::{num::ParseIntError, str::FromStr};
+
 use bytesize::ByteSize;
 use derive_setters::Setters;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
 
 use crate::{
     backend::decrypt::{DecryptBackend, DecryptWriteBackend},
-    crypto::aespoly1305::Key,
+    crypto::{aespoly1305::Key, Cipher},
     error::CommandErrorKind,
     error::RusticResult,
     repofile::ConfigFile,
@@ -27,6 +31,7 @@ use crate::{
 ///
 /// * [`CommandErrorKind::VersionNotSupported`] - If the version is not supported
 /// * [`CommandErrorKind::CannotDowngrade`] - If the version is lower than the current version
+/// * [`CommandErrorKind::CannotChangeCipher`] - If the cipher would be changed
 /// * [`CommandErrorKind::NoCompressionV1Repo`] - If compression is set for a v1 repo
 /// * [`CommandErrorKind::CompressionLevelNotSupported`] - If the compression level is not supported
 /// * [`CommandErrorKind::SizeTooLarge`] - If the size is too large
@@ -42,6 +47,11 @@ pub(crate) fn apply_config<P, S: Open>(
     opts: &ConfigOptions,
 ) -> RusticResult<bool> {
     let mut new_config = repo.config().clone();
+    // existing data can't be read with another cipher
+    let cipher = repo.config().cipher();
+    if let Some(new_cipher) = opts.set_cipher.filter(|new_cipher| *new_cipher != cipher) {
+        return Err(CommandErrorKind::CannotChangeCipher(cipher, new_cipher).into());
+    }
     opts.apply(&mut new_config)?;
     if &new_config == repo.config() {
         Ok(false)
@@ -73,6 +83,11 @@ pub(crate) fn save_config<P, S>(
     key: Key,
 ) -> RusticResult<()> {
     new_config.is_hot = None;
+    // the config file of unencrypted repositories must be readable without any key
+    let key = match new_config.cipher() {
+        Cipher::None => key.with_cipher(Cipher::None),
+        _ => key,
+    };
     // don't compress the config file
     let mut dbe = DecryptBackend::new(&repo.be, key);
     dbe.set_zstd(None);
@@ -90,8 +105,11 @@ pub(crate) fn save_config<P, S>(
     Ok(())
 }
 
+#[serde_as]
 #[cfg_attr(feature = "clap", derive(clap::Parser))]
-#[derive(Debug, Clone, Copy, Default, Setters)]
+#[cfg_attr(feature = "merge", derive(merge::Merge))]
+#[derive(Debug, Clone, Copy, Default, Setters, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
 #[setters(into)]
 /// Options for the `config` command, used to set repository-wide options
 pub struct ConfigOptions {
@@ -108,12 +126,14 @@ pub struct ConfigOptions {
     /// Note that for large repos, this value is grown by the grown factor.
     /// Defaults to `4 MiB` if not set.
     #[cfg_attr(feature = "clap", clap(long, value_name = "SIZE"))]
+    #[serde_as(as = "Option<DisplayFromStr>")]
     pub set_treepack_size: Option<ByteSize>,
 
     /// Set upper limit for default packsize for tree packs.
     /// Note that packs actually can get up to some MiBs larger.
     /// If not set, pack sizes can grow up to approximately `4 GiB`.
     #[cfg_attr(feature = "clap", clap(long, value_name = "SIZE"))]
+    #[serde_as(as = "Option<DisplayFromStr>")]
     pub set_treepack_size_limit: Option<ByteSize>,
 
     /// Set grow factor for tree packs. The default packsize grows by the square root of the total size of all
@@ -127,6 +147,7 @@ pub struct ConfigOptions {
     /// Note that for large repos, this value is grown by the grown factor.
     /// Defaults to `32 MiB` if not set.
     #[cfg_attr(feature = "clap", clap(long, value_name = "SIZE"))]
+    #[serde_as(as = "Option<DisplayFromStr>")]
     pub set_datapack_size: Option<ByteSize>,
 
     /// Set grow factor for data packs. The default packsize grows by the square root of the total size of all
@@ -140,6 +161,7 @@ pub struct ConfigOptions {
     /// Note that packs actually can get up to some MiBs larger.
     /// If not set, pack sizes can grow up to approximately `4 GiB`.
     #[cfg_attr(feature = "clap", clap(long, value_name = "SIZE"))]
+    #[serde_as(as = "Option<DisplayFromStr>")]
     pub set_datapack_size_limit: Option<ByteSize>,
 
     /// Set minimum tolerated packsize in percent of the targeted packsize.
@@ -152,9 +174,76 @@ pub struct ConfigOptions {
     /// tolerated. Default if not set: larger packfiles are always tolerated.
     #[cfg_attr(feature = "clap", clap(long, value_name = "PERCENT"))]
     pub set_max_packsize_tolerate_percent: Option<u32>,
+
+    /// Set the cipher used to encrypt the repository data. This can only be set when initializing the
+    /// repository. Other ciphers than the default set the repository version to 3, which restic can't read.
+    #[cfg_attr(feature = "clap", clap(long, value_name = "CIPHER", value_enum))]
+    pub set_cipher: Option<Cipher>,
+
+    /// Pad pack files to a multiple of this size (e.g. 1MiB) to hide their exact sizes from the storage.
+    /// A value of `0` disables padding. Default if not set: packs are not padded.
+    /// Enabling padding sets the repository version to 3, which restic can't read. Padded packs are only
+    /// accepted by `repair index` while padding is enabled.
+    #[cfg_attr(feature = "clap", clap(long, value_name = "SIZE"))]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub set_pack_padding: Option<ByteSize>,
+
+    /// Save the trees of directories with more than 50000 entries in several tree blobs. Enabling this
+    /// sets the repository version to 3, which restic can't read. Default if not set: trees are not sharded.
+    #[cfg_attr(feature = "clap", clap(long, value_name = "BOOL"))]
+    pub set_shard_trees: Option<bool>,
 }
 
 impl ConfigOptions {
+    /// Set the option given by its config key to the given value
+    ///
+    /// The key is the name of the corresponding field of the [`ConfigFile`], e.g. `compression`
+    /// or `datapack-size`. Both `-` and `_` are accepted as separator.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The config key to set
+    /// * `value` - The value to set
+    ///
+    /// # Errors
+    ///
+    /// * [`CommandErrorKind::UnknownConfigKey`] - If the key is unknown or cannot be set
+    /// * [`CommandErrorKind::FromParseIntError`] - If the value is not a valid number
+    /// * [`CommandErrorKind::FromByteSizeParser`] - If the value is not a valid size
+    /// * [`CommandErrorKind::FromParseBoolError`] - If the value is not `true` or `false`
+    ///
+    /// # Note
+    ///
+    /// The value is only parsed here. It is validated when the options are applied to a [`ConfigFile`].
+    pub fn set_option(&mut self, key: &str, value: &str) -> RusticResult<()> {
+        match key.replace('_', "-").as_str() {
+            "version" => self.set_version = Some(parse_int(value)?),
+            "compression" => self.set_compression = Some(parse_int(value)?),
+            "treepack-size" => self.set_treepack_size = Some(parse_size(value)?),
+            "treepack-size-limit" => self.set_treepack_size_limit = Some(parse_size(value)?),
+            "treepack-growfactor" => self.set_treepack_growfactor = Some(parse_int(value)?),
+            "datapack-size" => self.set_datapack_size = Some(parse_size(value)?),
+            "datapack-size-limit" => self.set_datapack_size_limit = Some(parse_size(value)?),
+            "datapack-growfactor" => self.set_datapack_growfactor = Some(parse_int(value)?),
+            "min-packsize-tolerate-percent" => {
+                self.set_min_packsize_tolerate_percent = Some(parse_int(value)?);
+            }
+            "max-packsize-tolerate-percent" => {
+                self.set_max_packsize_tolerate_percent = Some(parse_int(value)?);
+            }
+            "pack-padding" => self.set_pack_padding = Some(parse_size(value)?),
+            "shard-trees" => {
+                self.set_shard_trees = Some(
+                    value
+                        .parse()
+                        .map_err(CommandErrorKind::FromParseBoolError)?,
+                );
+            }
+            _ => return Err(CommandErrorKind::UnknownConfigKey(key.to_string()).into()),
+        }
+        Ok(())
+    }
+
     /// Apply the [`ConfigOptions`] to a given [`ConfigFile`]
     ///
     /// # Arguments
@@ -166,6 +255,8 @@ impl ConfigOptions {
     /// * [`CommandErrorKind::VersionNotSupported`] - If the version is not supported
     /// * [`CommandErrorKind::CannotDowngrade`] - If the version is lower than the current version
     /// * [`CommandErrorKind::NoCompressionV1Repo`] - If compression is set for a v1 repo
+    /// * [`CommandErrorKind::CipherNeedsVersion2`] - If a non-default cipher is set for a v1 repo
+    /// * [`CommandErrorKind::ShardTreesNeedVersion2`] - If sharded trees are enabled for a v1 repo
     /// * [`CommandErrorKind::CompressionLevelNotSupported`] - If the compression level is not supported
     /// * [`CommandErrorKind::SizeTooLarge`] - If the size is too large
     /// * [`CommandErrorKind::MinPackSizeTolerateWrong`] - If the min packsize tolerate percent is wrong
@@ -244,6 +335,56 @@ impl ConfigOptions {
             config.max_packsize_tolerate_percent = Some(percent);
         }
 
+        if let Some(cipher) = self.set_cipher {
+            // don't save the default to stay compatible with restic
+            config.cipher = (cipher != Cipher::default()).then_some(cipher);
+            if config.cipher.is_some() {
+                if config.version < 2 {
+                    return Err(CommandErrorKind::CipherNeedsVersion2(cipher).into());
+                }
+                // clients not knowing the cipher would write files using the default cipher
+                config.version = config.version.max(ConfigFile::EXTENDED_VERSION);
+            }
+        }
+
+        if let Some(size) = self.set_pack_padding {
+            let size: u32 = size
+                .as_u64()
+                .try_into()
+                .map_err(|_| CommandErrorKind::SizeTooLarge(size))?;
+            config.pack_padding = (size > 0).then_some(size);
+            if size > 0 {
+                if config.version < 2 {
+                    return Err(CommandErrorKind::PackPaddingNeedsVersion2.into());
+                }
+                // restic would treat padded packs as damaged
+                config.version = config.version.max(ConfigFile::EXTENDED_VERSION);
+            }
+        }
+
+        if let Some(shard_trees) = self.set_shard_trees {
+            config.shard_trees = shard_trees.then_some(true);
+            if shard_trees {
+                if config.version < 2 {
+                    return Err(CommandErrorKind::ShardTreesNeedVersion2.into());
+                }
+                // restic only sees the first shard and its prune would remove the other shards
+                config.version = config.version.max(ConfigFile::EXTENDED_VERSION);
+            }
+        }
+
         Ok(())
     }
 }
+
+/// Parse an integer config value
+fn parse_int<T: FromStr<Err = ParseIntError>>(value: &str) -> RusticResult<T> {
+    Ok(value.parse().map_err(CommandErrorKind::FromParseIntError)?)
+}
+
+/// Parse a size config value
+fn parse_size(value: &str) -> RusticResult<ByteSize> {
+    Ok(value
+        .parse()
+        .map_err(CommandErrorKind::FromByteSizeParser)?)
+}
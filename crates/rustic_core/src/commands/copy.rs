@@ -61,6 +61,7 @@ pub(crate) fn copy<'a, Q, R: IndexedFull, P: ProgressBars, S: IndexedIds>(
         indexer.clone(),
         repo_dest.config(),
         index.total_size(BlobType::Data),
+        repo_dest.upload_connections(),
     )?;
     let tree_packer = Packer::new(
         be_dest.clone(),
@@ -68,6 +69,7 @@ pub(crate) fn copy<'a, Q, R: IndexedFull, P: ProgressBars, S: IndexedIds>(
         indexer.clone(),
         repo_dest.config(),
         index.total_size(BlobType::Tree),
+        repo_dest.upload_connections(),
     )?;
 
     let p = pb.progress_counter("copying blobs in snapshots...");
@@ -88,6 +90,13 @@ pub(crate) fn copy<'a, Q, R: IndexedFull, P: ProgressBars, S: IndexedIds>(
         .par_bridge()
         .try_for_each(|item| -> RusticResult<_> {
             let (_, tree) = item?;
+            for id in &tree.shards {
+                trace!("copy tree shard blob {id}");
+                if !index_dest.has_tree(id) {
+                    let data = index.get_tree(id).unwrap().read_data(index.be())?;
+                    tree_packer.add(data, *id)?;
+                }
+            }
             tree.nodes.par_iter().try_for_each(|node| {
                 match node.node_type {
                     NodeType::File => {
@@ -150,15 +159,16 @@ pub(crate) fn relevant_snapshots<F, P: ProgressBars, S: Open>(
     filter: F,
 ) -> RusticResult<Vec<CopySnapshot>>
 where
-    F: FnMut(&SnapshotFile) -> bool,
+    F: Fn(&SnapshotFile) -> bool + Sync,
 {
     let p = dest_repo
         .pb
         .progress_counter("finding relevant snapshots...");
     // save snapshots in destination in BTreeSet, as we want to efficiently search within to filter out already existing snapshots before copying.
-    let snapshots_dest: BTreeSet<_> = SnapshotFile::all_from_backend(dest_repo.dbe(), filter, &p)?
-        .into_iter()
-        .collect();
+    let snapshots_dest: BTreeSet<_> =
+        SnapshotFile::all_from_backend(dest_repo.dbe(), filter, dest_repo.list_connections(), &p)?
+            .into_iter()
+            .collect();
 
     let relevant = snaps
         .iter()
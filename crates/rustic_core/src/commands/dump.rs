@@ -1,9 +1,12 @@
-use std::io::Write;
+use std::io::{self, Read, Write};
+
+use bytes::{Buf, Bytes};
 
 use crate::{
     backend::node::{Node, NodeType},
     blob::BlobType,
     error::{CommandErrorKind, RusticResult},
+    id::Id,
     index::IndexedBackend,
     repository::{IndexedFull, IndexedTree, Repository},
 };
@@ -40,3 +43,66 @@ pub(crate) fn dump<P, S: IndexedFull>(
     }
     Ok(())
 }
+
+/// A [`Read`]er which streams the contents of a file [`Node`] blob by blob.
+///
+/// Only the blob which is currently read is held in memory.
+///
+/// # Type Parameters
+///
+/// * `P` - The progress bar type.
+/// * `S` - The type of the indexed tree.
+#[derive(Debug)]
+pub struct FileReader<'a, P, S> {
+    /// The repository to read from
+    repo: &'a Repository<P, S>,
+    /// The ids of the data blobs which are not yet read
+    ids: std::vec::IntoIter<Id>,
+    /// The remaining data of the current blob
+    data: Bytes,
+}
+
+impl<'a, P, S: IndexedFull> FileReader<'a, P, S> {
+    /// Create a new [`FileReader`] for the given node.
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The repository to read from.
+    /// * `node` - The node to read.
+    ///
+    /// # Errors
+    ///
+    /// * [`CommandErrorKind::DumpNotSupported`] - If the node is not a file.
+    pub(crate) fn new(repo: &'a Repository<P, S>, node: &Node) -> RusticResult<Self> {
+        if node.node_type != NodeType::File {
+            return Err(CommandErrorKind::DumpNotSupported(node.node_type.clone()).into());
+        }
+
+        Ok(Self {
+            repo,
+            ids: node.content.clone().unwrap_or_default().into_iter(),
+            data: Bytes::new(),
+        })
+    }
+}
+
+impl<P, S: IndexedFull> Read for FileReader<'_, P, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.data.is_empty() {
+            let id = match self.ids.next() {
+                Some(id) => id,
+                None => return Ok(0),
+            };
+            self.data = self
+                .repo
+                .index()
+                .blob_from_backend(BlobType::Data, &id)
+                .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        }
+
+        let len = buf.len().min(self.data.len());
+        buf[..len].copy_from_slice(&self.data[..len]);
+        self.data.advance(len);
+        Ok(len)
+    }
+}
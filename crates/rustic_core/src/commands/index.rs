@@ -0,0 +1,181 @@
+//! `index` subcommand
+use derive_setters::Setters;
+use log::info;
+
+use std::collections::HashSet;
+
+use crate::{
+    backend::{
+        decrypt::{DecryptReadBackend, DecryptWriteBackend},
+        FileType,
+    },
+    error::RusticResult,
+    index::indexer::Indexer,
+    progress::{Progress, ProgressBars},
+    repofile::{IndexFile, IndexPack},
+    repository::{Open, Repository},
+};
+
+pub(super) mod constants {
+    /// Index files with less entries than this are considered too small and will be compacted
+    pub(super) const MIN_INDEX_LEN: usize = 10_000;
+}
+
+#[cfg_attr(feature = "clap", derive(clap::Parser))]
+#[derive(Default, Debug, Clone, Copy, Setters)]
+#[setters(into)]
+#[non_exhaustive]
+/// Options for the `index compact` command
+pub struct CompactIndexOptions {
+    /// Rewrite all index files, not only the ones which are too small
+    #[cfg_attr(feature = "clap", clap(long))]
+    pub all: bool,
+}
+
+impl CompactIndexOptions {
+    /// Runs the `index compact` command
+    ///
+    /// This merges all index files which are too small (or all index files, if `all` is set)
+    /// into as few index files as possible. The new index files are saved before the old ones
+    /// are removed, so the repository always contains a complete index.
+    ///
+    /// # Type Parameters
+    ///
+    /// * `P` - The progress bar type
+    /// * `S` - The state the repository is in
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The repository to compact the index of
+    /// * `dry_run` - Whether to actually modify the repository or just print what would be done
+    ///
+    /// # Returns
+    ///
+    /// The number of index files which have been (or would have been) compacted
+    pub(crate) fn compact<P: ProgressBars, S: Open>(
+        self,
+        repo: &Repository<P, S>,
+        dry_run: bool,
+    ) -> RusticResult<usize> {
+        let be = repo.dbe();
+
+        let p = repo.pb.progress_counter("reading index...");
+        let mut index_files: Vec<_> = be
+            .stream_all::<IndexFile>(&p)?
+            .into_iter()
+            .collect::<RusticResult<_>>()?;
+        p.finish();
+
+        index_files.retain(|(_, index)| self.all || index_len(index) < constants::MIN_INDEX_LEN);
+
+        if index_files.len() < 2 {
+            info!("index is already compact, nothing to do.");
+            return Ok(0);
+        }
+
+        let count = index_files.len();
+        if dry_run {
+            info!("would have compacted {count} index files.");
+            return Ok(count);
+        }
+
+        let (ids, index_files): (Vec<_>, Vec<_>) = index_files.into_iter().unzip();
+        let (packs, packs_to_delete) = merge_packs(index_files);
+        let mut indexer = Indexer::new_unindexed(be.clone());
+        for pack in packs {
+            indexer.add(pack)?;
+        }
+        for pack in packs_to_delete {
+            indexer.add_remove(pack)?;
+        }
+        indexer.finalize()?;
+
+        let p = repo.pb.progress_counter("removing old index files...");
+        be.delete_list(FileType::Index, true, ids.iter(), p)?;
+        info!("compacted {count} index files.");
+
+        Ok(count)
+    }
+}
+
+/// Merges the packs of the given index files, removing duplicate packs.
+///
+/// A pack which is marked for deletion in one of the index files stays marked, even if it is also
+/// contained in the used packs of another index file.
+///
+/// # Arguments
+///
+/// * `index_files` - The index files to merge
+///
+/// # Returns
+///
+/// The used packs and the packs marked for deletion
+fn merge_packs(index_files: Vec<IndexFile>) -> (Vec<IndexPack>, Vec<IndexPack>) {
+    let mut packs_to_delete = Vec::new();
+    let mut marked = HashSet::new();
+    for index in &index_files {
+        for pack in &index.packs_to_delete {
+            if marked.insert(pack.id) {
+                packs_to_delete.push(pack.clone());
+            }
+        }
+    }
+
+    let mut packs = Vec::new();
+    let mut processed = HashSet::new();
+    for pack in index_files.into_iter().flat_map(|index| index.packs) {
+        if !marked.contains(&pack.id) && processed.insert(pack.id) {
+            packs.push(pack);
+        }
+    }
+    (packs, packs_to_delete)
+}
+
+/// Returns the number of blobs contained in an index file
+fn index_len(index: &IndexFile) -> usize {
+    index
+        .packs
+        .iter()
+        .chain(&index.packs_to_delete)
+        .map(|p| p.blobs.len())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::id::Id;
+
+    fn pack(id: Id) -> IndexPack {
+        IndexPack {
+            id,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn merge_packs_keeps_delete_marks() {
+        let (id1, id2, id3) = (Id::random(), Id::random(), Id::random());
+        let index_files = vec![
+            IndexFile {
+                packs: vec![pack(id1), pack(id2)],
+                ..Default::default()
+            },
+            IndexFile {
+                packs: vec![pack(id1), pack(id3)],
+                packs_to_delete: vec![pack(id2)],
+                ..Default::default()
+            },
+            IndexFile {
+                packs_to_delete: vec![pack(id2), pack(id3)],
+                ..Default::default()
+            },
+        ];
+
+        let (packs, packs_to_delete) = merge_packs(index_files);
+        let packs: Vec<_> = packs.iter().map(|pack| pack.id).collect();
+        let packs_to_delete: Vec<_> = packs_to_delete.iter().map(|pack| pack.id).collect();
+        assert_eq!(packs, vec![id1]);
+        assert_eq!(packs_to_delete, vec![id2, id3]);
+    }
+}
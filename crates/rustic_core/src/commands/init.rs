@@ -7,7 +7,7 @@ use crate::{
     chunker::random_poly,
     commands::config::{save_config, ConfigOptions},
     commands::key::KeyOptions,
-    crypto::aespoly1305::Key,
+    crypto::{aespoly1305::Key, Cipher},
     error::RusticResult,
     id::Id,
     repofile::ConfigFile,
@@ -63,7 +63,7 @@ pub(crate) fn init<P, S>(
 /// # Arguments
 ///
 /// * `repo` - The repository to initialize.
-/// * `pass` - The password to encrypt the key with.
+/// * `pass` - The password to encrypt the key with, not used for unencrypted repositories.
 /// * `key_opts` - The options to create the key with.
 /// * `config` - The config to use.
 ///
@@ -77,8 +77,14 @@ pub(crate) fn init_with_config<P, S>(
     config: &ConfigFile,
 ) -> RusticResult<Key> {
     repo.be.create()?;
-    let (key, id) = key_opts.init_key(repo, pass)?;
-    info!("key {id} successfully added.");
+    let key = if config.cipher() == Cipher::None {
+        // unencrypted repositories don't use any key
+        Key::default()
+    } else {
+        let (key, id) = key_opts.init_key(repo, pass)?;
+        info!("key {id} successfully added.");
+        key
+    };
     save_config(repo, config.clone(), key)?;
 
     Ok(key)
@@ -0,0 +1,280 @@
+//! Journaled removal of repository files to be able to complete interrupted removals
+use std::{collections::HashSet, path::Path};
+
+use chrono::{DateTime, Duration, Local};
+use gethostname::gethostname;
+use itertools::Itertools;
+use log::{debug, warn};
+
+use crate::{
+    backend::{
+        decrypt::{DecryptReadBackend, DecryptWriteBackend},
+        FileType,
+    },
+    commands::trash::read_trash,
+    error::RusticResult,
+    id::Id,
+    progress::{Progress, ProgressBars},
+    repofile::{IndexFile, JournalFile},
+    repository::{Open, Repository},
+};
+
+pub(super) mod constants {
+    /// Minimum age of a journal before it is completed if it can't be checked whether its process is still running
+    pub(super) const MIN_AGE_HOURS: i64 = 24;
+}
+
+/// Remove the files listed in the given journal.
+///
+/// The journal is saved before removing any file and removed after all files have been removed.
+/// If the backend doesn't support the journal, the files are removed without it.
+///
+/// # Type Parameters
+///
+/// * `P` - The progress bar type.
+/// * `S` - The state the repository is in.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to remove the files from
+/// * `journal` - The files to remove
+pub(crate) fn remove_journaled<P: ProgressBars, S: Open>(
+    repo: &Repository<P, S>,
+    journal: &JournalFile,
+) -> RusticResult<()> {
+    if journal.is_empty() {
+        return Ok(());
+    }
+    let be = repo.dbe();
+    // not all backends support the journal, e.g. REST servers only accept the file types used by restic
+    let id = match be.save_file(journal) {
+        Ok(id) => Some(id),
+        Err(err) => {
+            warn!("cannot save journal, removing files without it: {err}");
+            None
+        }
+    };
+    remove_files(repo, journal)?;
+    if let Some(id) = id {
+        be.remove(FileType::Journal, &id, false)?;
+    }
+    Ok(())
+}
+
+/// Complete all removals which have been interrupted, i.e. whose journal is still present.
+///
+/// # Type Parameters
+///
+/// * `P` - The progress bar type.
+/// * `S` - The state the repository is in.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+///
+/// # Notes
+///
+/// Not all backends support the journal. If the journal cannot be listed, it is treated as empty.
+///
+/// A journal is only completed if the process which saved it is not running anymore. If this can't
+/// be checked, e.g. for journals from other hosts, the journal must be older than one day.
+/// Files which have changed since the journal was saved are kept, i.e. snapshots which have been
+/// undeleted from the trash and packs which are used by an index file not listed in the journal.
+pub(crate) fn complete_interrupted<P: ProgressBars, S: Open>(
+    repo: &Repository<P, S>,
+) -> RusticResult<()> {
+    let be = repo.dbe();
+    let list = match be.list(FileType::Journal) {
+        Ok(list) => list,
+        Err(err) => {
+            debug!("cannot list journal, assuming it is empty: {err}");
+            return Ok(());
+        }
+    };
+    if list.is_empty() {
+        return Ok(());
+    }
+
+    let p = repo.pb.progress_counter("reading journal...");
+    let journals: Vec<(Id, JournalFile)> = be.stream_list(list, &p)?.into_iter().try_collect()?;
+    p.finish();
+
+    let now = Local::now();
+    for (journal_id, mut journal) in journals {
+        if !is_interrupted(&journal, now) {
+            warn!(
+                "found {} started at {} by process {} on {}, which may still be running. Skipping it.",
+                journal.operation, journal.time, journal.pid, journal.hostname
+            );
+            continue;
+        }
+        warn!(
+            "completing interrupted {} started at {}...",
+            journal.operation, journal.time
+        );
+        // files which have already been removed must be skipped
+        retain_existing(be, FileType::Snapshot, &mut [&mut journal.snapshots])?;
+        retain_existing(be, FileType::Trash, &mut [&mut journal.trash])?;
+        retain_existing(be, FileType::Index, &mut [&mut journal.index])?;
+        retain_existing(
+            be,
+            FileType::Pack,
+            &mut [&mut journal.data_packs, &mut journal.tree_packs],
+        )?;
+        if journal.trashed {
+            retain_trashed(repo, &mut journal.snapshots)?;
+        }
+        retain_unused_packs(
+            repo,
+            &journal.index,
+            &mut [&mut journal.data_packs, &mut journal.tree_packs],
+        )?;
+
+        remove_files(repo, &journal)?;
+        be.remove(FileType::Journal, &journal_id, false)?;
+    }
+    Ok(())
+}
+
+/// Check whether the removal of the given journal has been interrupted, i.e. its process is not running anymore
+///
+/// If this can't be checked, the journal is treated as interrupted if it is older than [`constants::MIN_AGE_HOURS`].
+fn is_interrupted(journal: &JournalFile, now: DateTime<Local>) -> bool {
+    if journal.hostname == gethostname().to_string_lossy() {
+        if let Some(running) = process_is_running(journal.pid) {
+            return !running;
+        }
+    }
+    now - journal.time > Duration::hours(constants::MIN_AGE_HOURS)
+}
+
+/// Check whether the process with the given id is running. Returns `None` if this can't be checked.
+fn process_is_running(pid: u32) -> Option<bool> {
+    let proc = Path::new("/proc");
+    proc.is_dir().then(|| proc.join(pid.to_string()).exists())
+}
+
+/// Only keep the snapshots which are still in the trash, i.e. which have not been undeleted
+fn retain_trashed<P: ProgressBars, S: Open>(
+    repo: &Repository<P, S>,
+    snapshots: &mut Vec<Id>,
+) -> RusticResult<()> {
+    if snapshots.is_empty() {
+        return Ok(());
+    }
+    let p = repo.pb.progress_counter("reading trash...");
+    let trashed: HashSet<_> = read_trash(repo.dbe(), &p)?
+        .into_iter()
+        .map(|(_, trash)| trash.snapshot.id)
+        .collect();
+    p.finish();
+    snapshots.retain(|id| {
+        let keep = trashed.contains(id);
+        if !keep {
+            warn!("snapshot {id} is not in the trash anymore, keeping it.");
+        }
+        keep
+    });
+    Ok(())
+}
+
+/// Only keep the packs which are not used by any index file except the ones removed by the journal
+fn retain_unused_packs<P: ProgressBars, S: Open>(
+    repo: &Repository<P, S>,
+    index_remove: &[Id],
+    lists: &mut [&mut Vec<Id>],
+) -> RusticResult<()> {
+    if lists.iter().all(|ids| ids.is_empty()) {
+        return Ok(());
+    }
+    let be = repo.dbe();
+    let index_remove: HashSet<_> = index_remove.iter().collect();
+    let list = be
+        .list(FileType::Index)?
+        .into_iter()
+        .filter(|id| !index_remove.contains(id))
+        .collect();
+
+    let p = repo.pb.progress_counter("reading index...");
+    let mut used = HashSet::new();
+    for index in be.stream_list::<IndexFile>(list, &p)? {
+        let (_, index) = index?;
+        used.extend(index.packs.into_iter().map(|pack| pack.id));
+    }
+    p.finish();
+
+    for ids in lists {
+        ids.retain(|id| {
+            let keep = !used.contains(id);
+            if !keep {
+                warn!("pack {id} is used by the index, keeping it.");
+            }
+            keep
+        });
+    }
+    Ok(())
+}
+
+/// Only keep the ids of files of the given type which are still present in the backend
+fn retain_existing(
+    be: &impl DecryptReadBackend,
+    tpe: FileType,
+    lists: &mut [&mut Vec<Id>],
+) -> RusticResult<()> {
+    if lists.iter().all(|ids| ids.is_empty()) {
+        return Ok(());
+    }
+    let existing: HashSet<_> = be.list(tpe)?.into_iter().collect();
+    for ids in lists {
+        ids.retain(|id| existing.contains(id));
+    }
+    Ok(())
+}
+
+/// Remove the files listed in the given journal
+fn remove_files<P: ProgressBars, S: Open>(
+    repo: &Repository<P, S>,
+    journal: &JournalFile,
+) -> RusticResult<()> {
+    let be = repo.dbe();
+    let lists = [
+        (
+            FileType::Snapshot,
+            true,
+            &journal.snapshots,
+            "removing snapshots...",
+        ),
+        (
+            FileType::Trash,
+            false,
+            &journal.trash,
+            "removing expired snapshots from trash...",
+        ),
+        // remove old index files first as they may reference pack files which are removed soon.
+        (
+            FileType::Index,
+            true,
+            &journal.index,
+            "removing old index files...",
+        ),
+        (
+            FileType::Pack,
+            false,
+            &journal.data_packs,
+            "removing old data packs...",
+        ),
+        (
+            FileType::Pack,
+            true,
+            &journal.tree_packs,
+            "removing old tree packs...",
+        ),
+    ];
+    for (tpe, cacheable, ids, title) in lists {
+        if !ids.is_empty() {
+            let p = repo.pb.progress_counter(title);
+            be.delete_list(tpe, cacheable, ids.iter(), p)?;
+        }
+    }
+    Ok(())
+}
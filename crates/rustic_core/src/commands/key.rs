@@ -1,15 +1,23 @@
 //! `key` subcommand
+use bytes::Bytes;
 use derive_setters::Setters;
+use zeroize::Zeroizing;
 
 use crate::{
-    backend::{FileType, WriteBackend},
+    backend::{FileType, ReadBackend, WriteBackend},
     crypto::aespoly1305::Key,
     crypto::hasher::hash,
+    crypto::kms::Kms,
+    crypto::shamir,
+    crypto::Cipher,
     error::CommandErrorKind,
-    error::RusticResult,
+    error::{KeyFileErrorKind, RusticResult},
     id::Id,
-    repofile::KeyFile,
-    repository::{Open, Repository},
+    repofile::{
+        keyfile::{find_key_in_backend, key_from_shares, KeyShare},
+        KeyDerivation, KeyFile, KeyRestriction, ScryptOptions,
+    },
+    repository::{Open, Repository, RepositoryOptions},
 };
 
 #[cfg_attr(feature = "clap", derive(clap::Parser))]
@@ -25,12 +33,176 @@ pub struct KeyOptions {
     #[cfg_attr(feature = "clap", clap(long))]
     pub username: Option<String>,
 
+    /// Set 'label' in public key information, e.g. to describe which machine or person the key belongs to
+    #[cfg_attr(feature = "clap", clap(long))]
+    pub label: Option<String>,
+
     /// Add 'created' date in public key information
     #[cfg_attr(feature = "clap", clap(long))]
     pub with_created: bool,
+
+    /// Key derivation function to use for the new key
+    #[cfg_attr(
+        feature = "clap",
+        clap(long, value_name = "KDF", value_enum, default_value = "scrypt")
+    )]
+    pub kdf: KeyDerivation,
+
+    /// Parameters of `scrypt`
+    #[cfg_attr(feature = "clap", clap(flatten))]
+    pub scrypt: ScryptOptions,
+
+    /// Additionally require the connected FIDO2 security key (with hmac-secret extension) to unlock the new key
+    #[cfg_attr(feature = "clap", clap(long))]
+    pub key_fido2: bool,
+
+    /// Restrict the operations allowed when the repository is opened with the new key. This is only
+    /// checked by the client and doesn't protect against a compromised host
+    #[cfg_attr(feature = "clap", clap(long, value_name = "RESTRICTION", value_enum))]
+    pub restriction: Option<KeyRestriction>,
+
+    /// Wrap the master key by this KMS instead of encrypting it with a password
+    #[cfg_attr(
+        feature = "clap",
+        clap(
+            long,
+            value_name = "KMS",
+            value_enum,
+            requires = "kms_key_id",
+            conflicts_with = "key_fido2"
+        )
+    )]
+    pub kms: Option<Kms>,
+
+    /// Id of the key within the KMS, e.g. the ARN for AWS KMS, `<mount>/keys/<name>` for Vault or the hex id
+    /// of the key pair for PKCS#11
+    #[cfg_attr(feature = "clap", clap(long, value_name = "ID", requires = "kms"))]
+    pub kms_key_id: Option<String>,
+
+    /// Address of the KMS, e.g. the Vault server [default: $VAULT_ADDR] or the absolute path of the PKCS#11
+    /// module and the slot as `<module>#<slot>` [default: $PKCS11_MODULE]
+    #[cfg_attr(feature = "clap", clap(long, value_name = "URL", requires = "kms"))]
+    pub kms_address: Option<String>,
+
+    /// Refuse passwords for the new key whose estimated strength is below this score
+    /// (0: too guessable, ..., 4: very unguessable)
+    #[cfg_attr(
+        feature = "clap",
+        clap(long, value_name = "SCORE", value_parser = clap::value_parser!(u8).range(0..=4))
+    )]
+    pub min_password_score: Option<u8>,
+}
+
+/// Strength of a password as estimated by `zxcvbn`
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct PasswordStrength {
+    /// Score from 0 (too guessable) to 4 (very unguessable)
+    pub score: u8,
+    /// Estimated number of guesses needed to find the password, as base-10 logarithm
+    pub guesses_log10: f64,
+    /// Warning explaining why the password is weak
+    pub warning: Option<String>,
+    /// Suggestions to make the password stronger
+    pub suggestions: Vec<String>,
+}
+
+impl PasswordStrength {
+    /// Estimate the strength of a password.
+    ///
+    /// This needs rustic to be compiled with the feature `zxcvbn`.
+    ///
+    /// # Arguments
+    ///
+    /// * `password` - The password to estimate
+    /// * `user_inputs` - Words which are easy to guess for an attacker, e.g. the hostname or username
+    #[cfg(feature = "zxcvbn")]
+    #[must_use]
+    pub fn estimate(password: &str, user_inputs: &[&str]) -> Self {
+        match zxcvbn::zxcvbn(password, user_inputs) {
+            Ok(entropy) => {
+                let feedback = entropy.feedback().as_ref();
+                Self {
+                    score: entropy.score(),
+                    guesses_log10: entropy.guesses_log10(),
+                    warning: feedback
+                        .and_then(zxcvbn::feedback::Feedback::warning)
+                        .map(|warning| warning.to_string()),
+                    suggestions: feedback
+                        .map(|feedback| {
+                            feedback
+                                .suggestions()
+                                .iter()
+                                .map(ToString::to_string)
+                                .collect()
+                        })
+                        .unwrap_or_default(),
+                }
+            }
+            // only empty passwords can't be estimated
+            Err(_) => Self {
+                score: 0,
+                guesses_log10: 0.0,
+                warning: Some("The password is empty.".to_string()),
+                suggestions: Vec::new(),
+            },
+        }
+    }
 }
 
 impl KeyOptions {
+    /// Estimate the strength of a password for a new key.
+    ///
+    /// The hostname, username and label of the new key are considered to be known by an attacker.
+    ///
+    /// # Arguments
+    ///
+    /// * `pass` - The password to estimate
+    ///
+    /// # Returns
+    ///
+    /// The estimated strength or `None` if rustic was compiled without the feature `zxcvbn`
+    #[must_use]
+    pub fn password_strength(&self, pass: &str) -> Option<PasswordStrength> {
+        #[cfg(feature = "zxcvbn")]
+        {
+            let user_inputs: Vec<_> = [&self.hostname, &self.username, &self.label]
+                .into_iter()
+                .flatten()
+                .map(String::as_str)
+                .collect();
+            Some(PasswordStrength::estimate(pass, &user_inputs))
+        }
+        #[cfg(not(feature = "zxcvbn"))]
+        {
+            _ = pass;
+            None
+        }
+    }
+
+    /// Check that a password for a new key satisfies the minimum score, if given.
+    ///
+    /// # Arguments
+    ///
+    /// * `pass` - The password to check
+    ///
+    /// # Errors
+    ///
+    /// * [`KeyFileErrorKind::PasswordTooWeak`] - If the score of the password is below the minimum score
+    /// * [`KeyFileErrorKind::PasswordStrengthNotSupported`] - If a minimum score is given, but rustic was compiled without the feature `zxcvbn`
+    pub fn check_password(&self, pass: &str) -> RusticResult<()> {
+        if let Some(min) = self.min_password_score {
+            let score = self
+                .password_strength(pass)
+                .ok_or(KeyFileErrorKind::PasswordStrengthNotSupported)?
+                .score;
+            if score < min {
+                return Err(KeyFileErrorKind::PasswordTooWeak(score, min).into());
+            }
+        }
+        Ok(())
+    }
+
     /// Add the current key to the repository.
     ///
     /// # Type Parameters
@@ -45,6 +217,7 @@ impl KeyOptions {
     ///
     /// # Errors
     ///
+    /// * [`CommandErrorKind::RepositoryNotEncrypted`] - If the repository is not encrypted.
     /// * [`CommandErrorKind::FromJsonError`] - If the key could not be serialized.
     ///
     /// # Returns
@@ -55,10 +228,59 @@ impl KeyOptions {
         repo: &Repository<P, S>,
         pass: &str,
     ) -> RusticResult<Id> {
+        if repo.config().cipher() == Cipher::None {
+            return Err(CommandErrorKind::RepositoryNotEncrypted.into());
+        }
         let key = repo.key();
         self.add(repo, pass, *key)
     }
 
+    /// Replace the key file matching `old_pass` by a new key file for the same master key.
+    ///
+    /// # Type Parameters
+    ///
+    /// * `P` - The progress bar type.
+    /// * `S` - The state the repository is in.
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The repository to rotate the key of.
+    /// * `old_pass` - The password of the key file to replace.
+    /// * `new_pass` - The password to encrypt the new key with.
+    ///
+    /// # Errors
+    ///
+    /// * [`KeyFileErrorKind::NoSuitableKeyFound`] - If no key file matches `old_pass`.
+    /// * [`CommandErrorKind::KeyMismatch`] - If a key file doesn't contain the master key of the repository.
+    ///
+    /// # Returns
+    ///
+    /// The ids of the removed and the new key file.
+    pub(crate) fn rotate_key<P, S: Open>(
+        &self,
+        repo: &Repository<P, S>,
+        old_pass: &str,
+        new_pass: &str,
+    ) -> RusticResult<(Id, Id)> {
+        let master_key = repo.key().to_keys();
+        let (old_id, old_key) = find_key_in_backend(&repo.be, &old_pass, None)?;
+        if old_key.to_keys() != master_key {
+            return Err(CommandErrorKind::KeyMismatch(old_id).into());
+        }
+
+        let new_id = self.add(repo, new_pass, *repo.key())?;
+        // check that the new key can be used before removing the old one
+        let (_, new_key) = find_key_in_backend(&repo.be, &new_pass, Some(&new_id))?;
+        if new_key.to_keys() != master_key {
+            return Err(CommandErrorKind::KeyMismatch(new_id).into());
+        }
+
+        if new_id != old_id {
+            repo.be.remove(FileType::Key, &old_id, false)?;
+        }
+        Ok((old_id, new_id))
+    }
+
     /// Initialize a new key.
     ///
     /// # Type Parameters
@@ -89,24 +311,265 @@ impl KeyOptions {
     /// # Arguments
     ///
     /// * `repo` - The repository to add the key to.
-    /// * `pass` - The password to encrypt the key with.
+    /// * `pass` - The password to encrypt the key with. Not used if the key is wrapped by a KMS.
     /// * `key` - The key to add.
     ///
     /// # Errors
     ///
     /// * [`CommandErrorKind::FromJsonError`] - If the key could not be serialized.
+    /// * [`BackendErrorKind::NotAllowedByKeyRestriction`] - If the repository has been opened with a restricted key.
     ///
     /// # Returns
     ///
     /// The id of the key.
-    fn add<P, S>(&self, repo: &Repository<P, S>, pass: &str, key: Key) -> RusticResult<Id> {
+    ///
+    /// [`BackendErrorKind::NotAllowedByKeyRestriction`]: crate::error::BackendErrorKind::NotAllowedByKeyRestriction
+    pub(crate) fn add<P, S>(
+        &self,
+        repo: &Repository<P, S>,
+        pass: &str,
+        key: Key,
+    ) -> RusticResult<Id> {
         let ko = self.clone();
-        let keyfile = KeyFile::generate(key, &pass, ko.hostname, ko.username, ko.with_created)?;
+        let keyfile = match ko.kms {
+            Some(kms) => {
+                let key_id = ko.kms_key_id.ok_or(KeyFileErrorKind::KmsKeyIdMissing)?;
+                let wrapper = kms.wrapper(key_id, ko.kms_address)?;
+                KeyFile::generate_wrapped(
+                    key,
+                    wrapper.as_ref(),
+                    ko.hostname,
+                    ko.username,
+                    ko.with_created,
+                )?
+            }
+            None => {
+                ko.check_password(pass)?;
+                KeyFile::generate(
+                    key,
+                    &pass,
+                    ko.kdf,
+                    ko.scrypt,
+                    ko.key_fido2,
+                    ko.hostname,
+                    ko.username,
+                    ko.with_created,
+                )?
+            }
+        };
+        let keyfile = KeyFile {
+            label: ko.label,
+            restriction: ko.restriction,
+            ..keyfile
+        };
 
-        let data = serde_json::to_vec(&keyfile).map_err(CommandErrorKind::FromJsonError)?;
-        let id = hash(&data);
-        repo.be
-            .write_bytes(FileType::Key, &id, false, data.into())?;
-        Ok(id)
+        save_keyfile(repo, &keyfile)
     }
+
+    /// Split the master key into shares which are saved as key files, each encrypted with its own password.
+    ///
+    /// # Type Parameters
+    ///
+    /// * `P` - The progress bar type.
+    /// * `S` - The state the repository is in.
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The repository to split the key of.
+    /// * `passwords` - The passwords of the shares, one for each share.
+    /// * `threshold` - The number of shares needed to recover the master key.
+    /// * `remove_keys` - Whether to remove all key files containing the whole master key.
+    ///
+    /// # Errors
+    ///
+    /// * [`KeyFileErrorKind::InvalidShareThreshold`] - If the threshold or the number of shares is invalid.
+    /// * [`CommandErrorKind::KeyMismatch`] - If the master key can't be recovered from the new shares.
+    ///
+    /// # Returns
+    ///
+    /// The ids of the key files containing the shares.
+    pub(crate) fn split_key<P, S: Open>(
+        &self,
+        repo: &Repository<P, S>,
+        passwords: &[String],
+        threshold: u8,
+        remove_keys: bool,
+    ) -> RusticResult<Vec<Id>> {
+        let n = match u8::try_from(passwords.len()) {
+            Ok(n) if threshold >= 2 && threshold <= n => n,
+            _ => {
+                return Err(
+                    KeyFileErrorKind::InvalidShareThreshold(threshold, passwords.len()).into(),
+                )
+            }
+        };
+        for pass in passwords {
+            self.check_password(pass)?;
+        }
+        let (encrypt, k, r) = repo.key().to_keys();
+        let (encrypt, k, r) = (
+            Zeroizing::new(encrypt),
+            Zeroizing::new(k),
+            Zeroizing::new(r),
+        );
+        let secret = Zeroizing::new([&encrypt[..], &k[..], &r[..]].concat());
+        let set = Id::random();
+        let ids = shamir::split(&secret, threshold, n)
+            .into_iter()
+            .zip(passwords)
+            .map(|((index, data), pass)| {
+                let ko = self.clone();
+                let share = KeyShare {
+                    set,
+                    threshold,
+                    index,
+                };
+                let keyfile = KeyFile {
+                    label: ko.label,
+                    ..KeyFile::generate_share(
+                        share,
+                        data,
+                        pass,
+                        ko.kdf,
+                        ko.scrypt,
+                        ko.key_fido2,
+                        ko.hostname,
+                        ko.username,
+                        ko.with_created,
+                    )?
+                };
+                save_keyfile(repo, &keyfile)
+            })
+            .collect::<RusticResult<Vec<_>>>()?;
+
+        // check that the master key can be recovered before removing any key
+        let key = key_from_shares(&repo.be, &passwords[..usize::from(threshold)])?;
+        if key.to_keys() != repo.key().to_keys() {
+            return Err(CommandErrorKind::KeyMismatch(ids[0]).into());
+        }
+
+        if remove_keys {
+            for (id, keyfile) in repo.get_keys()? {
+                if keyfile.share.is_none() {
+                    repo.be.remove(FileType::Key, &id, false)?;
+                }
+            }
+        }
+        Ok(ids)
+    }
+}
+
+/// Export a key file of the repository, e.g. to back it up out-of-band.
+///
+/// The key file is exported as-is, so it stays compatible with restic.
+///
+/// # Type Parameters
+///
+/// * `P` - The progress bar type.
+/// * `S` - The state the repository is in.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to export the key file from.
+/// * `id` - The id of the key file; parts of ids are resolved.
+///
+/// # Errors
+///
+/// * [`BackendErrorKind::NoSuitableIdFound`] - If no key file could be found.
+/// * [`KeyFileErrorKind::DeserializingFromSliceFailed`] - If the key file is no valid key file.
+///
+/// # Returns
+///
+/// The id and the contents of the key file.
+///
+/// [`BackendErrorKind::NoSuitableIdFound`]: crate::error::BackendErrorKind::NoSuitableIdFound
+pub(crate) fn export_key<P, S>(repo: &Repository<P, S>, id: &str) -> RusticResult<(Id, Bytes)> {
+    let id = repo.be.find_id(FileType::Key, id)?;
+    let data = repo.be.read_full(FileType::Key, &id)?;
+    // make sure not to export anything else than a key file
+    _ = serde_json::from_slice::<KeyFile>(&data)
+        .map_err(KeyFileErrorKind::DeserializingFromSliceFailed)?;
+    Ok((id, data))
+}
+
+/// Import a key file, e.g. one exported from another repository sharing the same master key.
+///
+/// The key file is only saved if it can be decrypted with the given password and contains the master
+/// key of the repository. Shares of the master key are only checked to be decryptable.
+///
+/// # Type Parameters
+///
+/// * `P` - The progress bar type.
+/// * `S` - The state the repository is in.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to import the key file into.
+/// * `data` - The contents of the key file.
+/// * `pass` - The password of the key file. Not used if the key is wrapped by a KMS.
+/// * `repo_opts` - The repository options giving the KMS to unwrap a wrapped key file with.
+///
+/// # Errors
+///
+/// * [`CommandErrorKind::RepositoryNotEncrypted`] - If the repository is not encrypted.
+/// * [`KeyFileErrorKind::KmsMissing`] - If the key file is wrapped by a KMS, but no KMS is configured.
+/// * [`KeyFileErrorKind::DeserializingFromSliceFailed`] - If the data is no valid key file.
+/// * [`BackendErrorKind::NotAllowedByKeyRestriction`] - If the repository has been opened with a restricted key.
+/// * [`CommandErrorKind::KeyMismatch`] - If the key file doesn't contain the master key of the repository.
+///
+/// # Returns
+///
+/// The id of the imported key file.
+///
+/// [`BackendErrorKind::NotAllowedByKeyRestriction`]: crate::error::BackendErrorKind::NotAllowedByKeyRestriction
+pub(crate) fn import_key<P, S: Open>(
+    repo: &Repository<P, S>,
+    data: &[u8],
+    pass: &str,
+    repo_opts: &RepositoryOptions,
+) -> RusticResult<Id> {
+    if repo.config().cipher() == Cipher::None {
+        return Err(CommandErrorKind::RepositoryNotEncrypted.into());
+    }
+    let keyfile: KeyFile =
+        serde_json::from_slice(data).map_err(KeyFileErrorKind::DeserializingFromSliceFailed)?;
+    // keep the key file as-is, so its id doesn't change
+    let id = hash(data);
+    let key = if keyfile.share.is_some() {
+        _ = keyfile.share_from_password(&pass)?;
+        None
+    } else if keyfile.wrapped.is_some() {
+        // only unwrap using the locally configured KMS
+        Some(keyfile.key_from_wrapper(repo_opts.kms_wrapper()?.as_ref())?)
+    } else {
+        Some(keyfile.key_from_password(&pass)?)
+    };
+    if key.map_or(false, |key| key.to_keys() != repo.key().to_keys()) {
+        return Err(CommandErrorKind::KeyMismatch(id).into());
+    }
+    repo.be
+        .write_bytes(FileType::Key, &id, false, data.to_vec().into())?;
+    Ok(id)
+}
+
+/// Save a key file to the repository.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to save the key file to.
+/// * `keyfile` - The key file to save.
+///
+/// # Errors
+///
+/// * [`CommandErrorKind::FromJsonError`] - If the key could not be serialized.
+///
+/// # Returns
+///
+/// The id of the key file.
+fn save_keyfile<P, S>(repo: &Repository<P, S>, keyfile: &KeyFile) -> RusticResult<Id> {
+    let data = serde_json::to_vec(keyfile).map_err(CommandErrorKind::FromJsonError)?;
+    let id = hash(&data);
+    repo.be
+        .write_bytes(FileType::Key, &id, false, data.into())?;
+    Ok(id)
 }
@@ -97,12 +97,19 @@ pub(crate) fn merge_trees<P: ProgressBars, S: IndexedTree>(
         indexer.clone(),
         repo.config(),
         index.total_size(BlobType::Tree),
+        repo.upload_connections(),
     )?;
     let save = |tree: Tree| {
-        let (chunk, new_id) = tree.serialize()?;
-        let size = u64::try_from(chunk.len()).map_err(CommandErrorKind::ConversionToU64Failed)?;
+        let tree = tree.serialize(repo.config().shard_trees())?;
+        let new_id = tree.id;
+        let size = u64::try_from(tree.len()).map_err(CommandErrorKind::ConversionToU64Failed)?;
         if !index.has_tree(&new_id) {
-            packer.add(chunk.into(), new_id)?;
+            for (chunk, shard_id) in tree.shards {
+                if !index.has_tree(&shard_id) {
+                    packer.add(chunk.into(), shard_id)?;
+                }
+            }
+            packer.add(tree.chunk.into(), new_id)?;
         }
         Ok((new_id, size))
     };
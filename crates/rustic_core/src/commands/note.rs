@@ -0,0 +1,120 @@
+//! Notes attached to snapshots, packs or keys
+use chrono::Local;
+use gethostname::gethostname;
+use log::debug;
+
+use crate::{
+    backend::{
+        decrypt::{DecryptReadBackend, DecryptWriteBackend},
+        FileType, ReadBackend,
+    },
+    error::{CommandErrorKind, RusticResult},
+    id::Id,
+    progress::{Progress, ProgressBars},
+    repofile::NoteFile,
+    repository::{Open, Repository},
+};
+
+/// The file types notes can be attached to, in the order they are searched for a given id
+const NOTE_TARGETS: [FileType; 3] = [FileType::Snapshot, FileType::Key, FileType::Pack];
+
+/// Add a note to the snapshot, pack or key with the given id.
+///
+/// # Type Parameters
+///
+/// * `P` - The progress bar type.
+/// * `S` - The state the repository is in.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+/// * `tpe` - The type of the file to attach the note to. If not given, snapshots, keys and packs are searched.
+/// * `target` - The id of the file to attach the note to; parts of ids are resolved
+/// * `text` - The text of the note
+///
+/// # Errors
+///
+/// * [`CommandErrorKind::NoteTargetNotSupported`] - If notes can't be attached to the given file type
+/// * [`CommandErrorKind::NoteTargetNotFound`] - If no file is found for the given id
+///
+/// # Returns
+///
+/// The id of the saved note
+pub(crate) fn add_note<P, S: Open>(
+    repo: &Repository<P, S>,
+    tpe: Option<FileType>,
+    target: &str,
+    text: &str,
+) -> RusticResult<Id> {
+    let types = match tpe {
+        Some(tpe) if !NOTE_TARGETS.contains(&tpe) => {
+            return Err(CommandErrorKind::NoteTargetNotSupported(tpe).into())
+        }
+        Some(tpe) => vec![tpe],
+        None => NOTE_TARGETS.to_vec(),
+    };
+    let be = repo.dbe();
+    let (tpe, target) = types
+        .into_iter()
+        .find_map(|tpe| match be.find_id(tpe, target) {
+            Ok(id) => Some((tpe, id)),
+            Err(err) => {
+                debug!("no {tpe:?} found for {target}: {err}");
+                None
+            }
+        })
+        .ok_or_else(|| CommandErrorKind::NoteTargetNotFound(target.to_string()))?;
+
+    let note = NoteFile {
+        time: Local::now(),
+        hostname: gethostname().to_str().map(ToString::to_string),
+        tpe,
+        target,
+        text: text.to_string(),
+    };
+    be.save_file(&note)
+}
+
+/// Read all notes.
+///
+/// # Arguments
+///
+/// * `be` - The backend to read from
+/// * `p` - The progress bar to use
+///
+/// # Notes
+///
+/// If the notes cannot be listed, e.g. because no note has been added yet, no notes are returned.
+pub(crate) fn read_notes(
+    be: &impl DecryptReadBackend,
+    p: &impl Progress,
+) -> RusticResult<Vec<(Id, NoteFile)>> {
+    let list = match be.list(FileType::Note) {
+        Ok(list) => list,
+        Err(err) => {
+            debug!("cannot list notes, assuming there are none: {err}");
+            Vec::new()
+        }
+    };
+    be.stream_list::<NoteFile>(list, p)?.into_iter().collect()
+}
+
+/// Get all notes, sorted by the time they have been added.
+///
+/// # Type Parameters
+///
+/// * `P` - The progress bar type.
+/// * `S` - The state the repository is in.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+pub(crate) fn get_notes<P: ProgressBars, S: Open>(
+    repo: &Repository<P, S>,
+) -> RusticResult<Vec<(Id, NoteFile)>> {
+    let p = repo.pb.progress_counter("reading notes...");
+    let mut notes = read_notes(repo.dbe(), &p)?;
+    p.finish();
+    notes.sort_unstable_by_key(|(_, note)| note.time);
+    Ok(notes)
+}
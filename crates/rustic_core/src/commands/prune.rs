@@ -30,6 +30,7 @@ use crate::{
         tree::TreeStreamerOnce,
         BlobType, BlobTypeMap, Initialize,
     },
+    commands::{journal::remove_journaled, trash::read_trash},
     error::CommandErrorKind,
     error::RusticResult,
     id::Id,
@@ -39,7 +40,7 @@ use crate::{
         IndexBackend, IndexedBackend, ReadIndex,
     },
     progress::{Progress, ProgressBars},
-    repofile::{HeaderEntry, IndexBlob, IndexFile, IndexPack, SnapshotFile},
+    repofile::{HeaderEntry, IndexBlob, IndexFile, IndexPack, JournalFile, SnapshotFile},
     repository::{Open, Repository},
 };
 
@@ -186,11 +187,20 @@ impl PruneOptions {
         }
         p.finish();
 
+        // snapshots in the trash are kept until they are expired
+        let p = pb.progress_counter("reading trash...");
+        let now = Local::now();
+        let (trash_expired, trash): (Vec<_>, Vec<_>) = read_trash(be, &p)?
+            .into_iter()
+            .partition(|(_, trash)| trash.is_expired(now));
+        p.finish();
+        let trash_trees: Vec<_> = trash.iter().map(|(_, trash)| trash.snapshot.tree).collect();
+
         let (used_ids, total_size) = {
             let index = index_collector.into_index();
             let total_size = BlobTypeMap::init(|blob_type| index.total_size(blob_type));
             let indexed_be = IndexBackend::new_from_index(&be.clone(), index);
-            let used_ids = find_used_blobs(&indexed_be, &self.ignore_snaps, pb)?;
+            let used_ids = find_used_blobs(&indexed_be, &self.ignore_snaps, trash_trees, pb)?;
             (used_ids, total_size)
         };
 
@@ -201,6 +211,9 @@ impl PruneOptions {
         p.finish();
 
         let mut pruner = PrunePlan::new(used_ids, existing_packs, index_files);
+        pruner.stats.trash_snapshots = trash.len() as u64;
+        pruner.stats.trash_expired = trash_expired.len() as u64;
+        pruner.trash_remove = trash_expired.into_iter().map(|(id, _)| id).collect();
         pruner.count_used_blobs();
         pruner.check()?;
         let repack_cacheable_only = self
@@ -341,6 +354,10 @@ pub struct PruneStats {
     pub index_files: u64,
     /// Number of index files which will be rebuilt during the prune
     pub index_files_rebuild: u64,
+    /// Number of snapshots in the trash
+    pub trash_snapshots: u64,
+    /// Number of expired snapshots which will be removed from the trash
+    pub trash_expired: u64,
 }
 
 impl PruneStats {
@@ -359,6 +376,35 @@ impl PruneStats {
     }
 }
 
+/// Usage information about a single pack as determined by a [`PrunePlan`]
+#[derive(Debug, Clone, Copy)]
+pub struct PackUsage {
+    /// The id of the pack
+    pub id: Id,
+    /// The type of the blobs in the pack
+    pub blob_type: BlobType,
+    /// The size of the pack
+    pub size: u32,
+    /// The time the pack was created
+    pub time: Option<DateTime<Local>>,
+    /// The size of the used blobs in the pack
+    pub used_size: u32,
+    /// The size of the unused blobs in the pack
+    pub unused_size: u32,
+}
+
+impl PackUsage {
+    /// Returns the fraction of unused blob data in the pack (between `0.0` and `1.0`)
+    #[must_use]
+    pub fn unused_ratio(&self) -> f64 {
+        if self.unused_size == 0 {
+            0.0
+        } else {
+            f64::from(self.unused_size) / (f64::from(self.used_size) + f64::from(self.unused_size))
+        }
+    }
+}
+
 // TODO: add documentation!
 #[derive(Debug)]
 struct PruneIndex {
@@ -568,6 +614,10 @@ pub struct PrunePlan {
     repack_candidates: Vec<(PackInfo, RepackReason, usize, usize)>,
     /// The index files
     index_files: Vec<PruneIndex>,
+    /// Usage information about all packs which are not marked for deletion
+    pack_usage: Vec<PackUsage>,
+    /// The expired trash files to remove
+    trash_remove: Vec<Id>,
     /// `prune` statistics
     pub stats: PruneStats,
 }
@@ -643,6 +693,8 @@ impl PrunePlan {
             existing_packs,
             repack_candidates: Vec::new(),
             index_files,
+            pack_usage: Vec::new(),
+            trash_remove: Vec::new(),
             stats: PruneStats::default(),
         }
     }
@@ -714,6 +766,16 @@ impl PrunePlan {
                     .filter(|(_, p)| p.delete_mark == mark_case)
                 {
                     let pi = PackInfo::from_pack(pack, &mut self.used_ids);
+                    if !pack.delete_mark {
+                        self.pack_usage.push(PackUsage {
+                            id: pack.id,
+                            blob_type: pack.blob_type,
+                            size: pack.size,
+                            time: pack.time,
+                            used_size: pi.used_size,
+                            unused_size: pi.unused_size,
+                        });
+                    }
 
                     // Various checks to determine if packs need to be kept
                     let too_young = pack.time > Some(self.time - keep_pack);
@@ -962,6 +1024,11 @@ impl PrunePlan {
         // repacks come at end
     }
 
+    /// Get usage information about all packs which are not marked for deletion.
+    pub fn pack_usage(&self) -> &[PackUsage] {
+        &self.pack_usage
+    }
+
     /// Get the list of packs-to-repack from the [`PrunePlan`].
     pub fn repack_packs(&self) -> Vec<Id> {
         self.index_files
@@ -1012,6 +1079,7 @@ impl PrunePlan {
             indexer.clone(),
             repo.config(),
             size_after_prune[BlobType::Tree],
+            repo.upload_connections(),
         )?;
 
         let data_repacker = Repacker::new(
@@ -1020,6 +1088,7 @@ impl PrunePlan {
             indexer.clone(),
             repo.config(),
             size_after_prune[BlobType::Data],
+            repo.upload_connections(),
         )?;
 
         // mark unreferenced packs for deletion
@@ -1154,25 +1223,16 @@ impl PrunePlan {
         indexer.write().unwrap().finalize()?;
         p.finish();
 
-        // remove old index files first as they may reference pack files which are removed soon.
-        if !indexes_remove.is_empty() {
-            let p = pb.progress_counter("removing old index files...");
-            be.delete_list(FileType::Index, true, indexes_remove.iter(), p)?;
-        }
-
-        // get variable out of Arc<Mutex<_>>
-        let data_packs_remove = data_packs_remove.lock().unwrap();
-        if !data_packs_remove.is_empty() {
-            let p = pb.progress_counter("removing old data packs...");
-            be.delete_list(FileType::Pack, false, data_packs_remove.iter(), p)?;
-        }
-
-        // get variable out of Arc<Mutex<_>>
-        let tree_packs_remove = tree_packs_remove.lock().unwrap();
-        if !tree_packs_remove.is_empty() {
-            let p = pb.progress_counter("removing old tree packs...");
-            be.delete_list(FileType::Pack, true, tree_packs_remove.iter(), p)?;
-        }
+        // journal the removal of files, so that it can be completed if it gets interrupted.
+        // Expired snapshots are removed from the trash as their data is not used anymore.
+        let journal = JournalFile {
+            trash: self.trash_remove,
+            index: indexes_remove,
+            data_packs: std::mem::take(&mut *data_packs_remove.lock().unwrap()),
+            tree_packs: std::mem::take(&mut *tree_packs_remove.lock().unwrap()),
+            ..JournalFile::new("prune")
+        };
+        remove_journaled(repo, &journal)?;
 
         Ok(())
     }
@@ -1300,6 +1360,7 @@ impl PackInfo {
 ///
 /// * `index` - The index to use
 /// * `ignore_snaps` - The snapshots to ignore
+/// * `extra_trees` - Additional root trees to keep, e.g. from trashed snapshots
 /// * `pb` - The progress bars
 ///
 /// # Errors
@@ -1308,6 +1369,7 @@ impl PackInfo {
 fn find_used_blobs(
     index: &impl IndexedBackend,
     ignore_snaps: &[Id],
+    extra_trees: Vec<Id>,
     pb: &impl ProgressBars,
 ) -> RusticResult<HashMap<Id, u8>> {
     let ignore_snaps: HashSet<_> = ignore_snaps.iter().collect();
@@ -1319,13 +1381,14 @@ fn find_used_blobs(
         .into_iter()
         .filter(|id| !ignore_snaps.contains(id))
         .collect();
-    let snap_trees: Vec<_> = index
+    let mut snap_trees: Vec<_> = index
         .be()
         .stream_list::<SnapshotFile>(list, &p)?
         .into_iter()
         .map_ok(|(_, snap)| snap.tree)
         .try_collect()?;
     p.finish();
+    snap_trees.extend(extra_trees);
 
     let mut ids: HashMap<_, _> = snap_trees.iter().map(|id| (*id, 0)).collect();
     let p = pb.progress_counter("finding used blobs...");
@@ -1333,6 +1396,7 @@ fn find_used_blobs(
     let mut tree_streamer = TreeStreamerOnce::new(index.clone(), snap_trees, p)?;
     while let Some(item) = tree_streamer.next().transpose()? {
         let (_, tree) = item;
+        ids.extend(tree.shards.iter().map(|id| (*id, 0)));
         for node in tree.nodes {
             match node.node_type {
                 NodeType::File => {
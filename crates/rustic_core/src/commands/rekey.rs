@@ -0,0 +1,270 @@
+//! `rekey` subcommand
+use std::collections::{HashMap, HashSet};
+
+use itertools::Itertools;
+use log::{info, warn};
+use rayon::prelude::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
+
+use crate::{
+    backend::{
+        decrypt::{DecryptBackend, DecryptReadBackend, DecryptWriteBackend},
+        FileType, ReadBackend,
+    },
+    blob::{packer::Packer, BlobType},
+    commands::{config::save_config, key::KeyOptions, note::read_notes, trash::read_trash},
+    crypto::{aespoly1305::Key, Cipher},
+    error::{CommandErrorKind, RusticResult},
+    id::Id,
+    index::{indexer::Indexer, IndexEntry},
+    progress::{Progress, ProgressBars},
+    repofile::{keyfile::find_key_in_backend, IndexFile, SnapshotFile},
+    repository::{Open, Repository},
+};
+
+/// Generate a new master key and re-encrypt all files of the repository with it.
+///
+/// All blobs are repacked into new pack files and new index, snapshot, trash and note files are written
+/// using the new key. References to parent snapshots and notes attached to snapshots are changed to the
+/// new snapshot ids; notes attached to packs, index or key files are removed as these files are replaced. Then a key file for the new master key and the new config file are saved.
+/// Finally, all files encrypted with the old master key, including all other key files, are removed.
+///
+/// # Notes
+///
+/// The repository must not be used by other processes while rekeying.
+/// If rekeying is interrupted before the new config file has been saved, the repository contains
+/// index files which cannot be read with the old master key and need to be removed manually.
+///
+/// # Type Parameters
+///
+/// * `P` - The progress bar type.
+/// * `S` - The state the repository is in.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to rekey.
+/// * `pass` - The password to encrypt the new key with.
+/// * `key_opts` - The options to create the new key with.
+///
+/// # Errors
+///
+/// * [`CommandErrorKind::RepositoryNotEncrypted`] - If the repository is not encrypted.
+/// * [`CommandErrorKind::KeyMismatch`] - If the new key file doesn't contain the new master key.
+///
+/// # Returns
+///
+/// The id of the new key file.
+pub(crate) fn rekey_all_data<P: ProgressBars, S: Open>(
+    repo: &Repository<P, S>,
+    pass: &str,
+    key_opts: &KeyOptions,
+) -> RusticResult<Id> {
+    let old_be = repo.dbe();
+    let config = repo.config();
+    if config.cipher() == Cipher::None {
+        return Err(CommandErrorKind::RepositoryNotEncrypted.into());
+    }
+
+    // list all files encrypted with the old key before writing anything
+    let old_keys = repo.be.list(FileType::Key)?;
+    let old_snapshots = repo.be.list(FileType::Snapshot)?;
+    let old_indexes = repo.be.list(FileType::Index)?;
+    let old_packs = repo.be.list(FileType::Pack)?;
+
+    let p = repo.pb.progress_counter("reading index...");
+    let indexes: Vec<IndexFile> = old_be
+        .stream_list::<IndexFile>(old_indexes.clone(), &p)?
+        .into_iter()
+        .map_ok(|(_, index)| index)
+        .try_collect()?;
+    p.finish();
+
+    let tree_packs: HashSet<_> = indexes
+        .iter()
+        .flat_map(|index| index.packs.iter().chain(&index.packs_to_delete))
+        .filter(|pack| pack.blob_type() == BlobType::Tree)
+        .map(|pack| pack.id)
+        .collect();
+    let (old_tree_packs, old_data_packs): (Vec<_>, Vec<_>) = old_packs
+        .into_iter()
+        .partition(|id| tree_packs.contains(id));
+
+    // only copy each blob once, even if it is contained in multiple packs
+    let mut seen = HashSet::new();
+    let blobs: Vec<_> = indexes
+        .iter()
+        .flat_map(|index| &index.packs)
+        .flat_map(|pack| pack.blobs.iter().map(|blob| (pack.id, blob)))
+        .filter(|(_, blob)| seen.insert(blob.id))
+        .collect();
+    let total_size = |tpe: BlobType| -> u64 {
+        blobs
+            .iter()
+            .filter(|(_, blob)| blob.tpe == tpe)
+            .map(|(_, blob)| u64::from(blob.length))
+            .sum()
+    };
+
+    let key = Key::new().with_cipher(config.cipher());
+    let mut be = DecryptBackend::new(&repo.be, key);
+    be.set_zstd(config.zstd()?);
+
+    let indexer = Indexer::new(be.clone()).into_shared();
+    let data_packer = Packer::new(
+        be.clone(),
+        BlobType::Data,
+        indexer.clone(),
+        config,
+        total_size(BlobType::Data),
+        repo.upload_connections(),
+    )?;
+    let tree_packer = Packer::new(
+        be.clone(),
+        BlobType::Tree,
+        indexer.clone(),
+        config,
+        total_size(BlobType::Tree),
+        repo.upload_connections(),
+    )?;
+
+    let p = repo.pb.progress_bytes("re-encrypting blobs...");
+    p.set_length(blobs.iter().map(|(_, blob)| u64::from(blob.length)).sum());
+    blobs
+        .par_iter()
+        .try_for_each(|(pack, blob)| -> RusticResult<_> {
+            let data = IndexEntry::from_index_blob(blob, *pack).read_data(old_be)?;
+            match blob.tpe {
+                BlobType::Data => data_packer.add(data, blob.id)?,
+                BlobType::Tree => tree_packer.add(data, blob.id)?,
+            }
+            p.inc(u64::from(blob.length));
+            Ok(())
+        })?;
+    _ = data_packer.finalize()?;
+    _ = tree_packer.finalize()?;
+    indexer.write().unwrap().finalize()?;
+    p.finish();
+
+    let p = repo.pb.progress_counter("reading snapshots...");
+    let snaps: Vec<(Id, SnapshotFile)> = old_be
+        .stream_list::<SnapshotFile>(old_snapshots.clone(), &p)?
+        .into_iter()
+        .try_collect()?;
+    p.finish();
+    let p = repo.pb.progress_counter("re-encrypting snapshots...");
+    let snap_ids = save_snapshots(&be, snaps, &p)?;
+
+    let p = repo.pb.progress_counter("reading trash...");
+    let (old_trash, mut trash): (Vec<_>, Vec<_>) = read_trash(old_be, &p)?.into_iter().unzip();
+    p.finish();
+    for trash in &mut trash {
+        trash.snapshot.parent = trash.snapshot.parent.map(|id| remap(&snap_ids, id));
+    }
+    let p = repo.pb.progress_counter("re-encrypting trash...");
+    be.save_list(trash.iter(), p)?;
+
+    let p = repo.pb.progress_counter("reading notes...");
+    let (old_notes, notes): (Vec<_>, Vec<_>) = read_notes(old_be, &p)?.into_iter().unzip();
+    p.finish();
+    // the ids of all snapshots, packs, index and key files change
+    let notes: Vec<_> = notes
+        .into_iter()
+        .filter_map(|mut note| match (note.tpe, snap_ids.get(&note.target)) {
+            (FileType::Snapshot, Some(id)) => {
+                note.target = *id;
+                Some(note)
+            }
+            (FileType::Config, _) => Some(note),
+            (tpe, _) => {
+                warn!(
+                    "removing note attached to {tpe:?} {}, which doesn't exist after rekeying.",
+                    note.target
+                );
+                None
+            }
+        })
+        .collect();
+    let p = repo.pb.progress_counter("re-encrypting notes...");
+    be.save_list(notes.iter(), p)?;
+
+    // check that the new key can be used before switching to it
+    let key_id = key_opts.add(repo, pass, key)?;
+    let (_, new_key) = find_key_in_backend(&repo.be, &pass, Some(&key_id))?;
+    if new_key.to_keys() != key.to_keys() {
+        return Err(CommandErrorKind::KeyMismatch(key_id).into());
+    }
+    save_config(repo, config.clone(), key)?;
+    info!("repository is now encrypted with the new key {key_id}, removing old files...");
+
+    let p = repo.pb.progress_counter("removing old keys...");
+    old_be.delete_list(FileType::Key, false, old_keys.iter(), p)?;
+    let p = repo.pb.progress_counter("removing old snapshots...");
+    old_be.delete_list(FileType::Snapshot, true, old_snapshots.iter(), p)?;
+    let p = repo.pb.progress_counter("removing old trash...");
+    old_be.delete_list(FileType::Trash, false, old_trash.iter(), p)?;
+    let p = repo.pb.progress_counter("removing old notes...");
+    old_be.delete_list(FileType::Note, false, old_notes.iter(), p)?;
+    let p = repo.pb.progress_counter("removing old index files...");
+    old_be.delete_list(FileType::Index, true, old_indexes.iter(), p)?;
+    let p = repo.pb.progress_counter("removing old tree packs...");
+    old_be.delete_list(FileType::Pack, true, old_tree_packs.iter(), p)?;
+    let p = repo.pb.progress_counter("removing old data packs...");
+    old_be.delete_list(FileType::Pack, false, old_data_packs.iter(), p)?;
+
+    Ok(key_id)
+}
+
+/// Save the given snapshots using the new key.
+///
+/// The ids of all snapshots change, so the old id is kept as original id and references to parents
+/// are changed to the new ids. Therefore, parents are saved before the snapshots referencing them.
+///
+/// # Arguments
+///
+/// * `be` - The backend using the new key
+/// * `snaps` - The snapshots together with their old ids
+/// * `p` - The progress bar to use
+///
+/// # Returns
+///
+/// The new ids of the snapshots, mapped by their old ids
+fn save_snapshots(
+    be: &impl DecryptWriteBackend,
+    snaps: Vec<(Id, SnapshotFile)>,
+    p: &impl Progress,
+) -> RusticResult<HashMap<Id, Id>> {
+    let old_ids: HashSet<_> = snaps.iter().map(|(id, _)| *id).collect();
+    let mut new_ids = HashMap::new();
+    let mut pending = snaps;
+    p.set_length(pending.len() as u64);
+    while !pending.is_empty() {
+        let (mut ready, mut waiting): (Vec<_>, Vec<_>) =
+            pending.into_iter().partition(|(_, snap)| {
+                snap.parent.map_or(true, |parent| {
+                    !old_ids.contains(&parent) || new_ids.contains_key(&parent)
+                })
+            });
+        // parent references can't form cycles as ids are hashes of the contents; but don't loop forever
+        if ready.is_empty() {
+            std::mem::swap(&mut ready, &mut waiting);
+        }
+        let saved: Vec<_> = ready
+            .into_par_iter()
+            .map(|(id, mut snap)| -> RusticResult<_> {
+                snap.original = Some(snap.original.unwrap_or(id));
+                snap.parent = snap.parent.map(|parent| remap(&new_ids, parent));
+                let new_id = be.save_file(&snap)?;
+                p.inc(1);
+                Ok((id, new_id))
+            })
+            .collect::<RusticResult<_>>()?;
+        new_ids.extend(saved);
+        pending = waiting;
+    }
+    p.finish();
+    Ok(new_ids)
+}
+
+/// Get the new id of a snapshot; ids of unknown snapshots are kept
+fn remap(new_ids: &HashMap<Id, Id>, id: Id) -> Id {
+    new_ids.get(&id).copied().unwrap_or(id)
+}
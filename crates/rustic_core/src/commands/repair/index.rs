@@ -128,19 +128,24 @@ impl RepairIndexOptions {
                 .try_into()
                 .map_err(CommandErrorKind::ConversionToU64Failed)?,
         );
+        // only accept packs which are larger than computed from their header if they may be padded
+        let padded = repo.config().pack_padding.is_some();
         for (id, to_delete, size_hint, packsize) in pack_read_header {
             debug!("reading pack {id}...");
-            let pack = IndexPack {
+            let mut pack = IndexPack {
                 id,
-                blobs: match PackHeader::from_file(be, id, size_hint, packsize) {
-                    Err(err) => {
-                        warn!("error reading pack {id} (not processed): {err}");
-                        Vec::new()
-                    }
-                    Ok(header) => header.into_blobs(),
-                },
                 ..Default::default()
             };
+            match PackHeader::from_file(be, id, size_hint, packsize, padded) {
+                Err(err) => warn!("error reading pack {id} (not processed): {err}"),
+                Ok(header) => {
+                    pack.blobs = header.into_blobs();
+                    // padded packs are larger than computed from their header
+                    if pack.pack_size() != packsize {
+                        pack.size = Some(packsize);
+                    }
+                }
+            }
 
             if !dry_run {
                 indexer.write().unwrap().add_with(pack, to_delete)?;
@@ -94,6 +94,7 @@ impl RepairSnapshotsOptions {
             indexer.clone(),
             config_file,
             repo.index().total_size(BlobType::Tree),
+            repo.upload_connections(),
         )?;
 
         for mut snap in snapshots {
@@ -105,6 +106,7 @@ impl RepairSnapshotsOptions {
                 Some(snap.tree),
                 &mut replaced,
                 &mut seen,
+                config_file.shard_trees(),
                 dry_run,
             )? {
                 (Changed::None, _) => {
@@ -166,11 +168,13 @@ impl RepairSnapshotsOptions {
     /// * `id` - The id of the tree to repair
     /// * `replaced` - A map of already replaced trees
     /// * `seen` - A set of already seen trees
+    /// * `shard_trees` - Whether large trees are sharded
     /// * `dry_run` - Whether to actually modify the repository or just print what would be done
     ///
     /// # Returns
     ///
     /// A tuple containing the change status and the id of the repaired tree
+    #[allow(clippy::too_many_arguments)]
     fn repair_tree<BE: DecryptWriteBackend>(
         &self,
         be: &impl IndexedBackend,
@@ -178,6 +182,7 @@ impl RepairSnapshotsOptions {
         id: Option<Id>,
         replaced: &mut HashMap<Id, (Changed, Id)>,
         seen: &mut HashSet<Id>,
+        shard_trees: bool,
         dry_run: bool,
     ) -> RusticResult<(Changed, Id)> {
         let (tree, changed) = match id {
@@ -235,6 +240,7 @@ impl RepairSnapshotsOptions {
                                 node.subtree,
                                 replaced,
                                 seen,
+                                shard_trees,
                                 dry_run,
                             )?;
                             match c {
@@ -267,9 +273,15 @@ impl RepairSnapshotsOptions {
             (Some(id), Changed::None) => Ok((Changed::None, id)),
             (_, c) => {
                 // the tree has been changed => save it
-                let (chunk, new_id) = tree.serialize()?;
+                let tree = tree.serialize(shard_trees)?;
+                let new_id = tree.id;
                 if !be.has_tree(&new_id) && !dry_run {
-                    packer.add(chunk.into(), new_id)?;
+                    for (chunk, shard_id) in tree.shards {
+                        if !be.has_tree(&shard_id) {
+                            packer.add(chunk.into(), shard_id)?;
+                        }
+                    }
+                    packer.add(tree.chunk.into(), new_id)?;
                 }
                 if let Some(id) = id {
                     _ = replaced.insert(id, (c, new_id));
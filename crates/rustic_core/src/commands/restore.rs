@@ -5,21 +5,23 @@ use log::{debug, error, info, trace, warn};
 
 use std::{
     cmp::Ordering,
-    collections::BTreeMap,
+    collections::{BTreeMap, HashMap},
     num::NonZeroU32,
     path::{Path, PathBuf},
     sync::Mutex,
 };
 
+use bytesize::ByteSize;
 use chrono::{DateTime, Local, Utc};
 use ignore::{DirEntry, WalkBuilder};
 use itertools::Itertools;
 use rayon::ThreadPoolBuilder;
+use serde::Serialize;
 
 use crate::{
     backend::{
         decrypt::DecryptReadBackend,
-        local::LocalDestination,
+        local::{DestinationLimits, LocalDestination},
         node::{Node, NodeType},
         FileType, ReadBackend,
     },
@@ -29,13 +31,9 @@ use crate::{
     id::Id,
     progress::{Progress, ProgressBars},
     repository::{IndexedFull, IndexedTree, Open, Repository},
+    throttle::Throttle,
 };
 
-pub(crate) mod constants {
-    /// The maximum number of reader threads to use for restoring.
-    pub(crate) const MAX_READER_THREADS_NUM: usize = 20;
-}
-
 type RestoreInfo = BTreeMap<(Id, BlobLocation), Vec<FileLocation>>;
 type Filenames = Vec<PathBuf>;
 
@@ -64,9 +62,13 @@ pub struct RestoreOptions {
     /// Always read and verify existing files (don't trust correct modification time and file size)
     #[cfg_attr(feature = "clap", clap(long))]
     pub verify_existing: bool,
+
+    /// Limit the rate of data read from the repository, in bytes per second (e.g. 10MiB)
+    #[cfg_attr(feature = "clap", clap(long, value_name = "RATE"))]
+    pub limit_restore: Option<ByteSize>,
 }
 
-#[derive(Default, Debug, Clone, Copy)]
+#[derive(Default, Debug, Clone, Copy, Serialize)]
 /// Statistics for files or directories
 pub struct FileDirStats {
     /// Number of files or directories to restore
@@ -81,7 +83,7 @@ pub struct FileDirStats {
     pub additional: u64,
 }
 
-#[derive(Default, Debug, Clone, Copy)]
+#[derive(Default, Debug, Clone, Copy, Serialize)]
 /// Restore statistics
 pub struct RestoreStats {
     /// file statistics
@@ -90,7 +92,142 @@ pub struct RestoreStats {
     pub dirs: FileDirStats,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+/// What a restore does with an entry of the destination
+pub enum RestoreAction {
+    /// The entry doesn't exist and is created
+    Create,
+    /// The existing entry is overwritten
+    Overwrite,
+    /// The existing entry is kept as it is
+    Skip,
+    /// The existing entry is removed
+    Remove,
+}
+
+#[derive(Debug, Clone, Serialize)]
+/// An entry of the destination together with what a restore does with it, see [`RestorePlan::entries`]
+pub struct RestoreEntry {
+    /// The path of the entry, relative to the destination
+    pub path: PathBuf,
+    /// Whether the entry is a directory
+    pub is_dir: bool,
+    /// What is done with the entry
+    pub action: RestoreAction,
+    /// Why the action is taken
+    pub reason: &'static str,
+    /// The size of the entry, i.e. the size of the restored file or of the removed file
+    pub size: u64,
+}
+
+#[derive(Default, Debug, Clone)]
+#[non_exhaustive]
+/// Result of the pre-flight checks of a restore
+pub struct RestoreCheck {
+    /// The limits of the destination filesystem
+    pub limits: DestinationLimits,
+    /// Paths which exceed the name or path length limits of the destination
+    pub too_long: Vec<PathBuf>,
+    /// Number of entries whose owner cannot be restored
+    pub ownership: u64,
+    /// Number of special files which cannot be created
+    pub special: u64,
+    /// Total size of all files, i.e. the needed space if no existing contents can be used
+    pub total_size: u64,
+}
+
+impl RestoreCheck {
+    /// Returns `true` if the destination is writable and all entries can be restored as saved.
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.limits.writable && self.too_long.is_empty() && self.ownership == 0 && self.special == 0
+    }
+
+    /// Check whether the destination has enough free space to restore all files, even if no
+    /// existing contents can be used. If this is not the case, the free space must be compared
+    /// with a [`RestorePlan`] using [`RestoreCheck::has_space_for`].
+    ///
+    /// If the free space cannot be determined, this returns `true`.
+    #[must_use]
+    pub fn has_space_for_all(&self) -> bool {
+        self.limits
+            .free_space
+            .map_or(true, |free| self.total_size <= free)
+    }
+
+    /// Check whether the destination has enough free space to execute the given [`RestorePlan`].
+    ///
+    /// If the free space cannot be determined, this returns `true`.
+    ///
+    /// # Arguments
+    ///
+    /// * `plan` - The restore plan to check
+    #[must_use]
+    pub fn has_space_for(&self, plan: &RestorePlan) -> bool {
+        self.limits
+            .free_space
+            .map_or(true, |free| plan.restore_size <= free)
+    }
+}
+
 impl RestoreOptions {
+    /// Check if the given nodes can be restored to the destination without modifying anything.
+    ///
+    /// This checks the path length limits of the destination filesystem and whether the owners
+    /// and special files can be restored with the current permissions. It also queries the free space
+    /// of the destination which can be compared with the total size using [`RestoreCheck::has_space_for_all`]
+    /// or with a [`RestorePlan`] using [`RestoreCheck::has_space_for`].
+    ///
+    /// # Arguments
+    ///
+    /// * `node_streamer` - The node streamer to use.
+    /// * `dest` - The destination to restore to.
+    ///
+    /// # Errors
+    ///
+    /// If the nodes could not be read.
+    pub(crate) fn check(
+        self,
+        node_streamer: impl Iterator<Item = RusticResult<(PathBuf, Node)>>,
+        dest: &LocalDestination,
+    ) -> RusticResult<RestoreCheck> {
+        let limits = dest.limits();
+        let mut check = RestoreCheck {
+            limits,
+            ..Default::default()
+        };
+        // looking up users can be expensive, so cache the result per owner
+        let mut owners = HashMap::new();
+
+        for item in node_streamer {
+            let (path, node) = item?;
+            let name_len = path.file_name().map_or(0, |name| name.len());
+            if limits.max_name_len.map_or(false, |max| name_len > max)
+                || limits
+                    .max_path_len
+                    .map_or(false, |max| dest.path(&path).as_os_str().len() > max)
+            {
+                check.too_long.push(path);
+            }
+            if !self.no_ownership
+                && !*owners
+                    .entry((node.meta.user.clone(), node.meta.uid))
+                    .or_insert_with(|| LocalDestination::can_set_owner(&node.meta, self.numeric_id))
+            {
+                check.ownership += 1;
+            }
+            if node.is_special() && !LocalDestination::can_create_special(&node) {
+                check.special += 1;
+            }
+            if node.is_file() {
+                check.total_size += node.meta.size;
+            }
+        }
+
+        Ok(check)
+    }
+
     /// Restore the repository to the given destination.
     ///
     /// # Type Parameters
@@ -116,7 +253,7 @@ impl RestoreOptions {
         dest: &LocalDestination,
     ) -> RusticResult<()> {
         repo.warm_up_wait(file_infos.to_packs().into_iter())?;
-        restore_contents(repo, dest, file_infos)?;
+        restore_contents(repo, dest, file_infos, self.limit_restore)?;
 
         let p = repo.pb.progress_spinner("setting metadata...");
         self.restore_metadata(node_streamer, dest)?;
@@ -137,7 +274,8 @@ impl RestoreOptions {
     /// * `repo` - The repository to restore.
     /// * `node_streamer` - The node streamer to use.
     /// * `dest` - The destination to restore to.
-    /// * `dry_run` - If true, don't actually restore anything, but only print out what would be done.
+    /// * `dry_run` - If true, don't actually restore anything, but only collect what would be done
+    ///   in [`RestorePlan::entries`].
     ///
     /// # Errors
     ///
@@ -157,19 +295,44 @@ impl RestoreOptions {
         let mut restore_infos = RestorePlan::default();
         let mut additional_existing = false;
         let mut removed_dir = None;
+        // the entries are only collected for dry runs to not use memory otherwise
+        let mut existing_entries = Vec::new();
+        let mut node_entries = Vec::new();
 
-        let mut process_existing = |entry: &DirEntry| -> RusticResult<_> {
+        let mut process_existing = |entry: &DirEntry, reason| -> RusticResult<_> {
             if entry.depth() == 0 {
                 // don't process the root dir which should be existing
                 return Ok(());
             }
 
             debug!("additional {:?}", entry.path());
-            if entry.file_type().unwrap().is_dir() {
+            let is_dir = entry.file_type().unwrap().is_dir();
+            if is_dir {
                 stats.dirs.additional += 1;
             } else {
                 stats.files.additional += 1;
             }
+            if dry_run {
+                existing_entries.push(RestoreEntry {
+                    path: entry
+                        .path()
+                        .strip_prefix(&dest_path)
+                        .unwrap_or_else(|_| entry.path())
+                        .to_path_buf(),
+                    is_dir,
+                    action: if self.delete {
+                        RestoreAction::Remove
+                    } else {
+                        RestoreAction::Skip
+                    },
+                    reason,
+                    size: if is_dir {
+                        0
+                    } else {
+                        entry.metadata().map_or(0, |meta| meta.len())
+                    },
+                });
+            }
             match (self.delete, dry_run, entry.file_type().unwrap().is_dir()) {
                 (true, true, true) => {
                     info!("would have removed the additional dir: {:?}", entry.path());
@@ -205,12 +368,28 @@ impl RestoreOptions {
         };
 
         let mut process_node = |path: &PathBuf, node: &Node, exists: bool| -> RusticResult<_> {
+            let mut record = |action, reason| {
+                if dry_run {
+                    node_entries.push(RestoreEntry {
+                        path: path.clone(),
+                        is_dir: node.is_dir(),
+                        action,
+                        reason,
+                        size: if node.is_file() { node.meta.size } else { 0 },
+                    });
+                }
+            };
             match node.node_type {
                 NodeType::Dir => {
                     if exists {
                         stats.dirs.modify += 1;
                         trace!("existing dir {path:?}");
+                        record(
+                            RestoreAction::Skip,
+                            "directory exists, only metadata is restored",
+                        );
                     } else {
+                        record(RestoreAction::Create, "does not exist");
                         stats.dirs.restore += 1;
                         debug!("to restore: {path:?}");
                         if !dry_run {
@@ -235,29 +414,35 @@ impl RestoreOptions {
                         (_, AddFileResult::Existing) => {
                             stats.files.unchanged += 1;
                             trace!("identical file: {path:?}");
+                            record(RestoreAction::Skip, "size and modification time match");
                         }
                         (_, AddFileResult::Verified) => {
                             stats.files.verified += 1;
                             trace!("verified identical file: {path:?}");
+                            record(RestoreAction::Skip, "contents verified");
                         }
                         // TODO: The differentiation between files to modify and files to create could be done only by add_file
                         // Currently, add_file never returns Modify, but always New, so we differentiate based on exists
                         (true, AddFileResult::Modify) => {
                             stats.files.modify += 1;
                             debug!("to modify: {path:?}");
+                            record(RestoreAction::Overwrite, "contents differ");
                         }
                         (false, AddFileResult::Modify) => {
                             stats.files.restore += 1;
                             debug!("to restore: {path:?}");
+                            record(RestoreAction::Create, "does not exist");
                         }
                     }
                 }
-                _ => {} // nothing to do for symlink, device, etc.
+                // nothing to do for symlink, device, etc. as they are created when restoring the metadata
+                _ if exists => record(RestoreAction::Overwrite, "exists"),
+                _ => record(RestoreAction::Create, "does not exist"),
             }
             Ok(())
         };
 
-        let mut dst_iter = WalkBuilder::new(dest_path)
+        let mut dst_iter = WalkBuilder::new(&dest_path)
             .follow_links(false)
             .hidden(false)
             .ignore(false)
@@ -273,12 +458,12 @@ impl RestoreOptions {
                 (None, None) => break,
 
                 (Some(dst), None) => {
-                    process_existing(dst)?;
+                    process_existing(dst, "not contained in snapshot")?;
                     next_dst = dst_iter.next();
                 }
                 (Some(dst), Some((path, node))) => match dst.path().cmp(&dest.path(path)) {
                     Ordering::Less => {
-                        process_existing(dst)?;
+                        process_existing(dst, "not contained in snapshot")?;
                         next_dst = dst_iter.next();
                     }
                     Ordering::Equal => {
@@ -288,7 +473,7 @@ impl RestoreOptions {
                             || node.is_special()
                         {
                             // if types do not match, first remove the existing file
-                            process_existing(dst)?;
+                            process_existing(dst, "type differs from snapshot")?;
                         }
                         process_node(path, node, true)?;
                         next_dst = dst_iter.next();
@@ -311,6 +496,11 @@ impl RestoreOptions {
         }
 
         restore_infos.stats = stats;
+        restore_infos.entries = existing_entries;
+        restore_infos.entries.append(&mut node_entries);
+        restore_infos
+            .entries
+            .sort_by(|entry1, entry2| entry1.path.cmp(&entry2.path));
         p.finish();
 
         Ok(restore_infos)
@@ -389,6 +579,9 @@ impl RestoreOptions {
             .unwrap_or_else(|_| warn!("restore {:?}: setting extended attributes failed.", path));
         dest.set_times(path, &node.meta)
             .unwrap_or_else(|_| warn!("restore {:?}: setting file times failed.", path));
+        // file flags must be set last, as immutable files cannot be changed afterwards
+        dest.set_flags(path, node)
+            .unwrap_or_else(|_| warn!("restore {:?}: setting file flags failed.", path));
     }
 }
 
@@ -405,6 +598,7 @@ impl RestoreOptions {
 /// * `repo` - The repository to restore.
 /// * `dest` - The destination to restore to.
 /// * `file_infos` - The restore information.
+/// * `limit` - The maximum rate of data read from the repository per second, if any.
 ///
 /// # Errors
 ///
@@ -414,16 +608,19 @@ fn restore_contents<P: ProgressBars, S: Open>(
     repo: &Repository<P, S>,
     dest: &LocalDestination,
     file_infos: RestorePlan,
+    limit: Option<ByteSize>,
 ) -> RusticResult<()> {
     let RestorePlan {
         names: filenames,
         file_lengths,
         r: restore_info,
         restore_size: total_size,
+        priority,
         ..
     } = file_infos;
     let filenames = &filenames;
     let be = repo.dbe();
+    let throttle = &limit.map(|limit| Throttle::new(limit.as_u64()));
 
     // first create needed empty files, as they are not created later.
     for (i, size) in file_lengths.iter().enumerate() {
@@ -466,72 +663,85 @@ fn restore_contents<P: ProgressBars, S: Open>(
         })
         .collect();
 
+    // blobs needed by prioritized files are restored first
+    let (blobs_first, blobs): (Vec<_>, Vec<_>) = blobs.into_iter().partition(|blob| {
+        blob.4
+            .iter()
+            .any(|(_, file_idx, _)| priority.get(*file_idx).copied().unwrap_or_default())
+    });
+
     let pool = ThreadPoolBuilder::new()
-        .num_threads(constants::MAX_READER_THREADS_NUM)
+        .num_threads(repo.download_connections())
         .build()
         .map_err(CommandErrorKind::FromRayonError)?;
-    pool.in_place_scope(|s| {
-        for (pack, offset, length, from_file, name_dests) in blobs {
-            let p = &p;
-
-            if !name_dests.is_empty() {
-                // TODO: error handling!
-                s.spawn(move |s1| {
-                    let read_data = match &from_file {
-                        Some((file_idx, offset_file, length_file)) => {
-                            // read from existing file
-                            dest.read_at(&filenames[*file_idx], *offset_file, *length_file)
-                                .unwrap()
-                        }
-                        None => {
-                            // read needed part of the pack
-                            be.read_partial(FileType::Pack, &pack, false, offset, length)
-                                .unwrap()
-                        }
-                    };
-
-                    // save into needed files in parallel
-                    for (bl, group) in &name_dests.into_iter().group_by(|item| item.0.clone()) {
-                        let size = bl.data_length();
-                        let data = if from_file.is_some() {
-                            read_data.clone()
-                        } else {
-                            let start = usize::try_from(bl.offset - offset).unwrap();
-                            let end = usize::try_from(bl.offset + bl.length - offset).unwrap();
-                            be.read_encrypted_from_partial(
-                                &read_data[start..end],
-                                bl.uncompressed_length,
-                            )
-                            .unwrap()
-                        };
-                        for (_, file_idx, start) in group {
-                            let data = data.clone();
-                            s1.spawn(move |_| {
-                                let path = &filenames[file_idx];
-                                // Allocate file if it is not yet allocated
-                                let mut sizes_guard = sizes.lock().unwrap();
-                                let filesize = sizes_guard[file_idx];
-                                if filesize > 0 {
-                                    dest.set_length(path, filesize)
-                                        .map_err(|err| {
-                                            CommandErrorKind::ErrorSettingLength(
-                                                path.to_path_buf(),
-                                                Box::new(err),
-                                            )
-                                        })
-                                        .unwrap();
-                                    sizes_guard[file_idx] = 0;
+    // the scope waits for all prioritized blobs to be restored before continuing
+    for blobs in [blobs_first, blobs] {
+        pool.in_place_scope(|s| {
+            for (pack, offset, length, from_file, name_dests) in blobs {
+                let p = &p;
+
+                if !name_dests.is_empty() {
+                    // TODO: error handling!
+                    s.spawn(move |s1| {
+                        let read_data = match &from_file {
+                            Some((file_idx, offset_file, length_file)) => {
+                                // read from existing file
+                                dest.read_at(&filenames[*file_idx], *offset_file, *length_file)
+                                    .unwrap()
+                            }
+                            None => {
+                                // read needed part of the pack
+                                if let Some(throttle) = throttle {
+                                    throttle.wait(u64::from(length));
                                 }
-                                drop(sizes_guard);
-                                dest.write_at(path, start, &data).unwrap();
-                                p.inc(size);
-                            });
+                                be.read_partial(FileType::Pack, &pack, false, offset, length)
+                                    .unwrap()
+                            }
+                        };
+
+                        // save into needed files in parallel
+                        for (bl, group) in &name_dests.into_iter().group_by(|item| item.0.clone()) {
+                            let size = bl.data_length();
+                            let data = if from_file.is_some() {
+                                read_data.clone()
+                            } else {
+                                let start = usize::try_from(bl.offset - offset).unwrap();
+                                let end = usize::try_from(bl.offset + bl.length - offset).unwrap();
+                                be.read_encrypted_from_partial(
+                                    &read_data[start..end],
+                                    bl.uncompressed_length,
+                                )
+                                .unwrap()
+                            };
+                            for (_, file_idx, start) in group {
+                                let data = data.clone();
+                                s1.spawn(move |_| {
+                                    let path = &filenames[file_idx];
+                                    // Allocate file if it is not yet allocated
+                                    let mut sizes_guard = sizes.lock().unwrap();
+                                    let filesize = sizes_guard[file_idx];
+                                    if filesize > 0 {
+                                        dest.set_length(path, filesize)
+                                            .map_err(|err| {
+                                                CommandErrorKind::ErrorSettingLength(
+                                                    path.to_path_buf(),
+                                                    Box::new(err),
+                                                )
+                                            })
+                                            .unwrap();
+                                        sizes_guard[file_idx] = 0;
+                                    }
+                                    drop(sizes_guard);
+                                    dest.write_at(path, start, &data).unwrap();
+                                    p.inc(size);
+                                });
+                            }
                         }
-                    }
-                });
+                    });
+                }
             }
-        }
-    });
+        });
+    }
 
     p.finish();
 
@@ -559,6 +769,12 @@ pub struct RestorePlan {
     pub matched_size: u64,
     /// Statistics about the restore.
     pub stats: RestoreStats,
+    /// All entries of the destination and what the restore does with them, sorted by path.
+    ///
+    /// This is only filled for dry runs.
+    pub entries: Vec<RestoreEntry>,
+    /// Whether the file contents should be restored before all other contents, indexed like `names`
+    priority: Vec<bool>,
 }
 
 /// `BlobLocation` contains information about a blob within a pack
@@ -703,6 +919,31 @@ impl RestorePlan {
         }
     }
 
+    /// Restore the contents of the given paths before all other contents.
+    ///
+    /// A path also prioritizes all files contained in it. The remaining contents are
+    /// restored afterwards.
+    ///
+    /// # Arguments
+    ///
+    /// * `paths` - The paths to prioritize, relative to the restore destination
+    ///
+    /// # Returns
+    ///
+    /// The number of prioritized files which need to be restored.
+    pub fn prioritize(&mut self, paths: &[PathBuf]) -> usize {
+        let paths: Vec<_> = paths
+            .iter()
+            .map(|path| path.strip_prefix("/").unwrap_or(path))
+            .collect();
+        self.priority = self
+            .names
+            .iter()
+            .map(|name| paths.iter().any(|path| name.starts_with(path)))
+            .collect();
+        self.priority.iter().filter(|prio| **prio).count()
+    }
+
     /// Get a list of all pack files needed to perform the restore
     ///
     /// This can be used e.g. to warm-up those pack files before doing the atual restore.
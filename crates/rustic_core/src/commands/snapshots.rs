@@ -1,6 +1,9 @@
 //! `smapshot` subcommand
 
+use std::cmp::Ordering;
+
 use crate::{
+    backend::decrypt::DecryptReadBackend,
     error::RusticResult,
     progress::ProgressBars,
     repofile::snapshotfile::{SnapshotGroup, SnapshotGroupCriterion},
@@ -29,23 +32,26 @@ pub(crate) fn get_snapshot_group<P: ProgressBars, S: Open>(
     repo: &Repository<P, S>,
     ids: &[String],
     group_by: SnapshotGroupCriterion,
-    filter: impl FnMut(&SnapshotFile) -> bool,
+    filter: impl Fn(&SnapshotFile) -> bool + Sync,
 ) -> RusticResult<Vec<(SnapshotGroup, Vec<SnapshotFile>)>> {
     let pb = &repo.pb;
     let dbe = repo.dbe();
+    let connections = repo.list_connections();
     let p = pb.progress_counter("getting snapshots...");
     let groups = match ids {
-        [] => SnapshotFile::group_from_backend(dbe, filter, group_by, &p)?,
-        [id] if id == "latest" => SnapshotFile::group_from_backend(dbe, filter, group_by, &p)?
-            .into_iter()
-            .map(|(group, mut snaps)| {
-                snaps.sort_unstable();
-                let last_idx = snaps.len() - 1;
-                snaps.swap(0, last_idx);
-                snaps.truncate(1);
-                (group, snaps)
-            })
-            .collect::<Vec<_>>(),
+        [] => SnapshotFile::group_from_backend(dbe, filter, group_by, connections, &p)?,
+        [id] if id == "latest" => {
+            SnapshotFile::group_from_backend(dbe, filter, group_by, connections, &p)?
+                .into_iter()
+                .map(|(group, mut snaps)| {
+                    snaps.sort_unstable();
+                    let last_idx = snaps.len() - 1;
+                    snaps.swap(0, last_idx);
+                    snaps.truncate(1);
+                    (group, snaps)
+                })
+                .collect::<Vec<_>>()
+        }
         _ => {
             let item = (
                 SnapshotGroup::default(),
@@ -57,3 +63,66 @@ pub(crate) fn get_snapshot_group<P: ProgressBars, S: Open>(
 
     Ok(groups)
 }
+
+/// A page of snapshots, see [`Repository::snapshots_paged`].
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotPage {
+    /// The snapshots of the requested page
+    pub snapshots: Vec<SnapshotFile>,
+    /// The total number of snapshots matching the filter
+    pub total: usize,
+}
+
+/// Get a sorted page of the snapshots matching the given filter.
+///
+/// # Type Parameters
+///
+/// * `P` - The progress bar type.
+/// * `S` - The state the repository is in.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to get the snapshots from.
+/// * `filter` - The filter to apply to the snapshots.
+/// * `offset` - The number of (sorted) snapshots to skip.
+/// * `limit` - The maximum number of snapshots to return.
+/// * `sort` - The ordering to sort the snapshots by.
+///
+/// # Note
+///
+/// All snapshot files have to be read to determine the page, but only about `2 * (offset + limit)`
+/// snapshots are kept in memory at the same time.
+pub(crate) fn snapshots_paged<P: ProgressBars, S: Open>(
+    repo: &Repository<P, S>,
+    mut filter: impl FnMut(&SnapshotFile) -> bool,
+    offset: usize,
+    limit: usize,
+    sort: impl Fn(&SnapshotFile, &SnapshotFile) -> Ordering,
+) -> RusticResult<SnapshotPage> {
+    let p = repo.pb.progress_counter("getting snapshots...");
+    // snapshots are read in parallel, so ties are broken by the id to get deterministic pages
+    let sort =
+        |sn1: &SnapshotFile, sn2: &SnapshotFile| sort(sn1, sn2).then_with(|| sn1.id.cmp(&sn2.id));
+    let keep = offset.saturating_add(limit);
+    let mut snapshots = Vec::new();
+    let mut total = 0;
+
+    for snap in repo.dbe().stream_all::<SnapshotFile>(&p)? {
+        let snap = SnapshotFile::set_id(snap?);
+        if !filter(&snap) {
+            continue;
+        }
+        total += 1;
+        snapshots.push(snap);
+        if snapshots.len() >= keep.saturating_mul(2).max(1024) {
+            snapshots.sort_by(sort);
+            snapshots.truncate(keep);
+        }
+    }
+    p.finish();
+
+    snapshots.sort_by(sort);
+    let snapshots = snapshots.into_iter().skip(offset).take(limit).collect();
+
+    Ok(SnapshotPage { snapshots, total })
+}
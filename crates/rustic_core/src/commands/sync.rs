@@ -0,0 +1,208 @@
+//! `sync` subcommand
+use std::collections::HashSet;
+
+use chrono::{DateTime, Local};
+use log::info;
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::{
+    backend::{decrypt::DecryptReadBackend, FileType, ReadBackend, WriteBackend},
+    blob::BlobType,
+    error::{CommandErrorKind, RusticResult},
+    id::Id,
+    progress::{Progress, ProgressBars},
+    repofile::{ConfigFile, IndexFile, SnapshotFile},
+    repository::{Open, Repository},
+};
+
+/// Statistics about a `sync` run
+#[derive(Debug, Default, Clone, Copy)]
+#[non_exhaustive]
+pub struct SyncStats {
+    /// Number of transferred pack files
+    pub packs: u64,
+    /// Total size of the transferred pack files
+    pub packs_size: u64,
+    /// Number of transferred index files
+    pub index_files: u64,
+    /// Number of transferred snapshot files
+    pub snapshots: u64,
+    /// Number of transferred key files
+    pub keys: u64,
+    /// The new sync mark, i.e. the time of the newest transferred snapshot or the previous mark
+    pub mark: Option<DateTime<Local>>,
+}
+
+/// Synchronize a repository to a mirror by transferring the encrypted files as they are.
+///
+/// Index, snapshot and key files are small, so all which are missing in the target are
+/// transferred together with the packs contained in the missing index files. Files removed from
+/// the source repository are not removed from the target.
+///
+/// Only the missing index and snapshot files are read. Pack times are not used to select packs, as
+/// packs written by restic have no time and times written by other hosts may be wrong. The sync
+/// mark is the time of the newest transferred snapshot.
+///
+/// # Type Parameters
+///
+/// * `P` - The progress bar type.
+/// * `S` - The state the repository is in.
+/// * `Q` - The progress bar type of the target.
+/// * `T` - The state the target is in.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to sync from
+/// * `target` - The (possibly not yet initialized) mirror to sync to
+/// * `since` - The mark of the last sync. If set, the packs in the target are not listed and all packs
+///   of missing index files are transferred; if `None`, packs already present in the target are skipped
+/// * `dry_run` - Don't transfer anything, only compute what would be transferred
+///
+/// # Errors
+///
+/// * [`CommandErrorKind::NotAMirror`] - If the target is a different repository
+pub(crate) fn sync<P: ProgressBars, S: Open, Q, T>(
+    repo: &Repository<P, S>,
+    target: &Repository<Q, T>,
+    since: Option<DateTime<Local>>,
+    dry_run: bool,
+) -> RusticResult<SyncStats> {
+    let be = &repo.be;
+    let dest = &target.be;
+    let mut stats = SyncStats::default();
+
+    // config: initialize the mirror or check that it is one
+    let config = be.read_full(FileType::Config, &Id::default())?;
+    if dest.list(FileType::Config)?.is_empty() {
+        info!("initializing mirror...");
+    } else {
+        let dest_config = dest.read_full(FileType::Config, &Id::default())?;
+        let dest_config: ConfigFile = repo
+            .dbe()
+            .decrypt(&dest_config)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .ok_or(CommandErrorKind::NotAMirror)?;
+        if dest_config.id != repo.config().id {
+            return Err(CommandErrorKind::NotAMirror.into());
+        }
+    }
+    if !dry_run {
+        dest.write_bytes(FileType::Config, &Id::default(), false, config)?;
+    }
+
+    // keys
+    let keys = missing(be, dest, FileType::Key)?;
+    stats.keys = keys.len() as u64;
+    if !dry_run {
+        transfer(repo, target, FileType::Key, false, &keys, "copying keys...")?;
+    }
+
+    // packs from index files missing in the target
+    let index_ids = missing(be, dest, FileType::Index)?;
+    let p = repo.pb.progress_counter("reading missing index files...");
+    let index_files: Vec<(Id, IndexFile)> = repo
+        .dbe()
+        .stream_list::<IndexFile>(index_ids.clone(), &p)?
+        .into_iter()
+        .collect::<RusticResult<_>>()?;
+    p.finish();
+
+    let dest_packs: HashSet<_> = if since.is_none() {
+        dest.list(FileType::Pack)?.into_iter().collect()
+    } else {
+        HashSet::new()
+    };
+    let mut packs = Vec::new();
+    for (_, index) in &index_files {
+        for pack in &index.packs {
+            if !dest_packs.contains(&pack.id) {
+                stats.packs_size += u64::from(pack.pack_size());
+                packs.push((pack.id, pack.blob_type()));
+            }
+        }
+    }
+    stats.packs = packs.len() as u64;
+
+    // snapshots
+    let snapshots = missing(be, dest, FileType::Snapshot)?;
+    let p = repo.pb.progress_counter("reading missing snapshots...");
+    stats.mark = repo
+        .dbe()
+        .stream_list::<SnapshotFile>(snapshots.clone(), &p)?
+        .into_iter()
+        .map(|snap| snap.map(|(_, snap)| snap.time))
+        .collect::<RusticResult<Vec<_>>>()?
+        .into_iter()
+        .chain(since)
+        .max();
+    p.finish();
+    stats.index_files = index_ids.len() as u64;
+    stats.snapshots = snapshots.len() as u64;
+
+    if dry_run {
+        return Ok(stats);
+    }
+
+    // transfer packs before index files before snapshots, so the mirror is always consistent
+    let p = repo.pb.progress_bytes("copying packs...");
+    p.set_length(stats.packs_size);
+    packs
+        .par_iter()
+        .try_for_each(|(id, tpe)| -> RusticResult<_> {
+            let data = be.read_full(FileType::Pack, id)?;
+            p.inc(data.len() as u64);
+            dest.write_bytes(FileType::Pack, id, *tpe == BlobType::Tree, data)
+        })?;
+    p.finish();
+
+    transfer(
+        repo,
+        target,
+        FileType::Index,
+        true,
+        &index_ids,
+        "copying index files...",
+    )?;
+    transfer(
+        repo,
+        target,
+        FileType::Snapshot,
+        true,
+        &snapshots,
+        "copying snapshots...",
+    )?;
+
+    Ok(stats)
+}
+
+/// List the files of the given type which exist in `be`, but not in `dest`.
+fn missing(be: &impl ReadBackend, dest: &impl ReadBackend, tpe: FileType) -> RusticResult<Vec<Id>> {
+    let existing: HashSet<_> = dest.list(tpe)?.into_iter().collect();
+    Ok(be
+        .list(tpe)?
+        .into_iter()
+        .filter(|id| !existing.contains(id))
+        .collect())
+}
+
+/// Transfer the given files from `repo` to `target` without modifying them.
+fn transfer<P: ProgressBars, S, Q, T>(
+    repo: &Repository<P, S>,
+    target: &Repository<Q, T>,
+    tpe: FileType,
+    cacheable: bool,
+    ids: &[Id],
+    title: &'static str,
+) -> RusticResult<()> {
+    let p = repo.pb.progress_counter(title);
+    p.set_length(ids.len() as u64);
+    ids.par_iter().try_for_each(|id| -> RusticResult<_> {
+        let data = repo.be.read_full(tpe, id)?;
+        target.be.write_bytes(tpe, id, cacheable, data)?;
+        p.inc(1);
+        Ok(())
+    })?;
+    p.finish();
+    Ok(())
+}
@@ -0,0 +1,168 @@
+//! Moving snapshots to the trash and undeleting them
+use chrono::{Duration, Local};
+use itertools::Itertools;
+
+use crate::{
+    backend::{
+        decrypt::{DecryptReadBackend, DecryptWriteBackend},
+        FileType,
+    },
+    commands::journal::remove_journaled,
+    error::{CommandErrorKind, RusticResult},
+    id::Id,
+    progress::{Progress, ProgressBars},
+    repofile::{JournalFile, SnapshotFile, TrashFile},
+    repository::{Open, Repository},
+};
+
+/// Move the given snapshots to the trash.
+///
+/// The trashed snapshots can be undeleted until they expire. Expired snapshots are
+/// removed from the trash by `prune`.
+///
+/// # Type Parameters
+///
+/// * `P` - The progress bar type.
+/// * `S` - The state the repository is in.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+/// * `ids` - The ids of the snapshots to move to the trash
+/// * `keep` - How long the snapshots are kept in the trash
+pub(crate) fn trash_snapshots<P: ProgressBars, S: Open>(
+    repo: &Repository<P, S>,
+    ids: &[Id],
+    keep: Duration,
+) -> RusticResult<()> {
+    let be = repo.dbe();
+    let time = Local::now();
+    let expires = time + keep;
+
+    let p = repo.pb.progress_counter("reading snapshots...");
+    let trash: Vec<_> = be
+        .stream_list::<SnapshotFile>(ids.to_vec(), &p)?
+        .into_iter()
+        .map_ok(|(id, mut snapshot)| {
+            snapshot.id = id;
+            TrashFile {
+                time,
+                expires,
+                snapshot,
+            }
+        })
+        .try_collect()?;
+    p.finish();
+
+    // save the trash files first such that no snapshot is lost if something goes wrong
+    let p = repo.pb.progress_counter("moving snapshots to trash...");
+    be.save_list(trash.iter(), p)?;
+
+    let journal = JournalFile {
+        snapshots: ids.to_vec(),
+        trashed: true,
+        ..JournalFile::new("moving of snapshots to trash")
+    };
+    remove_journaled(repo, &journal)
+}
+
+/// Read all files from the trash.
+///
+/// # Arguments
+///
+/// * `be` - The backend to read from
+/// * `p` - The progress bar to use
+///
+/// # Errors
+///
+/// If the trash could not be listed or a trash file could not be read. These errors must not be
+/// ignored, as `prune` would otherwise remove data which is still needed by trashed snapshots.
+pub(crate) fn read_trash(
+    be: &impl DecryptReadBackend,
+    p: &impl Progress,
+) -> RusticResult<Vec<(Id, TrashFile)>> {
+    let list = be.list(FileType::Trash)?;
+    be.stream_list::<TrashFile>(list, p)?.into_iter().collect()
+}
+
+/// Get all snapshots in the trash.
+///
+/// # Type Parameters
+///
+/// * `P` - The progress bar type.
+/// * `S` - The state the repository is in.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+pub(crate) fn get_trash<P: ProgressBars, S: Open>(
+    repo: &Repository<P, S>,
+) -> RusticResult<Vec<TrashFile>> {
+    let p = repo.pb.progress_counter("reading trash...");
+    let mut trash: Vec<_> = read_trash(repo.dbe(), &p)?
+        .into_iter()
+        .map(|(_, trash)| trash)
+        .collect();
+    p.finish();
+    trash.sort_unstable_by_key(|trash| trash.snapshot.time);
+    Ok(trash)
+}
+
+/// Undelete the given snapshots from the trash.
+///
+/// # Type Parameters
+///
+/// * `P` - The progress bar type.
+/// * `S` - The state the repository is in.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+/// * `ids` - The (original) ids of the snapshots to undelete; parts of ids are resolved.
+///
+/// # Errors
+///
+/// * [`CommandErrorKind::NoUniqueTrashedSnapshot`] - If no unique snapshot is found in the trash for a given id.
+///
+/// # Returns
+///
+/// The undeleted snapshots. As snapshots are saved again, they get new ids.
+pub(crate) fn undelete<P: ProgressBars, S: Open, T: AsRef<str>>(
+    repo: &Repository<P, S>,
+    ids: &[T],
+) -> RusticResult<Vec<SnapshotFile>> {
+    let be = repo.dbe();
+    let p = repo.pb.progress_counter("reading trash...");
+    let trash = read_trash(be, &p)?;
+    p.finish();
+
+    let mut undelete = Vec::new();
+    for id in ids {
+        let id = id.as_ref();
+        let mut found = trash
+            .iter()
+            .filter(|(_, trash)| trash.snapshot.id.to_hex().starts_with(id));
+        match (found.next(), found.next()) {
+            (Some(item), None) => {
+                if !undelete.iter().any(|(trash_id, _)| trash_id == &item.0) {
+                    undelete.push(item);
+                }
+            }
+            _ => return Err(CommandErrorKind::NoUniqueTrashedSnapshot(id.to_string()).into()),
+        }
+    }
+
+    let mut snaps = Vec::new();
+    for (_, trash) in &undelete {
+        let mut snap = trash.snapshot.clone();
+        snap.id = Id::default();
+        snap.id = be.save_file(&snap)?;
+        snaps.push(snap);
+    }
+
+    let trash_ids: Vec<_> = undelete.iter().map(|(id, _)| *id).collect();
+    let p = repo.pb.progress_counter("removing from trash...");
+    be.delete_list(FileType::Trash, false, trash_ids.iter(), p)?;
+
+    Ok(snaps)
+}
@@ -1,7 +1,34 @@
+use serde::{Deserialize, Serialize};
+
 use crate::RusticResult;
 
 pub(crate) mod aespoly1305;
+pub(crate) mod fido2;
 pub(crate) mod hasher;
+pub(crate) mod kms;
+pub(crate) mod pkcs11;
+pub(crate) mod plain;
+pub(crate) mod shamir;
+pub(crate) mod xchacha20poly1305;
+
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+/// Cipher used to encrypt the repository data
+pub enum Cipher {
+    /// AES-256 in counter mode with Poly1305-AES (default, compatible with restic)
+    #[default]
+    #[cfg_attr(feature = "clap", value(name = "aes256-poly1305"))]
+    #[serde(rename = "aes256-poly1305")]
+    Aes256Poly1305,
+    /// XChaCha20-Poly1305, faster on machines without hardware AES support (not supported by restic)
+    #[cfg_attr(feature = "clap", value(name = "xchacha20-poly1305"))]
+    #[serde(rename = "xchacha20-poly1305")]
+    XChaCha20Poly1305,
+    /// No encryption, only a SHA-256 hash is added to detect corrupted data. Use this only for storage which
+    /// is already encrypted or for testing (not supported by restic)
+    None,
+}
 
 /// A trait for encrypting and decrypting data.
 pub trait CryptoKey: Clone + Sized + Send + Sync + 'static {
@@ -4,7 +4,11 @@ use aes256ctr_poly1305aes::{
 };
 use rand::{thread_rng, RngCore};
 
-use crate::{crypto::CryptoKey, error::CryptoErrorKind, error::RusticResult};
+use crate::{
+    crypto::{plain::PlainKey, xchacha20poly1305::XChaChaKey, Cipher, CryptoKey},
+    error::CryptoErrorKind,
+    error::RusticResult,
+};
 
 pub(crate) type Nonce = aead::Nonce<Aes256CtrPoly1305Aes>;
 pub(crate) type AeadKey = aead::Key<Aes256CtrPoly1305Aes>;
@@ -19,8 +23,27 @@ pub(crate) type AeadKey = aead::Key<Aes256CtrPoly1305Aes>;
 ///
 /// The last 16 bytes are used for the number `r` of `Poly1305AES`.
 ///
+/// If the [`Cipher`] is set to `XChaCha20Poly1305`, a key for `XChaCha20Poly1305` is derived once from the
+/// whole 64 byte key and used instead. If it is set to `None`, the key is not used at all.
+///
+/// # Notes
+///
+/// As the `Key` is `Copy`, it is not wiped from memory on drop. All intermediate secrets like the
+/// decrypted key files or the output of the key derivation function are wiped, though.
 #[derive(Clone, Default, Debug, Copy)]
-pub struct Key(AeadKey);
+pub struct Key(AeadKey, CipherKey);
+
+/// The cipher used by a [`Key`] together with the key derived for it
+#[derive(Clone, Default, Debug, Copy)]
+enum CipherKey {
+    /// `AES256` with `Poly1305AES` using the [`Key`] itself
+    #[default]
+    Aes256Poly1305,
+    /// `XChaCha20Poly1305` using the derived key
+    XChaCha20Poly1305(XChaChaKey),
+    /// No encryption
+    None,
+}
 
 impl Key {
     /// Create a new random [`Key`] using a suitable entropy source.
@@ -28,7 +51,7 @@ impl Key {
     pub fn new() -> Self {
         let mut key = AeadKey::default();
         thread_rng().fill_bytes(&mut key);
-        Self(key)
+        Self(key, CipherKey::default())
     }
 
     /// Create a new [`Key`] from a slice.
@@ -38,7 +61,7 @@ impl Key {
     /// * `key` - The slice to create the [`Key`] from.
     #[must_use]
     pub fn from_slice(key: &[u8]) -> Self {
-        Self(*AeadKey::from_slice(key))
+        Self(*AeadKey::from_slice(key), CipherKey::default())
     }
 
     /// Create a new [`Key`] from the AES key and numbers `k` and `r` for `Poly1305AES`.
@@ -55,7 +78,7 @@ impl Key {
         key[32..48].copy_from_slice(k);
         key[48..64].copy_from_slice(r);
 
-        Self(key)
+        Self(key, CipherKey::default())
     }
 
     /// Returns the AES key and numbers `k`and `r` for `Poly1305AES`.
@@ -70,6 +93,21 @@ impl Key {
 
         (encrypt, k, r)
     }
+
+    /// Returns this [`Key`] using the given [`Cipher`] to encrypt and decrypt data.
+    ///
+    /// # Arguments
+    ///
+    /// * `cipher` - The cipher to use.
+    #[must_use]
+    pub fn with_cipher(self, cipher: Cipher) -> Self {
+        let cipher_key = match cipher {
+            Cipher::Aes256Poly1305 => CipherKey::Aes256Poly1305,
+            Cipher::XChaCha20Poly1305 => CipherKey::XChaCha20Poly1305(XChaChaKey::derive(&self.0)),
+            Cipher::None => CipherKey::None,
+        };
+        Self(self.0, cipher_key)
+    }
 }
 
 impl CryptoKey for Key {
@@ -83,6 +121,11 @@ impl CryptoKey for Key {
     ///
     /// If the MAC couldn't be checked.
     fn decrypt_data(&self, data: &[u8]) -> RusticResult<Vec<u8>> {
+        match &self.1 {
+            CipherKey::XChaCha20Poly1305(key) => return key.decrypt_data(data),
+            CipherKey::None => return PlainKey.decrypt_data(data),
+            CipherKey::Aes256Poly1305 => {}
+        }
         if data.len() < 16 {
             return Err(CryptoErrorKind::CryptoKeyTooShort)?;
         }
@@ -103,6 +146,11 @@ impl CryptoKey for Key {
     ///
     /// If the data could not be encrypted.
     fn encrypt_data(&self, data: &[u8]) -> RusticResult<Vec<u8>> {
+        match &self.1 {
+            CipherKey::XChaCha20Poly1305(key) => return key.encrypt_data(data),
+            CipherKey::None => return PlainKey.encrypt_data(data),
+            CipherKey::Aes256Poly1305 => {}
+        }
         let mut nonce = Nonce::default();
         thread_rng().fill_bytes(&mut nonce);
 
@@ -139,6 +187,30 @@ mod tests {
         assert_eq!(data, dec);
     }
 
+    #[test]
+    fn encrypt_decrypt_xchacha() {
+        let key = Key::new().with_cipher(Cipher::XChaCha20Poly1305);
+        let data: Vec<u8> = b"Hello!".to_vec();
+        let enc = key.encrypt_data(&data).unwrap();
+        assert_eq!(data, key.decrypt_data(&enc).unwrap());
+        // data encrypted with another cipher must not be decryptable
+        assert!(key
+            .with_cipher(Cipher::Aes256Poly1305)
+            .decrypt_data(&enc)
+            .is_err());
+    }
+
+    #[test]
+    fn encrypt_decrypt_none() {
+        let key = Key::new().with_cipher(Cipher::None);
+        let data: Vec<u8> = b"Hello!".to_vec();
+        let mut enc = key.encrypt_data(&data).unwrap();
+        assert_eq!(data, key.decrypt_data(&enc).unwrap());
+        // corrupted data must be detected
+        enc[0] ^= 1;
+        assert!(key.decrypt_data(&enc).is_err());
+    }
+
     #[test]
     fn decrypt_empty() {
         let key = Key::default();
@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+use serde_with::{base64::Base64, serde_as};
+
+use crate::error::{KeyFileErrorKind, RusticResult};
+
+pub(super) mod constants {
+    /// The relying party id used for credentials created by rustic
+    pub(super) const RP_ID: &str = "rustic";
+}
+
+/// Parameters to obtain a secret from a FIDO2 security key using the `hmac-secret` extension.
+///
+/// The security key computes a HMAC of the salt using a secret bound to the credential.
+/// This secret never leaves the security key, so the result can only be obtained with the
+/// physical security key present.
+#[serde_as]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct Fido2Params {
+    /// The relying party id the credential was created for
+    rp_id: String,
+
+    /// The id of the credential on the security key
+    #[serde_as(as = "Base64")]
+    credential: Vec<u8>,
+
+    /// The salt to compute the HMAC of
+    #[serde_as(as = "Base64")]
+    salt: Vec<u8>,
+}
+
+#[cfg(feature = "fido2")]
+impl Fido2Params {
+    /// Create a new credential with the `hmac-secret` extension on the connected security key.
+    ///
+    /// # Errors
+    ///
+    /// * [`KeyFileErrorKind::Fido2Failed`] - If no security key is connected or creating the credential failed
+    pub(crate) fn create() -> RusticResult<Self> {
+        use ctap_hid_fido2::{
+            fidokey::{
+                make_credential::make_credential_params::Extension, MakeCredentialArgsBuilder,
+            },
+            verifier, FidoKeyHidFactory, LibCfg,
+        };
+        use log::info;
+        use rand::{thread_rng, RngCore};
+
+        let device = FidoKeyHidFactory::create(&LibCfg::init()).map_err(fido2_err)?;
+        let challenge = verifier::create_challenge();
+        let args = MakeCredentialArgsBuilder::new(constants::RP_ID, &challenge)
+            .extensions(&[Extension::HmacSecret(Some(true))])
+            .without_pin_and_uv()
+            .build();
+        info!("creating credential, please touch your security key...");
+        let attestation = device.make_credential_with_args(&args).map_err(fido2_err)?;
+
+        let mut salt = vec![0; 32];
+        thread_rng().fill_bytes(&mut salt);
+        Ok(Self {
+            rp_id: constants::RP_ID.to_string(),
+            credential: attestation.credential_descriptor.id,
+            salt,
+        })
+    }
+
+    /// Obtain the secret from the connected security key.
+    ///
+    /// # Errors
+    ///
+    /// * [`KeyFileErrorKind::Fido2Failed`] - If no security key is connected or the assertion failed
+    /// * [`KeyFileErrorKind::Fido2NoSecret`] - If the security key didn't return a secret
+    pub(crate) fn secret(&self) -> RusticResult<[u8; 32]> {
+        use ctap_hid_fido2::{
+            fidokey::{get_assertion::get_assertion_params::Extension, GetAssertionArgsBuilder},
+            verifier, FidoKeyHidFactory, LibCfg,
+        };
+        use log::info;
+
+        let salt: [u8; 32] = self
+            .salt
+            .as_slice()
+            .try_into()
+            .map_err(|_| KeyFileErrorKind::Fido2NoSecret)?;
+        let device = FidoKeyHidFactory::create(&LibCfg::init()).map_err(fido2_err)?;
+        let challenge = verifier::create_challenge();
+        let args = GetAssertionArgsBuilder::new(&self.rp_id, &challenge)
+            .credential_id(&self.credential)
+            .extensions(&[Extension::HmacSecret(Some(salt))])
+            .without_pin_and_uv()
+            .build();
+        info!("please touch your security key...");
+        let assertions = device.get_assertion_with_args(&args).map_err(fido2_err)?;
+
+        assertions
+            .iter()
+            .flat_map(|assertion| &assertion.extensions)
+            .find_map(|ext| match ext {
+                Extension::HmacSecret(secret) => *secret,
+                _ => None,
+            })
+            .ok_or_else(|| KeyFileErrorKind::Fido2NoSecret.into())
+    }
+}
+
+/// Map an error of the security key to a [`KeyFileErrorKind`]
+#[cfg(feature = "fido2")]
+fn fido2_err(err: impl std::fmt::Display) -> KeyFileErrorKind {
+    KeyFileErrorKind::Fido2Failed(err.to_string())
+}
+
+#[cfg(not(feature = "fido2"))]
+impl Fido2Params {
+    /// Create a new credential on the connected security key.
+    ///
+    /// # Errors
+    ///
+    /// * [`KeyFileErrorKind::Fido2NotSupported`] - Always, as rustic was compiled without FIDO2 support
+    pub(crate) fn create() -> RusticResult<Self> {
+        Err(KeyFileErrorKind::Fido2NotSupported.into())
+    }
+
+    /// Obtain the secret from the connected security key.
+    ///
+    /// # Errors
+    ///
+    /// * [`KeyFileErrorKind::Fido2NotSupported`] - Always, as rustic was compiled without FIDO2 support
+    pub(crate) fn secret(&self) -> RusticResult<[u8; 32]> {
+        Err(KeyFileErrorKind::Fido2NotSupported.into())
+    }
+}
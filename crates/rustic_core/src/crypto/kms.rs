@@ -0,0 +1,300 @@
+//! Wrapping of master keys by an external key management system (KMS)
+use std::{
+    error::Error,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use serde_with::{base64::Base64, serde_as};
+
+use crate::{
+    crypto::pkcs11::Pkcs11,
+    error::{KeyFileErrorKind, RusticResult},
+};
+
+/// Result type of a [`KeyWrapper`]. Errors are reported to the user as they are.
+pub type KeyWrapperResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// A key management system (KMS) which wraps (encrypts) and unwraps (decrypts) master keys
+/// using a key which never leaves the KMS.
+///
+/// Key files wrapped by a KMS contain the [`WrappedKey`] parameters. As key files are not authenticated,
+/// these are only used to find the key file fitting to the locally configured [`KeyWrapper`] and
+/// never to decide which KMS to contact.
+pub trait KeyWrapper {
+    /// The parameters which identify the KMS and the key within the KMS.
+    ///
+    /// These are saved in the key file and must match when unwrapping the key.
+    fn params(&self) -> WrappedKey;
+
+    /// Wrap the given data.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data to wrap.
+    fn wrap(&self, data: &[u8]) -> KeyWrapperResult<Vec<u8>>;
+
+    /// Unwrap data which has been wrapped by [`KeyWrapper::wrap`].
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The wrapped data.
+    fn unwrap(&self, data: &[u8]) -> KeyWrapperResult<Vec<u8>>;
+}
+
+/// Information about the KMS which wrapped the master key of a key file
+#[serde_with::apply(Option => #[serde(default, skip_serializing_if = "Option::is_none")])]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WrappedKey {
+    /// Name of the KMS, e.g. `aws-kms` or `vault-transit`
+    pub kms: String,
+
+    /// Id of the key within the KMS
+    pub key_id: String,
+
+    /// Address of the KMS, if needed
+    pub address: Option<String>,
+}
+
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+/// Key management systems supported by rustic
+pub enum Kms {
+    /// AWS KMS, accessed using the `aws` command line interface and its configured credentials
+    AwsKms,
+    /// `HashiCorp` Vault transit secrets engine, authenticated by the token given in `VAULT_TOKEN`
+    VaultTransit,
+    /// RSA key pair on a PKCS#11 token like a smartcard or HSM, unlocked by the PIN given in `PKCS11_PIN`
+    Pkcs11,
+}
+
+impl Kms {
+    /// The name of the KMS saved in [`WrappedKey`]
+    pub(crate) const fn name(self) -> &'static str {
+        match self {
+            Self::AwsKms => "aws-kms",
+            Self::VaultTransit => "vault-transit",
+            Self::Pkcs11 => "pkcs11",
+        }
+    }
+
+    /// Create the [`KeyWrapper`] for the given key of this KMS.
+    ///
+    /// # Arguments
+    ///
+    /// * `key_id` - The id of the key within the KMS
+    /// * `address` - The address of the KMS, needed for Vault. Defaults to `VAULT_ADDR` for Vault.
+    ///   For PKCS#11, this is the absolute path of the PKCS#11 module and the slot id given as
+    ///   `<module>#<slot>`, which defaults to `PKCS11_MODULE`.
+    ///
+    /// # Errors
+    ///
+    /// * [`KeyFileErrorKind::KmsAddressMissing`] - If the KMS needs an address, but none is given
+    /// * [`KeyFileErrorKind::InvalidPkcs11Address`] - If the PKCS#11 address is invalid
+    pub fn wrapper(
+        self,
+        key_id: String,
+        address: Option<String>,
+    ) -> RusticResult<Box<dyn KeyWrapper>> {
+        Ok(match self {
+            Self::AwsKms => Box::new(AwsKms { key_id }),
+            Self::VaultTransit => Box::new(VaultTransit {
+                address: address
+                    .or_else(|| std::env::var("VAULT_ADDR").ok())
+                    .ok_or(KeyFileErrorKind::KmsAddressMissing(self.name()))?,
+                key_id,
+            }),
+            Self::Pkcs11 => Box::new(Pkcs11::new(
+                &address
+                    .or_else(|| std::env::var("PKCS11_MODULE").ok())
+                    .ok_or(KeyFileErrorKind::KmsAddressMissing(self.name()))?,
+                key_id,
+            )?),
+        })
+    }
+}
+
+/// Wrap keys using AWS KMS.
+///
+/// This calls the `aws` command line interface, so its credentials and region configuration are used.
+#[derive(Debug, Clone)]
+struct AwsKms {
+    /// Key id, ARN or alias of the KMS key
+    key_id: String,
+}
+
+/// Output of `aws kms encrypt`
+#[serde_as]
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AwsEncryptOutput {
+    /// The wrapped data
+    #[serde_as(as = "Base64")]
+    ciphertext_blob: Vec<u8>,
+}
+
+/// Output of `aws kms decrypt`
+#[serde_as]
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AwsDecryptOutput {
+    /// The unwrapped data
+    #[serde_as(as = "Base64")]
+    plaintext: Vec<u8>,
+}
+
+impl AwsKms {
+    /// Run `aws kms` with the given arguments and pass `data` using stdin
+    fn run(args: &[&str], data: &[u8]) -> KeyWrapperResult<Vec<u8>> {
+        let mut child = Command::new("aws")
+            .arg("kms")
+            .args(args)
+            .args(["--output", "json"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        child
+            .stdin
+            .take()
+            .ok_or("cannot write to stdin of aws")?
+            .write_all(data)?;
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(format!(
+                "aws kms failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        Ok(output.stdout)
+    }
+}
+
+impl KeyWrapper for AwsKms {
+    fn params(&self) -> WrappedKey {
+        WrappedKey {
+            kms: Kms::AwsKms.name().to_string(),
+            key_id: self.key_id.clone(),
+            address: None,
+        }
+    }
+
+    fn wrap(&self, data: &[u8]) -> KeyWrapperResult<Vec<u8>> {
+        let output = Self::run(
+            &[
+                "encrypt",
+                "--key-id",
+                &self.key_id,
+                "--plaintext",
+                "fileb:///dev/stdin",
+            ],
+            data,
+        )?;
+        Ok(serde_json::from_slice::<AwsEncryptOutput>(&output)?.ciphertext_blob)
+    }
+
+    fn unwrap(&self, data: &[u8]) -> KeyWrapperResult<Vec<u8>> {
+        let output = Self::run(
+            &[
+                "decrypt",
+                "--key-id",
+                &self.key_id,
+                "--ciphertext-blob",
+                "fileb:///dev/stdin",
+            ],
+            data,
+        )?;
+        Ok(serde_json::from_slice::<AwsDecryptOutput>(&output)?.plaintext)
+    }
+}
+
+/// Wrap keys using the transit secrets engine of `HashiCorp` Vault.
+///
+/// The token is read from the environment variable `VAULT_TOKEN`.
+#[derive(Debug, Clone)]
+struct VaultTransit {
+    /// Address of the Vault server, e.g. `https://vault:8200`
+    address: String,
+    /// Path of the transit key including the mount, e.g. `transit/keys/rustic`
+    key_id: String,
+}
+
+/// Request and response data of the Vault transit engine
+#[serde_as]
+#[serde_with::apply(Option => #[serde(default, skip_serializing_if = "Option::is_none")])]
+#[derive(Serialize, Deserialize, Default)]
+struct VaultTransitData {
+    /// The unwrapped data
+    #[serde_as(as = "Option<Base64>")]
+    plaintext: Option<Vec<u8>>,
+    /// The wrapped data, e.g. `vault:v1:...`
+    ciphertext: Option<String>,
+}
+
+/// Response of the Vault transit engine
+#[derive(Deserialize)]
+struct VaultTransitResponse {
+    /// The response data
+    data: VaultTransitData,
+}
+
+impl VaultTransit {
+    /// Call the given operation of the transit engine
+    fn call(&self, operation: &str, data: &VaultTransitData) -> KeyWrapperResult<VaultTransitData> {
+        let token = std::env::var("VAULT_TOKEN").map_err(|_| "VAULT_TOKEN is not set")?;
+        // the key id is given as `<mount>/keys/<name>`, the operations are `<mount>/<operation>/<name>`
+        let (mount, name) = self
+            .key_id
+            .rsplit_once("/keys/")
+            .ok_or("key id must be given as <mount>/keys/<name>")?;
+        let url = format!(
+            "{}/v1/{mount}/{operation}/{name}",
+            self.address.trim_end_matches('/')
+        );
+        let response: VaultTransitResponse = Client::new()
+            .post(url)
+            .header("X-Vault-Token", token)
+            .json(data)
+            .send()?
+            .error_for_status()?
+            .json()?;
+        Ok(response.data)
+    }
+}
+
+impl KeyWrapper for VaultTransit {
+    fn params(&self) -> WrappedKey {
+        WrappedKey {
+            kms: Kms::VaultTransit.name().to_string(),
+            key_id: self.key_id.clone(),
+            address: Some(self.address.clone()),
+        }
+    }
+
+    fn wrap(&self, data: &[u8]) -> KeyWrapperResult<Vec<u8>> {
+        let request = VaultTransitData {
+            plaintext: Some(data.to_vec()),
+            ..Default::default()
+        };
+        let ciphertext = self
+            .call("encrypt", &request)?
+            .ciphertext
+            .ok_or("vault returned no ciphertext")?;
+        Ok(ciphertext.into_bytes())
+    }
+
+    fn unwrap(&self, data: &[u8]) -> KeyWrapperResult<Vec<u8>> {
+        let request = VaultTransitData {
+            ciphertext: Some(String::from_utf8(data.to_vec())?),
+            ..Default::default()
+        };
+        Ok(self
+            .call("decrypt", &request)?
+            .plaintext
+            .ok_or("vault returned no plaintext")?)
+    }
+}
@@ -0,0 +1,200 @@
+//! Wrapping of master keys by an RSA key pair on a PKCS#11 token like a smartcard or HSM
+use std::path::Path;
+
+use crate::{
+    crypto::kms::{KeyWrapper, KeyWrapperResult, Kms, WrappedKey},
+    error::{KeyFileErrorKind, RusticResult},
+};
+
+pub(super) mod constants {
+    /// Environment variable containing the PIN of the token
+    pub(super) const PIN_ENV: &str = "PKCS11_PIN";
+}
+
+/// Wrap keys using an RSA key pair on a PKCS#11 token.
+///
+/// Wrapping only uses the public key. For unwrapping, rustic logs in to the token using the PIN given
+/// in `PKCS11_PIN`. If it is not set, the protected authentication path of the token (e.g. a PIN pad) is used.
+///
+/// The module is loaded into the rustic process, so its path and the slot of the token must always be
+/// given locally as `<module>#<slot>` and are never read from a key file.
+#[derive(Debug, Clone)]
+pub(crate) struct Pkcs11 {
+    /// Absolute path of the PKCS#11 module, e.g. `/usr/lib/opensc-pkcs11.so`
+    module: String,
+    /// Id of the slot containing the token
+    slot: u64,
+    /// Hex id of the key pair on the token
+    key_id: String,
+}
+
+impl Pkcs11 {
+    /// Create a new [`Pkcs11`] key wrapper.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Absolute path of the PKCS#11 module and the slot id, given as `<module>#<slot>`
+    /// * `key_id` - Hex id of the key pair on the token
+    ///
+    /// # Errors
+    ///
+    /// * [`KeyFileErrorKind::InvalidPkcs11Address`] - If the address is no absolute path followed by a slot id
+    pub(crate) fn new(address: &str, key_id: String) -> RusticResult<Self> {
+        let invalid = || KeyFileErrorKind::InvalidPkcs11Address(address.to_string());
+        let (module, slot) = address.rsplit_once('#').ok_or_else(invalid)?;
+        // a relative path would be searched in the library search path
+        if !Path::new(module).is_absolute() {
+            return Err(invalid().into());
+        }
+        let slot = slot.parse().map_err(|_| invalid())?;
+        Ok(Self {
+            module: module.to_string(),
+            slot,
+            key_id,
+        })
+    }
+}
+
+#[cfg(feature = "pkcs11")]
+impl Pkcs11 {
+    /// Find the key of the given class on the token in the configured slot and run `f` with it.
+    ///
+    /// # Arguments
+    ///
+    /// * `class` - The class of the key, i.e. public or private key
+    /// * `login` - Whether to log in to the token before searching the key
+    /// * `f` - The operation to run with the key
+    fn with_key<T>(
+        &self,
+        class: cryptoki::object::ObjectClass,
+        login: bool,
+        f: impl Fn(&cryptoki::session::Session, cryptoki::object::ObjectHandle) -> KeyWrapperResult<T>,
+    ) -> KeyWrapperResult<T> {
+        use cryptoki::{
+            context::{CInitializeArgs, Pkcs11 as Context},
+            object::Attribute,
+            session::UserType,
+            types::AuthPin,
+        };
+
+        let id = hex::decode(&self.key_id)?;
+        let context = Context::new(&self.module)?;
+        context.initialize(CInitializeArgs::OsThreads)?;
+        let slot = context
+            .get_slots_with_token()?
+            .into_iter()
+            .find(|slot| slot.id() == self.slot)
+            .ok_or_else(|| format!("no PKCS#11 token found in slot {}", self.slot))?;
+        let session = context.open_ro_session(slot)?;
+        if login {
+            let pin = std::env::var(constants::PIN_ENV).ok().map(AuthPin::new);
+            session.login(UserType::User, pin.as_ref())?;
+        }
+        let keys = session.find_objects(&[Attribute::Class(class), Attribute::Id(id)])?;
+        let key = keys.first().ok_or_else(|| {
+            format!(
+                "no key with id {} found on the PKCS#11 token in slot {}",
+                self.key_id, self.slot
+            )
+        })?;
+        f(&session, *key)
+    }
+
+    /// Encrypt the data using the public key.
+    fn encrypt(&self, data: &[u8]) -> KeyWrapperResult<Vec<u8>> {
+        self.with_key(
+            cryptoki::object::ObjectClass::PUBLIC_KEY,
+            false,
+            |session, key| Ok(session.encrypt(&Self::mechanism(), key, data)?),
+        )
+    }
+
+    /// Decrypt the data using the private key.
+    fn decrypt(&self, data: &[u8]) -> KeyWrapperResult<Vec<u8>> {
+        self.with_key(
+            cryptoki::object::ObjectClass::PRIVATE_KEY,
+            true,
+            |session, key| Ok(session.decrypt(&Self::mechanism(), key, data)?),
+        )
+    }
+
+    /// The mechanism used to wrap and unwrap keys, i.e. RSA-OAEP with SHA-256
+    fn mechanism() -> cryptoki::mechanism::Mechanism<'static> {
+        use cryptoki::mechanism::{
+            rsa::{PkcsMgfType, PkcsOaepParams, PkcsOaepSource},
+            Mechanism, MechanismType,
+        };
+
+        Mechanism::RsaPkcsOaep(PkcsOaepParams::new(
+            MechanismType::SHA256,
+            PkcsMgfType::MGF1_SHA256,
+            PkcsOaepSource::empty(),
+        ))
+    }
+}
+
+#[cfg(not(feature = "pkcs11"))]
+impl Pkcs11 {
+    /// Encrypt the data using the public key.
+    ///
+    /// # Errors
+    ///
+    /// Always, as rustic was compiled without PKCS#11 support
+    fn encrypt(&self, _data: &[u8]) -> KeyWrapperResult<Vec<u8>> {
+        Err("rustic has been compiled without PKCS#11 support".into())
+    }
+
+    /// Decrypt the data using the private key.
+    ///
+    /// # Errors
+    ///
+    /// Always, as rustic was compiled without PKCS#11 support
+    fn decrypt(&self, _data: &[u8]) -> KeyWrapperResult<Vec<u8>> {
+        Err("rustic has been compiled without PKCS#11 support".into())
+    }
+}
+
+impl KeyWrapper for Pkcs11 {
+    fn params(&self) -> WrappedKey {
+        WrappedKey {
+            kms: Kms::Pkcs11.name().to_string(),
+            key_id: self.key_id.clone(),
+            address: Some(format!("{}#{}", self.module, self.slot)),
+        }
+    }
+
+    fn wrap(&self, data: &[u8]) -> KeyWrapperResult<Vec<u8>> {
+        self.encrypt(data)
+    }
+
+    fn unwrap(&self, data: &[u8]) -> KeyWrapperResult<Vec<u8>> {
+        self.decrypt(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn module_and_slot_are_parsed() {
+        let pkcs11 = Pkcs11::new("/usr/lib/opensc-pkcs11.so#1", "01".to_string()).unwrap();
+        assert_eq!(pkcs11.module, "/usr/lib/opensc-pkcs11.so");
+        assert_eq!(pkcs11.slot, 1);
+        assert_eq!(
+            pkcs11.params().address.as_deref(),
+            Some("/usr/lib/opensc-pkcs11.so#1")
+        );
+    }
+
+    #[test]
+    fn invalid_addresses_are_rejected() {
+        for address in [
+            "/usr/lib/opensc-pkcs11.so",
+            "opensc-pkcs11.so#0",
+            "/usr/lib/opensc-pkcs11.so#slot",
+        ] {
+            assert!(Pkcs11::new(address, "01".to_string()).is_err(), "{address}");
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use sha2::{Digest, Sha256};
+
+use crate::{
+    crypto::CryptoKey,
+    error::{CryptoErrorKind, RusticResult},
+};
+
+pub(super) mod constants {
+    /// Length of the appended hash in bytes
+    pub(super) const HASH_LEN: usize = 32;
+}
+
+/// "Key" for unencrypted repositories.
+///
+/// The data is not encrypted, but its SHA-256 hash is appended. This detects corrupted data
+/// like the MAC of the ciphers, but of course doesn't protect against intentional modifications.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct PlainKey;
+
+impl CryptoKey for PlainKey {
+    /// Returns the data after checking the appended hash.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data followed by its hash.
+    ///
+    /// # Errors
+    ///
+    /// If the hash doesn't match the data.
+    fn decrypt_data(&self, data: &[u8]) -> RusticResult<Vec<u8>> {
+        if data.len() < constants::HASH_LEN {
+            return Err(CryptoErrorKind::CryptoKeyTooShort)?;
+        }
+
+        let (data, hash) = data.split_at(data.len() - constants::HASH_LEN);
+        if Sha256::digest(data).as_slice() != hash {
+            return Err(CryptoErrorKind::DecryptionFailed)?;
+        }
+        Ok(data.to_vec())
+    }
+
+    /// Returns the data followed by its hash.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data to "encrypt".
+    fn encrypt_data(&self, data: &[u8]) -> RusticResult<Vec<u8>> {
+        let mut res = Vec::with_capacity(data.len() + constants::HASH_LEN);
+        res.extend_from_slice(data);
+        res.extend_from_slice(&Sha256::digest(data));
+        Ok(res)
+    }
+}
@@ -0,0 +1,118 @@
+//! Shamir's secret sharing over GF(2^8)
+use rand::{thread_rng, RngCore};
+use zeroize::Zeroize;
+
+/// Multiply two elements of GF(2^8) using the AES polynomial `x^8 + x^4 + x^3 + x + 1`
+const fn mul(mut a: u8, mut b: u8) -> u8 {
+    let mut res = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            res ^= a;
+        }
+        let carry = a & 0x80;
+        a <<= 1;
+        if carry != 0 {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    res
+}
+
+/// Get the multiplicative inverse of a non-zero element of GF(2^8), i.e. `a^254`
+const fn inv(a: u8) -> u8 {
+    let mut res = 1;
+    let mut base = a;
+    let mut exp = 254;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            res = mul(res, base);
+        }
+        base = mul(base, base);
+        exp >>= 1;
+    }
+    res
+}
+
+/// Split a secret into `n` shares such that any `k` of them can recover it.
+///
+/// Every byte of the secret is shared using a random polynomial of degree `k - 1`.
+///
+/// # Arguments
+///
+/// * `secret` - The secret to split
+/// * `k` - The number of shares needed to recover the secret, must be at least 1
+/// * `n` - The number of shares to create, must be at least `k`
+///
+/// # Returns
+///
+/// The shares as pairs of their index (which is never 0) and their data
+pub(crate) fn split(secret: &[u8], k: u8, n: u8) -> Vec<(u8, Vec<u8>)> {
+    let mut shares: Vec<_> = (1..=n)
+        .map(|x| (x, Vec::with_capacity(secret.len())))
+        .collect();
+    let mut coefficients = vec![0; usize::from(k)];
+    for byte in secret {
+        coefficients[0] = *byte;
+        thread_rng().fill_bytes(&mut coefficients[1..]);
+        for (x, share) in &mut shares {
+            // evaluate the polynomial at x using Horner's method
+            let y = coefficients
+                .iter()
+                .rev()
+                .fold(0, |acc, coefficient| mul(acc, *x) ^ coefficient);
+            share.push(y);
+        }
+    }
+    // the constant coefficient is the last byte of the secret
+    coefficients.zeroize();
+    shares
+}
+
+/// Recover a secret from shares created by [`split`].
+///
+/// The shares must have distinct indices. If less shares than needed are given, the result is garbage.
+///
+/// # Arguments
+///
+/// * `shares` - The shares as pairs of their index and their data
+pub(crate) fn combine(shares: &[(u8, Vec<u8>)]) -> Vec<u8> {
+    let len = shares.iter().map(|(_, data)| data.len()).min().unwrap_or(0);
+    // Lagrange basis polynomials evaluated at 0
+    let basis: Vec<_> = shares
+        .iter()
+        .map(|(xj, _)| {
+            shares
+                .iter()
+                .filter(|(xm, _)| xm != xj)
+                .fold(1, |acc, (xm, _)| mul(acc, mul(*xm, inv(xm ^ xj))))
+        })
+        .collect();
+    (0..len)
+        .map(|i| {
+            shares
+                .iter()
+                .zip(&basis)
+                .fold(0, |acc, ((_, data), l)| acc ^ mul(data[i], *l))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_and_combine() {
+        let secret: Vec<u8> = (0..=255).collect();
+        let shares = split(&secret, 3, 5);
+        assert_eq!(shares.len(), 5);
+        assert_eq!(combine(&shares[0..3]), secret);
+        assert_eq!(
+            combine(&[shares[4].clone(), shares[1].clone(), shares[2].clone()]),
+            secret
+        );
+        assert_eq!(combine(&shares), secret);
+        assert_ne!(combine(&shares[0..2]), secret);
+    }
+}
@@ -0,0 +1,96 @@
+use chacha20poly1305::{
+    aead::{Aead, AeadInPlace, KeyInit},
+    XChaCha20Poly1305, XNonce,
+};
+use rand::{thread_rng, RngCore};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    crypto::{aespoly1305::AeadKey, CryptoKey},
+    error::CryptoErrorKind,
+    error::RusticResult,
+};
+
+pub(super) mod constants {
+    /// Context used to derive the `XChaCha20Poly1305` key from the repository key
+    pub(super) const KEY_CONTEXT: &[u8] = b"rustic xchacha20-poly1305 key";
+    /// Length of the nonce in bytes
+    pub(super) const NONCE_LEN: usize = 24;
+}
+
+/// Key for `XChaCha20Poly1305`.
+///
+/// The encrypted data consists of a random 24 byte nonce, followed by the ciphertext and a 16 byte tag.
+/// The long nonce makes it safe to use random nonces for a very large number of encrypted files.
+///
+/// # Notes
+///
+/// The key is derived once and saved within the repository [`Key`], so like it, it is `Copy` and
+/// not wiped from memory on drop.
+///
+/// [`Key`]: crate::crypto::aespoly1305::Key
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct XChaChaKey(chacha20poly1305::Key);
+
+impl XChaChaKey {
+    /// Derive the `XChaCha20Poly1305` key from the 64 byte repository key.
+    ///
+    /// A separate key is derived, so that no key material is shared with `AES256` and `Poly1305AES`
+    /// which are still used for the config file.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The repository key.
+    pub(crate) fn derive(key: &AeadKey) -> Self {
+        let hash = Sha256::new()
+            .chain_update(constants::KEY_CONTEXT)
+            .chain_update(key)
+            .finalize();
+        Self(hash)
+    }
+}
+
+impl CryptoKey for XChaChaKey {
+    /// Returns the decrypted data from the given encrypted/MACed data.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The encrypted/MACed data.
+    ///
+    /// # Errors
+    ///
+    /// If the MAC couldn't be checked.
+    fn decrypt_data(&self, data: &[u8]) -> RusticResult<Vec<u8>> {
+        if data.len() < constants::NONCE_LEN {
+            return Err(CryptoErrorKind::CryptoKeyTooShort)?;
+        }
+
+        let nonce = XNonce::from_slice(&data[0..constants::NONCE_LEN]);
+        XChaCha20Poly1305::new(&self.0)
+            .decrypt(nonce, &data[constants::NONCE_LEN..])
+            .map_err(|_| CryptoErrorKind::DecryptionFailed.into())
+    }
+
+    /// Returns the encrypted+MACed data from the given data.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data to encrypt.
+    ///
+    /// # Errors
+    ///
+    /// If the data could not be encrypted.
+    fn encrypt_data(&self, data: &[u8]) -> RusticResult<Vec<u8>> {
+        let mut nonce = XNonce::default();
+        thread_rng().fill_bytes(&mut nonce);
+
+        let mut res = Vec::with_capacity(data.len() + constants::NONCE_LEN + 16);
+        res.extend_from_slice(&nonce);
+        res.extend_from_slice(data);
+        let tag = XChaCha20Poly1305::new(&self.0)
+            .encrypt_in_place_detached(&nonce, &[], &mut res[constants::NONCE_LEN..])
+            .map_err(|_| CryptoErrorKind::DataEncryptionFailed)?;
+        res.extend_from_slice(&tag);
+        Ok(res)
+    }
+}
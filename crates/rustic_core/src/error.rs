@@ -10,7 +10,7 @@ use std::{
     ops::RangeInclusive,
     path::{PathBuf, StripPrefixError},
     process::ExitStatus,
-    str::Utf8Error,
+    str::{ParseBoolError, Utf8Error},
     time::SystemTimeError,
 };
 
@@ -22,7 +22,12 @@ use chrono::OutOfRangeError;
 use displaydoc::Display;
 use thiserror::Error;
 
-use crate::{backend::node::NodeType, id::Id, repofile::indexfile::IndexPack};
+use crate::{
+    backend::{node::NodeType, FileType},
+    crypto::Cipher,
+    id::Id,
+    repofile::{indexfile::IndexPack, KeyRestriction},
+};
 
 /// Result type that is being returned from methods that can fail and thus have [`RusticError`]s.
 pub type RusticResult<T> = Result<T, RusticError>;
@@ -41,6 +46,21 @@ impl RusticError {
     pub fn into_inner(self) -> RusticErrorKind {
         self.0
     }
+
+    /// Returns the inner error kind.
+    pub(crate) const fn kind(&self) -> &RusticErrorKind {
+        &self.0
+    }
+
+    /// Checks if the error is due to shares of the master key being found, but not enough of them.
+    ///
+    /// This is useful to ask for more passwords when opening a repository using shares.
+    pub fn is_not_enough_shares(&self) -> bool {
+        matches!(
+            self.0,
+            RusticErrorKind::KeyFile(KeyFileErrorKind::NotEnoughShares(..))
+        )
+    }
 }
 
 /// [`RusticErrorKind`] describes the errors that can happen while executing a high-level command.
@@ -135,6 +155,30 @@ pub enum RusticErrorKind {
     #[error(transparent)]
     Rest(#[from] RestErrorKind),
 
+    /// [`HttpErrorKind`] describes the errors that can be returned by the HTTP client shared by the HTTP based backends
+    #[error(transparent)]
+    Http(#[from] HttpErrorKind),
+
+    /// [`S3ErrorKind`] describes the errors that can be returned while dealing with the S3 API
+    #[error(transparent)]
+    S3(#[from] S3ErrorKind),
+
+    /// [`B2ErrorKind`] describes the errors that can be returned while dealing with the B2 API
+    #[error(transparent)]
+    B2(#[from] B2ErrorKind),
+
+    /// [`WebDavErrorKind`] describes the errors that can be returned while dealing with a `WebDAV` server
+    #[error(transparent)]
+    WebDav(#[from] WebDavErrorKind),
+
+    /// [`IpfsErrorKind`] describes the errors that can be returned while dealing with the API of an IPFS node
+    #[error(transparent)]
+    Ipfs(#[from] IpfsErrorKind),
+
+    /// [`PathTranslationErrorKind`] describes the errors that can be returned while translating paths
+    #[error(transparent)]
+    PathTranslation(#[from] PathTranslationErrorKind),
+
     /// [`StdInErrorKind`] describes the errors that can be returned while dealing IO from CLI
     #[error(transparent)]
     StdIn(#[from] StdInErrorKind),
@@ -163,6 +207,8 @@ pub enum CommandErrorKind {
     NoDecision(Id),
     /// {0:?}
     FromParseIntError(#[from] ParseIntError),
+    /// {0:?}
+    FromParseBoolError(#[from] ParseBoolError),
     /// {0}
     FromByteSizeParser(String),
     /// --repack-uncompressed makes no sense for v1 repo!
@@ -177,6 +223,16 @@ pub enum CommandErrorKind {
     VersionNotSupported(u32, RangeInclusive<u32>),
     /// cannot downgrade version from {0} to {1}
     CannotDowngrade(u32, u32),
+    /// cannot change the cipher of an existing repository from {0:?} to {1:?}
+    CannotChangeCipher(Cipher, Cipher),
+    /// cipher {0:?} needs repository version 2 or later
+    CipherNeedsVersion2(Cipher),
+    /// sharded trees need repository version 2 or later
+    ShardTreesNeedVersion2,
+    /// pack padding needs repository version 2 or later
+    PackPaddingNeedsVersion2,
+    /// the repository is not encrypted, so it has no keys
+    RepositoryNotEncrypted,
     /// compression level {0} is not supported for repo v1
     NoCompressionV1Repo(i32),
     /// compression level {0} is not supported. Allowed values: {1:?}
@@ -187,6 +243,18 @@ pub enum CommandErrorKind {
     MinPackSizeTolerateWrong,
     /// max_packsize_tolerate_percent must be >= 100 or 0"
     MaxPackSizeTolerateWrong,
+    /// config key `{0}` is unknown or cannot be set
+    UnknownConfigKey(String),
+    /// no unique snapshot found in the trash for `{0}`
+    NoUniqueTrashedSnapshot(String),
+    /// notes can only be attached to snapshots, packs or keys, not to {0:?}
+    NoteTargetNotSupported(FileType),
+    /// no snapshot, pack or key found for `{0}`
+    NoteTargetNotFound(String),
+    /// target repository is not a mirror of this repository
+    NotAMirror,
+    /// key file {0} doesn't contain the master key of this repository
+    KeyMismatch(Id),
     /// error creating {0:?}: {1:?}
     ErrorCreating(PathBuf, Box<RusticError>),
     /// error collecting information for {0:?}: {1:?}
@@ -208,6 +276,8 @@ pub enum CryptoErrorKind {
     DataEncryptionFailed,
     /// crypto key too short
     CryptoKeyTooShort,
+    /// data decryption failed, the key or cipher is wrong or the data is corrupted
+    DecryptionFailed,
 }
 
 /// [`PolynomialErrorKind`] describes the errors that can happen while dealing with Polynomials
@@ -248,6 +318,8 @@ pub enum RepositoryErrorKind {
     OpeningPasswordFileFailed(std::io::Error),
     /// No repository config file found. Is there a repo at {0}?
     NoRepositoryConfigFound(String),
+    /// repository {0} is encrypted, a key is needed to open it
+    RepositoryIsEncrypted(String),
     /// More than one repository config file at {0}. Aborting.
     MoreThanOneRepositoryConfig(String),
     /// keys from repo and repo-hot do not match for {0}. Aborting.
@@ -282,6 +354,30 @@ pub enum RepositoryErrorKind {
     ConfigFileExists,
     /// did not find id {0} in index
     IdNotFound(Id),
+    /// accessing the OS keyring failed: {0}
+    KeyringFailed(String),
+    /// the OS keyring can't be used, as rustic was compiled without the feature `keyring`
+    KeyringNotSupported,
+    /// accessing the TPM failed: {0}
+    TpmFailed(String),
+    /// `{0}` is no valid name of a systemd credential
+    InvalidCredentialName(String),
+    /// $CREDENTIALS_DIRECTORY is not set, is rustic running as systemd service with LoadCredential=?
+    NoCredentialsDirectory,
+    /// password-command is empty
+    EmptyPasswordCommand,
+    /// failed to start password-command `{0}`: `{1:?}`
+    StartingPasswordCommandFailed(String, std::io::Error),
+    /// password-command `{0}` {1}
+    PasswordCommandFailed(String, String),
+    /// no runtime dir (`$XDG_RUNTIME_DIR`) for the key cache found; the key is not cached in persistent directories
+    NoKeyCacheDirectory,
+    /// accessing the key cache {0:?} failed: `{1:?}`
+    KeyCacheFailed(PathBuf, std::io::Error),
+    /// key cache ttl is out of range
+    KeyCacheTtlOutOfRange,
+    /// no valid cached key found for repository {0}
+    NoCachedKey(String),
 }
 
 /// [`IndexErrorKind`] describes the errors that can be returned by processing Indizes
@@ -295,6 +391,12 @@ pub enum IndexErrorKind {
     SavingIndexFileFailed,
     /// couldn't get elapsed time from system time: {0:?}
     CouldNotGetElapsedTimeFromSystemTime(#[from] SystemTimeError),
+    /// failed writing binary representation of the super index: {0:?}
+    WritingSuperIndexFailed(binrw::Error),
+    /// failed reading binary representation of the super index: {0:?}
+    ReadingSuperIndexFailed(binrw::Error),
+    /// conversion to `u32` failed: `{0:?}`
+    ConversionToU32Failed(TryFromIntError),
 }
 
 /// [`BackendErrorKind`] describes the errors that can be returned by the various Backends
@@ -306,6 +408,8 @@ pub enum BackendErrorKind {
     IdNotUnique(String),
     /// backend {0:?} is not supported!
     BackendNotSupported(String),
+    /// backend {0:?} is not supported, as rustic was compiled without the feature `{0}`
+    BackendNotCompiled(String),
     /// Rest API threw an error: `{0:?}`
     RestApiError(#[from] RestErrorKind),
     /// building REST client failed: `{0:?}`
@@ -318,6 +422,8 @@ pub enum BackendErrorKind {
     PartiallyReadingFromBackendDataFailed,
     /// listing with size failed
     ListingWithSizeFailed,
+    /// {0} {1:?} files is not allowed by the key restriction {2}
+    NotAllowedByKeyRestriction(&'static str, FileType, KeyRestriction),
     /// {0:?}
     #[error(transparent)]
     FromBackendCacheError(#[from] CacheBackendErrorKind),
@@ -388,6 +494,42 @@ pub enum KeyFileErrorKind {
     OutputLengthInvalid(scrypt::errors::InvalidOutputLen),
     /// invalid scrypt parameters
     InvalidSCryptParameters(scrypt::errors::InvalidParams),
+    /// scrypt parameter N must be a power of 2 greater than 1, but is {0}
+    ScryptNNotPowerOfTwo(u32),
+    /// parameter {0} of the key derivation function is missing
+    MissingKdfParameter(&'static str),
+    /// argon2 key derivation failed: `{0:?}`
+    Argon2Failed(argon2::Error),
+    /// FIDO2 security key failed: {0}
+    Fido2Failed(String),
+    /// FIDO2 security key didn't return a secret
+    Fido2NoSecret,
+    /// this key requires a FIDO2 security key, but rustic was compiled without FIDO2 support
+    Fido2NotSupported,
+    /// this key only contains a share of the master key
+    KeyIsShare,
+    /// this key contains the whole master key and no share
+    KeyIsNoShare,
+    /// found {0} shares of the master key, but {1} are needed
+    NotEnoughShares(usize, usize),
+    /// {0} needs the address of the KMS
+    KmsAddressMissing(&'static str),
+    /// the password is too weak: score {0} is below the required score {1}
+    PasswordTooWeak(u8, u8),
+    /// the password strength can't be estimated, as rustic was compiled without the feature `zxcvbn`
+    PasswordStrengthNotSupported,
+    /// this key is wrapped by the KMS {0} and can only be unwrapped by a locally configured KMS
+    KeyIsWrapped(String),
+    /// no KMS is configured to unwrap the key
+    KmsMissing,
+    /// the PKCS#11 address `{0}` must be given as `<absolute path of the module>#<slot id>`
+    InvalidPkcs11Address(String),
+    /// KMS failed: {0}
+    KmsFailed(String),
+    /// the id of the KMS key is needed to wrap or unwrap a key
+    KmsKeyIdMissing,
+    /// the master key can only be split into 2 to 255 shares with a threshold between 2 and the number of shares, got threshold {0} for {1} shares
+    InvalidShareThreshold(u8, usize),
 }
 
 /// [`PackFileErrorKind`] describes the errors that can be returned for `PackFile`s
@@ -613,6 +755,12 @@ pub enum LocalErrorKind {
     },
     /// file `{0:?}` should have a parent
     FileDoesNotHaveParent(PathBuf),
+    /// value `{1}` not supported for option {0}!
+    InvalidOptionValue(String, String),
+    /// renaming file failed: `{0:?}`
+    RenamingFileFailed(std::io::Error),
+    /// canonicalizing path failed: `{0:?}`
+    CanonicalizingPathFailed(std::io::Error),
     /// error building automaton `{0:?}`
     FromAhoCorasick(#[from] aho_corasick::BuildError),
     /// {0:?}
@@ -655,6 +803,8 @@ pub enum LocalErrorKind {
     FileRemovalFailed(std::io::Error),
     /// setting time metadata failed: `{0:?}`
     SettingTimeMetadataFailed(std::io::Error),
+    /// setting file flags on {1:?} failed: {0}
+    SettingFileFlagsFailed(std::io::Error, PathBuf),
     /// opening file failed: `{0:?}`
     OpeningFileFailed(std::io::Error),
     /// setting file length failed: `{0:?}`
@@ -740,6 +890,91 @@ pub enum RestErrorKind {
     BuildingClientFailed(reqwest::Error),
     /// joining URL failed on: {0:?}
     JoiningUrlFailed(url::ParseError),
+    /// reading {0:?} failed: `{1:?}`
+    ReadingFileFailed(PathBuf, std::io::Error),
+    /// parsing certificate {0:?} failed: `{1:?}`
+    ParsingCertificateFailed(PathBuf, reqwest::Error),
+    /// reading {0:?} {1} returned a wrong range: requested `{2}`, got `{3}`
+    RangeMismatch(FileType, Id, String, String),
+    /// `HEAD` request for {0} returned no valid content length
+    MissingContentLength(url::Url),
+}
+
+/// [`HttpErrorKind`] describes the errors that can be returned by the HTTP client shared by the HTTP based backends
+#[derive(Error, Debug, Display)]
+pub enum HttpErrorKind {
+    /// value `{1}` not supported for option {0}!
+    InvalidOptionValue(String, String),
+    /// couldn't parse duration in humantime library: `{0:?}`
+    CouldNotParseDuration(#[from] humantime::DurationError),
+    /// Failed to build HTTP client: `{0:?}`
+    BuildingClientFailed(reqwest::Error),
+}
+
+/// [`S3ErrorKind`] describes the errors that can be returned while dealing with the S3 API
+#[derive(Error, Debug, Display)]
+pub enum S3ErrorKind {
+    /// invalid S3 url `{0}`, use s3:[http(s)://]host[:port]/bucket[/prefix]
+    InvalidUrl(String),
+    /// parsing failed for url: `{0:?}`
+    UrlParsingFailed(#[from] url::ParseError),
+    /// value `{1}` not supported for option {0}!
+    InvalidOptionValue(String, String),
+    /// backoff failed: {0:?}
+    BackoffError(#[from] backoff::Error<reqwest::Error>),
+    /// no S3 credentials found. Set `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`, use a profile or an instance role
+    NoCredentials,
+    /// unexpected response from S3: {0}
+    UnexpectedResponse(String),
+}
+
+/// [`B2ErrorKind`] describes the errors that can be returned while dealing with the B2 API
+#[derive(Error, Debug, Display)]
+pub enum B2ErrorKind {
+    /// invalid B2 path `{0}`, use b2:bucket[/prefix]
+    InvalidPath(String),
+    /// value `{1}` not supported for option {0}!
+    InvalidOptionValue(String, String),
+    /// backoff failed: {0:?}
+    BackoffError(#[from] backoff::Error<reqwest::Error>),
+    /// no B2 credentials found. Set `B2_ACCOUNT_ID` and `B2_ACCOUNT_KEY` to the application key id and key
+    NoCredentials,
+}
+
+/// [`WebDavErrorKind`] describes the errors that can be returned while dealing with a `WebDAV` server
+#[derive(Error, Debug, Display)]
+pub enum WebDavErrorKind {
+    /// parsing failed for url: `{0:?}`
+    UrlParsingFailed(#[from] url::ParseError),
+    /// value `{1}` not supported for option {0}!
+    InvalidOptionValue(String, String),
+    /// backoff failed: {0:?}
+    BackoffError(#[from] backoff::Error<reqwest::Error>),
+    /// unexpected response from `WebDAV` server: {0}
+    UnexpectedResponse(String),
+}
+
+/// [`IpfsErrorKind`] describes the errors that can be returned while dealing with the API of an IPFS node
+#[derive(Error, Debug, Display)]
+pub enum IpfsErrorKind {
+    /// parsing failed for url: `{0:?}`
+    UrlParsingFailed(#[from] url::ParseError),
+    /// backoff failed: {0:?}
+    BackoffError(#[from] backoff::Error<reqwest::Error>),
+    /// IPFS API returned an error: {0}
+    ApiError(String),
+    /// file `{0}` does not exist
+    FileNotFound(String),
+    /// {0:?}
+    #[error(transparent)]
+    FromTryIntError(#[from] TryFromIntError),
+}
+
+/// [`PathTranslationErrorKind`] describes the errors that can be returned while translating paths
+#[derive(Error, Debug, Display)]
+pub enum PathTranslationErrorKind {
+    /// invalid drive mapping `{0}`, use LETTER=PATH
+    InvalidDriveMapping(String),
 }
 
 /// [`StdInErrorKind`] describes the errors that can be returned while dealing IO from CLI
@@ -762,6 +997,8 @@ pub enum ArchiverErrorKind {
     CouldNotGetSizeForArchive(#[from] BackendErrorKind),
     /// couldn't determine size for item in Archiver
     CouldNotDetermineSize,
+    /// file `{0:?}` changed while being read, skipping it
+    UnstableFile(PathBuf),
     /// failed to save index: `{0:?}`
     IndexSavingFailed(#[from] IndexErrorKind),
     /// failed to save file in backend: `{0:?}`
@@ -809,6 +1046,12 @@ impl RusticErrorMarker for LocalErrorKind {}
 impl RusticErrorMarker for NodeErrorKind {}
 impl RusticErrorMarker for ProviderErrorKind {}
 impl RusticErrorMarker for RestErrorKind {}
+impl RusticErrorMarker for HttpErrorKind {}
+impl RusticErrorMarker for S3ErrorKind {}
+impl RusticErrorMarker for B2ErrorKind {}
+impl RusticErrorMarker for WebDavErrorKind {}
+impl RusticErrorMarker for IpfsErrorKind {}
+impl RusticErrorMarker for PathTranslationErrorKind {}
 impl RusticErrorMarker for StdInErrorKind {}
 impl RusticErrorMarker for ArchiverErrorKind {}
 impl RusticErrorMarker for CommandErrorKind {}
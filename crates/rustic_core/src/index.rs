@@ -3,18 +3,25 @@ use std::{num::NonZeroU32, sync::Arc, thread::sleep, time::Duration};
 use bytes::Bytes;
 use derive_more::Constructor;
 
+use log::warn;
+
 use crate::{
-    backend::{decrypt::DecryptReadBackend, FileType},
+    backend::{cache::Cache, decrypt::DecryptReadBackend, FileType, ReadBackend},
     blob::BlobType,
+    crypto::CryptoKey,
     error::{IndexErrorKind, RusticResult},
     id::Id,
-    index::binarysorted::{Index, IndexCollector, IndexType},
+    index::{
+        binarysorted::{Index, IndexCollector, IndexType},
+        superindex::SuperIndexWriter,
+    },
     progress::Progress,
     repofile::indexfile::{IndexBlob, IndexFile},
 };
 
 pub(crate) mod binarysorted;
 pub(crate) mod indexer;
+pub(crate) mod superindex;
 
 /// An entry in the index
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Constructor)]
@@ -289,6 +296,68 @@ impl<BE: DecryptReadBackend> IndexBackend<BE> {
         Ok(Self::new_from_index(be, collector.into_index()))
     }
 
+    /// Create a new [`IndexBackend`] using the super index saved in the cache, if possible.
+    ///
+    /// If the super index doesn't match the index files present in the repository, all index
+    /// files are read and the super index is rebuilt.
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The backend to read from
+    /// * `cache` - The cache containing the super index
+    /// * `key` - The key to encrypt and decrypt the super index
+    /// * `p` - The progress tracker
+    /// * `tpe` - The type of index to create
+    ///
+    /// # Errors
+    ///
+    /// If the index could not be read
+    pub(crate) fn new_cached(
+        be: &BE,
+        cache: Option<&Cache>,
+        key: &impl CryptoKey,
+        p: &impl Progress,
+        tpe: IndexType,
+    ) -> RusticResult<Self> {
+        let cache = match cache {
+            Some(cache) => cache,
+            None => return Self::new_from_collector(be, p, IndexCollector::new(tpe)),
+        };
+        let mut collector = IndexCollector::new(tpe);
+
+        p.set_title("reading index...");
+        let mut index_ids = be.list(FileType::Index)?;
+        index_ids.sort_unstable();
+
+        if !superindex::load(cache, key, &index_ids, &mut collector) {
+            collector = IndexCollector::new(tpe);
+            let mut writer = match SuperIndexWriter::new(cache, key, &index_ids) {
+                Ok(writer) => Some(writer),
+                Err(err) => {
+                    warn!("error saving super index to cache: {err}");
+                    None
+                }
+            };
+            for index in be.stream_list::<IndexFile>(index_ids, p)? {
+                let (id, index) = index?;
+                if let Some(w) = &mut writer {
+                    if let Err(err) = w.add(id, &index.packs) {
+                        warn!("error saving super index to cache: {err}");
+                        writer = None;
+                    }
+                }
+                collector.extend(index.packs);
+            }
+            if let Some(Err(err)) = writer.map(SuperIndexWriter::finish) {
+                warn!("error saving super index to cache: {err}");
+            }
+        }
+
+        p.finish();
+
+        Ok(Self::new_from_index(be, collector.into_index()))
+    }
+
     /// Create a new [`IndexBackend`]
     ///
     /// # Type Parameters
@@ -8,6 +8,7 @@ use crate::{
     backend::decrypt::DecryptWriteBackend,
     error::{IndexErrorKind, RusticResult},
     id::Id,
+    profile::Phase,
     repofile::indexfile::{IndexFile, IndexPack},
 };
 
@@ -111,7 +112,7 @@ impl<BE: DecryptWriteBackend> Indexer<BE> {
     /// * [`CryptBackendErrorKind::SerializingToJsonByteVectorFailed`] - If the index file could not be serialized.
     pub fn save(&self) -> RusticResult<()> {
         if (self.file.packs.len() + self.file.packs_to_delete.len()) > 0 {
-            _ = self.be.save_file(&self.file)?;
+            _ = Phase::Index.measure(|| self.be.save_file(&self.file))?;
         }
         Ok(())
     }
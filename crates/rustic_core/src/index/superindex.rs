@@ -0,0 +1,361 @@
+//! A binary representation of the complete index which is saved in the local cache.
+//!
+//! Reading hundreds of JSON index files can take a considerable time for large repositories.
+//! The super index contains all information needed to build the in-memory index in a compact
+//! binary form together with the list of index files it was built from. It is only used if this
+//! list exactly matches the index files present in the repository.
+//!
+//! Like all other files in the cache, the super index is encrypted with the repository key. It
+//! consists of a header listing the index files followed by one block per index file, so it can be
+//! written and read without holding the complete index in memory. Each block is encrypted
+//! separately and prefixed by its length.
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+};
+
+use binrw::{io::Cursor, BinRead, BinWrite};
+use log::debug;
+
+use crate::{
+    backend::cache::Cache,
+    blob::BlobType,
+    crypto::CryptoKey,
+    error::{CacheBackendErrorKind, IndexErrorKind, RusticResult},
+    id::Id,
+    index::binarysorted::IndexCollector,
+    repofile::indexfile::{IndexBlob, IndexPack},
+};
+
+/// Magic bytes at the start of the super index file
+const MAGIC: &[u8; 4] = b"RSI2";
+
+/// The header of the super index
+#[derive(BinRead, BinWrite, Debug)]
+#[brw(little)]
+struct SuperIndexHeader {
+    /// Number of index files
+    index_count: u32,
+    /// The (sorted) ids of the index files contained in this super index
+    #[br(count = index_count)]
+    index_ids: Vec<Id>,
+}
+
+/// The packs of a single index file
+#[derive(BinRead, BinWrite, Debug)]
+#[brw(little)]
+struct SuperIndexBlock {
+    /// Id of the index file
+    index_id: Id,
+    /// Number of packs
+    pack_count: u32,
+    /// The packs contained in the index file
+    #[br(count = pack_count)]
+    packs: Vec<SuperIndexPack>,
+}
+
+/// A pack within the super index
+#[derive(BinRead, BinWrite, Debug)]
+#[brw(little)]
+struct SuperIndexPack {
+    /// pack Id
+    id: Id,
+    /// The pack size
+    size: u32,
+    /// Number of blobs
+    blob_count: u32,
+    /// The blobs contained in the pack
+    #[br(count = blob_count)]
+    blobs: Vec<SuperIndexBlob>,
+}
+
+impl SuperIndexPack {
+    /// Create a [`SuperIndexPack`] from an [`IndexPack`]
+    ///
+    /// # Errors
+    ///
+    /// * [`IndexErrorKind::ConversionToU32Failed`] - If the pack contains too many blobs
+    fn from_pack(pack: &IndexPack) -> RusticResult<Self> {
+        Ok(Self {
+            id: pack.id,
+            size: pack.pack_size(),
+            blob_count: to_u32(pack.blobs.len())?,
+            blobs: pack
+                .blobs
+                .iter()
+                .map(|blob| SuperIndexBlob {
+                    id: blob.id,
+                    tpe: match blob.tpe {
+                        BlobType::Tree => 0,
+                        BlobType::Data => 1,
+                    },
+                    offset: blob.offset,
+                    length: blob.length,
+                    uncompressed_length: blob
+                        .uncompressed_length
+                        .map_or(0, std::num::NonZeroU32::get),
+                })
+                .collect(),
+        })
+    }
+
+    /// Convert into an [`IndexPack`]; pack creation times are not set.
+    fn into_pack(self) -> IndexPack {
+        IndexPack {
+            id: self.id,
+            blobs: self
+                .blobs
+                .into_iter()
+                .map(|blob| IndexBlob {
+                    id: blob.id,
+                    tpe: if blob.tpe == 0 {
+                        BlobType::Tree
+                    } else {
+                        BlobType::Data
+                    },
+                    offset: blob.offset,
+                    length: blob.length,
+                    uncompressed_length: blob.uncompressed_length.try_into().ok(),
+                })
+                .collect(),
+            time: None,
+            size: Some(self.size),
+        }
+    }
+}
+
+/// A blob within the super index
+#[derive(BinRead, BinWrite, Debug, Clone, Copy)]
+#[brw(little)]
+struct SuperIndexBlob {
+    /// Blob Id
+    id: Id,
+    /// The blob type; `0` for tree blobs and `1` for data blobs
+    tpe: u8,
+    /// The blob offset within the pack
+    offset: u32,
+    /// The blob length within the pack
+    length: u32,
+    /// The uncompressed blob length or `0` if the blob is not compressed
+    uncompressed_length: u32,
+}
+
+/// Load the packs from the super index saved in the cache into the given collector.
+///
+/// # Arguments
+///
+/// * `cache` - The cache to read the super index from
+/// * `key` - The key to decrypt the super index
+/// * `index_ids` - The sorted ids of all index files present in the repository
+/// * `collector` - The collector to add the packs to
+///
+/// # Returns
+///
+/// Whether the packs have been loaded. If `false`, there is no usable super index and the
+/// collector may contain some of the packs, so it must not be used.
+pub(crate) fn load(
+    cache: &Cache,
+    key: &impl CryptoKey,
+    index_ids: &[Id],
+    collector: &mut IndexCollector,
+) -> bool {
+    match read(cache, key, index_ids, collector) {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            debug!("could not read super index: {err}");
+            false
+        }
+    }
+}
+
+/// Read the super index, see [`load`].
+///
+/// # Errors
+///
+/// If the super index could not be read, decrypted or parsed
+fn read(
+    cache: &Cache,
+    key: &impl CryptoKey,
+    index_ids: &[Id],
+    collector: &mut IndexCollector,
+) -> RusticResult<bool> {
+    let mut reader = BufReader::new(cache.open_super_index()?);
+    let mut magic = [0; 4];
+    reader
+        .read_exact(&mut magic)
+        .map_err(CacheBackendErrorKind::FromIoError)?;
+    if &magic != MAGIC {
+        debug!("super index has an unsupported format");
+        return Ok(false);
+    }
+
+    let header = match read_block(&mut reader, key)? {
+        Some(mut data) => {
+            SuperIndexHeader::read(&mut data).map_err(IndexErrorKind::ReadingSuperIndexFailed)?
+        }
+        None => return Ok(false),
+    };
+    if header.index_ids != index_ids {
+        debug!("super index is outdated");
+        return Ok(false);
+    }
+
+    // every index file must be contained exactly once
+    let mut seen = vec![false; index_ids.len()];
+    while let Some(mut data) = read_block(&mut reader, key)? {
+        let block =
+            SuperIndexBlock::read(&mut data).map_err(IndexErrorKind::ReadingSuperIndexFailed)?;
+        match index_ids.binary_search(&block.index_id) {
+            Ok(i) if !seen[i] => seen[i] = true,
+            _ => {
+                debug!("super index contains unexpected index {}", block.index_id);
+                return Ok(false);
+            }
+        }
+        collector.extend(block.packs.into_iter().map(SuperIndexPack::into_pack));
+    }
+    if seen.contains(&false) {
+        debug!("super index is incomplete");
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Writes the super index to the cache while the index files are read.
+///
+/// The super index is first written to a temporary file which replaces the old super index in
+/// [`SuperIndexWriter::finish`].
+pub(crate) struct SuperIndexWriter<'a, K: CryptoKey> {
+    /// The cache to save the super index to
+    cache: &'a Cache,
+    /// The key to encrypt the super index
+    key: &'a K,
+    /// The temporary file
+    file: BufWriter<File>,
+}
+
+impl<'a, K: CryptoKey> SuperIndexWriter<'a, K> {
+    /// Start writing a new super index.
+    ///
+    /// # Arguments
+    ///
+    /// * `cache` - The cache to save the super index to
+    /// * `key` - The key to encrypt the super index
+    /// * `index_ids` - The sorted ids of the index files which will be added
+    ///
+    /// # Errors
+    ///
+    /// * [`CacheBackendErrorKind::FromIoError`] - If the super index could not be written
+    /// * [`IndexErrorKind::WritingSuperIndexFailed`] - If the binary representation could not be written
+    pub(crate) fn new(cache: &'a Cache, key: &'a K, index_ids: &[Id]) -> RusticResult<Self> {
+        let mut file = BufWriter::new(cache.create_super_index()?);
+        file.write_all(MAGIC)
+            .map_err(CacheBackendErrorKind::FromIoError)?;
+        let header = SuperIndexHeader {
+            index_count: to_u32(index_ids.len())?,
+            index_ids: index_ids.to_vec(),
+        };
+        let mut data = Cursor::new(Vec::new());
+        header
+            .write(&mut data)
+            .map_err(IndexErrorKind::WritingSuperIndexFailed)?;
+        write_block(&mut file, key, &data.into_inner())?;
+        Ok(Self { cache, key, file })
+    }
+
+    /// Add the packs of an index file.
+    ///
+    /// # Arguments
+    ///
+    /// * `index_id` - The id of the index file
+    /// * `packs` - The packs contained in the index file
+    ///
+    /// # Errors
+    ///
+    /// * [`CacheBackendErrorKind::FromIoError`] - If the super index could not be written
+    /// * [`IndexErrorKind::WritingSuperIndexFailed`] - If the binary representation could not be written
+    pub(crate) fn add(&mut self, index_id: Id, packs: &[IndexPack]) -> RusticResult<()> {
+        let block = SuperIndexBlock {
+            index_id,
+            pack_count: to_u32(packs.len())?,
+            packs: packs
+                .iter()
+                .map(SuperIndexPack::from_pack)
+                .collect::<RusticResult<_>>()?,
+        };
+        let mut data = Cursor::new(Vec::new());
+        block
+            .write(&mut data)
+            .map_err(IndexErrorKind::WritingSuperIndexFailed)?;
+        write_block(&mut self.file, self.key, &data.into_inner())
+    }
+
+    /// Finish the super index and replace the old super index by it.
+    ///
+    /// # Errors
+    ///
+    /// * [`CacheBackendErrorKind::FromIoError`] - If the super index could not be saved
+    pub(crate) fn finish(self) -> RusticResult<()> {
+        let file = self
+            .file
+            .into_inner()
+            .map_err(|err| CacheBackendErrorKind::FromIoError(err.into_error()))?;
+        file.sync_all()
+            .map_err(CacheBackendErrorKind::FromIoError)?;
+        self.cache.persist_super_index()
+    }
+}
+
+/// Encrypt a block and write it prefixed by its length.
+///
+/// # Arguments
+///
+/// * `w` - The writer to write to
+/// * `key` - The key to encrypt the block
+/// * `block` - The binary representation of the block
+///
+/// # Errors
+///
+/// * [`CacheBackendErrorKind::FromIoError`] - If the block could not be written
+fn write_block(w: &mut impl Write, key: &impl CryptoKey, block: &[u8]) -> RusticResult<()> {
+    let data = key.encrypt_data(block)?;
+    w.write_all(&to_u32(data.len())?.to_le_bytes())
+        .map_err(CacheBackendErrorKind::FromIoError)?;
+    w.write_all(&data)
+        .map_err(CacheBackendErrorKind::FromIoError)?;
+    Ok(())
+}
+
+/// Read a block written by [`write_block`] and decrypt it.
+///
+/// # Arguments
+///
+/// * `r` - The reader to read from
+/// * `key` - The key to decrypt the block
+///
+/// # Returns
+///
+/// The decrypted block or `None` if the end of the file has been reached
+///
+/// # Errors
+///
+/// If the block could not be read or decrypted
+fn read_block(r: &mut impl Read, key: &impl CryptoKey) -> RusticResult<Option<Cursor<Vec<u8>>>> {
+    let mut len = [0; 4];
+    match r.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(CacheBackendErrorKind::FromIoError(err).into()),
+    }
+    let mut data = vec![0; u32::from_le_bytes(len) as usize];
+    r.read_exact(&mut data)
+        .map_err(CacheBackendErrorKind::FromIoError)?;
+    Ok(Some(Cursor::new(key.decrypt_data(&data)?)))
+}
+
+/// Convert a length into a `u32`
+fn to_u32(len: usize) -> RusticResult<u32> {
+    Ok(len
+        .try_into()
+        .map_err(IndexErrorKind::ConversionToU32Failed)?)
+}
@@ -137,38 +137,61 @@ pub(crate) mod crypto;
 pub(crate) mod error;
 pub(crate) mod id;
 pub(crate) mod index;
+pub(crate) mod profile;
 pub(crate) mod progress;
 /// Structs which are saved in JSON or binary format in the repository
 pub mod repofile;
 pub(crate) mod repository;
+pub(crate) mod throttle;
 
 // rustic_core Public API
 pub use crate::{
+    archiver::control::BackupControl,
     backend::{
         decrypt::{compression_level_range, max_compression_level},
         ignore::{LocalSource, LocalSourceFilterOptions, LocalSourceSaveOptions},
-        local::LocalDestination,
+        local::{DestinationLimits, LocalDestination},
+        mirror::{MirrorBackend, MirrorResyncStats},
         node::last_modified_node,
+        normalize::{
+            neutral_path, DriveMapping, PathNormalizationOptions, PathTranslationOptions,
+            UnicodeNormalization, WINDOWS_PLATFORM,
+        },
+        retry::{ErrorClass, RetryOptions},
+        route::{BackendRouter, RoutingBackend, SizeRouter},
         ReadSourceEntry,
     },
     blob::tree::TreeStreamerOptions as LsOptions,
     commands::{
+        backend_check::{BackendCheckOptions, BackendCheckResult, BackendCheckStep},
         backup::{BackupOptions, ParentOptions},
-        check::CheckOptions,
+        check::{CheckFinding, CheckFindingCode, CheckOptions, CheckResults},
         config::ConfigOptions,
         copy::CopySnapshot,
+        dump::FileReader,
         forget::{ForgetGroup, ForgetGroups, ForgetSnapshot, KeepOptions},
-        key::KeyOptions,
-        prune::{PruneOptions, PrunePlan, PruneStats},
+        index::CompactIndexOptions,
+        key::{KeyOptions, PasswordStrength},
+        prune::{PackUsage, PruneOptions, PrunePlan, PruneStats},
         repair::{index::RepairIndexOptions, snapshots::RepairSnapshotsOptions},
         repoinfo::{BlobInfo, IndexInfos, PackInfo, RepoFileInfo, RepoFileInfos},
-        restore::{FileDirStats, RestoreOptions, RestorePlan, RestoreStats},
+        restore::{
+            FileDirStats, RestoreAction, RestoreCheck, RestoreEntry, RestoreOptions, RestorePlan,
+            RestoreStats,
+        },
+        snapshots::SnapshotPage,
+        sync::SyncStats,
+    },
+    crypto::{
+        kms::{KeyWrapper, KeyWrapperResult, Kms, WrappedKey},
+        Cipher,
     },
     error::{RusticError, RusticResult},
     id::{HexId, Id},
+    profile::ResourceMeter,
     progress::{NoProgress, NoProgressBars, Progress, ProgressBars},
     repofile::snapshotfile::{
         PathList, SnapshotGroup, SnapshotGroupCriterion, SnapshotOptions, StringList,
     },
-    repository::{IndexedFull, OpenStatus, Repository, RepositoryOptions},
+    repository::{IndexedFull, IndexedTree, OpenStatus, Repository, RepositoryOptions},
 };
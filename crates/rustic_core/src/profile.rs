@@ -0,0 +1,123 @@
+//! Measuring the resources used while running a command
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::repofile::snapshotfile::ResourceUsage;
+
+/// Time spent in each [`Phase`] in nanoseconds, summed over all threads of the process
+static PHASE_NANOS: [AtomicU64; 4] = [
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+];
+
+/// The phases of a backup whose durations are measured
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Phase {
+    /// Reading the entries of the backup source
+    Scan = 0,
+    /// Reading and chunking files
+    Chunk = 1,
+    /// Uploading pack files
+    Upload = 2,
+    /// Saving index files
+    Index = 3,
+}
+
+impl Phase {
+    /// Runs the given function and adds its duration to this phase.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - The function to run
+    pub(crate) fn measure<T>(self, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        let nanos = u64::try_from(start.elapsed().as_nanos()).unwrap_or(u64::MAX);
+        _ = PHASE_NANOS[self as usize].fetch_add(nanos, Ordering::Relaxed);
+        result
+    }
+
+    /// Returns the total time spent in this phase so far.
+    fn total(self) -> Duration {
+        Duration::from_nanos(PHASE_NANOS[self as usize].load(Ordering::Relaxed))
+    }
+}
+
+/// Measures the resources used between its creation and [`ResourceMeter::finish`].
+///
+/// # Note
+///
+/// Peak memory and CPU time are always given for the whole process. Phase durations
+/// are measured for the whole process, so they also contain the work of backups running in parallel.
+#[derive(Clone, Copy, Debug)]
+pub struct ResourceMeter {
+    /// The phase durations at the creation of the meter
+    start: [Duration; 4],
+}
+
+impl ResourceMeter {
+    /// Starts measuring.
+    #[must_use]
+    pub fn start() -> Self {
+        Self {
+            start: [Phase::Scan, Phase::Chunk, Phase::Upload, Phase::Index].map(Phase::total),
+        }
+    }
+
+    /// Returns the resources used since the meter has been started.
+    ///
+    /// The meter can be used further, so this can be called repeatedly to get intermediate results.
+    #[must_use]
+    pub fn finish(&self) -> ResourceUsage {
+        let elapsed = |phase: Phase| {
+            phase
+                .total()
+                .saturating_sub(self.start[phase as usize])
+                .as_secs_f64()
+        };
+        let (peak_memory, cpu_time) = process_usage();
+        ResourceUsage {
+            peak_memory,
+            cpu_time,
+            scan_duration: elapsed(Phase::Scan),
+            chunk_duration: elapsed(Phase::Chunk),
+            upload_duration: elapsed(Phase::Upload),
+            index_duration: elapsed(Phase::Index),
+        }
+    }
+}
+
+/// Returns the peak resident memory in bytes and the used CPU time in seconds of this process.
+#[cfg(not(windows))]
+fn process_usage() -> (Option<u64>, Option<f64>) {
+    use nix::sys::{
+        resource::{getrusage, UsageWho},
+        time::{TimeVal, TimeValLike},
+    };
+
+    match getrusage(UsageWho::RUSAGE_SELF) {
+        Ok(usage) => {
+            let max_rss = u64::try_from(usage.max_rss()).ok();
+            // ru_maxrss is given in bytes on macOS and in KiB on other platforms
+            let peak_memory = if cfg!(target_os = "macos") {
+                max_rss
+            } else {
+                max_rss.map(|kib| kib * 1024)
+            };
+            #[allow(clippy::cast_precision_loss)]
+            let secs = |time: TimeVal| time.num_microseconds() as f64 / 1_000_000.0;
+            let cpu_time = secs(usage.user_time()) + secs(usage.system_time());
+            (peak_memory, Some(cpu_time))
+        }
+        Err(_) => (None, None),
+    }
+}
+
+#[cfg(windows)]
+fn process_usage() -> (Option<u64>, Option<f64>) {
+    (None, None)
+}
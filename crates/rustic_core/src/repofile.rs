@@ -2,9 +2,12 @@ use serde::{de::DeserializeOwned, Serialize};
 
 pub(crate) mod configfile;
 pub(crate) mod indexfile;
+pub(crate) mod journalfile;
 pub(crate) mod keyfile;
+pub(crate) mod notefile;
 pub(crate) mod packfile;
 pub(crate) mod snapshotfile;
+pub(crate) mod trashfile;
 
 /// Marker trait for repository files which are stored as encrypted JSON
 pub trait RepoFile: Serialize + DeserializeOwned + Sized + Send + Sync + 'static {
@@ -24,7 +27,12 @@ pub use {
     },
     configfile::ConfigFile,
     indexfile::{IndexBlob, IndexFile, IndexPack},
-    keyfile::KeyFile,
+    journalfile::JournalFile,
+    keyfile::{KeyDerivation, KeyFile, KeyRestriction, KeyShare, ScryptOptions},
+    notefile::NoteFile,
     packfile::{HeaderEntry, PackHeader, PackHeaderLength, PackHeaderRef},
-    snapshotfile::{DeleteOption, PathList, SnapshotFile, SnapshotSummary, StringList},
+    snapshotfile::{
+        DeleteOption, PathList, ResourceUsage, SnapshotFile, SnapshotSummary, StringList,
+    },
+    trashfile::TrashFile,
 };
@@ -1,8 +1,8 @@
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    backend::FileType, blob::BlobType, error::ConfigFileErrorKind, id::Id, repofile::RepoFile,
-    RusticResult,
+    backend::FileType, blob::BlobType, crypto::Cipher, error::ConfigFileErrorKind, id::Id,
+    repofile::RepoFile, RusticResult,
 };
 
 pub(super) mod constants {
@@ -33,7 +33,10 @@ pub(super) mod constants {
 ///
 /// It is usually saved in the repository as `config`
 pub struct ConfigFile {
-    /// Repository version. Currently 1 and 2 are supported
+    /// Repository version. Currently 1, 2 and 3 are supported
+    ///
+    /// Version 3 is identical to version 2, but is used for repositories using features which are
+    /// not supported by restic, see [`ConfigFile::EXTENDED_VERSION`].
     pub version: u32,
 
     /// The [`Id`] identifying the repsitors
@@ -93,6 +96,27 @@ pub struct ConfigFile {
     ///
     /// If not set or set to `0` this is unlimited.
     pub max_packsize_tolerate_percent: Option<u32>,
+
+    /// Cipher used to encrypt all repository files except the config file and the key files
+    ///
+    /// If not set, `AES256` with `Poly1305AES` is used like by restic.
+    pub cipher: Option<Cipher>,
+    /// Pad pack files to a multiple of this size, such that the storage can't infer the exact sizes of packs
+    ///
+    /// The padding is inserted in front of the pack header, so it is skipped when reading packs.
+    /// If not set, packs are not padded and packs which are larger than given by their header are
+    /// treated as damaged.
+    ///
+    /// The padding is not recorded in the pack headers: the header already determines the size of the
+    /// pack without padding and this setting marks that packs may be larger. Enabling padding sets the
+    /// repository version to 3, as restic would report padded packs as damaged.
+    pub pack_padding: Option<u32>,
+
+    /// Save the trees of directories with more than 50000 entries in several tree blobs
+    ///
+    /// The first tree blob contains the ids of the other blobs. Sharded trees are not supported by
+    /// restic, so this needs repository version 3. If not set, trees are not sharded.
+    pub shard_trees: Option<bool>,
 }
 
 impl RepoFile for ConfigFile {
@@ -101,6 +125,11 @@ impl RepoFile for ConfigFile {
 }
 
 impl ConfigFile {
+    /// Repository version of repositories using features which are not supported by restic, e.g. a
+    /// non-default cipher or sharded trees. restic and older rustic versions refuse to open such repositories instead
+    /// of writing files they can't handle.
+    pub const EXTENDED_VERSION: u32 = 3;
+
     #[must_use]
     /// Creates a new `ConfigFile`.
     ///
@@ -135,9 +164,9 @@ impl ConfigFile {
     /// * [`ConfigFileErrorKind::ConfigVersionNotSupported`] - If the version is not supported
     pub fn zstd(&self) -> RusticResult<Option<i32>> {
         match (self.version, self.compression) {
-            (1, _) | (2, Some(0)) => Ok(None),
-            (2, None) => Ok(Some(0)), // use default (=0) zstd compression
-            (2, Some(c)) => Ok(Some(c)),
+            (1, _) | (2 | 3, Some(0)) => Ok(None),
+            (2 | 3, None) => Ok(Some(0)), // use default (=0) zstd compression
+            (2 | 3, Some(c)) => Ok(Some(c)),
             _ => Err(ConfigFileErrorKind::ConfigVersionNotSupported.into()),
         }
     }
@@ -171,6 +200,18 @@ impl ConfigFile {
         }
     }
 
+    /// Get the cipher used to encrypt the repository files
+    #[must_use]
+    pub fn cipher(&self) -> Cipher {
+        self.cipher.unwrap_or_default()
+    }
+
+    /// Whether the trees of large directories are sharded
+    #[must_use]
+    pub fn shard_trees(&self) -> bool {
+        self.shard_trees.unwrap_or_default()
+    }
+
     /// Get pack size toleration limits
     ///
     /// # Returns
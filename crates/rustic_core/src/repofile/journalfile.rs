@@ -0,0 +1,87 @@
+use chrono::{DateTime, Local};
+use gethostname::gethostname;
+use serde::{Deserialize, Serialize};
+
+use crate::{backend::FileType, id::Id, repofile::RepoFile};
+
+/// Journal files record which files are going to be removed by `forget` or `prune`.
+///
+/// They are saved before removing the files and removed afterwards. A journal file which is
+/// still present means that the removal has been interrupted and needs to be completed.
+///
+/// They are usually stored in the repository under `/journal/<ID>`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JournalFile {
+    /// The time the removal has been started
+    pub time: DateTime<Local>,
+    /// The operation which removes the files
+    pub operation: String,
+    /// The host running the removal
+    #[serde(default)]
+    pub hostname: String,
+    /// The id of the process running the removal
+    #[serde(default)]
+    pub pid: u32,
+    /// Snapshots to remove
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub snapshots: Vec<Id>,
+    /// Whether the snapshots to remove have been moved to the trash. If so, they are only
+    /// removed while they are still in the trash, i.e. have not been undeleted.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub trashed: bool,
+    /// Files to remove from the trash
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trash: Vec<Id>,
+    /// Index files to remove
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub index: Vec<Id>,
+    /// Data packs to remove
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub data_packs: Vec<Id>,
+    /// Tree packs to remove
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tree_packs: Vec<Id>,
+}
+
+impl RepoFile for JournalFile {
+    /// The [`FileType`] associated with the [`JournalFile`]
+    const TYPE: FileType = FileType::Journal;
+}
+
+impl JournalFile {
+    /// Create an empty journal for the given operation
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - The operation which removes the files
+    pub(crate) fn new(operation: &str) -> Self {
+        Self {
+            time: Local::now(),
+            operation: operation.to_string(),
+            hostname: gethostname().to_string_lossy().to_string(),
+            pid: std::process::id(),
+            snapshots: Vec::new(),
+            trashed: false,
+            trash: Vec::new(),
+            index: Vec::new(),
+            data_packs: Vec::new(),
+            tree_packs: Vec::new(),
+        }
+    }
+
+    /// Returns the number of files to remove
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+            + self.trash.len()
+            + self.index.len()
+            + self.data_packs.len()
+            + self.tree_packs.len()
+    }
+
+    /// Returns whether there are no files to remove
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
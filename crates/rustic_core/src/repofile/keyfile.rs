@@ -1,12 +1,23 @@
+use argon2::{Algorithm, Argon2, Version};
 use chrono::{DateTime, Local};
+use derive_setters::Setters;
+use log::{debug, warn};
 use rand::{thread_rng, RngCore};
 use scrypt::Params;
 use serde::{Deserialize, Serialize};
 use serde_with::{base64::Base64, serde_as};
+use zeroize::{Zeroize, Zeroizing};
+
+use std::{collections::HashMap, fmt::Display};
 
 use crate::{
     backend::{FileType, ReadBackend},
-    crypto::{aespoly1305::Key, CryptoKey},
+    crypto::{
+        aespoly1305::Key,
+        fido2::Fido2Params,
+        kms::{KeyWrapper, WrappedKey},
+        shamir, CryptoKey,
+    },
     error::{KeyFileErrorKind, RusticResult},
     id::Id,
 };
@@ -16,6 +27,73 @@ pub(super) mod constants {
     pub(super) const fn num_bits<T>() -> usize {
         std::mem::size_of::<T>() * 8
     }
+
+    /// Memory cost of `argon2id` in KiB, as recommended by RFC 9106
+    pub(super) const ARGON2_MEMORY: u32 = 64 * 1024;
+    /// Number of iterations of `argon2id`, as recommended by RFC 9106
+    pub(super) const ARGON2_ITERATIONS: u32 = 3;
+    /// Degree of parallelism of `argon2id`, as recommended by RFC 9106
+    pub(super) const ARGON2_PARALLELISM: u32 = 4;
+}
+
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+/// Key derivation function used to derive the key which encrypts the key data from the password
+pub enum KeyDerivation {
+    /// `scrypt` (default, compatible with restic)
+    #[default]
+    Scrypt,
+    /// `argon2id`
+    Argon2id,
+}
+
+#[cfg_attr(feature = "clap", derive(clap::Parser))]
+#[derive(Debug, Clone, Copy, Default, Setters)]
+#[setters(into)]
+/// Parameters of `scrypt` used for new keys. Unset parameters use the recommended values.
+pub struct ScryptOptions {
+    /// scrypt parameter N (CPU/memory cost, must be a power of 2) for the new key [default: 32768]
+    #[cfg_attr(feature = "clap", clap(long = "scrypt-n", value_name = "N"))]
+    pub n: Option<u32>,
+
+    /// scrypt parameter r (block size) for the new key [default: 8]
+    #[cfg_attr(feature = "clap", clap(long = "scrypt-r", value_name = "R"))]
+    pub r: Option<u32>,
+
+    /// scrypt parameter p (parallelization) for the new key [default: 1]
+    #[cfg_attr(feature = "clap", clap(long = "scrypt-p", value_name = "P"))]
+    pub p: Option<u32>,
+}
+
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+/// Restriction of the operations allowed when the repository is opened with a key.
+///
+/// The variants are ordered from the weakest to the strongest restriction. No restriction allows
+/// to write key files or the config file.
+///
+/// This is a client-side guard and no access control, as the restriction is saved unauthenticated
+/// in the key file. Use an append-only server to protect the repository against a compromised host.
+pub enum KeyRestriction {
+    /// No files can be removed and no keys or config be written, e.g. `forget`, `prune` and `key add` are not possible
+    NoDelete,
+    /// Only new snapshots, index and pack files can be written, e.g. by `backup`
+    AppendOnly,
+    /// No files can be written or removed
+    ReadOnly,
+}
+
+impl Display for KeyRestriction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::NoDelete => "no-delete",
+            Self::AppendOnly => "append-only",
+            Self::ReadOnly => "read-only",
+        };
+        f.write_str(s)
+    }
 }
 
 /// Key files describe information about repository access keys.
@@ -26,60 +104,121 @@ pub(super) mod constants {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct KeyFile {
     /// Hostname where the key was created
-    hostname: Option<String>,
+    pub hostname: Option<String>,
 
     /// User which created the key
-    username: Option<String>,
+    pub username: Option<String>,
 
     /// Creation time of the key
-    created: Option<DateTime<Local>>,
+    pub created: Option<DateTime<Local>>,
 
-    /// The used key derivation function (currently only `scrypt`)
-    kdf: String,
+    /// Label describing the key, e.g. the machine or person it belongs to
+    pub label: Option<String>,
+
+    /// The used key derivation function
+    pub kdf: KeyDerivation,
 
     /// Parameter N for `scrypt`
     #[serde(rename = "N")]
-    n: u32,
+    n: Option<u32>,
 
     /// Parameter r for `scrypt`
-    r: u32,
+    r: Option<u32>,
 
-    /// Parameter p for `scrypt`
+    /// Parameter p for `scrypt` or the degree of parallelism for `argon2id`
     p: u32,
 
-    /// The key data encrypted by `scrypt`
+    /// Memory cost in KiB for `argon2id`
+    m: Option<u32>,
+
+    /// Number of iterations for `argon2id`
+    t: Option<u32>,
+
+    /// The key data encrypted by the key derived from the password
     #[serde_as(as = "Base64")]
     data: Vec<u8>,
 
-    /// The salt used with `scrypt`
+    /// The salt used with the key derivation function
     #[serde_as(as = "Base64")]
     salt: Vec<u8>,
+
+    /// Parameters of a FIDO2 security key which is additionally needed to unlock this key
+    fido2: Option<Fido2Params>,
+
+    /// If this key only contains a share of the master key: Information about the share
+    pub share: Option<KeyShare>,
+
+    /// If the master key is wrapped by a KMS instead of a password: Information about the KMS
+    pub wrapped: Option<WrappedKey>,
+    /// Restriction of the operations allowed when the repository is opened with this key
+    pub restriction: Option<KeyRestriction>,
+}
+
+/// Information about a share of the master key.
+///
+/// The master key can be split into shares using Shamir's secret sharing, such that
+/// `threshold` shares of the same set are needed to recover it.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyShare {
+    /// Id of the set of shares which have been created together
+    pub set: Id,
+
+    /// Number of shares needed to recover the master key
+    pub threshold: u8,
+
+    /// Index of this share within its set
+    pub index: u8,
 }
 
 impl KeyFile {
     /// Generate a Key using the key derivation function from [`KeyFile`] and a given password
     ///
+    /// If the [`KeyFile`] requires a FIDO2 security key, the secret obtained from the security key
+    /// is appended to the password.
+    ///
     /// # Arguments
     ///
     /// * `passwd` - The password to use for the key derivation function
     ///
     /// # Errors
     ///
-    /// * [`KeyFileErrorKind::InvalidSCryptParameters`] - If the parameters of the key derivation function are invalid
-    /// * [`KeyFileErrorKind::OutputLengthInvalid`] - If the output length of the key derivation function is invalid
+    /// * [`KeyFileErrorKind::MissingKdfParameter`] - If a parameter of the key derivation function is missing
+    /// * [`KeyFileErrorKind::InvalidSCryptParameters`] - If the parameters of `scrypt` are invalid
+    /// * [`KeyFileErrorKind::OutputLengthInvalid`] - If the output length of `scrypt` is invalid
+    /// * [`KeyFileErrorKind::Argon2Failed`] - If the parameters of `argon2id` are invalid
+    /// * [`KeyFileErrorKind::Fido2Failed`] - If the secret couldn't be obtained from the security key
     ///
     /// # Returns
     ///
     /// The generated key
     pub fn kdf_key(&self, passwd: &impl AsRef<[u8]>) -> RusticResult<Key> {
-        let params = Params::new(log_2(self.n)?, self.r, self.p, Params::RECOMMENDED_LEN)
-            .map_err(KeyFileErrorKind::InvalidSCryptParameters)?;
-
-        let mut key = [0; 64];
-        scrypt::scrypt(passwd.as_ref(), &self.salt, &params, &mut key)
-            .map_err(KeyFileErrorKind::OutputLengthInvalid)?;
+        let param =
+            |value: Option<u32>, name| value.ok_or(KeyFileErrorKind::MissingKdfParameter(name));
+        let mut passwd = Zeroizing::new(passwd.as_ref().to_vec());
+        if let Some(fido2) = &self.fido2 {
+            passwd.extend_from_slice(&Zeroizing::new(fido2.secret()?)[..]);
+        }
+        let mut key = Zeroizing::new([0; 64]);
+        match self.kdf {
+            KeyDerivation::Scrypt => {
+                let params = Params::new(
+                    log_2(param(self.n, "N")?)?,
+                    param(self.r, "r")?,
+                    self.p,
+                    Params::RECOMMENDED_LEN,
+                )
+                .map_err(KeyFileErrorKind::InvalidSCryptParameters)?;
+                scrypt::scrypt(&passwd, &self.salt, &params, &mut key[..])
+                    .map_err(KeyFileErrorKind::OutputLengthInvalid)?;
+            }
+            KeyDerivation::Argon2id => {
+                argon2(param(self.m, "m")?, param(self.t, "t")?, self.p)?
+                    .hash_password_into(&passwd, &self.salt, &mut key[..])
+                    .map_err(KeyFileErrorKind::Argon2Failed)?;
+            }
+        }
 
-        Ok(Key::from_slice(&key))
+        Ok(Key::from_slice(&key[..]))
     }
 
     /// Extract a key from the data of the [`KeyFile`] using the given key.
@@ -97,12 +236,40 @@ impl KeyFile {
     ///
     /// The extracted key
     pub fn key_from_data(&self, key: &Key) -> RusticResult<Key> {
-        let dec_data = key.decrypt_data(&self.data)?;
+        if self.share.is_some() {
+            return Err(KeyFileErrorKind::KeyIsShare.into());
+        }
+        let dec_data = Zeroizing::new(key.decrypt_data(&self.data)?);
         Ok(serde_json::from_slice::<MasterKey>(&dec_data)
             .map_err(KeyFileErrorKind::DeserializingFromSliceFailed)?
             .key())
     }
 
+    /// Extract the share of the master key from the data of the [`KeyFile`] using the given password.
+    ///
+    /// # Arguments
+    ///
+    /// * `passwd` - The password to use for the key derivation function
+    ///
+    /// # Errors
+    ///
+    /// * [`KeyFileErrorKind::KeyIsNoShare`] - If the [`KeyFile`] contains the whole master key
+    /// * [`KeyFileErrorKind::DeserializingFromSliceFailed`] - If the data could not be deserialized
+    ///
+    /// # Returns
+    ///
+    /// The information about the share and the share data
+    pub fn share_from_password(
+        &self,
+        passwd: &impl AsRef<[u8]>,
+    ) -> RusticResult<(KeyShare, Vec<u8>)> {
+        let share = self.share.ok_or(KeyFileErrorKind::KeyIsNoShare)?;
+        let dec_data = Zeroizing::new(self.kdf_key(passwd)?.decrypt_data(&self.data)?);
+        let mut data = serde_json::from_slice::<MasterKeyShare>(&dec_data)
+            .map_err(KeyFileErrorKind::DeserializingFromSliceFailed)?;
+        Ok((share, std::mem::take(&mut data.share)))
+    }
+
     /// Extract a key from the data of the [`KeyFile`] using the key
     /// from the derivation function in combination with the given password.
     ///
@@ -113,20 +280,60 @@ impl KeyFile {
     /// # Errors
     ///
     /// * [`KeyFileErrorKind::InvalidSCryptParameters`] - If the parameters of the key derivation function are invalid
+    /// * [`KeyFileErrorKind::KeyIsWrapped`] - If the key is wrapped by a KMS and doesn't use a password
     ///
     /// # Returns
     ///
     /// The extracted key
     pub fn key_from_password(&self, passwd: &impl AsRef<[u8]>) -> RusticResult<Key> {
+        // keys wrapped by a KMS don't use a password. The KMS saved in the key file must not be used
+        // to unwrap it, as the key file is not authenticated.
+        if let Some(params) = &self.wrapped {
+            return Err(KeyFileErrorKind::KeyIsWrapped(params.kms.clone()).into());
+        }
         self.key_from_data(&self.kdf_key(passwd)?)
     }
 
+    /// Extract a key from the data of the [`KeyFile`] by unwrapping it using the given KMS.
+    ///
+    /// # Arguments
+    ///
+    /// * `wrapper` - The KMS which wrapped the key
+    ///
+    /// # Errors
+    ///
+    /// * [`KeyFileErrorKind::KmsFailed`] - If the KMS couldn't unwrap the key or the key file was wrapped by another KMS
+    /// * [`KeyFileErrorKind::DeserializingFromSliceFailed`] - If the data could not be deserialized
+    ///
+    /// # Returns
+    ///
+    /// The extracted key
+    pub fn key_from_wrapper(&self, wrapper: &dyn KeyWrapper) -> RusticResult<Key> {
+        if self.wrapped.as_ref() != Some(&wrapper.params()) {
+            return Err(KeyFileErrorKind::KmsFailed(
+                "key file was not wrapped by the configured KMS".to_string(),
+            )
+            .into());
+        }
+        let dec_data = Zeroizing::new(
+            wrapper
+                .unwrap(&self.data)
+                .map_err(|err| KeyFileErrorKind::KmsFailed(err.to_string()))?,
+        );
+        Ok(serde_json::from_slice::<MasterKey>(&dec_data)
+            .map_err(KeyFileErrorKind::DeserializingFromSliceFailed)?
+            .key())
+    }
+
     /// Generate a new [`KeyFile`] from a given key and password.
     ///
     /// # Arguments
     ///
     /// * `key` - The key to use for encryption
     /// * `passwd` - The password to use for the key derivation function
+    /// * `kdf` - The key derivation function to use
+    /// * `scrypt` - The parameters to use if `kdf` is `scrypt`
+    /// * `fido2` - Whether to additionally require the connected FIDO2 security key to unlock the key
     /// * `hostname` - The hostname to use for the [`KeyFile`]
     /// * `username` - The username to use for the [`KeyFile`]
     /// * `with_created` - Whether to set the creation time of the [`KeyFile`] to the current time
@@ -134,46 +341,214 @@ impl KeyFile {
     /// # Errors
     ///
     /// * [`KeyFileErrorKind::OutputLengthInvalid`] - If the output length of the key derivation function is invalid
+    /// * [`KeyFileErrorKind::ScryptNNotPowerOfTwo`] - If the scrypt parameter N is not a power of 2
+    /// * [`KeyFileErrorKind::InvalidSCryptParameters`] - If the parameters of `scrypt` are invalid
     /// * [`KeyFileErrorKind::CouldNotSerializeAsJsonByteVector`] - If the [`KeyFile`] could not be serialized
+    /// * [`KeyFileErrorKind::Fido2Failed`] - If no credential could be created on the security key
     ///
     /// # Returns
     ///
     /// The generated [`KeyFile`]
+    #[allow(clippy::too_many_arguments)]
     pub fn generate(
         key: Key,
         passwd: &impl AsRef<[u8]>,
+        kdf: KeyDerivation,
+        scrypt: ScryptOptions,
+        fido2: bool,
         hostname: Option<String>,
         username: Option<String>,
         with_created: bool,
     ) -> RusticResult<Self> {
-        let masterkey = MasterKey::from_key(key);
-        let params = Params::recommended();
-        let mut salt = vec![0; 64];
-        thread_rng().fill_bytes(&mut salt);
-
-        let mut key = [0; 64];
-        scrypt::scrypt(passwd.as_ref(), &salt, &params, &mut key)
-            .map_err(KeyFileErrorKind::OutputLengthInvalid)?;
+        let masterkey = Zeroizing::new(
+            serde_json::to_vec(&MasterKey::from_key(key))
+                .map_err(KeyFileErrorKind::CouldNotSerializeAsJsonByteVector)?,
+        );
+        Self::generate_with_data(
+            &masterkey,
+            passwd,
+            kdf,
+            scrypt,
+            fido2,
+            hostname,
+            username,
+            with_created,
+        )
+    }
 
-        let key = Key::from_slice(&key);
-        let data = key.encrypt_data(
-            &serde_json::to_vec(&masterkey)
+    /// Generate a new [`KeyFile`] containing a share of a master key.
+    ///
+    /// # Arguments
+    ///
+    /// * `share` - The information about the share
+    /// * `data` - The share data
+    /// * `passwd` - The password to use for the key derivation function
+    /// * `kdf` - The key derivation function to use
+    /// * `scrypt` - The parameters to use if `kdf` is `scrypt`
+    /// * `fido2` - Whether to additionally require the connected FIDO2 security key to unlock the key
+    /// * `hostname` - The hostname to use for the [`KeyFile`]
+    /// * `username` - The username to use for the [`KeyFile`]
+    /// * `with_created` - Whether to set the creation time of the [`KeyFile`] to the current time
+    ///
+    /// # Errors
+    ///
+    /// See [`KeyFile::generate`]
+    ///
+    /// # Returns
+    ///
+    /// The generated [`KeyFile`]
+    #[allow(clippy::too_many_arguments)]
+    pub fn generate_share(
+        share: KeyShare,
+        data: Vec<u8>,
+        passwd: &impl AsRef<[u8]>,
+        kdf: KeyDerivation,
+        scrypt: ScryptOptions,
+        fido2: bool,
+        hostname: Option<String>,
+        username: Option<String>,
+        with_created: bool,
+    ) -> RusticResult<Self> {
+        let data = Zeroizing::new(
+            serde_json::to_vec(&MasterKeyShare { share: data })
                 .map_err(KeyFileErrorKind::CouldNotSerializeAsJsonByteVector)?,
-        )?;
+        );
+        Ok(Self {
+            share: Some(share),
+            ..Self::generate_with_data(
+                &data,
+                passwd,
+                kdf,
+                scrypt,
+                fido2,
+                hostname,
+                username,
+                with_created,
+            )?
+        })
+    }
 
+    /// Generate a new [`KeyFile`] from a given key which is wrapped by a KMS.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to wrap
+    /// * `wrapper` - The KMS to wrap the key with
+    /// * `hostname` - The hostname to use for the [`KeyFile`]
+    /// * `username` - The username to use for the [`KeyFile`]
+    /// * `with_created` - Whether to set the creation time of the [`KeyFile`] to the current time
+    ///
+    /// # Errors
+    ///
+    /// * [`KeyFileErrorKind::CouldNotSerializeAsJsonByteVector`] - If the key could not be serialized
+    /// * [`KeyFileErrorKind::KmsFailed`] - If the KMS couldn't wrap the key
+    ///
+    /// # Returns
+    ///
+    /// The generated [`KeyFile`]
+    pub fn generate_wrapped(
+        key: Key,
+        wrapper: &dyn KeyWrapper,
+        hostname: Option<String>,
+        username: Option<String>,
+        with_created: bool,
+    ) -> RusticResult<Self> {
+        let masterkey = Zeroizing::new(
+            serde_json::to_vec(&MasterKey::from_key(key))
+                .map_err(KeyFileErrorKind::CouldNotSerializeAsJsonByteVector)?,
+        );
+        let data = wrapper
+            .wrap(&masterkey)
+            .map_err(|err| KeyFileErrorKind::KmsFailed(err.to_string()))?;
         Ok(Self {
             hostname,
             username,
-            kdf: "scrypt".to_string(),
-            n: 2_u32.pow(u32::from(params.log_n())),
-            r: params.r(),
-            p: params.p(),
+            label: None,
+            kdf: KeyDerivation::default(),
+            n: None,
+            r: None,
+            p: 0,
+            m: None,
+            t: None,
             created: with_created.then(Local::now),
             data,
-            salt,
+            salt: Vec::new(),
+            fido2: None,
+            share: None,
+            wrapped: Some(wrapper.params()),
+            restriction: None,
         })
     }
 
+    /// Generate a new [`KeyFile`] containing the given data encrypted by the key derived from the password.
+    #[allow(clippy::too_many_arguments)]
+    fn generate_with_data(
+        data: &[u8],
+        passwd: &impl AsRef<[u8]>,
+        kdf: KeyDerivation,
+        scrypt: ScryptOptions,
+        fido2: bool,
+        hostname: Option<String>,
+        username: Option<String>,
+        with_created: bool,
+    ) -> RusticResult<Self> {
+        let mut salt = vec![0; 64];
+        thread_rng().fill_bytes(&mut salt);
+
+        let mut keyfile = Self {
+            hostname,
+            username,
+            label: None,
+            kdf,
+            n: None,
+            r: None,
+            p: 0,
+            m: None,
+            t: None,
+            created: with_created.then(Local::now),
+            data: Vec::new(),
+            salt,
+            fido2: fido2.then(Fido2Params::create).transpose()?,
+            share: None,
+            wrapped: None,
+            restriction: None,
+        };
+        match kdf {
+            KeyDerivation::Scrypt => {
+                let recommended = Params::recommended();
+                let recommended_n = 2_u32.pow(u32::from(recommended.log_n()));
+                let recommended_r = recommended.r();
+                let n = scrypt.n.unwrap_or(recommended_n);
+                if n < 2 || !n.is_power_of_two() {
+                    return Err(KeyFileErrorKind::ScryptNNotPowerOfTwo(n).into());
+                }
+                let r = scrypt.r.unwrap_or(recommended_r);
+                let p = scrypt.p.unwrap_or(recommended.p());
+                // check the parameters before using them
+                _ = Params::new(log_2(n)?, r, p, Params::RECOMMENDED_LEN)
+                    .map_err(KeyFileErrorKind::InvalidSCryptParameters)?;
+                if n < recommended_n || r < recommended_r {
+                    warn!(
+                        "scrypt parameters N={n}, r={r} are weaker than recommended (N={recommended_n}, r={recommended_r})."
+                    );
+                }
+                keyfile.n = Some(n);
+                keyfile.r = Some(r);
+                keyfile.p = p;
+            }
+            KeyDerivation::Argon2id => {
+                keyfile.m = Some(constants::ARGON2_MEMORY);
+                keyfile.t = Some(constants::ARGON2_ITERATIONS);
+                keyfile.p = constants::ARGON2_PARALLELISM;
+            }
+        }
+
+        let key = keyfile.kdf_key(passwd)?;
+        keyfile.data = key.encrypt_data(data)?;
+
+        Ok(keyfile)
+    }
+
     /// Get a [`KeyFile`] from the backend
     ///
     /// # Arguments
@@ -188,7 +563,7 @@ impl KeyFile {
     /// # Returns
     ///
     /// The [`KeyFile`] read from the backend
-    fn from_backend<B: ReadBackend>(be: &B, id: &Id) -> RusticResult<Self> {
+    pub(crate) fn from_backend<B: ReadBackend>(be: &B, id: &Id) -> RusticResult<Self> {
         let data = be.read_full(FileType::Key, id)?;
         Ok(
             serde_json::from_slice(&data)
@@ -218,6 +593,23 @@ fn log_2(x: u32) -> RusticResult<u8> {
         - 1)
 }
 
+/// Create an `argon2id` hasher with the given parameters
+///
+/// # Arguments
+///
+/// * `memory` - The memory cost in KiB
+/// * `iterations` - The number of iterations
+/// * `parallelism` - The degree of parallelism
+///
+/// # Errors
+///
+/// * [`KeyFileErrorKind::Argon2Failed`] - If the parameters are invalid
+fn argon2(memory: u32, iterations: u32, parallelism: u32) -> RusticResult<Argon2<'static>> {
+    let params = argon2::Params::new(memory, iterations, parallelism, Some(64))
+        .map_err(KeyFileErrorKind::Argon2Failed)?;
+    Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+}
+
 /// The mac of a [`Key`]
 ///
 /// This is used to verify the integrity of the key
@@ -246,6 +638,35 @@ pub(crate) struct MasterKey {
     encrypt: Vec<u8>,
 }
 
+/// A share of a master key, see [`KeyShare`]
+#[serde_as]
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct MasterKeyShare {
+    /// The share data
+    #[serde_as(as = "Base64")]
+    share: Vec<u8>,
+}
+
+// The key material is wiped from memory when it is no longer needed
+impl Drop for Mac {
+    fn drop(&mut self) {
+        self.k.zeroize();
+        self.r.zeroize();
+    }
+}
+
+impl Drop for MasterKey {
+    fn drop(&mut self) {
+        self.encrypt.zeroize();
+    }
+}
+
+impl Drop for MasterKeyShare {
+    fn drop(&mut self) {
+        self.share.zeroize();
+    }
+}
+
 impl MasterKey {
     /// Create a [`MasterKey`] from a [`Key`]
     ///
@@ -305,20 +726,394 @@ pub(crate) fn key_from_backend<B: ReadBackend>(
 ///
 /// # Returns
 ///
-/// The found key
+/// The id of the found [`KeyFile`] and the contained key
 pub(crate) fn find_key_in_backend<B: ReadBackend>(
     be: &B,
     passwd: &impl AsRef<[u8]>,
     hint: Option<&Id>,
-) -> RusticResult<Key> {
+) -> RusticResult<(Id, Key)> {
     if let Some(id) = hint {
-        key_from_backend(be, id, passwd)
+        Ok((*id, key_from_backend(be, id, passwd)?))
     } else {
-        for id in be.list(FileType::Key)? {
-            if let Ok(key) = key_from_backend(be, &id, passwd) {
-                return Ok(key);
+        let (id, _, key) = find_derived_key_in_backend(be, passwd)?;
+        Ok((id, key))
+    }
+}
+
+/// Find a [`KeyFile`] in the backend that fits to the given password and return the key derived
+/// from the password as well as the contained key.
+///
+/// # Arguments
+///
+/// * `be` - The backend to use
+/// * `passwd` - The password to use
+///
+/// # Errors
+///
+/// * [`KeyFileErrorKind::NoSuitableKeyFound`] - If no suitable key was found
+///
+/// # Returns
+///
+/// The id of the found [`KeyFile`], the key derived from the password and the contained key
+pub(crate) fn find_derived_key_in_backend<B: ReadBackend>(
+    be: &B,
+    passwd: &impl AsRef<[u8]>,
+) -> RusticResult<(Id, Key, Key)> {
+    let keys_from_password = |keyfile: &KeyFile| {
+        keyfile
+            .kdf_key(passwd)
+            .and_then(|derived| keyfile.key_from_data(&derived).map(|key| (derived, key)))
+    };
+    let mut fido2_keys = Vec::new();
+    for id in be.list(FileType::Key)? {
+        match KeyFile::from_backend(be, &id) {
+            // shares of the master key can't be used alone and wrapped keys don't use the password
+            Ok(keyfile) if keyfile.share.is_some() || keyfile.wrapped.is_some() => {}
+            Ok(keyfile) if keyfile.fido2.is_some() => fido2_keys.push((id, keyfile)),
+            Ok(keyfile) => {
+                if let Ok((derived, key)) = keys_from_password(&keyfile) {
+                    return Ok((id, derived, key));
+                }
+            }
+            Err(_) => {}
+        }
+    }
+    // keys requiring a security key are tried last as they need user interaction
+    for (id, keyfile) in fido2_keys {
+        match keys_from_password(&keyfile) {
+            Ok((derived, key)) => return Ok((id, derived, key)),
+            Err(err) => warn!("key {id} cannot be used: {err}"),
+        }
+    }
+    Err(KeyFileErrorKind::NoSuitableKeyFound.into())
+}
+
+/// Find a [`KeyFile`] in the backend which is wrapped by the given KMS and return the contained key.
+///
+/// Only key files whose saved KMS parameters match the parameters of `wrapper` are tried. The KMS
+/// is always configured locally, as the parameters in the (unauthenticated) key files could point
+/// to a KMS controlled by an attacker.
+///
+/// # Arguments
+///
+/// * `be` - The backend to use
+/// * `wrapper` - The locally configured KMS to use
+///
+/// # Errors
+///
+/// * [`KeyFileErrorKind::NoSuitableKeyFound`] - If no key file could be unwrapped
+///
+/// # Returns
+///
+/// The id of the found [`KeyFile`] and the contained key
+pub(crate) fn find_wrapped_key_in_backend<B: ReadBackend>(
+    be: &B,
+    wrapper: &dyn KeyWrapper,
+) -> RusticResult<(Id, Key)> {
+    let params = wrapper.params();
+    for id in be.list(FileType::Key)? {
+        let keyfile = match KeyFile::from_backend(be, &id) {
+            Ok(keyfile) => keyfile,
+            Err(_) => continue,
+        };
+        match &keyfile.wrapped {
+            Some(wrapped) if *wrapped == params => {}
+            Some(wrapped) => {
+                debug!(
+                    "key {id} is wrapped by KMS {} with key {}, which is not configured",
+                    wrapped.kms, wrapped.key_id
+                );
+                continue;
+            }
+            None => continue,
+        }
+        match keyfile.key_from_wrapper(wrapper) {
+            Ok(key) => return Ok((id, key)),
+            Err(err) => warn!("key {id} cannot be used: {err}"),
+        }
+    }
+    Err(KeyFileErrorKind::NoSuitableKeyFound.into())
+}
+
+/// Recover the master key from the shares in the backend which fit to the given passwords.
+///
+/// # Arguments
+///
+/// * `be` - The backend to use
+/// * `passwords` - The passwords of the shares
+///
+/// # Errors
+///
+/// * [`KeyFileErrorKind::NotEnoughShares`] - If shares have been found, but not enough to recover the master key
+/// * [`KeyFileErrorKind::NoSuitableKeyFound`] - If no share fits to the passwords
+///
+/// # Returns
+///
+/// The recovered master key
+pub(crate) fn key_from_shares<B: ReadBackend>(
+    be: &B,
+    passwords: &[impl AsRef<[u8]>],
+) -> RusticResult<Key> {
+    // the threshold and the found shares for each set of shares
+    let mut sets: HashMap<Id, (u8, Vec<(u8, Vec<u8>)>)> = HashMap::new();
+    for id in be.list(FileType::Key)? {
+        let keyfile = match KeyFile::from_backend(be, &id) {
+            Ok(keyfile) if keyfile.share.is_some() => keyfile,
+            _ => continue,
+        };
+        if let Some((share, data)) = passwords
+            .iter()
+            .find_map(|passwd| keyfile.share_from_password(passwd).ok())
+        {
+            let (_, shares) = sets
+                .entry(share.set)
+                .or_insert_with(|| (share.threshold, Vec::new()));
+            if shares.iter().all(|(index, _)| *index != share.index) {
+                shares.push((share.index, data));
+            }
+        }
+    }
+
+    let mut key = None;
+    let mut missing = None;
+    for (threshold, mut shares) in sets.into_values() {
+        let threshold = usize::from(threshold);
+        if key.is_none() && shares.len() >= threshold {
+            let secret = Zeroizing::new(shamir::combine(&shares[..threshold]));
+            if secret.len() == 64 {
+                key = Some(Key::from_slice(&secret));
             }
+        } else if shares.len() < threshold
+            && missing.map_or(true, |(found, needed)| {
+                needed - found > threshold - shares.len()
+            })
+        {
+            missing = Some((shares.len(), threshold));
+        }
+        // wipe all decrypted shares, also the ones which have not been used
+        for (_, data) in &mut shares {
+            data.zeroize();
+        }
+    }
+    if let Some(key) = key {
+        return Ok(key);
+    }
+    Err(missing
+        .map_or(KeyFileErrorKind::NoSuitableKeyFound, |(found, needed)| {
+            KeyFileErrorKind::NotEnoughShares(found, needed)
+        })
+        .into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        backend::{local::LocalBackend, WriteBackend},
+        crypto::hasher::hash,
+        error::{CryptoErrorKind, RusticError, RusticErrorKind},
+    };
+
+    // weak parameters to keep the tests fast
+    const SCRYPT: ScryptOptions = ScryptOptions {
+        n: Some(1024),
+        r: Some(8),
+        p: Some(1),
+    };
+
+    fn generate(key: Key, passwd: &[u8], kdf: KeyDerivation) -> KeyFile {
+        KeyFile::generate(key, &passwd, kdf, SCRYPT, false, None, None, false).unwrap()
+    }
+
+    fn same_key(key1: Key, key2: Key) -> bool {
+        key1.to_keys() == key2.to_keys()
+    }
+
+    fn is_decryption_error(err: RusticError) -> bool {
+        matches!(
+            err.into_inner(),
+            RusticErrorKind::Crypto(CryptoErrorKind::DataDecryptionFailed(_))
+        )
+    }
+
+    #[test]
+    fn generated_keys_can_be_opened() {
+        for kdf in [KeyDerivation::Scrypt, KeyDerivation::Argon2id] {
+            let key = Key::new();
+            let keyfile = generate(key, b"password", kdf);
+            assert!(same_key(
+                keyfile.key_from_password(&"password").unwrap(),
+                key
+            ));
+
+            // the same key can be opened after saving the key file
+            let data = serde_json::to_vec(&keyfile).unwrap();
+            let keyfile: KeyFile = serde_json::from_slice(&data).unwrap();
+            assert_eq!(keyfile.kdf, kdf);
+            assert!(same_key(
+                keyfile.key_from_password(&"password").unwrap(),
+                key
+            ));
+
+            let err = keyfile.key_from_password(&"wrong").unwrap_err();
+            assert!(is_decryption_error(err));
+        }
+    }
+
+    #[test]
+    fn argon2_parameters() {
+        let keyfile = generate(Key::new(), b"password", KeyDerivation::Argon2id);
+        assert_eq!((keyfile.n, keyfile.r), (None, None));
+        assert_eq!(keyfile.m, Some(constants::ARGON2_MEMORY));
+        assert_eq!(keyfile.t, Some(constants::ARGON2_ITERATIONS));
+        assert_eq!(keyfile.p, constants::ARGON2_PARALLELISM);
+        let data = serde_json::to_value(&keyfile).unwrap();
+        assert_eq!(data["kdf"], "argon2id");
+        assert!(data.get("N").is_none());
+    }
+
+    #[test]
+    fn tampered_parameters_are_rejected() {
+        let tampered: [fn(&mut KeyFile); 6] = [
+            |keyfile| keyfile.m = keyfile.m.map(|m| m / 2),
+            |keyfile| keyfile.t = keyfile.t.map(|t| t - 1),
+            |keyfile| keyfile.p = 1,
+            |keyfile| keyfile.salt[0] ^= 1,
+            |keyfile| keyfile.data[0] ^= 1,
+            |keyfile| {
+                keyfile.n = Some(1024);
+                keyfile.r = Some(8);
+                keyfile.p = 1;
+                keyfile.kdf = KeyDerivation::Scrypt;
+            },
+        ];
+        for tamper in tampered {
+            let mut keyfile = generate(Key::new(), b"password", KeyDerivation::Argon2id);
+            tamper(&mut keyfile);
+            let err = keyfile.key_from_password(&"password").unwrap_err();
+            assert!(is_decryption_error(err));
         }
-        Err(KeyFileErrorKind::NoSuitableKeyFound.into())
+
+        let tampered: [fn(&mut KeyFile); 3] = [
+            |keyfile| keyfile.n = Some(2048),
+            |keyfile| keyfile.r = Some(4),
+            |keyfile| keyfile.salt[0] ^= 1,
+        ];
+        for tamper in tampered {
+            let mut keyfile = generate(Key::new(), b"password", KeyDerivation::Scrypt);
+            tamper(&mut keyfile);
+            let err = keyfile.key_from_password(&"password").unwrap_err();
+            assert!(is_decryption_error(err));
+        }
+
+        let mut keyfile = generate(Key::new(), b"password", KeyDerivation::Scrypt);
+        keyfile.kdf = KeyDerivation::Argon2id;
+        assert!(matches!(
+            keyfile
+                .key_from_password(&"password")
+                .unwrap_err()
+                .into_inner(),
+            RusticErrorKind::KeyFile(KeyFileErrorKind::MissingKdfParameter("m"))
+        ));
+    }
+
+    #[test]
+    fn fido2_keys_need_the_secret() {
+        // a key file whose password is extended by the secret of a security key
+        let secret = [7; 32];
+        let passwd = [&b"password"[..], &secret].concat();
+        let key = Key::new();
+        let mut keyfile = generate(key, &passwd, KeyDerivation::Scrypt);
+        assert!(same_key(keyfile.key_from_password(&passwd).unwrap(), key));
+
+        // removing the FIDO2 parameters doesn't allow to open the key with the password alone
+        assert!(keyfile.fido2.is_none());
+        let err = keyfile.key_from_password(&"password").unwrap_err();
+        assert!(is_decryption_error(err));
+
+        // invalid FIDO2 parameters are rejected before the security key is used
+        keyfile.fido2 = Some(
+            serde_json::from_str(r#"{"rp_id":"rustic","credential":"AAAA","salt":"AAAA"}"#)
+                .unwrap(),
+        );
+        let err = keyfile.key_from_password(&"password").unwrap_err();
+        assert!(matches!(
+            err.into_inner(),
+            RusticErrorKind::KeyFile(
+                KeyFileErrorKind::Fido2NoSecret | KeyFileErrorKind::Fido2NotSupported
+            )
+        ));
+    }
+
+    #[test]
+    fn shares_recover_the_master_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let be = LocalBackend::new(dir.path().to_str().unwrap()).unwrap();
+        be.create().unwrap();
+
+        let key = Key::new();
+        let (encrypt, k, r) = key.to_keys();
+        let secret = [&encrypt[..], &k[..], &r[..]].concat();
+        let set = Id::random();
+        let mut keyfiles: Vec<_> = shamir::split(&secret, 2, 3)
+            .into_iter()
+            .zip(["share1", "share2", "share3"])
+            .map(|((index, data), passwd)| {
+                let share = KeyShare {
+                    set,
+                    threshold: 2,
+                    index,
+                };
+                KeyFile::generate_share(
+                    share,
+                    data,
+                    &passwd,
+                    KeyDerivation::Scrypt,
+                    SCRYPT,
+                    false,
+                    None,
+                    None,
+                    false,
+                )
+                .unwrap()
+            })
+            .collect();
+
+        // a share can't be used alone
+        assert!(matches!(
+            keyfiles[0]
+                .key_from_password(&"share1")
+                .unwrap_err()
+                .into_inner(),
+            RusticErrorKind::KeyFile(KeyFileErrorKind::KeyIsShare)
+        ));
+        let (share, _) = keyfiles[0].share_from_password(&"share1").unwrap();
+        assert_eq!(share, keyfiles[0].share.unwrap());
+        assert!(keyfiles[0].share_from_password(&"share2").is_err());
+
+        // tamper with the kdf parameters of the third share
+        keyfiles[2].salt[0] ^= 1;
+        for keyfile in &keyfiles {
+            let data = serde_json::to_vec(keyfile).unwrap();
+            be.write_bytes(FileType::Key, &hash(&data), false, data.into())
+                .unwrap();
+        }
+
+        let recovered = key_from_shares(&be, &["share1", "share2"]).unwrap();
+        assert!(same_key(recovered, key));
+        let recovered = key_from_shares(&be, &["share2", "wrong", "share1"]).unwrap();
+        assert!(same_key(recovered, key));
+
+        assert!(key_from_shares(&be, &["share1"])
+            .unwrap_err()
+            .is_not_enough_shares());
+        assert!(key_from_shares(&be, &["share1", "share3"])
+            .unwrap_err()
+            .is_not_enough_shares());
+        assert!(matches!(
+            key_from_shares(&be, &["wrong"]).unwrap_err().into_inner(),
+            RusticErrorKind::KeyFile(KeyFileErrorKind::NoSuitableKeyFound)
+        ));
     }
 }
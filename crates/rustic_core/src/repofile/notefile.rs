@@ -0,0 +1,28 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::{backend::FileType, id::Id, repofile::RepoFile};
+
+/// Note files contain small annotations which are attached to a snapshot, pack or key of the repository.
+///
+/// They are usually stored in the repository under `/notes/<ID>`
+#[serde_with::apply(Option => #[serde(default, skip_serializing_if = "Option::is_none")])]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NoteFile {
+    /// The time the note has been added
+    pub time: DateTime<Local>,
+    /// The host which added the note
+    pub hostname: Option<String>,
+    /// The type of the file the note is attached to
+    #[serde(rename = "type")]
+    pub tpe: FileType,
+    /// The id of the file the note is attached to
+    pub target: Id,
+    /// The text of the note
+    pub text: String,
+}
+
+impl RepoFile for NoteFile {
+    /// The [`FileType`] associated with the [`NoteFile`]
+    const TYPE: FileType = FileType::Note;
+}
@@ -239,6 +239,7 @@ impl PackHeader {
     /// * `id` - The id of the packfile
     /// * `size_hint` - The size hint for the pack header
     /// * `pack_size` - The size of the packfile
+    /// * `padded` - Whether the packfile may be padded, see [`ConfigFile::pack_padding`]
     ///
     /// # Errors
     ///
@@ -246,11 +247,14 @@ impl PackHeader {
     /// * [`PackFileErrorKind::HeaderLengthTooLarge`] - If the header length is too large
     /// * [`PackFileErrorKind::HeaderLengthDoesNotMatchHeaderContents`] - If the header length does not match the header contents
     /// * [`PackFileErrorKind::HeaderPackSizeComputedDoesNotMatchRealPackFile`] - If the pack size computed from the header does not match the real pack file size
+    ///
+    /// [`ConfigFile::pack_padding`]: crate::repofile::configfile::ConfigFile::pack_padding
     pub(crate) fn from_file(
         be: &impl DecryptReadBackend,
         id: Id,
         size_hint: Option<u32>,
         pack_size: u32,
+        padded: bool,
     ) -> RusticResult<Self> {
         // guess the header size from size_hint and pack_size
         // If the guess is too small, we have to re-read. If the guess is too large, we have to have read too much
@@ -295,11 +299,13 @@ impl PackHeader {
             .into());
         }
 
-        if header.pack_size() != pack_size {
+        // padded packs contain additional data in front of the header
+        let size_computed = header.pack_size();
+        if size_computed > pack_size || (!padded && size_computed != pack_size) {
             return Err(
                 PackFileErrorKind::HeaderPackSizeComputedDoesNotMatchRealPackFile {
                     size_real: pack_size,
-                    size_computed: header.pack_size(),
+                    size_computed,
                 }
                 .into(),
             );
@@ -13,6 +13,10 @@ use gethostname::gethostname;
 use itertools::Itertools;
 use log::info;
 use path_dedot::ParseDot;
+use rayon::{
+    prelude::{IntoParallelIterator, ParallelIterator},
+    ThreadPoolBuilder,
+};
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
 use shell_words::split;
@@ -20,7 +24,7 @@ use shell_words::split;
 use crate::{
     backend::{decrypt::DecryptReadBackend, FileType},
     error::SnapshotFileErrorKind,
-    error::{RusticError, RusticResult},
+    error::{CommandErrorKind, RusticError, RusticResult},
     id::Id,
     progress::Progress,
     repofile::RepoFile,
@@ -76,8 +80,13 @@ pub struct SnapshotOptions {
     #[cfg_attr(feature = "merge", merge(strategy = merge::bool::overwrite_false))]
     pub delete_never: bool,
 
-    /// Mark snapshot to be deleted after given duration (e.g. 10d)
-    #[cfg_attr(feature = "clap", clap(long, value_name = "DURATION"))]
+    /// Mark snapshot to be deleted after given duration (e.g. 10d). Until then, `forget` keeps the
+    /// snapshot, afterwards it removes the snapshot regardless of the keep options.
+    #[cfg_attr(
+        feature = "clap",
+        clap(long, value_name = "DURATION", visible_alias = "expire-after")
+    )]
+    #[serde(alias = "expire-after")]
     #[serde_as(as = "Option<DisplayFromStr>")]
     pub delete_after: Option<humantime::Duration>,
 
@@ -124,6 +133,22 @@ pub struct SnapshotSummary {
     /// Unchanged files compared to the last (i.e. parent) snapshot
     pub files_unmodified: u64,
 
+    /// Files which changed while being read, even after re-reading them
+    #[serde(default)]
+    pub files_unstable: u64,
+
+    /// Files which could not be backed up, e.g. because they could not be read
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failed_paths: Vec<PathBuf>,
+
+    /// Errors while reading the backup source which lead to missing entries, e.g. unreadable directories
+    #[serde(default)]
+    pub source_errors: u64,
+
+    /// Whether the snapshot is incomplete because some files or directories could not be backed up
+    #[serde(default)]
+    pub partial: bool,
+
     /// Total processed files
     pub total_files_processed: u64,
 
@@ -189,6 +214,34 @@ pub struct SnapshotSummary {
 
     /// Total duration that the rustic command ran in seconds
     pub total_duration: f64,
+
+    /// Resources used by the backup
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ResourceUsage>,
+}
+
+/// Resources used while creating a snapshot
+#[serde_with::apply(Option => #[serde(default, skip_serializing_if = "Option::is_none")])]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[non_exhaustive]
+pub struct ResourceUsage {
+    /// Peak resident memory of the rustic process in bytes
+    pub peak_memory: Option<u64>,
+
+    /// CPU time (user and system) used by the rustic process in seconds
+    pub cpu_time: Option<f64>,
+
+    /// Time spent reading the backup source in seconds
+    pub scan_duration: f64,
+
+    /// Time spent reading and chunking files in seconds, summed over all threads
+    pub chunk_duration: f64,
+
+    /// Time spent uploading pack files in seconds, summed over all threads
+    pub upload_duration: f64,
+
+    /// Time spent saving index files in seconds
+    pub index_duration: f64,
 }
 
 impl SnapshotSummary {
@@ -276,6 +329,13 @@ pub struct SnapshotFile {
     #[serde(default)]
     pub hostname: String,
 
+    /// The platform (as given by [`std::env::consts::OS`]) on which the snapshot has been created.
+    ///
+    /// This is used to interpret `paths` which are saved in the notation of this platform.
+    #[derivative(Default(value = "std::env::consts::OS.to_string()"))]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub platform: String,
+
     /// The username that started the backup run
     #[serde(default)]
     pub username: String,
@@ -393,7 +453,7 @@ impl SnapshotFile {
     /// # Arguments
     ///
     /// * `tuple` - A tuple of the [`Id`] and the [`RepoFile`] to use
-    fn set_id(tuple: (Id, Self)) -> Self {
+    pub(crate) fn set_id(tuple: (Id, Self)) -> Self {
         let (id, mut snap) = tuple;
         snap.id = id;
         _ = snap.original.get_or_insert(id);
@@ -579,18 +639,20 @@ impl SnapshotFile {
     /// * `be` - The backend to use
     /// * `filter` - A filter to filter the snapshots
     /// * `crit` - The criteria to use for grouping
+    /// * `connections` - The number of snapshot files to load in parallel
     /// * `p` - A progress bar to use
     pub(crate) fn group_from_backend<B, F>(
         be: &B,
         filter: F,
         crit: SnapshotGroupCriterion,
+        connections: usize,
         p: &impl Progress,
     ) -> RusticResult<Vec<(SnapshotGroup, Vec<Self>)>>
     where
         B: DecryptReadBackend,
-        F: FnMut(&Self) -> bool,
+        F: Fn(&Self) -> bool + Sync,
     {
-        let mut snaps = Self::all_from_backend(be, filter, p)?;
+        let mut snaps = Self::all_from_backend(be, filter, connections, p)?;
         snaps.sort_unstable_by(|sn1, sn2| sn1.cmp_group(crit, sn2));
 
         let mut result = Vec::new();
@@ -604,21 +666,50 @@ impl SnapshotFile {
         Ok(result)
     }
 
-    // TODO: add documentation!
+    /// Get all [`SnapshotFile`]s from the backend which match the filter
+    ///
+    /// The snapshot files are read and filtered in parallel using a bounded number of threads.
+    /// Only the matching snapshots are kept in memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The backend to use
+    /// * `filter` - A filter to filter the snapshots
+    /// * `connections` - The number of snapshot files to load in parallel
+    /// * `p` - A progress bar to use
+    ///
+    /// # Errors
+    ///
+    /// * [`CommandErrorKind::FromRayonError`] - If the thread pool could not be created.
     pub(crate) fn all_from_backend<B, F>(
         be: &B,
         filter: F,
+        connections: usize,
         p: &impl Progress,
     ) -> RusticResult<Vec<Self>>
     where
         B: DecryptReadBackend,
-        F: FnMut(&Self) -> bool,
+        F: Fn(&Self) -> bool + Sync,
     {
-        be.stream_all::<Self>(p)?
-            .into_iter()
-            .map_ok(Self::set_id)
-            .filter_ok(filter)
-            .try_collect()
+        let list = be.list(FileType::Snapshot)?;
+        p.set_length(list.len() as u64);
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(connections)
+            .build()
+            .map_err(CommandErrorKind::FromRayonError)?;
+
+        pool.install(|| {
+            list.into_par_iter()
+                .filter_map(|id| {
+                    let snap = Self::from_backend(be, &id);
+                    p.inc(1);
+                    match snap {
+                        Ok(snap) if !filter(&snap) => None,
+                        snap => Some(snap),
+                    }
+                })
+                .collect()
+        })
     }
 
     /// Add tag lists to snapshot.
@@ -1093,7 +1184,7 @@ impl PathList {
 
     /// Clone the internal `Vec<PathBuf>`.
     #[must_use]
-    pub(crate) fn paths(&self) -> Vec<PathBuf> {
+    pub fn paths(&self) -> Vec<PathBuf> {
         self.0.clone()
     }
 
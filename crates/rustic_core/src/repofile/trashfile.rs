@@ -0,0 +1,37 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backend::FileType,
+    repofile::{RepoFile, SnapshotFile},
+};
+
+/// Trash files contain snapshots which have been forgotten but can still be undeleted.
+///
+/// They are usually stored in the repository under `/trash/<ID>`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TrashFile {
+    /// The time the snapshot has been moved to the trash
+    pub time: DateTime<Local>,
+    /// The time after which the snapshot will be removed by `prune`
+    pub expires: DateTime<Local>,
+    /// The forgotten snapshot, including its original id
+    pub snapshot: SnapshotFile,
+}
+
+impl RepoFile for TrashFile {
+    /// The [`FileType`] associated with the [`TrashFile`]
+    const TYPE: FileType = FileType::Trash;
+}
+
+impl TrashFile {
+    /// Returns whether the trashed snapshot is expired at the given time
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The time to compare with
+    #[must_use]
+    pub fn is_expired(&self, now: DateTime<Local>) -> bool {
+        self.expires < now
+    }
+}
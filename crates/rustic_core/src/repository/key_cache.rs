@@ -0,0 +1,199 @@
+use std::{
+    fs::{self, DirBuilder, Metadata, OpenOptions},
+    io::{self, ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Duration, Local};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_with::{base64::Base64, serde_as};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::{
+    crypto::{aespoly1305::Key, hasher::hash},
+    error::{KeyFileErrorKind, RepositoryErrorKind, RusticResult},
+    id::Id,
+};
+
+/// A key derived from the password which is saved in the key cache
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+struct CachedKey {
+    /// The id of the key file which can be decrypted by the derived key
+    key_id: Id,
+
+    /// The derived key
+    #[serde_as(as = "Base64")]
+    key: Vec<u8>,
+
+    /// The time after which the cached key is not used anymore
+    expires: DateTime<Local>,
+}
+
+impl Drop for CachedKey {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+/// Get the path of the key cache file for the given repository.
+///
+/// The key is saved unencrypted, so it is only cached in the runtime dir which is usually a tmpfs
+/// cleared on logout. Persistent locations like the cache dir are never used.
+///
+/// # Arguments
+///
+/// * `repo_name` - The name of the repository
+///
+/// # Errors
+///
+/// * [`RepositoryErrorKind::NoKeyCacheDirectory`] - If there is no runtime dir
+fn path(repo_name: &str) -> RusticResult<PathBuf> {
+    let dir = dirs::runtime_dir().ok_or(RepositoryErrorKind::NoKeyCacheDirectory)?;
+    Ok(dir
+        .join("rustic")
+        .join("keys")
+        .join(hash(repo_name.as_bytes()).to_hex().as_str()))
+}
+
+/// Check that the key cache file is a regular file which is only accessible by the current user.
+#[cfg(unix)]
+fn is_protected(meta: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    meta.is_file() && meta.uid() == nix::unistd::getuid().as_raw() && meta.mode() & 0o077 == 0
+}
+
+/// Check that the key cache file is a regular file.
+///
+/// The runtime dir is only accessible by the user, so the file is protected by its default permissions.
+#[cfg(not(unix))]
+fn is_protected(meta: &Metadata) -> bool {
+    meta.is_file()
+}
+
+/// Write the key cache file such that it is only accessible by the current user.
+fn write_protected(path: &Path, data: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        let mut builder = DirBuilder::new();
+        _ = builder.recursive(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            _ = builder.mode(0o700);
+        }
+        builder.create(dir)?;
+    }
+    // always create a new file to not write into a file with wrong permissions
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+    let mut options = OpenOptions::new();
+    _ = options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        _ = options.mode(0o600);
+    }
+    options.open(path)?.write_all(data)
+}
+
+/// Read the cached key for the given repository.
+///
+/// # Arguments
+///
+/// * `repo_name` - The name of the repository
+///
+/// # Returns
+///
+/// The id of the key file and the key derived from its password or `None` if no valid key is cached
+pub(super) fn read(repo_name: &str) -> Option<(Id, Key)> {
+    let path = path(repo_name).ok()?;
+    let meta = fs::symlink_metadata(&path).ok()?;
+    if !is_protected(&meta) {
+        warn!("ignoring key cache {path:?} which may be accessible by other users");
+        return None;
+    }
+    let data = Zeroizing::new(fs::read(&path).ok()?);
+    let cached: CachedKey = match serde_json::from_slice(&data) {
+        Ok(cached) => cached,
+        Err(err) => {
+            warn!("ignoring invalid key cache {path:?}: {err}");
+            return None;
+        }
+    };
+    if cached.expires < Local::now() || cached.key.len() != 64 {
+        debug!("cached key in {path:?} has expired");
+        _ = fs::remove_file(&path);
+        return None;
+    }
+    Some((cached.key_id, Key::from_slice(&cached.key)))
+}
+
+/// Save the key derived from the password of a key file in the key cache.
+///
+/// # Arguments
+///
+/// * `repo_name` - The name of the repository
+/// * `key_id` - The id of the key file
+/// * `key` - The key derived from the password of the key file
+/// * `ttl` - How long the cached key can be used
+///
+/// # Errors
+///
+/// * [`RepositoryErrorKind::NoKeyCacheDirectory`] - If there is no runtime dir
+/// * [`RepositoryErrorKind::KeyCacheTtlOutOfRange`] - If the ttl is too large
+/// * [`RepositoryErrorKind::KeyCacheFailed`] - If the key cache file could not be written
+pub(super) fn save(
+    repo_name: &str,
+    key_id: Id,
+    key: Key,
+    ttl: std::time::Duration,
+) -> RusticResult<()> {
+    let path = path(repo_name)?;
+    let expires = Duration::from_std(ttl)
+        .ok()
+        .and_then(|ttl| Local::now().checked_add_signed(ttl))
+        .ok_or(RepositoryErrorKind::KeyCacheTtlOutOfRange)?;
+    let (encrypt, k, r) = key.to_keys();
+    let (encrypt, k, r) = (
+        Zeroizing::new(encrypt),
+        Zeroizing::new(k),
+        Zeroizing::new(r),
+    );
+    let cached = CachedKey {
+        key_id,
+        key: [&encrypt[..], &k[..], &r[..]].concat(),
+        expires,
+    };
+    let data = Zeroizing::new(
+        serde_json::to_vec(&cached).map_err(KeyFileErrorKind::CouldNotSerializeAsJsonByteVector)?,
+    );
+    write_protected(&path, &data).map_err(|err| RepositoryErrorKind::KeyCacheFailed(path, err))?;
+    info!("cached key until {expires}");
+    Ok(())
+}
+
+/// Remove the cached key of the given repository.
+///
+/// # Arguments
+///
+/// * `repo_name` - The name of the repository
+///
+/// # Errors
+///
+/// * [`RepositoryErrorKind::NoKeyCacheDirectory`] - If there is no runtime dir
+/// * [`RepositoryErrorKind::KeyCacheFailed`] - If the key cache file could not be removed
+///
+/// # Returns
+///
+/// Whether a cached key has been removed
+pub(super) fn remove(repo_name: &str) -> RusticResult<bool> {
+    let path = path(repo_name)?;
+    match fs::remove_file(&path) {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(RepositoryErrorKind::KeyCacheFailed(path, err).into()),
+    }
+}
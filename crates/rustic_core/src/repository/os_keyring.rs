@@ -0,0 +1,97 @@
+//! Reading and saving passwords in the OS keyring
+//!
+//! This needs rustic to be compiled with the feature `keyring`.
+#[cfg(feature = "keyring")]
+use keyring::Entry;
+#[cfg(feature = "keyring")]
+use log::{debug, info};
+
+use crate::error::{RepositoryErrorKind, RusticResult};
+
+#[cfg(feature = "keyring")]
+pub(super) mod constants {
+    /// The service name under which rustic stores passwords in the OS keyring.
+    pub(super) const SERVICE: &str = "rustic";
+}
+
+/// Get the keyring entry with the given name.
+///
+/// # Arguments
+///
+/// * `name` - The name of the entry.
+///
+/// # Errors
+///
+/// * [`RepositoryErrorKind::KeyringFailed`] - If the keyring could not be accessed.
+#[cfg(feature = "keyring")]
+fn entry(name: &str) -> RusticResult<Entry> {
+    Ok(Entry::new(constants::SERVICE, name)
+        .map_err(|err| RepositoryErrorKind::KeyringFailed(err.to_string()))?)
+}
+
+/// Read a password from the OS keyring.
+///
+/// # Arguments
+///
+/// * `name` - The name of the entry to read.
+///
+/// # Errors
+///
+/// * [`RepositoryErrorKind::KeyringFailed`] - If the keyring could not be accessed.
+///
+/// # Returns
+///
+/// The password or `None` if there is no entry with the given name.
+#[cfg(feature = "keyring")]
+pub(super) fn read_password(name: &str) -> RusticResult<Option<String>> {
+    match entry(name)?.get_password() {
+        Ok(password) => {
+            debug!("using password from keyring entry {name}");
+            Ok(Some(password))
+        }
+        Err(keyring::Error::NoEntry) => {
+            info!("no password saved in keyring entry {name}");
+            Ok(None)
+        }
+        Err(err) => Err(RepositoryErrorKind::KeyringFailed(err.to_string()).into()),
+    }
+}
+
+/// Save a password in the OS keyring.
+///
+/// # Arguments
+///
+/// * `name` - The name of the entry to save.
+/// * `password` - The password to save.
+///
+/// # Errors
+///
+/// * [`RepositoryErrorKind::KeyringFailed`] - If the keyring could not be accessed.
+#[cfg(feature = "keyring")]
+pub(super) fn save_password(name: &str, password: &str) -> RusticResult<()> {
+    entry(name)?
+        .set_password(password)
+        .map_err(|err| RepositoryErrorKind::KeyringFailed(err.to_string()))?;
+    info!("saved password in keyring entry {name}");
+    Ok(())
+}
+
+/// Read a password from the OS keyring.
+///
+/// # Errors
+///
+/// * [`RepositoryErrorKind::KeyringNotSupported`] - Always, as rustic was compiled without keyring support
+#[cfg(not(feature = "keyring"))]
+pub(super) fn read_password(_name: &str) -> RusticResult<Option<String>> {
+    Err(RepositoryErrorKind::KeyringNotSupported.into())
+}
+
+/// Save a password in the OS keyring.
+///
+/// # Errors
+///
+/// * [`RepositoryErrorKind::KeyringNotSupported`] - Always, as rustic was compiled without keyring support
+#[cfg(not(feature = "keyring"))]
+pub(super) fn save_password(_name: &str, _password: &str) -> RusticResult<()> {
+    Err(RepositoryErrorKind::KeyringNotSupported.into())
+}
@@ -0,0 +1,216 @@
+//! Sealing the repository password to the PCR state of a TPM 2.0
+//!
+//! This calls the `tpm2_*` commands of `tpm2-tools`. The sealed password is saved in a directory
+//! and can only be unsealed by the same TPM as long as the selected PCRs have the same values.
+use std::{
+    fs,
+    io::{BufReader, Write},
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use log::{debug, info};
+use zeroize::Zeroizing;
+
+use crate::error::{RepositoryErrorKind, RusticResult};
+
+pub(super) mod constants {
+    /// The PCRs the password is sealed to by default, i.e. the secure boot state
+    pub(super) const DEFAULT_PCRS: &str = "sha256:7";
+    /// The public part of the sealed object
+    pub(super) const PUBLIC: &str = "seal.pub";
+    /// The private part of the sealed object, encrypted by the TPM
+    pub(super) const PRIVATE: &str = "seal.priv";
+    /// The PCR selection the password is sealed to
+    pub(super) const PCRS: &str = "pcrs";
+    /// Temporary context of the primary key
+    pub(super) const PRIMARY_CTX: &str = "primary.ctx";
+    /// Temporary context of the loaded sealed object
+    pub(super) const SEAL_CTX: &str = "seal.ctx";
+    /// Temporary policy digest
+    pub(super) const POLICY: &str = "policy.digest";
+}
+
+/// Run a `tpm2-tools` command in the given directory.
+///
+/// # Arguments
+///
+/// * `dir` - The directory containing the sealed password
+/// * `command` - The `tpm2-tools` command to run
+/// * `args` - The arguments of the command
+/// * `input` - The data to pass to stdin
+///
+/// # Errors
+///
+/// * [`RepositoryErrorKind::TpmFailed`] - If the command could not be run or did not succeed
+///
+/// # Returns
+///
+/// The output of the command
+fn tpm2(
+    dir: &Path,
+    command: &str,
+    args: &[&str],
+    input: Option<&[u8]>,
+) -> RusticResult<Zeroizing<Vec<u8>>> {
+    debug!("calling {command} {args:?}");
+    let mut child = Command::new(command)
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| RepositoryErrorKind::TpmFailed(format!("cannot run {command}: {err}")))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        if let Some(input) = input {
+            stdin
+                .write_all(input)
+                .map_err(|err| RepositoryErrorKind::TpmFailed(format!("{command}: {err}")))?;
+        }
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|err| RepositoryErrorKind::TpmFailed(format!("{command}: {err}")))?;
+    if !output.status.success() {
+        return Err(RepositoryErrorKind::TpmFailed(format!(
+            "{command}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+        .into());
+    }
+    Ok(Zeroizing::new(output.stdout))
+}
+
+/// Remove the temporary files created in the given directory.
+fn cleanup(dir: &Path) {
+    for file in [
+        constants::PRIMARY_CTX,
+        constants::SEAL_CTX,
+        constants::POLICY,
+    ] {
+        _ = fs::remove_file(dir.join(file));
+    }
+}
+
+/// Create the primary key of the owner hierarchy under which the password is sealed.
+///
+/// The primary key is derived from the seed of the TPM, so it is the same each time it is created.
+fn create_primary(dir: &Path) -> RusticResult<()> {
+    _ = tpm2(
+        dir,
+        "tpm2_createprimary",
+        &["-Q", "-C", "o", "-c", constants::PRIMARY_CTX],
+        None,
+    )?;
+    Ok(())
+}
+
+/// Unseal the password saved in the given directory.
+///
+/// # Arguments
+///
+/// * `dir` - The directory containing the sealed password
+///
+/// # Errors
+///
+/// * [`RepositoryErrorKind::TpmFailed`] - If the password could not be unsealed, e.g. because the PCRs changed
+///
+/// # Returns
+///
+/// The password or `None` if no password has been sealed yet.
+pub(super) fn read_password(dir: &Path) -> RusticResult<Option<String>> {
+    let pcrs = match fs::read_to_string(dir.join(constants::PCRS)) {
+        Ok(pcrs) => pcrs.trim().to_string(),
+        Err(_) => {
+            info!("no password sealed in {dir:?}");
+            return Ok(None);
+        }
+    };
+    let unseal = || -> RusticResult<Zeroizing<Vec<u8>>> {
+        create_primary(dir)?;
+        _ = tpm2(
+            dir,
+            "tpm2_load",
+            &[
+                "-Q",
+                "-C",
+                constants::PRIMARY_CTX,
+                "-u",
+                constants::PUBLIC,
+                "-r",
+                constants::PRIVATE,
+                "-c",
+                constants::SEAL_CTX,
+            ],
+            None,
+        )?;
+        tpm2(
+            dir,
+            "tpm2_unseal",
+            &["-c", constants::SEAL_CTX, "-p", &format!("pcr:{pcrs}")],
+            None,
+        )
+    };
+    let result = unseal();
+    cleanup(dir);
+    let output = result?;
+    debug!("using password sealed in {dir:?}");
+    let mut reader = BufReader::new(&output[..]);
+    Ok(Some(super::read_password_from_reader(&mut reader)?))
+}
+
+/// Seal a password to the current state of the given PCRs and save it in the given directory.
+///
+/// # Arguments
+///
+/// * `dir` - The directory to save the sealed password in
+/// * `pcrs` - The PCR selection to seal the password to, e.g. `sha256:0,7`
+/// * `password` - The password to seal
+///
+/// # Errors
+///
+/// * [`RepositoryErrorKind::TpmFailed`] - If the password could not be sealed
+pub(super) fn save_password(dir: &Path, pcrs: Option<&str>, password: &str) -> RusticResult<()> {
+    let pcrs = pcrs.unwrap_or(constants::DEFAULT_PCRS);
+    fs::create_dir_all(dir)
+        .map_err(|err| RepositoryErrorKind::TpmFailed(format!("cannot create {dir:?}: {err}")))?;
+    let seal = || -> RusticResult<()> {
+        create_primary(dir)?;
+        _ = tpm2(
+            dir,
+            "tpm2_createpolicy",
+            &["-Q", "--policy-pcr", "-l", pcrs, "-L", constants::POLICY],
+            None,
+        )?;
+        // without `userwithauth` the password can only be unsealed by satisfying the PCR policy
+        _ = tpm2(
+            dir,
+            "tpm2_create",
+            &[
+                "-Q",
+                "-C",
+                constants::PRIMARY_CTX,
+                "-L",
+                constants::POLICY,
+                "-a",
+                "fixedtpm|fixedparent|adminwithpolicy|noda",
+                "-i",
+                "-",
+                "-u",
+                constants::PUBLIC,
+                "-r",
+                constants::PRIVATE,
+            ],
+            Some(password.as_bytes()),
+        )?;
+        fs::write(dir.join(constants::PCRS), pcrs)
+            .map_err(|err| RepositoryErrorKind::TpmFailed(format!("cannot write PCRs: {err}")))?;
+        Ok(())
+    };
+    let result = seal();
+    cleanup(dir);
+    result?;
+    info!("sealed password to TPM PCRs {pcrs} in {dir:?}");
+    Ok(())
+}
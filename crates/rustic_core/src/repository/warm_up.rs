@@ -1,12 +1,18 @@
 use std::process::Command;
 use std::thread::sleep;
+use std::time::{Duration, Instant};
 
-use log::{debug, warn};
-use rayon::ThreadPoolBuilder;
+use bytesize::ByteSize;
+use humantime::format_duration;
+use log::{debug, info, warn};
+use rayon::{
+    prelude::{IntoParallelRefIterator, ParallelIterator},
+    ThreadPool, ThreadPoolBuilder,
+};
 use shell_words::split;
 
 use crate::{
-    backend::{FileType, ReadBackend},
+    backend::{FileType, ReadBackend, ThawState},
     error::{RepositoryErrorKind, RusticResult},
     id::Id,
     progress::{Progress, ProgressBars},
@@ -14,8 +20,12 @@ use crate::{
 };
 
 pub(super) mod constants {
-    /// The maximum number of reader threads to use for warm-up.
-    pub(super) const MAX_READER_THREADS_NUM: usize = 20;
+    use std::time::Duration;
+
+    /// The first interval to check whether packs have been thawed.
+    pub(super) const THAW_POLL_INTERVAL_MIN: Duration = Duration::from_secs(60);
+    /// The maximum interval to check whether packs have been thawed.
+    pub(super) const THAW_POLL_INTERVAL_MAX: Duration = Duration::from_secs(30 * 60);
 }
 
 /// Warm up the repository and wait.
@@ -33,6 +43,11 @@ pub(crate) fn warm_up_wait<P: ProgressBars, S>(
     repo: &Repository<P, S>,
     packs: impl ExactSizeIterator<Item = Id>,
 ) -> RusticResult<()> {
+    if let Some(duration) = repo.be.thaw_duration() {
+        let thawing = thaw(repo, packs, duration)?;
+        wait_thawed(repo, thawing, duration)?;
+        return Ok(());
+    }
     warm_up(repo, packs)?;
     if let Some(wait) = repo.opts.warm_up_wait {
         let p = repo.pb.progress_spinner(format!("waiting {wait}..."));
@@ -57,7 +72,9 @@ pub(crate) fn warm_up<P: ProgressBars, S>(
     repo: &Repository<P, S>,
     packs: impl ExactSizeIterator<Item = Id>,
 ) -> RusticResult<()> {
-    if let Some(command) = &repo.opts.warm_up_command {
+    if let Some(duration) = repo.be.thaw_duration() {
+        _ = thaw(repo, packs, duration)?;
+    } else if let Some(command) = &repo.opts.warm_up_command {
         warm_up_command(packs, command, &repo.pb)?;
     } else if repo.opts.warm_up {
         warm_up_access(repo, packs)?;
@@ -116,10 +133,7 @@ fn warm_up_access<P: ProgressBars, S>(
     let p = repo.pb.progress_counter("warming up packs...");
     p.set_length(packs.len() as u64);
 
-    let pool = ThreadPoolBuilder::new()
-        .num_threads(constants::MAX_READER_THREADS_NUM)
-        .build()
-        .map_err(RepositoryErrorKind::FromThreadPoolbilderError)?;
+    let pool = reader_pool(repo)?;
     let p = &p;
     let be = &be;
     pool.in_place_scope(|s| {
@@ -136,3 +150,144 @@ fn warm_up_access<P: ProgressBars, S>(
 
     Ok(())
 }
+
+/// Create the thread pool used to access packs in parallel.
+///
+/// # Arguments
+///
+/// * `repo` - The repository whose download connections are used.
+///
+/// # Errors
+///
+/// * [`RepositoryErrorKind::FromThreadPoolbilderError`] - If the thread pool could not be created.
+fn reader_pool<P, S>(repo: &Repository<P, S>) -> RusticResult<ThreadPool> {
+    Ok(ThreadPoolBuilder::new()
+        .num_threads(repo.download_connections())
+        .build()
+        .map_err(RepositoryErrorKind::FromThreadPoolbilderError)?)
+}
+
+/// Request thawing of packs which are in archive storage.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to thaw packs in.
+/// * `packs` - The packs to thaw.
+/// * `duration` - The typical duration of thawing.
+///
+/// # Errors
+///
+/// * [`RepositoryErrorKind::FromThreadPoolbilderError`] - If the thread pool could not be created.
+/// * If thawing could not be requested.
+///
+/// # Returns
+///
+/// The packs which are being thawed.
+fn thaw<P: ProgressBars, S>(
+    repo: &Repository<P, S>,
+    packs: impl ExactSizeIterator<Item = Id>,
+    duration: Duration,
+) -> RusticResult<Vec<Id>> {
+    let packs: Vec<_> = packs.collect();
+    let p = repo.pb.progress_counter("requesting thawing of packs...");
+    p.set_length(packs.len() as u64);
+    let be = &repo.be;
+    let states = reader_pool(repo)?.install(|| {
+        packs
+            .par_iter()
+            .map(|id| {
+                let state = be.thaw(FileType::Pack, id);
+                p.inc(1);
+                state
+            })
+            .collect::<RusticResult<Vec<_>>>()
+    })?;
+    p.finish();
+
+    let mut size = 0;
+    let thawing: Vec<_> = packs
+        .into_iter()
+        .zip(states)
+        .filter_map(|(id, state)| match state {
+            ThawState::Available => None,
+            ThawState::Thawing { size: pack_size } => {
+                size += pack_size;
+                Some(id)
+            }
+        })
+        .collect();
+    if !thawing.is_empty() {
+        info!(
+            "thawing {} pack(s) with {}, which typically takes {}. Note that thawing may be charged per request and per size.",
+            thawing.len(),
+            ByteSize(size).to_string_as(true),
+            format_duration(duration)
+        );
+    }
+    Ok(thawing)
+}
+
+/// Wait until the given packs have been thawed.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to wait for.
+/// * `thawing` - The packs which are being thawed.
+/// * `duration` - The typical duration of thawing, used to estimate the remaining time.
+///
+/// # Errors
+///
+/// * [`RepositoryErrorKind::FromThreadPoolbilderError`] - If the thread pool could not be created.
+/// * If the state of the packs could not be determined.
+fn wait_thawed<P: ProgressBars, S>(
+    repo: &Repository<P, S>,
+    mut thawing: Vec<Id>,
+    duration: Duration,
+) -> RusticResult<()> {
+    if thawing.is_empty() {
+        return Ok(());
+    }
+    let total = thawing.len();
+    let be = &repo.be;
+    let pool = reader_pool(repo)?;
+    let p = repo.pb.progress_counter("waiting for thawed packs...");
+    p.set_length(total as u64);
+    let start = Instant::now();
+    let mut interval = constants::THAW_POLL_INTERVAL_MIN;
+    while !thawing.is_empty() {
+        sleep(interval);
+        interval = (interval * 2).min(constants::THAW_POLL_INTERVAL_MAX);
+
+        let states = pool.install(|| {
+            thawing
+                .par_iter()
+                .map(|id| be.thaw(FileType::Pack, id))
+                .collect::<RusticResult<Vec<_>>>()
+        })?;
+        let before = thawing.len();
+        thawing = thawing
+            .into_iter()
+            .zip(states)
+            .filter(|(_, state)| *state != ThawState::Available)
+            .map(|(id, _)| id)
+            .collect();
+        p.inc((before - thawing.len()) as u64);
+
+        let done = total - thawing.len();
+        let elapsed = start.elapsed();
+        // without progress, use the typical duration of thawing as estimate
+        #[allow(clippy::cast_precision_loss)]
+        let remaining = if done == 0 {
+            duration.saturating_sub(elapsed)
+        } else {
+            elapsed.mul_f64(thawing.len() as f64 / done as f64)
+        };
+        info!(
+            "{done} of {total} pack(s) thawed after {}, estimated remaining time: {}",
+            format_duration(Duration::from_secs(elapsed.as_secs())),
+            format_duration(Duration::from_secs(remaining.as_secs()))
+        );
+    }
+    p.finish();
+    Ok(())
+}
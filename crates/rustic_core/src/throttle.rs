@@ -0,0 +1,52 @@
+//! Limiting the throughput of data transfers
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// A throttle limiting the rate of data transferred by several threads.
+///
+/// Each transfer reserves the time it needs at the given rate. If the throttle is already
+/// reserved, the transfer waits until its reservation starts.
+#[derive(Debug)]
+pub(crate) struct Throttle {
+    /// The rate in bytes per second
+    rate: u64,
+    /// The time the next transfer may start
+    next: Mutex<Instant>,
+}
+
+impl Throttle {
+    /// Creates a new [`Throttle`].
+    ///
+    /// # Arguments
+    ///
+    /// * `rate` - The rate in bytes per second; a rate of 0 is treated as 1 byte per second.
+    pub(crate) fn new(rate: u64) -> Self {
+        Self {
+            rate: rate.max(1),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Waits until `bytes` may be transferred.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The number of bytes which are going to be transferred.
+    #[allow(clippy::cast_precision_loss)]
+    pub(crate) fn wait(&self, bytes: u64) {
+        let duration = Duration::from_secs_f64(bytes as f64 / self.rate as f64);
+        let now = Instant::now();
+        let start = {
+            // Note: a poisoned lock only means another transfer panicked; the instant is still valid
+            let mut next = self.next.lock().unwrap_or_else(|err| err.into_inner());
+            let start = (*next).max(now);
+            *next = start + duration;
+            start
+        };
+        if start > now {
+            std::thread::sleep(start - now);
+        }
+    }
+}
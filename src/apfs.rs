@@ -0,0 +1,151 @@
+//! Local APFS snapshots on macOS to back up a consistent state of the filesystem
+
+use std::{
+    ffi::OsStr,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::{info, warn};
+use tempfile::TempDir;
+
+pub(crate) mod constants {
+    /// The APFS volume containing the user data
+    pub(crate) const DATA_VOLUME: &str = "/System/Volumes/Data";
+    /// A directory which can only be read with Full Disk Access
+    pub(crate) const TCC_DIR: &str = "/Library/Application Support/com.apple.TCC";
+    /// Instructions shown if Full Disk Access is missing
+    pub(crate) const FULL_DISK_ACCESS_HINT: &str = "rustic needs Full Disk Access: Open System Settings > Privacy & Security > Full Disk Access and add the terminal or program running rustic.";
+}
+
+/// A mounted local APFS snapshot of the data volume.
+///
+/// The snapshot is unmounted and deleted when dropped.
+#[derive(Debug)]
+pub(crate) struct ApfsSnapshot {
+    /// The date identifying the snapshot as used by `tmutil`
+    date: String,
+    /// The directory where the snapshot is mounted
+    mountpoint: TempDir,
+    /// Whether the snapshot is currently mounted
+    mounted: bool,
+}
+
+impl ApfsSnapshot {
+    /// Create a local APFS snapshot and mount it read-only.
+    ///
+    /// # Errors
+    ///
+    /// If not running on macOS or if creating or mounting the snapshot fails.
+    pub(crate) fn create() -> Result<Self> {
+        if !cfg!(target_os = "macos") {
+            bail!("APFS snapshots are only supported on macOS.");
+        }
+
+        let output = run("tmutil", &["localsnapshot".as_ref()])?;
+        // output is like "Created local snapshot with date: 2023-10-16-120000"
+        let date = output
+            .lines()
+            .find_map(|line| line.rsplit_once("date: "))
+            .map(|(_, date)| date.trim().to_string())
+            .ok_or_else(|| anyhow!("cannot parse output of tmutil: {output}"))?;
+        info!("created local APFS snapshot {date}");
+
+        let mut snapshot = Self {
+            date,
+            mountpoint: tempfile::tempdir()?,
+            mounted: false,
+        };
+        let name = format!("com.apple.TimeMachine.{}.local", snapshot.date);
+        let volume = if Path::new(constants::DATA_VOLUME).exists() {
+            constants::DATA_VOLUME
+        } else {
+            "/"
+        };
+        _ = run(
+            "mount_apfs",
+            &[
+                "-o".as_ref(),
+                "rdonly,nobrowse".as_ref(),
+                "-s".as_ref(),
+                name.as_ref(),
+                volume.as_ref(),
+                snapshot.mountpoint.path().as_os_str(),
+            ],
+        )?;
+        snapshot.mounted = true;
+        info!("mounted snapshot at {:?}", snapshot.mountpoint.path());
+        Ok(snapshot)
+    }
+
+    /// Get the path within the snapshot corresponding to the given absolute path.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path on the live filesystem
+    pub(crate) fn path(&self, path: &Path) -> PathBuf {
+        self.mountpoint
+            .path()
+            .join(path.strip_prefix("/").unwrap_or(path))
+    }
+}
+
+impl Drop for ApfsSnapshot {
+    fn drop(&mut self) {
+        if self.mounted {
+            if let Err(err) = run("umount", &[self.mountpoint.path().as_os_str()]) {
+                warn!("error unmounting APFS snapshot: {err}");
+                return;
+            }
+        }
+        if let Err(err) = run(
+            "tmutil",
+            &["deletelocalsnapshots".as_ref(), self.date.as_ref()],
+        ) {
+            warn!("error deleting APFS snapshot {}: {err}", self.date);
+        }
+    }
+}
+
+/// Check whether rustic has Full Disk Access on macOS.
+///
+/// Always returns `true` on other platforms.
+pub(crate) fn has_full_disk_access() -> bool {
+    !cfg!(target_os = "macos")
+        || !matches!(
+            fs::read_dir(constants::TCC_DIR),
+            Err(err) if err.kind() == ErrorKind::PermissionDenied
+        )
+}
+
+/// Run a command and return its standard output.
+///
+/// # Arguments
+///
+/// * `command` - The command to run
+/// * `args` - The arguments to pass
+///
+/// # Errors
+///
+/// If the command could not be started or did not succeed.
+fn run(command: &str, args: &[&OsStr]) -> Result<String> {
+    let output = Command::new(command)
+        .args(args)
+        .output()
+        .with_context(|| format!("error running {command}"))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("not permitted") && !has_full_disk_access() {
+            bail!(
+                "{command} failed: {}\n{}",
+                stderr.trim(),
+                constants::FULL_DISK_ACCESS_HINT
+            );
+        }
+        bail!("{command} failed: {}", stderr.trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -1,6 +1,7 @@
 //! Rustic Abscissa Application
 use std::fs::File;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use abscissa_core::{
     application::{self, AppCell},
@@ -10,14 +11,110 @@ use abscissa_core::{
 };
 
 use anyhow::Result;
-use simplelog::{CombinedLogger, LevelFilter, TermLogger, TerminalMode, WriteLogger};
+use log::{Level, Log, Metadata, Record};
+use simplelog::{LevelFilter, SharedLogger, TermLogger, TerminalMode, WriteLogger};
 
 // use crate::helpers::*;
-use crate::{commands::EntryPoint, config::RusticConfig};
+use crate::{commands::EntryPoint, config::RusticConfig, timeout};
 
 /// Application state
 pub static RUSTIC_APP: AppCell<RusticApp> = AppCell::new();
 
+/// Number of errors logged so far
+static LOGGED_ERRORS: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the number of errors logged so far, e.g. by `check`
+pub fn logged_errors() -> usize {
+    LOGGED_ERRORS.load(Ordering::Relaxed)
+}
+
+/// The log level given by the configuration
+static CONFIGURED_LOG_LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Info as usize);
+
+/// The current log level, which can be changed while running
+static LOG_LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Info as usize);
+
+/// The most verbose level one of the loggers logs regardless of the current log level
+static ALWAYS_LOGGED_LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Error as usize);
+
+/// Convert a level stored in one of the atomics above back into a [`LevelFilter`]
+const fn level_from_usize(level: usize) -> LevelFilter {
+    match level {
+        0 => LevelFilter::Off,
+        1 => LevelFilter::Error,
+        2 => LevelFilter::Warn,
+        3 => LevelFilter::Info,
+        4 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+/// Returns the current log level
+pub fn log_level() -> LevelFilter {
+    level_from_usize(LOG_LEVEL.load(Ordering::Relaxed))
+}
+
+/// Change the log level of the running process, e.g. to capture debug logs of a long running command.
+///
+/// Note: This only uses atomic operations, so it is safe to call from a signal handler.
+pub fn set_log_level(level: LevelFilter) {
+    LOG_LEVEL.store(level as usize, Ordering::Relaxed);
+    let always_logged = level_from_usize(ALWAYS_LOGGED_LEVEL.load(Ordering::Relaxed));
+    // errors are always passed to the logger, so they are counted even if they are not shown
+    log::set_max_level(level.max(always_logged).max(LevelFilter::Error));
+}
+
+/// Increase the verbosity of the running process by one level, at most to `trace`.
+pub fn increase_log_level() {
+    set_log_level(level_from_usize(log_level() as usize + 1));
+}
+
+/// Reset the log level of the running process to the configured log level.
+pub fn reset_log_level() {
+    set_log_level(level_from_usize(
+        CONFIGURED_LOG_LEVEL.load(Ordering::Relaxed),
+    ));
+}
+
+/// Logger which counts the logged errors and passes the records to the configured loggers
+///
+/// Each logger is given together with the level it always logs; in addition, it logs all records
+/// allowed by the current log level.
+struct CountingLogger(Vec<(Box<dyn SharedLogger>, LevelFilter)>);
+
+impl CountingLogger {
+    /// Whether records of the given level are passed to a logger which always logs `always_logged`
+    fn passes(level: Level, always_logged: LevelFilter) -> bool {
+        level <= log_level().max(always_logged)
+    }
+}
+
+impl Log for CountingLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() == Level::Error
+            || self.0.iter().any(|(logger, always_logged)| {
+                Self::passes(metadata.level(), *always_logged) && logger.enabled(metadata)
+            })
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if record.level() == Level::Error {
+            _ = LOGGED_ERRORS.fetch_add(1, Ordering::Relaxed);
+        }
+        for (logger, always_logged) in &self.0 {
+            if Self::passes(record.level(), *always_logged) {
+                logger.log(record);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        for (logger, _) in &self.0 {
+            logger.flush();
+        }
+    }
+}
+
 /// Rustic Application
 #[derive(Debug)]
 pub struct RusticApp {
@@ -98,33 +195,44 @@ impl Application for RusticApp {
                 .map_err(|e| FrameworkErrorKind::ConfigError.context(e))?,
             None => LevelFilter::Info,
         };
-        match &config.global.log_file {
-            None => TermLogger::init(
-                level_filter,
-                simplelog::ConfigBuilder::new()
-                    .set_time_level(LevelFilter::Off)
-                    .build(),
-                TerminalMode::Stderr,
-                ColorChoice::Auto,
-            )
-            .map_err(|e| FrameworkErrorKind::ConfigError.context(e))?,
-
-            Some(file) => CombinedLogger::init(vec![
-                TermLogger::new(
-                    level_filter.max(LevelFilter::Warn),
-                    simplelog::ConfigBuilder::new()
-                        .set_time_level(LevelFilter::Off)
-                        .build(),
-                    TerminalMode::Stderr,
-                    ColorChoice::Auto,
-                ),
-                WriteLogger::new(
-                    level_filter,
+        // the loggers themselves log everything; the records are filtered by the `CountingLogger`
+        // such that the log level can be changed while running
+        let term_logger: Box<dyn SharedLogger> = TermLogger::new(
+            LevelFilter::Trace,
+            simplelog::ConfigBuilder::new()
+                .set_time_level(LevelFilter::Off)
+                .build(),
+            TerminalMode::Stderr,
+            ColorChoice::Auto,
+        );
+        let loggers = match &config.global.log_file {
+            None => vec![(term_logger, LevelFilter::Off)],
+            Some(file) => {
+                let file_logger: Box<dyn SharedLogger> = WriteLogger::new(
+                    LevelFilter::Trace,
                     simplelog::Config::default(),
                     File::options().create(true).append(true).open(file)?,
-                ),
-            ])
-            .map_err(|e| FrameworkErrorKind::ConfigError.context(e))?,
+                );
+                // warnings are always shown in the terminal
+                vec![
+                    (term_logger, LevelFilter::Warn),
+                    (file_logger, LevelFilter::Off),
+                ]
+            }
+        };
+        let always_logged = loggers
+            .iter()
+            .map(|(_, level)| *level)
+            .max()
+            .unwrap_or(LevelFilter::Off);
+        ALWAYS_LOGGED_LEVEL.store(always_logged as usize, Ordering::Relaxed);
+        CONFIGURED_LOG_LEVEL.store(level_filter as usize, Ordering::Relaxed);
+        set_log_level(level_filter);
+        log::set_boxed_logger(Box::new(CountingLogger(loggers)))
+            .map_err(|e| FrameworkErrorKind::ConfigError.context(e))?;
+
+        if let Some(timeout) = config.global.timeout {
+            timeout::start(*timeout);
         }
 
         self.config.set_once(config);
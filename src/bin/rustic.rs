@@ -5,6 +5,29 @@
 
 use rustic_rs::application::RUSTIC_APP;
 
+/// Signal handler changing the log level: SIGUSR1 increases the verbosity, SIGUSR2 resets it to the
+/// configured log level.
+#[cfg(not(windows))]
+extern "C" fn change_log_level(signal: libc::c_int) {
+    if signal == libc::SIGUSR1 {
+        rustic_rs::application::increase_log_level();
+    } else {
+        rustic_rs::application::reset_log_level();
+    }
+}
+
+/// Signal handler for SIGINT and SIGTERM: restores the terminal settings changed for keyboard control,
+/// then terminates the process like the default action of the signal.
+#[cfg(not(windows))]
+extern "C" fn terminate(signal: libc::c_int) {
+    rustic_rs::keyboard::restore_terminal();
+    #[allow(unsafe_code)]
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+        libc::raise(signal);
+    }
+}
+
 /// Boot Rustic
 fn main() {
     // TODO: this needs to be handled?
@@ -14,6 +37,12 @@ fn main() {
     #[allow(unsafe_code)]
     unsafe {
         libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+        // SIGUSR1 and SIGUSR2 change the log level of the running process
+        libc::signal(libc::SIGUSR1, change_log_level as libc::sighandler_t);
+        libc::signal(libc::SIGUSR2, change_log_level as libc::sighandler_t);
+        // SIGINT and SIGTERM restore the terminal before terminating
+        libc::signal(libc::SIGINT, terminate as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, terminate as libc::sighandler_t);
     }
 
     abscissa_core::boot(&RUSTIC_APP);
@@ -1,38 +1,63 @@
 //! Rustic Subcommands
 
+pub(crate) mod analyze;
+pub(crate) mod backend;
 pub(crate) mod backup;
 pub(crate) mod cat;
+#[cfg(feature = "catalog")]
+pub(crate) mod catalog;
 pub(crate) mod check;
 pub(crate) mod completions;
 pub(crate) mod config;
+pub(crate) mod control;
 pub(crate) mod copy;
 pub(crate) mod diff;
 pub(crate) mod dump;
 pub(crate) mod forget;
+pub(crate) mod grep;
+pub(crate) mod index;
 pub(crate) mod init;
 pub(crate) mod key;
 pub(crate) mod list;
 pub(crate) mod ls;
 pub(crate) mod merge;
+pub(crate) mod mirror_restore;
+pub(crate) mod note;
 pub(crate) mod prune;
+pub(crate) mod rekey;
 pub(crate) mod repair;
 pub(crate) mod repoinfo;
 pub(crate) mod restore;
+pub(crate) mod run;
 pub(crate) mod self_update;
+pub(crate) mod selftest;
+#[cfg(feature = "serve")]
+pub(crate) mod serve;
 pub(crate) mod show_config;
 pub(crate) mod snapshots;
+pub(crate) mod stats;
+pub(crate) mod sync;
 pub(crate) mod tag;
+pub(crate) mod undelete;
 
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 
+#[cfg(feature = "catalog")]
+use crate::commands::catalog::CatalogCmd;
+#[cfg(feature = "serve")]
+use crate::commands::serve::ServeCmd;
 use crate::{
     commands::{
-        backup::BackupCmd, cat::CatCmd, check::CheckCmd, completions::CompletionsCmd,
-        config::ConfigCmd, copy::CopyCmd, diff::DiffCmd, dump::DumpCmd, forget::ForgetCmd,
-        init::InitCmd, key::KeyCmd, list::ListCmd, ls::LsCmd, merge::MergeCmd, prune::PruneCmd,
-        repair::RepairCmd, repoinfo::RepoInfoCmd, restore::RestoreCmd, self_update::SelfUpdateCmd,
-        show_config::ShowConfigCmd, snapshots::SnapshotCmd, tag::TagCmd,
+        analyze::AnalyzeCmd, backend::BackendCmd, backup::BackupCmd, cat::CatCmd, check::CheckCmd,
+        completions::CompletionsCmd, config::ConfigCmd, control::ControlCmd, copy::CopyCmd,
+        diff::DiffCmd, dump::DumpCmd, forget::ForgetCmd, grep::GrepCmd, index::IndexCmd,
+        init::InitCmd, key::KeyCmd, list::ListCmd, ls::LsCmd, merge::MergeCmd,
+        mirror_restore::MirrorRestoreCmd, note::NoteCmd, prune::PruneCmd, rekey::RekeyCmd,
+        repair::RepairCmd, repoinfo::RepoInfoCmd, restore::RestoreCmd, run::RunCmd,
+        self_update::SelfUpdateCmd, selftest::SelfTestCmd, show_config::ShowConfigCmd,
+        snapshots::SnapshotCmd, stats::StatsCmd, sync::SyncCmd, tag::TagCmd, undelete::UndeleteCmd,
     },
     config::{progress_options::ProgressOptions, RusticConfig},
     {Application, RUSTIC_APP},
@@ -41,6 +66,7 @@ use crate::{
 use abscissa_core::{config::Override, Command, Configurable, FrameworkError, Runnable, Shutdown};
 use anyhow::{anyhow, Result};
 use dialoguer::Password;
+use log::info;
 use rustic_core::{OpenStatus, Repository};
 
 pub(super) mod constants {
@@ -51,18 +77,31 @@ pub(super) mod constants {
 /// Subcommands need to be listed in an enum.
 #[derive(clap::Parser, Command, Debug, Runnable)]
 enum RusticCmd {
+    /// Analyze the repository contents, e.g. how well data deduplicates
+    Analyze(AnalyzeCmd),
+
+    /// Check connectivity, permissions and capabilities of the repository backends
+    Backend(BackendCmd),
+
     /// Backup to the repository
     Backup(BackupCmd),
 
     /// Show raw data of repository files and blobs
     Cat(CatCmd),
 
+    /// Export the snapshot contents into a SQLite catalog for fast offline queries
+    #[cfg(feature = "catalog")]
+    Catalog(CatalogCmd),
+
     /// Change the repository configuration
     Config(ConfigCmd),
 
     /// Generate shell completions
     Completions(CompletionsCmd),
 
+    /// Pause, resume or query a running backup
+    Control(ControlCmd),
+
     /// Check the repository
     Check(CheckCmd),
 
@@ -79,6 +118,12 @@ enum RusticCmd {
     /// Remove snapshots from the repository
     Forget(ForgetCmd),
 
+    /// Search for a pattern in the contents of files within a snapshot
+    Grep(GrepCmd),
+
+    /// Manage the repository index
+    Index(IndexCmd),
+
     /// Initialize a new repository
     Init(InitCmd),
 
@@ -94,6 +139,12 @@ enum RusticCmd {
     /// Merge snapshots
     Merge(MergeCmd),
 
+    /// Keep a directory synchronized with the latest snapshot, e.g. for a warm standby server
+    MirrorRestore(MirrorRestoreCmd),
+
+    /// Add notes to snapshots, packs or keys or list them
+    Note(NoteCmd),
+
     /// Show a detailed overview of the snapshots within the repository
     Snapshots(SnapshotCmd),
 
@@ -103,20 +154,42 @@ enum RusticCmd {
     /// Update to the latest rustic release
     SelfUpdate(SelfUpdateCmd),
 
+    /// Test backup, restore, forget and prune using a small test dataset
+    Selftest(SelfTestCmd),
+
+    /// Show statistics about used and unused space in the repository packs
+    Stats(StatsCmd),
+
     /// Remove unused data or repack repository pack files
     Prune(PruneCmd),
 
+    /// Generate a new master key and re-encrypt all data with it
+    Rekey(RekeyCmd),
+
     /// Restore a snapshot/path
     Restore(RestoreCmd),
 
     /// Repair a snapshot/path
     Repair(RepairCmd),
 
+    /// Run the steps defined in the `[run]` section of a profile, e.g. backup, forget and check
+    Run(RunCmd),
+
+    /// Serve local repositories using the REST protocol of rest-server
+    #[cfg(feature = "serve")]
+    Serve(ServeCmd),
+
     /// Show general information about the repository
     Repoinfo(RepoInfoCmd),
 
+    /// Mirror new packs and snapshots to another repository
+    Sync(SyncCmd),
+
     /// Change tags of snapshots
     Tag(TagCmd),
+
+    /// List snapshots in the trash or restore them from the trash
+    Undelete(UndeleteCmd),
 }
 
 /// Entry point for the application. It needs to be a struct to allow using subcommands!
@@ -164,6 +237,7 @@ impl Configurable<RusticConfig> for EntryPoint {
 
         match &self.commands {
             RusticCmd::Forget(cmd) => cmd.override_config(config),
+            RusticCmd::Run(cmd) => cmd.override_config(config),
 
             // subcommands that don't need special overrides use a catch all
             _ => Ok(config),
@@ -185,24 +259,63 @@ impl Configurable<RusticConfig> for EntryPoint {
 /// * [`RepositoryErrorKind::ReadingPasswordFromCommandFailed`] - If reading the password from the command failed
 /// * [`RepositoryErrorKind::FromSplitError`] - If splitting the password command failed
 fn open_repository(config: &Arc<RusticConfig>) -> Result<Repository<ProgressOptions, OpenStatus>> {
+    open_repository_with_passwords(config, &mut HashMap::new())
+}
+
+/// Open the repository, reusing passwords which have already been entered for the same repository.
+///
+/// Passwords entered at the prompt are added to `passwords`.
+fn open_repository_with_passwords(
+    config: &RusticConfig,
+    passwords: &mut HashMap<String, String>,
+) -> Result<Repository<ProgressOptions, OpenStatus>> {
     let po = config.global.progress_options;
     let repo = Repository::new_with_progress(&config.repository, po)?;
-    match repo.password()? {
-        // if password is given, directly return the result of find_key_in_backend and don't retry
-        Some(pass) => {
-            return Ok(repo.open_with_password(&pass)?);
+    if config.repository.use_kms.is_some() {
+        return Ok(repo.open_with_kms()?);
+    }
+    if config.repository.no_encryption {
+        return Ok(repo.open_unencrypted()?);
+    }
+    // a key cached by a previous run doesn't need the password
+    if let Ok(repo) = repo.clone().open_with_cached_key() {
+        return Ok(repo);
+    }
+    // if password is given, directly return the result of find_key_in_backend and don't retry
+    if let Some(pass) = repo.password()? {
+        return Ok(repo.open_with_password(&pass)?);
+    }
+
+    let name = config.repository.repository.clone().unwrap_or_default();
+    if let Some(pass) = passwords.get(&name) {
+        if let Ok(repo) = repo.clone().open_with_password(pass) {
+            return Ok(repo);
         }
-        None => {
-            for _ in 0..constants::MAX_PASSWORD_RETRIES {
-                let pass = Password::new()
-                    .with_prompt("enter repository password")
-                    .allow_empty_password(true)
-                    .interact()?;
-                match repo.clone().open_with_password(&pass) {
-                    Ok(repo) => return Ok(repo),
-                    // TODO: fail if error != Password incorrect
-                    Err(_) => continue,
-                }
+    }
+    // passwords which don't open a key file may belong to shares of the master key
+    let mut share_passwords = Vec::new();
+    let mut retries = 0;
+    while retries < constants::MAX_PASSWORD_RETRIES {
+        let pass = Password::new()
+            .with_prompt("enter repository password")
+            .allow_empty_password(true)
+            .interact()?;
+        // TODO: fail if error != Password incorrect
+        if let Ok(repo) = repo.clone().open_with_password(&pass) {
+            repo.save_password(&pass)?;
+            _ = passwords.insert(name, pass);
+            return Ok(repo);
+        }
+        share_passwords.push(pass);
+        match repo.clone().open_with_shares(&share_passwords) {
+            Ok(repo) => return Ok(repo),
+            Err(err) if err.is_not_enough_shares() => {
+                // the password belongs to a share, so don't count it as retry
+                info!("{err}, please enter the password of another share.");
+            }
+            Err(_) => {
+                _ = share_passwords.pop();
+                retries += 1;
             }
         }
     }
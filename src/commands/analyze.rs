@@ -0,0 +1,223 @@
+//! `analyze` subcommand
+
+/// App-local prelude includes `app_reader()`/`app_writer()`/`app_config()`
+/// accessors along with logging macros. Customize as you see fit.
+use crate::{
+    commands::open_repository,
+    helpers::{bytes_size_to_string, table_right_from},
+    status_err, Application, RUSTIC_APP,
+};
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::Path,
+};
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::Result;
+use log::info;
+
+use rustic_core::{repofile::BlobType, LsOptions};
+
+/// Upper bounds (in KiB) of the file size classes
+const SIZE_CLASSES: [u64; 6] = [4, 64, 1024, 16 * 1024, 256 * 1024, u64::MAX];
+
+/// Column titles of the dedup statistics table
+const TITLES: [&str; 6] = ["Files", "Size", "Unique", "Stored", "Dedup %", "Stored %"];
+
+/// `analyze` subcommand
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct AnalyzeCmd {
+    #[clap(subcommand)]
+    cmd: AnalyzeSubCmd,
+}
+
+#[derive(clap::Subcommand, Debug, Runnable)]
+enum AnalyzeSubCmd {
+    /// Show which kinds of files contribute most to the stored data and how well they deduplicate
+    Dedup(DedupCmd),
+}
+
+#[derive(clap::Parser, Debug)]
+pub(crate) struct DedupCmd {
+    /// Snapshots to analyze. If none is given, use filter options to filter from all snapshots
+    #[clap(value_name = "ID")]
+    ids: Vec<String>,
+
+    /// Group files by
+    #[clap(long, value_enum, default_value = "extension")]
+    by: GroupBy,
+
+    /// Only show this number of extensions with the most stored data
+    #[clap(long, value_name = "N", default_value = "20")]
+    top: usize,
+}
+
+/// Criteria to group files by
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+enum GroupBy {
+    /// File extension
+    Extension,
+    /// File size class
+    Size,
+}
+
+impl GroupBy {
+    /// Get the group of a file; the first element is used to sort the size classes
+    fn group(self, path: &Path, size: u64) -> (usize, String) {
+        match self {
+            Self::Extension => (
+                0,
+                path.extension().map_or_else(
+                    || "(none)".to_string(),
+                    |ext| format!(".{}", ext.to_string_lossy().to_lowercase()),
+                ),
+            ),
+            Self::Size => {
+                let size_kib = size / 1024;
+                let class = SIZE_CLASSES
+                    .iter()
+                    .position(|limit| size_kib < *limit)
+                    .unwrap_or(SIZE_CLASSES.len() - 1);
+                let lower = if class == 0 {
+                    0
+                } else {
+                    SIZE_CLASSES[class - 1]
+                };
+                let title = match SIZE_CLASSES[class] {
+                    u64::MAX => format!(">= {}", kib_to_string(lower)),
+                    limit => format!("{} - {}", kib_to_string(lower), kib_to_string(limit)),
+                };
+                (class, title)
+            }
+        }
+    }
+}
+
+fn kib_to_string(kib: u64) -> String {
+    if kib >= 1024 {
+        format!("{} MiB", kib / 1024)
+    } else {
+        format!("{kib} KiB")
+    }
+}
+
+impl Runnable for AnalyzeCmd {
+    fn run(&self) {
+        self.cmd.run();
+    }
+}
+
+impl Runnable for DedupCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+/// Accumulated dedup statistics about a group of files
+#[derive(Default, Clone, Copy)]
+struct DedupSum {
+    /// Number of files, counted for every snapshot containing them
+    files: u64,
+    /// Size of the files, counted for every snapshot containing them
+    size: u64,
+    /// Size of the data blobs first referenced by files of this group
+    unique: u64,
+    /// Stored (i.e. compressed and encrypted) size of these data blobs
+    stored: u64,
+}
+
+impl DedupSum {
+    #[allow(clippy::cast_precision_loss)]
+    fn row(&self, title: &str, total_stored: u64) -> Vec<String> {
+        let percent = |part: u64, total: u64| {
+            if total == 0 {
+                "-".to_string()
+            } else {
+                format!("{:.1}%", part as f64 / total as f64 * 100.0)
+            }
+        };
+        vec![
+            title.to_string(),
+            self.files.to_string(),
+            bytes_size_to_string(self.size),
+            bytes_size_to_string(self.unique),
+            bytes_size_to_string(self.stored),
+            percent(self.size.saturating_sub(self.unique), self.size),
+            percent(self.stored, total_stored),
+        ]
+    }
+}
+
+impl DedupCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let repo = open_repository(&config)?.to_indexed()?;
+
+        let snapshots = if self.ids.is_empty() {
+            repo.get_matching_snapshots(|sn| config.snapshot_filter.matches(sn))?
+        } else {
+            repo.get_snapshots(&self.ids)?
+        };
+
+        // blobs are attributed to the group which first references them
+        let mut seen = HashSet::new();
+        let mut sums: BTreeMap<(usize, String), DedupSum> = BTreeMap::new();
+        let ls_opts = LsOptions::default().recursive(true);
+        for snap in &snapshots {
+            info!(
+                "analyzing snapshot {}...",
+                config.global.format_id(&snap.id)
+            );
+            let node = repo.node_from_snapshot_and_path(snap, "")?;
+            for item in repo.ls(&node, &ls_opts)? {
+                let (path, node) = item?;
+                if !node.is_file() {
+                    continue;
+                }
+                let sum = sums
+                    .entry(self.by.group(&path, node.meta.size))
+                    .or_default();
+                sum.files += 1;
+                sum.size += node.meta.size;
+                for id in node.content.iter().flatten() {
+                    if seen.insert(*id) {
+                        let ie = repo.get_index_entry(BlobType::Data, id)?;
+                        sum.unique += u64::from(ie.data_length());
+                        sum.stored += u64::from(ie.length);
+                    }
+                }
+            }
+        }
+
+        let mut total = DedupSum::default();
+        for sum in sums.values() {
+            total.files += sum.files;
+            total.size += sum.size;
+            total.unique += sum.unique;
+            total.stored += sum.stored;
+        }
+
+        let mut rows: Vec<_> = sums.into_iter().collect();
+        if matches!(self.by, GroupBy::Extension) {
+            rows.sort_by(|(_, sum1), (_, sum2)| sum2.stored.cmp(&sum1.stored));
+            rows.truncate(self.top);
+        }
+
+        let title = match self.by {
+            GroupBy::Extension => "Extension",
+            GroupBy::Size => "File size",
+        };
+        let mut table = table_right_from(1, [title].into_iter().chain(TITLES));
+        for ((_, group), sum) in rows {
+            _ = table.add_row(sum.row(&group, total.stored));
+        }
+        _ = table.add_row(total.row("Total", total.stored));
+        println!("{table}");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+//! `backend` subcommand
+
+/// App-local prelude includes `app_reader()`/`app_writer()`/`app_config()`
+/// accessors along with logging macros. Customize as you see fit.
+use crate::{
+    helpers::{bytes_size_to_string, table_right_from},
+    status_err, Application, RUSTIC_APP,
+};
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::{bail, Result};
+
+use rustic_core::{BackendCheckOptions, BackendCheckResult, Repository};
+
+/// `backend` subcommand
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct BackendCmd {
+    #[clap(subcommand)]
+    cmd: BackendSubCmd,
+}
+
+#[derive(clap::Subcommand, Debug, Runnable)]
+enum BackendSubCmd {
+    /// Check connectivity and permissions of the repository backends using a probe file. This also
+    /// measures latency and throughput and shows the capabilities of the backends
+    Check(CheckSubCmd),
+}
+
+#[derive(Debug, clap::Parser, Command)]
+struct CheckSubCmd {
+    #[clap(flatten)]
+    opts: BackendCheckOptions,
+
+    /// Show the results in json format
+    #[clap(long)]
+    json: bool,
+}
+
+impl Runnable for BackendCmd {
+    fn run(&self) {
+        self.cmd.run();
+    }
+}
+
+impl Runnable for CheckSubCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl CheckSubCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        if config.global.dry_run {
+            bail!("backend check needs to write to the backends and can't be run with --dry-run.");
+        }
+        // the backends are checked without opening the repository, so no password is needed
+        let repo =
+            Repository::new_with_progress(&config.repository, config.global.progress_options)?;
+        let results = repo.check_backends(&self.opts);
+
+        if self.json {
+            let mut stdout = std::io::stdout();
+            serde_json::to_writer_pretty(&mut stdout, &results)?;
+        } else {
+            for result in &results {
+                print_result(result);
+            }
+        }
+
+        let failed = results.iter().filter(|result| !result.is_ok()).count();
+        if failed > 0 {
+            bail!("{failed} of {} backend(s) failed the check.", results.len());
+        }
+        Ok(())
+    }
+}
+
+/// Print the operations and capabilities of a checked backend
+fn print_result(result: &BackendCheckResult) {
+    println!("backend {}:", result.location);
+    let mut table = table_right_from(1, ["Operation", "Duration", "Throughput", "Result"]);
+    for step in &result.steps {
+        let throughput = step.throughput().map_or_else(String::new, |throughput| {
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let throughput = throughput as u64;
+            format!("{}/s", bytes_size_to_string(throughput))
+        });
+        _ = table.add_row([
+            step.name.clone(),
+            format!("{:.1?}", step.duration),
+            throughput,
+            step.error.clone().unwrap_or_else(|| "ok".to_string()),
+        ]);
+    }
+    println!("{table}");
+
+    let capability = |value: Option<bool>| match value {
+        Some(true) => "yes",
+        Some(false) => "no",
+        None => "unknown",
+    };
+    println!(
+        "ranged reads:             {}",
+        capability(result.ranged_reads)
+    );
+    println!("overwrite existing files: {}", capability(result.overwrite));
+    println!(
+        "needs thawing (archive):  {}",
+        capability(Some(result.needs_thaw))
+    );
+    println!();
+}
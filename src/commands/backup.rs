@@ -1,26 +1,46 @@
 //! `backup` subcommand
 
-use std::path::PathBuf;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    thread,
+};
 
 /// App-local prelude includes `app_reader()`/`app_writer()`/`app_config()`
 /// accessors along with logging macros. Customize as you see fit.
 use crate::{
-    commands::open_repository,
-    helpers::bytes_size_to_string,
-    {status_err, Application, RUSTIC_APP},
+    apfs::{self, ApfsSnapshot},
+    commands::{
+        control::listen,
+        init::{apply_no_encryption, init},
+        open_repository_with_passwords,
+    },
+    config::{find_profiles, progress_options::ProgressOptions, RusticConfig},
+    helpers::{bytes_size_to_string, resources_to_string, table_right_from},
+    keyboard,
+    state::{load_failed_files, save_failed_files, FailedFiles},
+    timeout, {status_err, Application, RUSTIC_APP},
 };
 use abscissa_core::{Command, Runnable, Shutdown};
-use anyhow::{bail, Context, Result};
-use log::{debug, info, warn};
+use anyhow::{anyhow, bail, Context, Result};
+use log::{debug, error, info, warn};
 
 use merge::Merge;
 use serde::Deserialize;
 
 use rustic_core::{
-    BackupOptions, LocalSourceFilterOptions, LocalSourceSaveOptions, ParentOptions, PathList,
-    SnapshotOptions,
+    repofile::SnapshotFile, BackupControl, BackupOptions, ConfigOptions, KeyOptions,
+    LocalSourceFilterOptions, LocalSourceSaveOptions, OpenStatus, ParentOptions, PathList,
+    Repository, SnapshotOptions,
 };
 
+/// An opened repository together with the config of the profile to backup into it
+type ProfileRun = (
+    String,
+    RusticConfig,
+    Repository<ProgressOptions, OpenStatus>,
+);
+
 /// `backup` subcommand
 #[derive(Clone, Command, Default, Debug, clap::Parser, Deserialize, Merge)]
 #[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
@@ -51,11 +71,73 @@ pub struct BackupCmd {
     #[serde(flatten)]
     ignore_save_opts: LocalSourceSaveOptions,
 
+    /// Number of times to re-read files which changed while being read [default: 2]
+    #[clap(long, value_name = "N")]
+    unstable_retries: Option<u32>,
+
+    /// Don't save files which still change after re-reading them
+    #[clap(long)]
+    #[merge(strategy = merge::bool::overwrite_false)]
+    skip_unstable: bool,
+
+    /// Don't exclude the repository and its cache if they are within the backup sources
+    #[clap(long)]
+    #[merge(strategy = merge::bool::overwrite_false)]
+    include_repository: bool,
+
     /// Output generated snapshot in json format
     #[clap(long)]
     #[merge(strategy = merge::bool::overwrite_false)]
     json: bool,
 
+    /// Create a local APFS snapshot and backup from it (macOS only, needs root)
+    #[clap(long)]
+    #[merge(strategy = merge::bool::overwrite_false)]
+    use_apfs_snapshot: bool,
+
+    /// Retry a partial snapshot: Backup its paths using it as parent, so only the files which failed
+    /// (and files changed since then) are read
+    #[clap(long, value_name = "SNAPSHOT", conflicts_with_all = ["cli_sources", "parent", "force"])]
+    #[merge(skip)]
+    #[serde(skip)]
+    retry_partial: Option<String>,
+
+    /// Retry the files which failed in the last backups into this repository: Only read these files
+    /// (and new files) and take all other files from the partial snapshots
+    #[clap(long, conflicts_with_all = ["cli_sources", "parent", "force", "retry_partial"])]
+    #[merge(skip)]
+    #[serde(skip)]
+    retry_failed: bool,
+
+    /// Run the backups defined in all profiles matching PATTERN (wildcards `*` and `?` are allowed)
+    /// and show a summary of all backups
+    #[clap(long, value_name = "PATTERN", conflicts_with_all = ["cli_sources", "retry_partial", "retry_failed"])]
+    #[merge(skip)]
+    #[serde(skip)]
+    profiles: Option<String>,
+
+    /// Run the backups of profiles using different repositories in parallel
+    #[clap(long, requires = "profiles")]
+    #[merge(skip)]
+    #[serde(skip)]
+    parallel: bool,
+
+    /// Initialize the repository if it doesn't exist yet
+    #[clap(long)]
+    #[merge(strategy = merge::bool::overwrite_false)]
+    init_if_missing: bool,
+
+    #[clap(
+        flatten,
+        next_help_heading = "Config options (when using --init-if-missing)"
+    )]
+    #[serde(flatten)]
+    config_opts: ConfigOptions,
+
+    /// Listen on this socket to pause and resume the backup using `rustic control`
+    #[clap(long, value_name = "SOCKET", env = "RUSTIC_CONTROL_SOCKET")]
+    control_socket: Option<PathBuf>,
+
     #[clap(flatten, next_help_heading = "Options for parent processing")]
     #[serde(flatten)]
     parent_opts: ParentOptions,
@@ -94,11 +176,181 @@ impl Runnable for BackupCmd {
     }
 }
 
+/// Result of the backup of a profile
+struct ProfileResult {
+    /// Name of the profile
+    profile: String,
+    /// Repository used by the profile
+    repository: String,
+    /// The saved snapshots or the error which occurred
+    result: Result<Vec<SnapshotFile>>,
+}
+
 impl BackupCmd {
     fn inner_run(&self) -> Result<()> {
         let config = RUSTIC_APP.config();
 
-        let repo = open_repository(&config)?.to_indexed_ids()?;
+        if let Some(pattern) = &self.profiles {
+            return self.backup_profiles(&config, pattern);
+        }
+
+        let repo = self.open_or_init(&config, &mut HashMap::new())?;
+        _ = self.backup(&config, repo)?;
+        Ok(())
+    }
+
+    /// Open the repository, initializing it first if it doesn't exist and `init-if-missing` is set.
+    fn open_or_init(
+        &self,
+        config: &RusticConfig,
+        passwords: &mut HashMap<String, String>,
+    ) -> Result<Repository<ProgressOptions, OpenStatus>> {
+        if self.init_if_missing || config.backup.init_if_missing {
+            let po = config.global.progress_options;
+            let repo = Repository::new_with_progress(&config.repository, po)?;
+            if repo.config_id()?.is_none() {
+                if config.global.dry_run {
+                    bail!("repository does not exist, not initializing it in dry-run mode.");
+                }
+                info!("repository does not exist, initializing it...");
+                let mut config_opts = self.config_opts;
+                config_opts.merge(config.backup.config_opts);
+                apply_no_encryption(config.repository.no_encryption, &mut config_opts)?;
+                return init(repo, &KeyOptions::default(), &config_opts);
+            }
+        }
+        open_repository_with_passwords(config, passwords)
+    }
+
+    /// Run the backups of all profiles matching `pattern` and print a summary.
+    ///
+    /// All repositories are opened first, so that passwords are only asked once per repository.
+    fn backup_profiles(&self, config: &RusticConfig, pattern: &str) -> Result<()> {
+        let profiles = find_profiles(pattern);
+        if profiles.is_empty() {
+            bail!("no profile matches {pattern}.");
+        }
+        info!("running backups of profiles {}", profiles.join(", "));
+
+        let mut passwords = HashMap::new();
+        let mut results = Vec::new();
+        // profiles using the same repository are always run sequentially
+        let mut groups: BTreeMap<String, Vec<ProfileRun>> = BTreeMap::new();
+        for profile in profiles {
+            let mut profile_config = RusticConfig::default();
+            let opened = profile_config
+                .merge_profile(&profile)
+                .map_err(|err| anyhow!("error reading profile: {err}"))
+                .and_then(|()| {
+                    profile_config.global.dry_run |= config.global.dry_run;
+                    self.open_or_init(&profile_config, &mut passwords)
+                });
+            let repository = profile_config
+                .repository
+                .repository
+                .clone()
+                .unwrap_or_default();
+            match opened {
+                Ok(repo) => {
+                    groups
+                        .entry(repository)
+                        .or_default()
+                        .push((profile, profile_config, repo))
+                }
+                Err(err) => {
+                    error!("cannot open repository of profile {profile}: {err}");
+                    results.push(ProfileResult {
+                        profile,
+                        repository,
+                        result: Err(err),
+                    });
+                }
+            }
+        }
+
+        if self.parallel {
+            thread::scope(|scope| -> Result<()> {
+                let handles: Vec<_> = groups
+                    .into_values()
+                    .map(|group| scope.spawn(move || self.backup_group(group)))
+                    .collect();
+                for handle in handles {
+                    results.extend(
+                        handle
+                            .join()
+                            .map_err(|_| anyhow!("backup thread panicked"))?,
+                    );
+                }
+                Ok(())
+            })?;
+        } else {
+            for group in groups.into_values() {
+                results.extend(self.backup_group(group));
+            }
+        }
+        results.sort_by(|r1, r2| r1.profile.cmp(&r2.profile));
+
+        let mut table =
+            table_right_from(3, ["Profile", "Repository", "Status", "Snapshots", "Added"]);
+        for result in &results {
+            let row = match &result.result {
+                Ok(snaps) => {
+                    let summaries = snaps.iter().filter_map(|snap| snap.summary.as_ref());
+                    let status = if summaries.clone().any(|summary| summary.partial) {
+                        "partial"
+                    } else {
+                        "ok"
+                    };
+                    let added = summaries.map(|summary| summary.data_added_packed).sum();
+                    [
+                        status.to_string(),
+                        snaps.len().to_string(),
+                        bytes_size_to_string(added),
+                    ]
+                }
+                Err(err) => [format!("failed: {err}"), "-".to_string(), "-".to_string()],
+            };
+            _ = table.add_row(
+                [result.profile.clone(), result.repository.clone()]
+                    .into_iter()
+                    .chain(row),
+            );
+        }
+        println!("{table}");
+
+        let failed = results.iter().filter(|r| r.result.is_err()).count();
+        if failed > 0 {
+            bail!("backup of {failed} of {} profiles failed.", results.len());
+        }
+        Ok(())
+    }
+
+    /// Run the backups of profiles sharing a repository one after another
+    fn backup_group(&self, group: Vec<ProfileRun>) -> Vec<ProfileResult> {
+        group
+            .into_iter()
+            .map(|(profile, profile_config, repo)| {
+                info!("running backup of profile {profile}...");
+                let result = self.backup(&profile_config, repo);
+                if let Err(err) = &result {
+                    error!("backup of profile {profile} failed: {err}");
+                }
+                ProfileResult {
+                    profile,
+                    repository: profile_config.repository.repository.unwrap_or_default(),
+                    result,
+                }
+            })
+            .collect()
+    }
+
+    /// Backup all sources given on the command line or defined in the config to `repo`.
+    fn backup(
+        &self,
+        config: &RusticConfig,
+        repo: Repository<ProgressOptions, OpenStatus>,
+    ) -> Result<Vec<SnapshotFile>> {
+        let repo = repo.to_indexed_ids()?;
 
         // manually check for a "source" field, check is not done by serde, see above.
         if !config.backup.source.is_empty() {
@@ -131,21 +383,91 @@ impl BackupCmd {
             })
             .collect();
 
-        let sources = match (self.cli_sources.is_empty(), config_opts.is_empty()) {
-            (false, _) => {
-                let item = PathList::from_strings(&self.cli_sources).sanitize()?;
-                vec![item]
+        let repo_id = repo.config().id;
+        let mut failed_files = load_failed_files(&repo_id)?;
+
+        let mut retry = Vec::new();
+        if let Some(id) = &self.retry_partial {
+            let snap = repo.get_snapshot_from_str(id, |sn| config.snapshot_filter.matches(sn))?;
+            match &snap.summary {
+                Some(summary) if summary.partial => {
+                    info!(
+                        "retrying {} failed files of snapshot {}.",
+                        summary.failed_paths.len(),
+                        config.global.format_id(&snap.id)
+                    );
+                }
+                _ => bail!(
+                    "snapshot {} is not partial.",
+                    config.global.format_id(&snap.id)
+                ),
+            }
+            retry.push(snap);
+        }
+        if self.retry_failed {
+            if failed_files.is_empty() {
+                info!("no failed files of previous backups found, nothing to retry.");
+                return Ok(Vec::new());
             }
-            (true, false) => {
-                info!("using all backup sources from config file.");
-                config_sources.clone()
+            for failed in &failed_files {
+                info!(
+                    "retrying {} failed files of {} (snapshot {}).",
+                    failed.failed.len(),
+                    failed.paths,
+                    config.global.format_id(&failed.snapshot)
+                );
+                retry.push(repo.get_snapshot_from_str(&failed.snapshot.to_hex(), |_| true)?);
             }
-            (true, true) => {
-                bail!("no backup source given.");
+        }
+
+        let sources = if retry.is_empty() {
+            match (self.cli_sources.is_empty(), config_opts.is_empty()) {
+                (false, _) => {
+                    let item = PathList::from_strings(&self.cli_sources).sanitize()?;
+                    vec![item]
+                }
+                (true, false) => {
+                    info!("using all backup sources from config file.");
+                    config_sources.clone()
+                }
+                (true, true) => {
+                    bail!("no backup source given.");
+                }
             }
+        } else {
+            retry
+                .iter()
+                .map(|snap| PathList::from_strings(snap.paths.iter()).sanitize())
+                .collect::<Result<_, _>>()?
+        };
+
+        if !apfs::has_full_disk_access() {
+            warn!("{}", apfs::constants::FULL_DISK_ACCESS_HINT);
+        }
+        let apfs_snapshot = (self.use_apfs_snapshot || config.backup.use_apfs_snapshot)
+            .then(ApfsSnapshot::create)
+            .transpose()?;
+
+        let control = BackupControl::default();
+        timeout::register(&control);
+        let _listener = self
+            .control_socket
+            .as_ref()
+            .or(config.backup.control_socket.as_ref())
+            .map(|path| listen(path, Some(control.clone())))
+            .transpose()?;
+        // stdin can't be used for keyboard control when backing up from stdin
+        let _keyboard = if sources
+            .iter()
+            .any(|source| source.paths() == [PathBuf::from("-")])
+        {
+            None
+        } else {
+            keyboard::listen("backup", Some(control.clone()))
         };
 
-        for source in sources {
+        let mut snaps = Vec::new();
+        for (i, source) in sources.into_iter().enumerate() {
             let mut opts = self.clone();
 
             // merge Options from config file, if given
@@ -170,12 +492,37 @@ impl BackupCmd {
             // merge "backup" section from config file, if given
             opts.merge(config.backup.clone());
 
+            if let Some(snap) = retry.get(i) {
+                opts.parent_opts.parent = Some(snap.id.to_hex().to_string());
+                opts.parent_opts.force = false;
+                opts.parent_opts.assume_unchanged |= self.retry_failed;
+            }
+
+            // backup from the APFS snapshot, but save the original path
+            let source = match &apfs_snapshot {
+                Some(snapshot) => {
+                    let paths = source.paths();
+                    if paths.len() != 1 || !paths[0].is_absolute() {
+                        bail!("--use-apfs-snapshot only works with a single absolute path per source!");
+                    }
+                    if opts.as_path.is_none() {
+                        opts.as_path = Some(paths[0].clone());
+                    }
+                    PathList::from_strings([snapshot.path(&paths[0]).to_string_lossy()])
+                }
+                None => source,
+            };
+
             let backup_opts = BackupOptions::default()
                 .stdin_filename(opts.stdin_filename)
                 .as_path(opts.as_path)
                 .parent_opts(opts.parent_opts)
                 .ignore_save_opts(opts.ignore_save_opts)
                 .ignore_filter_opts(opts.ignore_filter_opts)
+                .unstable_retries(opts.unstable_retries)
+                .skip_unstable(opts.skip_unstable)
+                .include_repository(opts.include_repository)
+                .control(control.clone())
                 .dry_run(config.global.dry_run);
             let snap = repo.backup(&backup_opts, source.clone(), opts.snap_opts.to_snapshot()?)?;
 
@@ -183,7 +530,7 @@ impl BackupCmd {
                 let mut stdout = std::io::stdout();
                 serde_json::to_writer_pretty(&mut stdout, &snap)?;
             } else {
-                let summary = snap.summary.unwrap();
+                let summary = snap.summary.as_ref().unwrap();
                 println!(
                     "Files:       {} new, {} changed, {} unchanged",
                     summary.files_new, summary.files_changed, summary.files_unmodified
@@ -192,6 +539,24 @@ impl BackupCmd {
                     "Dirs:        {} new, {} changed, {} unchanged",
                     summary.dirs_new, summary.dirs_changed, summary.dirs_unmodified
                 );
+                if summary.files_unstable > 0 {
+                    warn!(
+                        "{} files changed while being read{}",
+                        summary.files_unstable,
+                        if opts.skip_unstable {
+                            " and have been skipped"
+                        } else {
+                            ", their contents may be inconsistent"
+                        }
+                    );
+                }
+                if summary.partial {
+                    warn!(
+                        "snapshot is partial: {} files failed, {} other errors. Use --retry-failed or --retry-partial to complete it.",
+                        summary.failed_paths.len(),
+                        summary.source_errors
+                    );
+                }
                 debug!("Data Blobs:  {} new", summary.data_blobs);
                 debug!("Tree Blobs:  {} new", summary.tree_blobs);
                 println!(
@@ -205,12 +570,34 @@ impl BackupCmd {
                     summary.total_files_processed,
                     bytes_size_to_string(summary.total_bytes_processed)
                 );
-                println!("snapshot {} successfully saved.", snap.id);
+                if let Some(resources) = &summary.resources {
+                    for line in resources_to_string(resources).lines() {
+                        info!("{line}");
+                    }
+                }
+                println!(
+                    "snapshot {} successfully saved.",
+                    config.global.format_id(&snap.id)
+                );
             }
 
             info!("backup of {source} done.");
+            if !config.global.dry_run {
+                let paths = snap.paths.to_string();
+                failed_files.retain(|failed| failed.paths != paths);
+                if let Some(summary) = snap.summary.as_ref().filter(|s| !s.failed_paths.is_empty())
+                {
+                    failed_files.push(FailedFiles {
+                        paths,
+                        snapshot: snap.id,
+                        failed: summary.failed_paths.clone(),
+                    });
+                }
+                save_failed_files(&repo_id, &failed_files)?;
+            }
+            snaps.push(snap);
         }
 
-        Ok(())
+        Ok(snaps)
     }
 }
@@ -0,0 +1,213 @@
+//! `catalog` subcommand
+
+/// App-local prelude includes `app_reader()`/`app_writer()`/`app_config()`
+/// accessors along with logging macros. Customize as you see fit.
+use crate::{commands::open_repository, status_err, Application, RUSTIC_APP};
+
+use std::{collections::HashSet, path::PathBuf};
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::Result;
+use log::info;
+use rusqlite::{params, Connection, Transaction};
+
+use rustic_core::{
+    repofile::{Node, NodeType, SnapshotFile},
+    IndexedTree, LsOptions, Repository,
+};
+
+use crate::config::progress_options::ProgressOptions;
+
+/// Schema of the catalog database
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS snapshots (
+    id TEXT PRIMARY KEY,
+    time TEXT NOT NULL,
+    hostname TEXT NOT NULL,
+    label TEXT NOT NULL,
+    paths TEXT NOT NULL,
+    tags TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS files (
+    snapshot_id TEXT NOT NULL,
+    path TEXT NOT NULL,
+    type TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    mtime TEXT,
+    content TEXT
+);
+CREATE INDEX IF NOT EXISTS files_snapshot_id ON files (snapshot_id);
+CREATE INDEX IF NOT EXISTS files_path ON files (path);
+";
+
+/// `catalog` subcommand
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct CatalogCmd {
+    #[clap(subcommand)]
+    cmd: CatalogSubCmd,
+}
+
+#[derive(clap::Subcommand, Debug, Runnable)]
+enum CatalogSubCmd {
+    /// Export the contents of the snapshots into a SQLite database. An existing database is
+    /// updated, i.e. only new snapshots are added and removed snapshots are deleted
+    Export(ExportSubCmd),
+}
+
+#[derive(Debug, clap::Parser, Command)]
+struct ExportSubCmd {
+    /// SQLite database to write the catalog to
+    #[clap(value_name = "DATABASE")]
+    database: PathBuf,
+
+    /// Snapshots to export. If none is given, use filter options to filter from all snapshots
+    #[clap(long, value_name = "ID")]
+    ids: Vec<String>,
+
+    /// Don't add new snapshots to the catalog, only remove snapshots which no longer exist
+    #[clap(long)]
+    no_add: bool,
+}
+
+impl Runnable for CatalogCmd {
+    fn run(&self) {
+        self.cmd.run();
+    }
+}
+
+impl Runnable for ExportSubCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl ExportSubCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let repo = open_repository(&config)?.to_indexed_ids()?;
+
+        let snapshots = if self.ids.is_empty() {
+            repo.get_matching_snapshots(|sn| config.snapshot_filter.matches(sn))?
+        } else {
+            repo.get_snapshots(&self.ids)?
+        };
+
+        let mut conn = Connection::open(&self.database)?;
+        conn.execute_batch(SCHEMA)?;
+
+        let existing = {
+            let mut stmt = conn.prepare("SELECT id FROM snapshots")?;
+            let ids = stmt.query_map([], |row| row.get::<_, String>(0))?;
+            ids.collect::<Result<HashSet<_>, _>>()?
+        };
+        let wanted: HashSet<_> = snapshots
+            .iter()
+            .map(|sn| sn.id.to_hex().to_string())
+            .collect();
+
+        // snapshots which have been removed from the repository or don't match anymore
+        let removed: Vec<_> = existing.difference(&wanted).collect();
+        if !config.global.dry_run && !removed.is_empty() {
+            let tx = conn.transaction()?;
+            for id in &removed {
+                _ = tx.execute("DELETE FROM files WHERE snapshot_id = ?1", params![id])?;
+                _ = tx.execute("DELETE FROM snapshots WHERE id = ?1", params![id])?;
+            }
+            tx.commit()?;
+        }
+
+        let mut added = 0;
+        if !self.no_add {
+            for sn in snapshots
+                .iter()
+                .filter(|sn| !existing.contains(sn.id.to_hex().as_str()))
+            {
+                info!("adding snapshot {} to the catalog...", sn.id);
+                added += 1;
+                if !config.global.dry_run {
+                    // each snapshot is added in an own transaction, so an interrupted export can be resumed
+                    let tx = conn.transaction()?;
+                    add_snapshot(&repo, &tx, sn)?;
+                    tx.commit()?;
+                }
+            }
+        }
+
+        let action = if config.global.dry_run {
+            "would have"
+        } else {
+            "have been"
+        };
+        info!(
+            "{added} snapshots {action} added to and {} snapshots {action} removed from {}.",
+            removed.len(),
+            self.database.display()
+        );
+        Ok(())
+    }
+}
+
+/// Add a snapshot and all of its files to the catalog
+///
+/// # Arguments
+///
+/// * `repo` - The repository to read the snapshot contents from
+/// * `tx` - The database transaction to add the snapshot in
+/// * `sn` - The snapshot to add
+fn add_snapshot<S: IndexedTree>(
+    repo: &Repository<ProgressOptions, S>,
+    tx: &Transaction<'_>,
+    sn: &SnapshotFile,
+) -> Result<()> {
+    let id = sn.id.to_hex().to_string();
+    _ = tx.execute(
+        "INSERT INTO snapshots (id, time, hostname, label, paths, tags) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            id,
+            sn.time.to_rfc3339(),
+            sn.hostname,
+            sn.label,
+            sn.paths.to_string(),
+            sn.tags.to_string()
+        ],
+    )?;
+
+    let mut stmt = tx.prepare_cached(
+        "INSERT INTO files (snapshot_id, path, type, size, mtime, content) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+    )?;
+    let node = repo.node_from_snapshot_and_path(sn, "")?;
+    for item in repo.ls(&node, &LsOptions::default().recursive(true))? {
+        let (path, node) = item?;
+        _ = stmt.execute(params![
+            id,
+            path.to_string_lossy(),
+            node_type(&node),
+            i64::try_from(node.meta.size)?,
+            node.meta.mtime.map(|mtime| mtime.to_rfc3339()),
+            node.content.as_ref().map(|content| {
+                content
+                    .iter()
+                    .map(|id| id.to_hex().to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+        ])?;
+    }
+    Ok(())
+}
+
+/// The type of a node as saved in the catalog
+fn node_type(node: &Node) -> &'static str {
+    match node.node_type {
+        NodeType::File => "file",
+        NodeType::Dir => "dir",
+        NodeType::Symlink { .. } => "symlink",
+        NodeType::Dev { .. } => "dev",
+        NodeType::Chardev { .. } => "chardev",
+        NodeType::Fifo => "fifo",
+        NodeType::Socket => "socket",
+    }
+}
@@ -2,17 +2,33 @@
 
 /// App-local prelude includes `app_reader()`/`app_writer()`/`app_config()`
 /// accessors along with logging macros. Customize as you see fit.
-use crate::{commands::open_repository, status_err, Application, RUSTIC_APP};
+use crate::{
+    application::logged_errors, commands::open_repository, helpers::bytes_size_to_string,
+    status_err, Application, RUSTIC_APP,
+};
+
+use std::str::FromStr;
 
 use abscissa_core::{Command, Runnable, Shutdown};
-use anyhow::Result;
-use rustic_core::CheckOptions;
+use anyhow::{anyhow, bail, Result};
+use bytesize::ByteSize;
+use chrono::{Duration, Local};
+use rustic_core::{CheckOptions, PruneOptions};
 
 /// `check` subcommand
 #[derive(clap::Parser, Command, Debug)]
 pub(crate) struct CheckCmd {
     #[clap(flatten)]
     opts: CheckOptions,
+
+    /// Fail with a message if the expression is true after checking, e.g.
+    /// 'errors>0 || unused>20% || last-snapshot-older-than 48h'. Conditions can be combined by `||` and `&&`.
+    #[clap(long, value_name = "EXPRESSION")]
+    alert_if: Option<AlertExpr>,
+
+    /// Output the findings in json format, each with a stable code, the affected ids and a suggested remediation
+    #[clap(long)]
+    json: bool,
 }
 
 impl Runnable for CheckCmd {
@@ -28,7 +44,245 @@ impl CheckCmd {
     fn inner_run(&self) -> Result<()> {
         let config = RUSTIC_APP.config();
         let repo = open_repository(&config)?;
-        repo.check(self.opts)?;
+        let errors_before = logged_errors();
+        let results = repo.check(self.opts)?;
+        if self.json {
+            let mut stdout = std::io::stdout();
+            serde_json::to_writer_pretty(&mut stdout, &results)?;
+        }
+
+        let expr = match &self.alert_if {
+            Some(expr) => expr,
+            None => return Ok(()),
+        };
+
+        let mut values = AlertValues {
+            errors: logged_errors() - errors_before,
+            ..Default::default()
+        };
+        if expr.uses_unused() {
+            let stats = repo.prune_plan(&PruneOptions::default())?.stats;
+            let size = stats.size_sum();
+            values.unused = Some((
+                size.unused + stats.size_unref,
+                size.total() + stats.size_unref,
+            ));
+        }
+        if expr.uses_snapshots() {
+            values.snapshot_age = Some(
+                repo.get_all_snapshots()?
+                    .into_iter()
+                    .map(|snap| snap.time)
+                    .max()
+                    .map(|time| Local::now() - time),
+            );
+        }
+
+        let triggered = expr.evaluate(&values);
+        if !triggered.is_empty() {
+            bail!("alert: {}", triggered.join("; "));
+        }
         Ok(())
     }
 }
+
+/// Comparison operator of an alert condition
+#[derive(Clone, Copy, Debug)]
+enum Comparison {
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Equal,
+}
+
+impl Comparison {
+    /// The operators in the order they are searched for in a condition
+    const ALL: [(&'static str, Self); 6] = [
+        (">=", Self::GreaterEqual),
+        ("<=", Self::LessEqual),
+        ("==", Self::Equal),
+        (">", Self::Greater),
+        ("<", Self::Less),
+        ("=", Self::Equal),
+    ];
+
+    /// Compare the value with the threshold
+    fn compare(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Self::Greater => value > threshold,
+            Self::GreaterEqual => value >= threshold,
+            Self::Less => value < threshold,
+            Self::LessEqual => value <= threshold,
+            Self::Equal => (value - threshold).abs() < f64::EPSILON,
+        }
+    }
+}
+
+/// A single condition of an alert expression
+#[derive(Clone, Debug)]
+enum Condition {
+    /// Number of errors found by the check
+    Errors(Comparison, usize),
+    /// Unused size in percent of the total size
+    UnusedPercent(Comparison, f64),
+    /// Unused size in bytes
+    UnusedSize(Comparison, u64),
+    /// Age of the latest snapshot
+    LastSnapshotOlderThan(humantime::Duration),
+}
+
+/// The values the conditions of an alert expression are evaluated against
+#[derive(Default)]
+struct AlertValues {
+    /// Number of errors found by the check
+    errors: usize,
+    /// Unused and total size of the repository, if needed
+    unused: Option<(u64, u64)>,
+    /// Age of the latest snapshot or `None` if there is no snapshot, if needed
+    snapshot_age: Option<Option<Duration>>,
+}
+
+impl Condition {
+    /// Evaluate the condition.
+    ///
+    /// # Returns
+    ///
+    /// The description of the actual value, if the condition is true
+    #[allow(clippy::cast_precision_loss)]
+    fn evaluate(&self, values: &AlertValues) -> Option<String> {
+        let (unused, total) = values.unused.unwrap_or_default();
+        let (triggered, actual) = match self {
+            Self::Errors(cmp, threshold) => (
+                cmp.compare(values.errors as f64, *threshold as f64),
+                format!("errors = {}", values.errors),
+            ),
+            Self::UnusedPercent(cmp, threshold) => {
+                let percent = if total == 0 {
+                    0.0
+                } else {
+                    unused as f64 / total as f64 * 100.0
+                };
+                (
+                    cmp.compare(percent, *threshold),
+                    format!("unused = {percent:.2}%"),
+                )
+            }
+            Self::UnusedSize(cmp, threshold) => (
+                cmp.compare(unused as f64, *threshold as f64),
+                format!("unused = {}", bytes_size_to_string(unused)),
+            ),
+            Self::LastSnapshotOlderThan(max_age) => match values.snapshot_age.flatten() {
+                Some(age) => (
+                    age.to_std().map_or(false, |age| age > **max_age),
+                    format!(
+                        "last snapshot is {} old",
+                        humantime::format_duration(std::time::Duration::from_secs(
+                            age.num_seconds().unsigned_abs()
+                        ))
+                    ),
+                ),
+                None => (true, "no snapshot exists".to_string()),
+            },
+        };
+        triggered.then_some(actual)
+    }
+}
+
+impl FromStr for Condition {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if let Some(age) = s.strip_prefix("last-snapshot-older-than") {
+            let age = age.trim().trim_start_matches('=').trim();
+            return Ok(Self::LastSnapshotOlderThan(age.parse()?));
+        }
+        let (metric, cmp, value) = Comparison::ALL
+            .iter()
+            .find_map(|(op, cmp)| {
+                s.split_once(op)
+                    .map(|(metric, value)| (metric.trim(), *cmp, value.trim()))
+            })
+            .ok_or_else(|| anyhow!("no comparison found in condition `{s}`"))?;
+        match metric {
+            "errors" => Ok(Self::Errors(cmp, value.parse()?)),
+            "unused" => match value.strip_suffix('%') {
+                Some(percent) => Ok(Self::UnusedPercent(cmp, percent.trim().parse()?)),
+                None => Ok(Self::UnusedSize(
+                    cmp,
+                    ByteSize::from_str(value)
+                        .map_err(|err| anyhow!(err))?
+                        .as_u64(),
+                )),
+            },
+            _ => bail!("unknown value `{metric}`, use errors, unused or last-snapshot-older-than"),
+        }
+    }
+}
+
+/// An alert expression, i.e. conditions combined by `||` and `&&` where `&&` binds stronger
+#[derive(Clone, Debug)]
+struct AlertExpr {
+    /// The conditions; the expression is true if all conditions of any inner list are true
+    any: Vec<Vec<(String, Condition)>>,
+}
+
+impl AlertExpr {
+    /// Whether the expression needs the unused size of the repository
+    fn uses_unused(&self) -> bool {
+        self.conditions().any(|cond| {
+            matches!(
+                cond,
+                Condition::UnusedPercent(..) | Condition::UnusedSize(..)
+            )
+        })
+    }
+
+    /// Whether the expression needs the snapshots of the repository
+    fn uses_snapshots(&self) -> bool {
+        self.conditions()
+            .any(|cond| matches!(cond, Condition::LastSnapshotOlderThan(_)))
+    }
+
+    /// All conditions of the expression
+    fn conditions(&self) -> impl Iterator<Item = &Condition> {
+        self.any.iter().flatten().map(|(_, cond)| cond)
+    }
+
+    /// Evaluate the expression.
+    ///
+    /// # Returns
+    ///
+    /// The triggered conditions together with the actual values; empty if the expression is false
+    fn evaluate(&self, values: &AlertValues) -> Vec<String> {
+        self.any
+            .iter()
+            .filter_map(|all| {
+                all.iter()
+                    .map(|(text, cond)| {
+                        cond.evaluate(values)
+                            .map(|actual| format!("{text} ({actual})"))
+                    })
+                    .collect::<Option<Vec<_>>>()
+            })
+            .flatten()
+            .collect()
+    }
+}
+
+impl FromStr for AlertExpr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let any = s
+            .split("||")
+            .map(|all| {
+                all.split("&&")
+                    .map(|cond| Ok((cond.trim().to_string(), cond.parse()?)))
+                    .collect::<Result<Vec<_>>>()
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { any })
+    }
+}
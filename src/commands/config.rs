@@ -7,16 +7,37 @@ use crate::{commands::open_repository, status_err, Application, RUSTIC_APP};
 use abscissa_core::{Command, Runnable, Shutdown};
 
 use anyhow::Result;
+use log::info;
 
 use rustic_core::ConfigOptions;
 
 /// `config` subcommand
 #[derive(clap::Parser, Command, Debug)]
+#[clap(args_conflicts_with_subcommands = true)]
 pub(crate) struct ConfigCmd {
+    #[clap(subcommand)]
+    cmd: Option<ConfigSubCmd>,
+
     #[clap(flatten)]
     config_opts: ConfigOptions,
 }
 
+#[derive(clap::Subcommand, Debug)]
+enum ConfigSubCmd {
+    /// Show a repository config value (or the whole config if no key is given)
+    Get {
+        /// Config key to show, e.g. `compression` or `datapack-size`
+        key: Option<String>,
+    },
+    /// Set a repository config value
+    Set {
+        /// Config key to set, e.g. `compression` or `datapack-size`
+        key: String,
+        /// Value to set
+        value: String,
+    },
+}
+
 impl Runnable for ConfigCmd {
     fn run(&self) {
         if let Err(err) = self.inner_run() {
@@ -31,7 +52,27 @@ impl ConfigCmd {
         let config = RUSTIC_APP.config();
         let repo = open_repository(&config)?;
 
-        let changed = repo.apply_config(&self.config_opts)?;
+        let config_opts = match &self.cmd {
+            Some(ConfigSubCmd::Get { key }) => {
+                let repo_config = serde_json::to_value(repo.config())?;
+                match key {
+                    None => println!("{}", serde_json::to_string_pretty(&repo_config)?),
+                    Some(key) => match repo_config.get(key.replace('-', "_")) {
+                        Some(value) => println!("{value}"),
+                        None => info!("{key} is not set, using default."),
+                    },
+                }
+                return Ok(());
+            }
+            Some(ConfigSubCmd::Set { key, value }) => {
+                let mut opts = ConfigOptions::default();
+                opts.set_option(key, value)?;
+                opts
+            }
+            None => self.config_opts,
+        };
+
+        let changed = repo.apply_config(&config_opts)?;
 
         if changed {
             println!("saved new config");
@@ -0,0 +1,250 @@
+//! `control` subcommand
+
+/// App-local prelude includes `app_reader()`/`app_writer()`/`app_config()`
+/// accessors along with logging macros. Customize as you see fit.
+use crate::{
+    application::{log_level, set_log_level},
+    status_err, Application, RUSTIC_APP,
+};
+
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::{anyhow, bail, Result};
+use log::{info, LevelFilter};
+
+use rustic_core::BackupControl;
+
+/// `control` subcommand
+///
+/// Talks to a backup or server which has been started with `--control-socket`.
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct ControlCmd {
+    /// Action to send to the running process
+    #[clap(value_enum)]
+    action: ControlAction,
+
+    /// New log level (off, error, warn, info, debug or trace) for the log-level action. If not given, show the current log level
+    #[clap(value_name = "LEVEL")]
+    level: Option<LevelFilter>,
+
+    /// Control socket of the running process
+    #[clap(long, value_name = "SOCKET", env = "RUSTIC_CONTROL_SOCKET")]
+    socket: PathBuf,
+}
+
+/// Actions which can be sent to a running process
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum ControlAction {
+    /// Pause reading the backup source
+    Pause,
+    /// Resume a paused backup
+    Resume,
+    /// Show whether the backup is running or paused
+    Status,
+    /// Show or change the log level
+    LogLevel,
+}
+
+impl ControlAction {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Pause => "pause",
+            Self::Resume => "resume",
+            Self::Status => "status",
+            Self::LogLevel => "log-level",
+        }
+    }
+}
+
+impl Runnable for ControlCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl ControlCmd {
+    fn inner_run(&self) -> Result<()> {
+        if self.action != ControlAction::LogLevel {
+            if self.level.is_some() {
+                bail!("a log level can only be given for the log-level action.");
+            }
+            let reply = send(&self.socket, self.action.as_str())?;
+            println!("backup is {reply}");
+            return Ok(());
+        }
+
+        let command = match self.level {
+            Some(level) => format!("{} {level}", self.action.as_str()),
+            None => self.action.as_str().to_string(),
+        };
+        let reply = send(&self.socket, &command)?;
+        println!("log level is {reply}");
+        Ok(())
+    }
+}
+
+/// Listener for the control socket of a running backup or server.
+///
+/// The socket file is removed when the listener is dropped.
+#[derive(Debug)]
+pub(crate) struct ControlListener {
+    path: PathBuf,
+}
+
+impl Drop for ControlListener {
+    fn drop(&mut self) {
+        _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(unix)]
+mod socket {
+    use std::{
+        fs,
+        io::{BufRead, BufReader, Write},
+        os::unix::net::{UnixListener, UnixStream},
+        path::Path,
+        thread,
+    };
+
+    use anyhow::{bail, Context, Result};
+    use log::{info, warn};
+
+    use rustic_core::BackupControl;
+
+    use super::{change_log_level, ControlListener};
+
+    /// Listen on `path` for control commands and apply them to `control`, if given.
+    pub(super) fn listen(path: &Path, control: Option<BackupControl>) -> Result<ControlListener> {
+        if UnixStream::connect(path).is_ok() {
+            bail!("control socket {path:?} is already used by another process.");
+        }
+        // remove a stale socket left over from a previous run
+        _ = fs::remove_file(path);
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("error creating control socket {path:?}"))?;
+        info!("listening for control commands on {path:?}");
+
+        _ = thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if let Err(err) = handle(&stream, control.as_ref()) {
+                    warn!("error handling control command: {err}");
+                }
+            }
+        });
+        Ok(ControlListener {
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Handle a single control command and reply with the resulting state.
+    fn handle(stream: &UnixStream, control: Option<&BackupControl>) -> Result<()> {
+        let mut command = String::new();
+        _ = BufReader::new(stream).read_line(&mut command)?;
+        match reply(command.trim(), control) {
+            Ok(reply) => writeln!(&*stream, "{reply}")?,
+            Err(err) => {
+                writeln!(&*stream, "error: {err}")?;
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply a control command and return the reply.
+    fn reply(command: &str, control: Option<&BackupControl>) -> Result<String> {
+        let (command, arg) = command.split_once(' ').unwrap_or((command, ""));
+        if command == "log-level" {
+            return change_log_level(arg.trim());
+        }
+
+        let Some(control) = control else {
+            bail!("command {command} is only supported for backups");
+        };
+        match command {
+            "pause" => {
+                control.pause();
+                info!("backup paused.");
+            }
+            "resume" => {
+                control.resume();
+                info!("backup resumed.");
+            }
+            "status" => {}
+            command => bail!("unknown command {command}"),
+        }
+        let state = if control.is_paused() {
+            "paused"
+        } else {
+            "running"
+        };
+        Ok(state.to_string())
+    }
+
+    /// Send `command` to the control socket at `path` and return the reply.
+    pub(super) fn send(path: &Path, command: &str) -> Result<String> {
+        let mut stream = UnixStream::connect(path).with_context(|| {
+            format!("cannot connect to control socket {path:?}. Is a backup or server running?")
+        })?;
+        writeln!(stream, "{command}")?;
+        let mut reply = String::new();
+        _ = BufReader::new(stream).read_line(&mut reply)?;
+        let reply = reply.trim();
+        if let Some(err) = reply.strip_prefix("error: ") {
+            bail!("{err}");
+        }
+        Ok(reply.to_string())
+    }
+}
+
+#[cfg(not(unix))]
+mod socket {
+    use std::path::Path;
+
+    use anyhow::{bail, Result};
+
+    use rustic_core::BackupControl;
+
+    use super::ControlListener;
+
+    pub(super) fn listen(_path: &Path, _control: Option<BackupControl>) -> Result<ControlListener> {
+        bail!("control sockets are not supported on this platform.");
+    }
+
+    pub(super) fn send(_path: &Path, _command: &str) -> Result<String> {
+        bail!("control sockets are not supported on this platform.");
+    }
+}
+
+/// Listen on the control socket `path` to change the log level and, if `control` is given, to pause
+/// and resume the backup.
+///
+/// # Errors
+///
+/// If the socket cannot be created or is already used by another process.
+pub(crate) fn listen(path: &Path, control: Option<BackupControl>) -> Result<ControlListener> {
+    socket::listen(path, control)
+}
+
+/// Change the log level to `level` or, if empty, only return the current log level.
+#[cfg_attr(not(unix), allow(dead_code))]
+fn change_log_level(level: &str) -> Result<String> {
+    if !level.is_empty() {
+        let level =
+            LevelFilter::from_str(level).map_err(|_| anyhow!("invalid log level {level}"))?;
+        set_log_level(level);
+        info!("log level changed to {level}.");
+    }
+    Ok(log_level().to_string().to_lowercase())
+}
+
+fn send(path: &Path, command: &str) -> Result<String> {
+    socket::send(path, command)
+}
@@ -3,16 +3,24 @@
 /// App-local prelude includes `app_reader()`/`app_writer()`/`app_config()`
 /// accessors along with logging macros. Customize as you see fit.
 use crate::{
-    commands::open_repository, helpers::table_with_titles, status_err, Application, RUSTIC_APP,
+    commands::{forget::ForgetOptions, open_repository},
+    helpers::table_with_titles,
+    status_err, Application, RUSTIC_APP,
 };
+use std::collections::HashSet;
+
 use abscissa_core::{Command, Runnable, Shutdown};
 use anyhow::{bail, Result};
-use log::{error, info};
+use chrono::Local;
+use log::{error, info, warn};
 
 use merge::Merge;
-use serde::Deserialize;
+use serde::{de::Error, Deserialize, Deserializer};
 
-use rustic_core::{CopySnapshot, Id, KeyOptions, Repository, RepositoryOptions};
+use rustic_core::{
+    repofile::SnapshotFile, CopySnapshot, Id, KeepOptions, KeyOptions, Repository,
+    RepositoryOptions, SnapshotGroup,
+};
 
 /// `copy` subcommand
 #[derive(clap::Parser, Command, Debug)]
@@ -25,6 +33,10 @@ pub(crate) struct CopyCmd {
     #[clap(long)]
     init: bool,
 
+    /// Only copy snapshots which would be kept by the retention policy of the target, given in [copy.targets.forget]
+    #[clap(long)]
+    apply_destination_policy: bool,
+
     #[clap(flatten, next_help_heading = "Key options (when using --init)")]
     key_opts: KeyOptions,
 }
@@ -32,7 +44,31 @@ pub(crate) struct CopyCmd {
 #[derive(Default, Clone, Debug, Deserialize, Merge)]
 pub struct Targets {
     #[merge(strategy = merge::vec::overwrite_empty)]
-    targets: Vec<RepositoryOptions>,
+    targets: Vec<CopyTarget>,
+}
+
+/// A target repository of the copy command
+#[derive(Default, Clone, Debug)]
+pub struct CopyTarget {
+    /// The options of the target repository
+    repo: RepositoryOptions,
+    /// The retention policy of the target repository
+    forget: Option<ForgetOptions>,
+}
+
+impl<'de> Deserialize<'de> for CopyTarget {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // the retention policy is given as `forget` table next to the repository options
+        let mut table = toml::value::Table::deserialize(deserializer)?;
+        let forget = table
+            .remove("forget")
+            .map(ForgetOptions::deserialize)
+            .transpose()
+            .map_err(D::Error::custom)?;
+        let repo =
+            RepositoryOptions::deserialize(toml::Value::Table(table)).map_err(D::Error::custom)?;
+        Ok(Self { repo, forget })
+    }
 }
 
 impl Runnable for CopyCmd {
@@ -63,8 +99,8 @@ impl CopyCmd {
         snapshots.sort_unstable();
 
         let poly = repo.config().poly()?;
-        for target_opt in &config.copy.targets {
-            let repo_dest = Repository::new(target_opt)?;
+        for target in &config.copy.targets {
+            let repo_dest = Repository::new(&target.repo)?;
 
             let repo_dest = if self.init && repo_dest.config_id()?.is_none() {
                 if config.global.dry_run {
@@ -87,11 +123,25 @@ impl CopyCmd {
                 bail!("cannot copy to repository with different chunker parameter (re-chunking not implemented)!");
             }
 
-            let snaps = repo_dest.relevant_copy_snapshots(
+            let mut snaps = repo_dest.relevant_copy_snapshots(
                 |sn| !self.ids.is_empty() || config.snapshot_filter.matches(sn),
                 &snapshots,
             )?;
 
+            let not_kept = match (&target.forget, self.apply_destination_policy) {
+                (Some(policy), true) if policy.keep != KeepOptions::default() => {
+                    apply_policy(policy, repo_dest.get_all_snapshots()?, &mut snaps)
+                }
+                (_, true) => {
+                    warn!(
+                        "target {} has no retention policy in [copy.targets.forget], copying all snapshots.",
+                        repo_dest.name
+                    );
+                    HashSet::new()
+                }
+                (_, false) => HashSet::new(),
+            };
+
             let mut table =
                 table_with_titles(["ID", "Time", "Host", "Label", "Tags", "Paths", "Status"]);
             for CopySnapshot { relevant, sn } in snaps.iter() {
@@ -99,13 +149,20 @@ impl CopyCmd {
                 let paths = sn.paths.formatln();
                 let time = sn.time.format("%Y-%m-%d %H:%M:%S").to_string();
                 _ = table.add_row([
-                    &sn.id.to_string(),
+                    &config.global.format_id(&sn.id),
                     &time,
                     &sn.hostname,
                     &sn.label,
                     &tags,
                     &paths,
-                    &(if *relevant { "to copy" } else { "existing" }).to_string(),
+                    &(if *relevant {
+                        "to copy"
+                    } else if not_kept.contains(&sn.id) {
+                        "not kept by target"
+                    } else {
+                        "existing"
+                    })
+                    .to_string(),
                 ]);
             }
             println!("{table}");
@@ -129,3 +186,57 @@ impl CopyCmd {
         Ok(())
     }
 }
+
+/// Apply the retention policy of the destination to the snapshots to copy.
+///
+/// Snapshots which would be removed by the policy, taking into account the snapshots already existing
+/// in the destination, are marked as not relevant.
+///
+/// # Arguments
+///
+/// * `policy` - The retention policy of the destination
+/// * `existing` - The snapshots existing in the destination
+/// * `snaps` - The snapshots to copy
+///
+/// # Returns
+///
+/// The ids of the snapshots which are not copied because of the policy
+fn apply_policy(
+    policy: &ForgetOptions,
+    existing: Vec<SnapshotFile>,
+    snaps: &mut [CopySnapshot],
+) -> HashSet<Id> {
+    let group_by = policy.group_by.unwrap_or_default();
+    let candidates = snaps
+        .iter()
+        .filter(|snap| snap.relevant)
+        .map(|snap| snap.sn.clone());
+
+    // snapshots not matching the filter of the policy are never removed
+    let mut groups: Vec<(SnapshotGroup, Vec<SnapshotFile>)> = Vec::new();
+    for sn in existing
+        .into_iter()
+        .chain(candidates)
+        .filter(|sn| policy.filter.matches(sn))
+    {
+        let group = SnapshotGroup::from_snapshot(&sn, group_by);
+        match groups.iter_mut().find(|(g, _)| g == &group) {
+            Some((_, group_snaps)) => group_snaps.push(sn),
+            None => groups.push((group, vec![sn])),
+        }
+    }
+
+    let now = Local::now();
+    let not_kept: HashSet<_> = groups
+        .into_iter()
+        .flat_map(|(_, group_snaps)| policy.keep.apply(group_snaps, now))
+        .filter(|sn| !sn.keep)
+        .map(|sn| sn.snapshot.id)
+        .collect();
+    for snap in snaps.iter_mut() {
+        if not_kept.contains(&snap.sn.id) {
+            snap.relevant = false;
+        }
+    }
+    not_kept
+}
@@ -6,14 +6,18 @@ use crate::{commands::open_repository, status_err, Application, RUSTIC_APP};
 
 use abscissa_core::{Command, Runnable, Shutdown};
 
-use std::path::{Path, PathBuf};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
 
 use anyhow::{bail, Context, Result};
+use log::{info, warn};
 
 use rustic_core::{
     repofile::{BlobType, Node, NodeType},
     IndexedFull, LocalDestination, LocalSource, LocalSourceFilterOptions, LocalSourceSaveOptions,
-    LsOptions, ReadSourceEntry, Repository, RusticResult,
+    LsOptions, PathNormalizationOptions, ReadSourceEntry, Repository, RusticResult,
 };
 
 /// `diff` subcommand
@@ -37,6 +41,18 @@ pub(crate) struct DiffCmd {
 
     #[clap(flatten)]
     ignore_opts: LocalSourceFilterOptions,
+
+    #[clap(flatten, next_help_heading = "Path normalization options")]
+    normalize_opts: PathNormalizationOptions,
+
+    /// Show differences in CSV format with the columns status and path
+    #[clap(long)]
+    csv: bool,
+
+    /// Verify that there are no differences, e.g. that the backup source still matches a snapshot.
+    /// Fails if any differences are found
+    #[clap(long)]
+    verify: bool,
 }
 
 impl Runnable for DiffCmd {
@@ -57,7 +73,23 @@ impl DiffCmd {
         let (id1, path1) = arg_to_snap_path(&self.snap1, "");
         let (id2, path2) = arg_to_snap_path(&self.snap2, path1);
 
-        _ = match (id1, id2) {
+        let mut csv = self
+            .csv
+            .then(|| csv::Writer::from_writer(std::io::stdout()));
+        if let Some(wtr) = &mut csv {
+            wtr.write_record(["status", "path"])?;
+        }
+        let mut differences = 0_u64;
+        let mut print = |status: char, path: &Path| -> Result<()> {
+            differences += 1;
+            match &mut csv {
+                Some(wtr) => wtr.write_record([&status.to_string(), &*path.to_string_lossy()])?,
+                None => println!("{status}    {path:?}"),
+            }
+            Ok(())
+        };
+
+        match (id1, id2) {
             (Some(id1), Some(id2)) => {
                 // diff between two snapshots
                 let snaps = repo.get_snapshots(&[id1, id2])?;
@@ -68,13 +100,22 @@ impl DiffCmd {
                 let node1 = repo.node_from_snapshot_and_path(snap1, path1)?;
                 let node2 = repo.node_from_snapshot_and_path(snap2, path2)?;
 
-                diff(
+                let (src1, _) = normalize_streamer(
                     repo.ls(&node1, &LsOptions::default())?,
+                    &self.normalize_opts,
+                )?;
+                let (src2, _) = normalize_streamer(
                     repo.ls(&node2, &LsOptions::default())?,
+                    &self.normalize_opts,
+                )?;
+                diff(
+                    src1,
+                    src2,
                     self.no_content,
                     |_path, node1, node2| Ok(node1.content == node2.content),
                     self.metadata,
-                )
+                    &mut print,
+                )?;
             }
             (Some(id1), None) => {
                 // diff between snapshot and local path
@@ -105,19 +146,38 @@ impl DiffCmd {
                     Ok((path, node))
                 });
 
-                diff(
+                let (src1, _) = normalize_streamer(
                     repo.ls(&node1, &LsOptions::default())?,
-                    src,
+                    &self.normalize_opts,
+                )?;
+                let (src2, local_paths) = normalize_streamer(src, &self.normalize_opts)?;
+                diff(
+                    src1,
+                    src2,
                     self.no_content,
-                    |path, node1, _node2| identical_content_local(&local, &repo, path, node1),
+                    |path, node1, _node2| {
+                        // use the real local path if the path has been normalized
+                        let path = local_paths.get(path).map_or(path, PathBuf::as_path);
+                        identical_content_local(&local, &repo, path, node1)
+                    },
                     self.metadata,
-                )
+                    &mut print,
+                )?;
             }
             (None, _) => {
                 bail!("cannot use local path as first argument");
             }
-        };
+        }
 
+        if let Some(wtr) = &mut csv {
+            wtr.flush()?;
+        }
+        if self.verify {
+            if differences > 0 {
+                bail!("found {differences} differences.");
+            }
+            info!("no differences found.");
+        }
         Ok(())
     }
 }
@@ -135,6 +195,36 @@ fn arg_to_snap_path<'a>(arg: &'a str, default_path: &'a str) -> (Option<&'a str>
     }
 }
 
+/// Normalize the paths of a tree streamer according to the given options.
+///
+/// If normalization is active, the items are collected and sorted by their normalized paths.
+/// Additionally, the mapping from normalized to original paths is returned.
+fn normalize_streamer<'a>(
+    streamer: impl Iterator<Item = RusticResult<(PathBuf, Node)>> + 'a,
+    opts: &PathNormalizationOptions,
+) -> Result<(
+    Box<dyn Iterator<Item = RusticResult<(PathBuf, Node)>> + 'a>,
+    BTreeMap<PathBuf, PathBuf>,
+)> {
+    if !opts.is_active() {
+        return Ok((Box::new(streamer), BTreeMap::new()));
+    }
+
+    let mut items = BTreeMap::new();
+    let mut originals = BTreeMap::new();
+    for item in streamer {
+        let (path, node) = item?;
+        let normalized = opts.normalize_path(&path);
+        if let Some(other) = originals.get(&normalized) {
+            warn!("{path:?} collides with {other:?} after normalization, ignoring it.");
+            continue;
+        }
+        _ = items.insert(normalized.clone(), node);
+        _ = originals.insert(normalized, path);
+    }
+    Ok((Box::new(items.into_iter().map(Ok)), originals))
+}
+
 fn identical_content_local<P, S: IndexedFull>(
     local: &LocalDestination,
     repo: &Repository<P, S>,
@@ -161,6 +251,7 @@ fn diff(
     no_content: bool,
     file_identical: impl Fn(&Path, &Node, &Node) -> Result<bool>,
     metadata: bool,
+    print: &mut impl FnMut(char, &Path) -> Result<()>,
 ) -> Result<()> {
     let mut item1 = tree_streamer1.next().transpose()?;
     let mut item2 = tree_streamer2.next().transpose()?;
@@ -169,19 +260,19 @@ fn diff(
         match (&item1, &item2) {
             (None, None) => break,
             (Some(i1), None) => {
-                println!("-    {:?}", i1.0);
+                print('-', &i1.0)?;
                 item1 = tree_streamer1.next().transpose()?;
             }
             (None, Some(i2)) => {
-                println!("+    {:?}", i2.0);
+                print('+', &i2.0)?;
                 item2 = tree_streamer2.next().transpose()?;
             }
             (Some(i1), Some(i2)) if i1.0 < i2.0 => {
-                println!("-    {:?}", i1.0);
+                print('-', &i1.0)?;
                 item1 = tree_streamer1.next().transpose()?;
             }
             (Some(i1), Some(i2)) if i1.0 > i2.0 => {
-                println!("+    {:?}", i2.0);
+                print('+', &i2.0)?;
                 item2 = tree_streamer2.next().transpose()?;
             }
             (Some(i1), Some(i2)) => {
@@ -189,16 +280,16 @@ fn diff(
                 let node1 = &i1.1;
                 let node2 = &i2.1;
                 match &node1.node_type {
-                    tpe if tpe != &node2.node_type => println!("T    {path:?}"), // type was changed
+                    tpe if tpe != &node2.node_type => print('T', path)?, // type was changed
                     NodeType::File if !no_content && !file_identical(path, node1, node2)? => {
-                        println!("M    {path:?}");
+                        print('M', path)?;
                     }
                     NodeType::File if metadata && node1.meta != node2.meta => {
-                        println!("U    {path:?}");
+                        print('U', path)?;
                     }
                     NodeType::Symlink { .. } => {
-                        if node1.node_type.to_link() != node1.node_type.to_link() {
-                            println!("U    {path:?}");
+                        if node1.node_type.to_link() != node2.node_type.to_link() {
+                            print('U', path)?;
                         }
                     }
                     _ => {} // no difference to show
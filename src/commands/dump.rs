@@ -1,11 +1,30 @@
 //! `dump` subcommand
 
+mod zip_writer;
+
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
 /// App-local prelude includes `app_reader()`/`app_writer()`/`app_config()`
 /// accessors along with logging macros. Customize as you see fit.
 use crate::{commands::open_repository, status_err, Application, RUSTIC_APP};
 
 use abscissa_core::{Command, Runnable, Shutdown};
 use anyhow::Result;
+use itertools::Itertools;
+use log::warn;
+use tar::{Builder, EntryType, Header};
+
+use rustic_core::{
+    repofile::{Node, NodeType},
+    IndexedFull, LsOptions, ProgressBars, Repository,
+};
+
+use zip_writer::{EntryOptions, ZipWriter};
 
 /// `dump` subcommand
 #[derive(clap::Parser, Command, Debug)]
@@ -13,6 +32,19 @@ pub(crate) struct DumpCmd {
     /// file from snapshot to dump
     #[clap(value_name = "SNAPSHOT[:PATH]")]
     snap: String,
+
+    /// Dump the given path (including all subdirectories) as archive of the given format
+    #[clap(long, value_name = "FORMAT", value_enum)]
+    archive: Option<ArchiveFormat>,
+}
+
+/// Archive formats supported by `dump --archive`
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub(super) enum ArchiveFormat {
+    /// Tar archive
+    Tar,
+    /// Zip archive (using zip64 extensions where needed)
+    Zip,
 }
 
 impl Runnable for DumpCmd {
@@ -32,9 +64,205 @@ impl DumpCmd {
         let node =
             repo.node_from_snapshot_path(&self.snap, |sn| config.snapshot_filter.matches(sn))?;
 
-        let mut stdout = std::io::stdout();
-        repo.dump(&node, &mut stdout)?;
+        let stdout = std::io::stdout();
+        match self.archive {
+            None => repo.dump(&node, &mut stdout.lock())?,
+            Some(ArchiveFormat::Tar) => {
+                let mut w = BufWriter::new(stdout.lock());
+                write_tar(&repo, &node, &mut w)?;
+                w.flush()?;
+            }
+            Some(ArchiveFormat::Zip) => {
+                let mut w = BufWriter::new(stdout.lock());
+                write_zip(&repo, &node, &mut w)?;
+                w.flush()?;
+            }
+        }
 
         Ok(())
     }
 }
+
+/// Write the given node (including all subdirectories) as tar archive
+///
+/// Files with more than one hardlink which are found multiple times are only added once,
+/// all further occurrences are added as hardlinks to the first occurrence.
+fn write_tar<P: ProgressBars, S: IndexedFull>(
+    repo: &Repository<P, S>,
+    node: &Node,
+    w: &mut impl Write,
+) -> Result<()> {
+    let mut ar = Builder::new(w);
+    let mut hardlinks: HashMap<(u64, u64), PathBuf> = HashMap::new();
+
+    for item in repo.ls(node, &LsOptions::default())? {
+        let (path, node) = item?;
+        let meta = &node.meta;
+
+        let mut header = Header::new_gnu();
+        header.set_mode(unix_mode(&node));
+        header.set_uid(meta.uid.unwrap_or_default().into());
+        header.set_gid(meta.gid.unwrap_or_default().into());
+        header.set_mtime(
+            meta.mtime
+                .map(|t| t.timestamp().try_into().unwrap_or_default())
+                .unwrap_or_default(),
+        );
+        if let Some(user) = &meta.user {
+            header.set_username(truncate_name(user, &path))?;
+        }
+        if let Some(group) = &meta.group {
+            header.set_groupname(truncate_name(group, &path))?;
+        }
+        header.set_size(0);
+
+        match &node.node_type {
+            NodeType::File => {
+                if meta.links > 1 && meta.inode != 0 {
+                    let key = (meta.device_id, meta.inode);
+                    if let Some(target) = hardlinks.get(&key) {
+                        header.set_entry_type(EntryType::Link);
+                        ar.append_link(&mut header, &path, target)?;
+                        continue;
+                    }
+                    _ = hardlinks.insert(key, path.clone());
+                }
+                header.set_entry_type(EntryType::Regular);
+                header.set_size(meta.size);
+                ar.append_data(&mut header, &path, repo.file_reader(&node)?)?;
+            }
+            NodeType::Dir => {
+                header.set_entry_type(EntryType::Directory);
+                ar.append_data(&mut header, &path, std::io::empty())?;
+            }
+            NodeType::Symlink { .. } => {
+                header.set_entry_type(EntryType::Symlink);
+                ar.append_link(&mut header, &path, node.node_type.to_link())?;
+            }
+            NodeType::Dev { device } | NodeType::Chardev { device } => {
+                header.set_entry_type(if matches!(node.node_type, NodeType::Dev { .. }) {
+                    EntryType::Block
+                } else {
+                    EntryType::Char
+                });
+                let (major, minor) = split_device(*device);
+                header.set_device_major(major)?;
+                header.set_device_minor(minor)?;
+                ar.append_data(&mut header, &path, std::io::empty())?;
+            }
+            NodeType::Fifo => {
+                header.set_entry_type(EntryType::Fifo);
+                ar.append_data(&mut header, &path, std::io::empty())?;
+            }
+            NodeType::Socket => {
+                warn!(
+                    "{}: sockets cannot be stored in tar archives, skipping",
+                    path.display()
+                );
+            }
+        }
+    }
+
+    ar.finish()?;
+    Ok(())
+}
+
+/// Write the given node (including all subdirectories) as zip archive
+///
+/// The archive is streamed, so it can be written to stdout. Names are always stored with `/` as
+/// separator and UTF-8 encoded. As zip has no notion of hardlinks, hardlinked files are stored
+/// once per occurrence. Special files are skipped. Besides the full unix mode (including setuid,
+/// setgid and sticky bit), the DOS attributes (read-only, directory) are set.
+fn write_zip<P: ProgressBars, S: IndexedFull>(
+    repo: &Repository<P, S>,
+    node: &Node,
+    w: impl Write,
+) -> Result<()> {
+    let mut zip = ZipWriter::new(w);
+
+    for item in repo.ls(node, &LsOptions::default())? {
+        let (path, node) = item?;
+        let meta = &node.meta;
+        let name = zip_name(&path);
+
+        let options = EntryOptions {
+            mode: unix_mode(&node),
+            read_only: meta.mode.map_or(false, |mode| mode & 0o222 == 0),
+            mtime: meta.mtime,
+        };
+        match &node.node_type {
+            NodeType::File => zip.add_file(&name, &options, repo.file_reader(&node)?)?,
+            NodeType::Dir => zip.add_directory(&name, &options)?,
+            NodeType::Symlink { .. } => zip.add_symlink(
+                &name,
+                node.node_type.to_link().to_string_lossy().as_bytes(),
+                &options,
+            )?,
+            _ => {
+                warn!(
+                    "{}: special files cannot be stored in zip archives, skipping",
+                    path.display()
+                );
+            }
+        }
+    }
+
+    _ = zip.finish()?;
+    Ok(())
+}
+
+/// Get the unix mode (permissions including setuid, setgid and sticky bit) of the given node
+///
+/// The mode is saved in the format used by golang, see <https://pkg.go.dev/io/fs#ModeType>.
+/// If no mode is saved, directories get `0o755` and all other entries get `0o644`.
+fn unix_mode(node: &Node) -> u32 {
+    const GO_MODE_SETUID: u32 = 1 << 23;
+    const GO_MODE_SETGID: u32 = 1 << 22;
+    const GO_MODE_STICKY: u32 = 1 << 20;
+
+    let default = if node.is_dir() { 0o755 } else { 0o644 };
+    node.meta.mode.map_or(default, |go_mode| {
+        let mut mode = go_mode & 0o777;
+        if go_mode & GO_MODE_SETUID != 0 {
+            mode |= 0o4000;
+        }
+        if go_mode & GO_MODE_SETGID != 0 {
+            mode |= 0o2000;
+        }
+        if go_mode & GO_MODE_STICKY != 0 {
+            mode |= 0o1000;
+        }
+        mode
+    })
+}
+
+/// Truncate a user or group name to the 32 bytes which fit into a GNU tar header
+fn truncate_name<'a>(name: &'a str, path: &Path) -> &'a str {
+    const MAX_NAME_LEN: usize = 32;
+
+    if name.len() <= MAX_NAME_LEN {
+        return name;
+    }
+    let mut len = MAX_NAME_LEN;
+    while !name.is_char_boundary(len) {
+        len -= 1;
+    }
+    warn!(
+        "{}: user or group name {name} is too long for tar archives, truncating",
+        path.display()
+    );
+    &name[..len]
+}
+
+/// Get the name of a zip entry for the given path
+fn zip_name(path: &Path) -> String {
+    path.iter().map(OsStr::to_string_lossy).join("/")
+}
+
+/// Split a (Linux-encoded) device id into its major and minor number
+#[allow(clippy::cast_possible_truncation)]
+const fn split_device(device: u64) -> (u32, u32) {
+    let major = ((device >> 8) & 0xfff) | ((device >> 32) & !0xfff);
+    let minor = (device & 0xff) | ((device >> 12) & !0xff);
+    (major as u32, minor as u32)
+}
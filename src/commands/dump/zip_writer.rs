@@ -0,0 +1,461 @@
+//! A streaming zip writer
+//!
+//! In contrast to the `zip` crate, the archive is written to a non-seekable writer like stdout:
+//! The sizes and checksums of files are written in data descriptors after the file contents and
+//! zip64 extensions are used for files, offsets and archives exceeding 4 GiB. Moreover, the full
+//! unix mode (including setuid, setgid and sticky bit) and the DOS attributes can be set.
+
+use std::io::{self, Read, Write};
+
+use chrono::{DateTime, Datelike, Local, Timelike};
+use crc32fast::Hasher;
+use flate2::{write::DeflateEncoder, Compression};
+
+mod consts {
+    pub(super) const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+    pub(super) const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x0807_4b50;
+    pub(super) const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+    pub(super) const ZIP64_END_SIGNATURE: u32 = 0x0606_4b50;
+    pub(super) const ZIP64_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+    pub(super) const END_SIGNATURE: u32 = 0x0605_4b50;
+
+    /// Version 4.5 is needed for zip64 extensions
+    pub(super) const VERSION_NEEDED: u16 = 45;
+    /// Version 4.5 made by a unix system, such that the upper 16 bits of the external
+    /// attributes are interpreted as unix mode
+    pub(super) const VERSION_MADE_BY: u16 = (3 << 8) | VERSION_NEEDED;
+
+    /// Sizes and checksum are given in a data descriptor following the file contents
+    pub(super) const FLAG_DATA_DESCRIPTOR: u16 = 1 << 3;
+    /// Names are UTF-8 encoded
+    pub(super) const FLAG_UTF8: u16 = 1 << 11;
+
+    pub(super) const METHOD_STORED: u16 = 0;
+    pub(super) const METHOD_DEFLATED: u16 = 8;
+
+    pub(super) const ZIP64_EXTRA_ID: u16 = 0x0001;
+
+    pub(super) const S_IFREG: u32 = 0o100_000;
+    pub(super) const S_IFDIR: u32 = 0o040_000;
+    pub(super) const S_IFLNK: u32 = 0o120_000;
+
+    pub(super) const DOS_READ_ONLY: u32 = 0x01;
+    pub(super) const DOS_DIRECTORY: u32 = 0x10;
+
+    /// Size of the buffer used to read file contents
+    pub(super) const BUFFER_SIZE: usize = 64 * 1024;
+}
+
+/// Options of a zip entry
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct EntryOptions {
+    /// The unix permissions including setuid, setgid and sticky bit
+    pub(super) mode: u32,
+    /// Whether the DOS attribute read-only should be set; ignored for symlinks
+    pub(super) read_only: bool,
+    /// The modification time
+    pub(super) mtime: Option<DateTime<Local>>,
+}
+
+/// A writer counting the written bytes
+struct CountingWriter<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// An entry as saved in the central directory
+struct CentralEntry {
+    name: String,
+    flags: u16,
+    method: u16,
+    time: u16,
+    date: u16,
+    crc: u32,
+    compressed_size: u64,
+    size: u64,
+    offset: u64,
+    external_attributes: u32,
+}
+
+/// A zip writer which writes to a non-seekable writer
+pub(super) struct ZipWriter<W: Write> {
+    w: CountingWriter<W>,
+    entries: Vec<CentralEntry>,
+}
+
+impl<W: Write> ZipWriter<W> {
+    /// Create a new [`ZipWriter`] writing to `w`
+    pub(super) const fn new(w: W) -> Self {
+        Self {
+            w: CountingWriter { inner: w, count: 0 },
+            entries: Vec::new(),
+        }
+    }
+
+    /// Add a file with the contents read from `reader`
+    ///
+    /// The contents are deflated. As the size is not known in advance, it is given in a data
+    /// descriptor after the contents and the local header always contains zip64 extensions.
+    pub(super) fn add_file(
+        &mut self,
+        name: &str,
+        options: &EntryOptions,
+        mut reader: impl Read,
+    ) -> io::Result<()> {
+        let mut entry = self.entry(name, options, consts::S_IFREG, consts::METHOD_DEFLATED);
+        entry.flags |= consts::FLAG_DATA_DESCRIPTOR;
+        // the sizes are not known yet: they are marked as zip64 and given in the data descriptor
+        self.write_local_header(&entry, u32::MAX, &zip64_extra(&[0, 0]))?;
+
+        let start = self.w.count;
+        let mut hasher = Hasher::new();
+        let mut encoder = DeflateEncoder::new(&mut self.w, Compression::default());
+        let mut buf = vec![0; consts::BUFFER_SIZE];
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            hasher.update(&buf[..n]);
+            entry.size += n as u64;
+            encoder.write_all(&buf[..n])?;
+        }
+        _ = encoder.finish()?;
+        entry.crc = hasher.finalize();
+        entry.compressed_size = self.w.count - start;
+
+        let mut descriptor = Vec::with_capacity(24);
+        put_u32(&mut descriptor, consts::DATA_DESCRIPTOR_SIGNATURE);
+        put_u32(&mut descriptor, entry.crc);
+        put_u64(&mut descriptor, entry.compressed_size);
+        put_u64(&mut descriptor, entry.size);
+        self.w.write_all(&descriptor)?;
+
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Add a directory; a trailing `/` is added to the name if missing
+    pub(super) fn add_directory(&mut self, name: &str, options: &EntryOptions) -> io::Result<()> {
+        let name = if name.ends_with('/') {
+            name.to_string()
+        } else {
+            format!("{name}/")
+        };
+        let mut entry = self.entry(&name, options, consts::S_IFDIR, consts::METHOD_STORED);
+        entry.external_attributes |= consts::DOS_DIRECTORY;
+        self.write_local_header(&entry, 0, &[])?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Add a symlink pointing to `target`
+    pub(super) fn add_symlink(
+        &mut self,
+        name: &str,
+        target: &[u8],
+        options: &EntryOptions,
+    ) -> io::Result<()> {
+        let options = EntryOptions {
+            read_only: false,
+            ..*options
+        };
+        let mut entry = self.entry(name, &options, consts::S_IFLNK, consts::METHOD_STORED);
+        entry.crc = crc32fast::hash(target);
+        entry.size = target.len() as u64;
+        entry.compressed_size = entry.size;
+        // Note: symlink targets are short, so their size always fits into the local header
+        self.write_local_header(&entry, u32::try_from(entry.size).unwrap_or(u32::MAX), &[])?;
+        self.w.write_all(target)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Write the central directory and return the inner writer
+    pub(super) fn finish(mut self) -> io::Result<W> {
+        let cd_offset = self.w.count;
+        for entry in &self.entries {
+            let mut zip64 = Vec::new();
+            let size = clamp_u32(entry.size, &mut zip64);
+            let compressed_size = clamp_u32(entry.compressed_size, &mut zip64);
+            let offset = clamp_u32(entry.offset, &mut zip64);
+            let extra = if zip64.is_empty() {
+                Vec::new()
+            } else {
+                zip64_extra(&zip64)
+            };
+
+            let mut header = Vec::with_capacity(46 + entry.name.len() + extra.len());
+            put_u32(&mut header, consts::CENTRAL_HEADER_SIGNATURE);
+            put_u16(&mut header, consts::VERSION_MADE_BY);
+            put_u16(&mut header, consts::VERSION_NEEDED);
+            put_u16(&mut header, entry.flags);
+            put_u16(&mut header, entry.method);
+            put_u16(&mut header, entry.time);
+            put_u16(&mut header, entry.date);
+            put_u32(&mut header, entry.crc);
+            put_u32(&mut header, compressed_size);
+            put_u32(&mut header, size);
+            put_u16(&mut header, name_len(&entry.name)?);
+            put_u16(&mut header, extra_len(&extra));
+            put_u16(&mut header, 0); // comment length
+            put_u16(&mut header, 0); // disk number
+            put_u16(&mut header, 0); // internal attributes
+            put_u32(&mut header, entry.external_attributes);
+            put_u32(&mut header, offset);
+            header.extend_from_slice(entry.name.as_bytes());
+            header.extend_from_slice(&extra);
+            self.w.write_all(&header)?;
+        }
+        let cd_end = self.w.count;
+        let cd_size = cd_end - cd_offset;
+        let count = self.entries.len() as u64;
+
+        let mut end = Vec::new();
+        let mut zip64 = Vec::new();
+        let count16 = u16::try_from(count).unwrap_or_else(|_| {
+            zip64.push(count);
+            u16::MAX
+        });
+        let cd_size32 = clamp_u32(cd_size, &mut zip64);
+        let cd_offset32 = clamp_u32(cd_offset, &mut zip64);
+        if !zip64.is_empty() {
+            put_u32(&mut end, consts::ZIP64_END_SIGNATURE);
+            put_u64(&mut end, 44); // size of the remaining record
+            put_u16(&mut end, consts::VERSION_MADE_BY);
+            put_u16(&mut end, consts::VERSION_NEEDED);
+            put_u32(&mut end, 0); // number of this disk
+            put_u32(&mut end, 0); // disk with the central directory
+            put_u64(&mut end, count);
+            put_u64(&mut end, count);
+            put_u64(&mut end, cd_size);
+            put_u64(&mut end, cd_offset);
+
+            put_u32(&mut end, consts::ZIP64_LOCATOR_SIGNATURE);
+            put_u32(&mut end, 0); // disk with the zip64 end of central directory
+            put_u64(&mut end, cd_end);
+            put_u32(&mut end, 1); // total number of disks
+        }
+        put_u32(&mut end, consts::END_SIGNATURE);
+        put_u16(&mut end, 0); // number of this disk
+        put_u16(&mut end, 0); // disk with the central directory
+        put_u16(&mut end, count16);
+        put_u16(&mut end, count16);
+        put_u32(&mut end, cd_size32);
+        put_u32(&mut end, cd_offset32);
+        put_u16(&mut end, 0); // comment length
+        self.w.write_all(&end)?;
+        self.w.flush()?;
+
+        Ok(self.w.inner)
+    }
+
+    /// Create the central directory entry for a new entry starting at the current offset
+    fn entry(
+        &self,
+        name: &str,
+        options: &EntryOptions,
+        file_type: u32,
+        method: u16,
+    ) -> CentralEntry {
+        let (time, date) = options.mtime.map_or((0, DOS_EPOCH), dos_time);
+        let dos_attributes = if options.read_only {
+            consts::DOS_READ_ONLY
+        } else {
+            0
+        };
+        CentralEntry {
+            name: name.to_string(),
+            flags: consts::FLAG_UTF8,
+            method,
+            time,
+            date,
+            crc: 0,
+            compressed_size: 0,
+            size: 0,
+            offset: self.w.count,
+            external_attributes: ((file_type | (options.mode & 0o7777)) << 16) | dos_attributes,
+        }
+    }
+
+    /// Write the local header of the given entry
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The entry to write the local header for
+    /// * `size` - The (compressed and uncompressed) size to write into the header
+    /// * `extra` - The extra field
+    fn write_local_header(
+        &mut self,
+        entry: &CentralEntry,
+        size: u32,
+        extra: &[u8],
+    ) -> io::Result<()> {
+        let mut header = Vec::with_capacity(30 + entry.name.len() + extra.len());
+        put_u32(&mut header, consts::LOCAL_HEADER_SIGNATURE);
+        put_u16(&mut header, consts::VERSION_NEEDED);
+        put_u16(&mut header, entry.flags);
+        put_u16(&mut header, entry.method);
+        put_u16(&mut header, entry.time);
+        put_u16(&mut header, entry.date);
+        put_u32(&mut header, entry.crc);
+        put_u32(&mut header, size);
+        put_u32(&mut header, size);
+        put_u16(&mut header, name_len(&entry.name)?);
+        put_u16(&mut header, extra_len(extra));
+        header.extend_from_slice(entry.name.as_bytes());
+        header.extend_from_slice(extra);
+        self.w.write_all(&header)
+    }
+}
+
+/// DOS date of 1980-01-01, the earliest representable date
+const DOS_EPOCH: u16 = (1 << 5) | 1;
+
+/// Convert a time into a DOS time and date. Times not representable are saved as 1980-01-01.
+fn dos_time(time: DateTime<Local>) -> (u16, u16) {
+    // DOS dates can represent the years 1980 to 2107
+    let years = match u32::try_from(time.year() - 1980) {
+        Ok(years) if years < 128 => years,
+        _ => return (0, DOS_EPOCH),
+    };
+    let dos_time = (time.hour() << 11) | (time.minute() << 5) | (time.second() / 2);
+    let dos_date = (years << 9) | (time.month() << 5) | time.day();
+    // Note: both values always fit into 16 bits
+    (
+        u16::try_from(dos_time).unwrap_or_default(),
+        u16::try_from(dos_date).unwrap_or(DOS_EPOCH),
+    )
+}
+
+/// Build a zip64 extended information extra field containing the given values
+fn zip64_extra(values: &[u64]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(8 * values.len());
+    for value in values {
+        put_u64(&mut data, *value);
+    }
+    let mut extra = Vec::with_capacity(4 + data.len());
+    put_u16(&mut extra, consts::ZIP64_EXTRA_ID);
+    put_u16(&mut extra, extra_len(&data));
+    extra.extend_from_slice(&data);
+    extra
+}
+
+/// Return the value if it fits into the 32 bit field; otherwise add it to the zip64 values and
+/// return the marker `0xFFFFFFFF`
+fn clamp_u32(value: u64, zip64: &mut Vec<u64>) -> u32 {
+    match u32::try_from(value) {
+        Ok(value) if value != u32::MAX => value,
+        _ => {
+            zip64.push(value);
+            u32::MAX
+        }
+    }
+}
+
+/// Returns the length of the given name which must fit into 16 bits
+fn name_len(name: &str) -> io::Result<u16> {
+    u16::try_from(name.len()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("name too long for zip archives: {name}"),
+        )
+    })
+}
+
+/// Returns the length of the given extra field
+#[allow(clippy::cast_possible_truncation)]
+const fn extra_len(extra: &[u8]) -> u16 {
+    // Note: extra fields written here are at most 28 bytes long
+    extra.len() as u16
+}
+
+fn put_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(buf: &mut Vec<u8>, value: u64) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use chrono::TimeZone;
+
+    #[test]
+    fn written_archive_can_be_read() {
+        let mtime = Local.with_ymd_and_hms(2023, 9, 14, 12, 34, 56).unwrap();
+        let options = EntryOptions {
+            mode: 0o4755,
+            read_only: false,
+            mtime: Some(mtime),
+        };
+        let data = b"hello zip ".repeat(10_000);
+
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.add_directory("dir", &options).unwrap();
+        zip.add_file("dir/file", &options, data.as_slice()).unwrap();
+        zip.add_symlink("dir/link", b"file", &EntryOptions::default())
+            .unwrap();
+        let archive = zip.finish().unwrap();
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(archive)).unwrap();
+        assert_eq!(archive.len(), 3);
+
+        let dir = archive.by_index(0).unwrap();
+        assert_eq!(dir.name(), "dir/");
+        assert!(dir.is_dir());
+        assert_eq!(dir.unix_mode(), Some(0o044_755));
+        drop(dir);
+
+        let mut file = archive.by_name("dir/file").unwrap();
+        assert_eq!(file.unix_mode(), Some(0o104_755));
+        assert_eq!(file.size(), data.len() as u64);
+        assert!(file.compressed_size() < file.size());
+        assert_eq!(file.last_modified().hour(), 12);
+        assert_eq!(file.last_modified().second(), 56);
+        let mut content = Vec::new();
+        _ = file.read_to_end(&mut content).unwrap();
+        assert_eq!(content, data);
+        drop(file);
+
+        let mut link = archive.by_name("dir/link").unwrap();
+        assert_eq!(link.unix_mode(), Some(0o120_000));
+        let mut target = String::new();
+        _ = link.read_to_string(&mut target).unwrap();
+        assert_eq!(target, "file");
+    }
+
+    #[test]
+    fn dos_time_is_clamped() {
+        let time = Local.with_ymd_and_hms(1970, 1, 1, 12, 0, 0).unwrap();
+        assert_eq!(dos_time(time), (0, DOS_EPOCH));
+        let time = Local.with_ymd_and_hms(2000, 2, 3, 4, 5, 6).unwrap();
+        assert_eq!(
+            dos_time(time),
+            ((4 << 11) | (5 << 5) | 3, (20 << 9) | (2 << 5) | 3)
+        );
+    }
+}
@@ -64,20 +64,25 @@ pub struct ForgetOptions {
     /// Group snapshots by any combination of host,label,paths,tags (default: "host,label,paths")
     #[clap(long, short = 'g', value_name = "CRITERION")]
     #[serde_as(as = "Option<DisplayFromStr>")]
-    group_by: Option<SnapshotGroupCriterion>,
+    pub(crate) group_by: Option<SnapshotGroupCriterion>,
 
     /// Also prune the repository
     #[clap(long)]
     #[merge(strategy = merge::bool::overwrite_false)]
     prune: bool,
 
+    /// Move forgotten snapshots to the trash for the given duration (e.g. 7d) instead of removing them
+    #[clap(long, value_name = "DURATION")]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    trash: Option<humantime::Duration>,
+
     #[clap(flatten, next_help_heading = "Snapshot filter options")]
     #[serde(flatten)]
-    filter: SnapshotFilter,
+    pub(crate) filter: SnapshotFilter,
 
     #[clap(flatten, next_help_heading = "Retention options")]
     #[serde(flatten)]
-    keep: KeepOptions,
+    pub(crate) keep: KeepOptions,
 }
 
 impl Runnable for ForgetCmd {
@@ -94,6 +99,10 @@ impl ForgetCmd {
         let config = RUSTIC_APP.config();
         let repo = open_repository(&config)?;
 
+        if !config.global.dry_run {
+            repo.complete_interrupted_removals()?;
+        }
+
         let group_by = config.forget.group_by.unwrap_or_default();
 
         let groups = if self.ids.is_empty() {
@@ -128,17 +137,29 @@ impl ForgetCmd {
         match (forget_snaps.is_empty(), config.global.dry_run, self.json) {
             (true, _, false) => println!("nothing to remove"),
             (false, true, false) => {
-                println!("would have removed the following snapshots:\n {forget_snaps:?}");
+                let action = if config.forget.trash.is_some() {
+                    "moved to trash"
+                } else {
+                    "removed"
+                };
+                println!("would have {action} the following snapshots:\n {forget_snaps:?}");
             }
             (false, false, _) => {
-                repo.delete_snapshots(&forget_snaps)?;
+                if let Some(keep) = config.forget.trash {
+                    repo.trash_snapshots(&forget_snaps, *keep)?;
+                } else {
+                    repo.delete_snapshots(&forget_snaps)?;
+                }
             }
             (_, _, true) => {}
         }
 
         if self.config.prune {
             let mut prune_opts = self.prune_opts.clone();
-            prune_opts.opts.ignore_snaps = forget_snaps;
+            // trashed snapshots are still in use, so their data must be kept
+            if config.forget.trash.is_none() {
+                prune_opts.opts.ignore_snaps = forget_snaps;
+            }
             prune_opts.run();
         }
 
@@ -147,6 +168,7 @@ impl ForgetCmd {
 }
 
 fn print_groups(groups: &ForgetGroups) {
+    let config = RUSTIC_APP.config();
     for ForgetGroup { group, snapshots } in &groups.0 {
         if !group.is_empty() {
             println!("snapshots for {group}");
@@ -167,7 +189,7 @@ fn print_groups(groups: &ForgetGroups) {
             let action = if *keep { "keep" } else { "remove" };
             let reason = reasons.join("\n");
             _ = table.add_row([
-                &sn.id.to_string(),
+                &config.global.format_id(&sn.id),
                 &time,
                 &sn.hostname,
                 &sn.label,
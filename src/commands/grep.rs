@@ -0,0 +1,132 @@
+//! `grep` subcommand
+
+use std::io::{BufRead, BufReader, Write};
+
+/// App-local prelude includes `app_reader()`/`app_writer()`/`app_config()`
+/// accessors along with logging macros. Customize as you see fit.
+use crate::{commands::open_repository, status_err, Application, RUSTIC_APP};
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::Result;
+use bytesize::ByteSize;
+use log::info;
+use regex::bytes::RegexBuilder;
+
+use rustic_core::LsOptions;
+
+/// `grep` subcommand
+///
+/// Searches the contents of files within a snapshot without restoring them.
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct GrepCmd {
+    /// Snapshot to search in
+    #[clap(value_name = "SNAPSHOT")]
+    snap: String,
+
+    /// Regular expression to search for
+    #[clap(value_name = "PATTERN")]
+    pattern: String,
+
+    /// Only search in this path within the snapshot
+    #[clap(value_name = "PATH")]
+    path: Option<String>,
+
+    /// Treat the pattern as literal string instead of a regular expression
+    #[clap(long, short = 'F')]
+    fixed_strings: bool,
+
+    /// Search case-insensitive
+    #[clap(long, short = 'i')]
+    ignore_case: bool,
+
+    /// Only print the paths of files containing a match
+    #[clap(long, short = 'l')]
+    files_with_matches: bool,
+
+    /// Skip files larger than this size
+    #[clap(long, value_name = "SIZE", default_value = "10MiB")]
+    max_filesize: ByteSize,
+
+    #[clap(flatten)]
+    ls_opts: LsOptions,
+}
+
+impl Runnable for GrepCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl GrepCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+
+        let pattern = if self.fixed_strings {
+            regex::escape(&self.pattern)
+        } else {
+            self.pattern.clone()
+        };
+        let re = RegexBuilder::new(&pattern)
+            .case_insensitive(self.ignore_case)
+            .build()?;
+
+        let repo = open_repository(&config)?.to_indexed()?;
+        let snap_path = match &self.path {
+            Some(path) => format!("{}:{path}", self.snap),
+            None => self.snap.clone(),
+        };
+        let node =
+            repo.node_from_snapshot_path(&snap_path, |sn| config.snapshot_filter.matches(sn))?;
+
+        let mut stdout = std::io::stdout().lock();
+        let mut skipped = 0;
+        for item in repo.ls(&node, &self.ls_opts)? {
+            let (path, node) = item?;
+            if !node.is_file() {
+                continue;
+            }
+            if node.meta.size > self.max_filesize.as_u64() {
+                skipped += 1;
+                continue;
+            }
+
+            // read the file line by line to not keep large files in memory
+            let mut reader = BufReader::new(repo.file_reader(&node)?);
+            let mut line = Vec::new();
+            let mut line_number = 0;
+            loop {
+                line.clear();
+                if reader.read_until(b'\n', &mut line)? == 0 {
+                    break;
+                }
+                line_number += 1;
+                if !re.is_match(&line) {
+                    continue;
+                }
+                if self.files_with_matches {
+                    writeln!(stdout, "{}", path.display())?;
+                    break;
+                }
+                let content = String::from_utf8_lossy(&line);
+                writeln!(
+                    stdout,
+                    "{}:{line_number}:{}",
+                    path.display(),
+                    content.trim_end_matches(['\n', '\r'])
+                )?;
+            }
+        }
+
+        if skipped > 0 {
+            info!(
+                "skipped {skipped} files larger than {}, use --max-filesize to search them.",
+                self.max_filesize
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+//! `index` subcommand
+
+/// App-local prelude includes `app_reader()`/`app_writer()`/`app_config()`
+/// accessors along with logging macros. Customize as you see fit.
+use crate::{commands::open_repository, status_err, Application, RUSTIC_APP};
+use abscissa_core::{Command, Runnable, Shutdown};
+
+use anyhow::Result;
+
+use rustic_core::CompactIndexOptions;
+
+/// `index` subcommand
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct IndexCmd {
+    #[clap(subcommand)]
+    cmd: IndexSubCmd,
+}
+
+#[derive(clap::Subcommand, Debug, Runnable)]
+enum IndexSubCmd {
+    /// Merge small index files into larger ones
+    Compact(CompactSubCmd),
+}
+
+#[derive(Default, Debug, clap::Parser, Command)]
+struct CompactSubCmd {
+    #[clap(flatten)]
+    opts: CompactIndexOptions,
+}
+
+impl Runnable for IndexCmd {
+    fn run(&self) {
+        self.cmd.run();
+    }
+}
+
+impl Runnable for CompactSubCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl CompactSubCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let repo = open_repository(&config)?;
+        _ = repo.compact_index(&self.opts, config.global.dry_run)?;
+        Ok(())
+    }
+}
@@ -5,11 +5,16 @@
 use abscissa_core::{status_err, Command, Runnable, Shutdown};
 use anyhow::{bail, Result};
 
-use crate::{Application, RUSTIC_APP};
+use crate::{commands::key::prompt_new_password, Application, RUSTIC_APP};
+
+use std::path::PathBuf;
 
 use dialoguer::Password;
 
-use rustic_core::{ConfigOptions, KeyOptions, Repository};
+use rustic_core::{
+    repofile::ConfigFile, Cipher, ConfigOptions, Id, KeyOptions, OpenStatus, Repository,
+    RepositoryOptions,
+};
 
 /// `init` subcommand
 #[derive(clap::Parser, Command, Debug)]
@@ -19,6 +24,19 @@ pub(crate) struct InitCmd {
 
     #[clap(flatten, next_help_heading = "Config options")]
     config_opts: ConfigOptions,
+
+    /// Use the same chunker parameters as the repository given by --from.
+    /// This allows to copy snapshots between both repositories without re-chunking and maximizes shared blobs.
+    #[clap(long, requires = "from")]
+    copy_chunker_params: bool,
+
+    /// Repository to copy the chunker parameters from
+    #[clap(long, value_name = "REPO", requires = "copy_chunker_params")]
+    from: Option<String>,
+
+    /// File to read the password of the repository given by --from from [default: prompt for the password]
+    #[clap(long, value_name = "FILE", requires = "from")]
+    from_password_file: Option<PathBuf>,
 }
 
 impl Runnable for InitCmd {
@@ -33,6 +51,8 @@ impl Runnable for InitCmd {
 impl InitCmd {
     fn inner_run(&self) -> Result<()> {
         let config = RUSTIC_APP.config();
+        let mut config_opts = self.config_opts;
+        apply_no_encryption(config.repository.no_encryption, &mut config_opts)?;
 
         let po = config.global.progress_options;
         let repo = Repository::new_with_progress(&config.repository, po)?;
@@ -42,31 +62,92 @@ impl InitCmd {
         if repo.config_id()?.is_some() {
             bail!("Config file already exists. Aborting.");
         }
-        init(repo, &self.key_opts, &self.config_opts)
+
+        if let Some(from) = &self.from {
+            let mut opts = RepositoryOptions::default().repository(from);
+            opts.password_file = self.from_password_file.clone();
+            let repo_from = Repository::new(&opts)?;
+            let pass = match repo_from.password()? {
+                Some(pass) => pass,
+                None => Password::new()
+                    .with_prompt(format!("enter password for repository {from}"))
+                    .allow_empty_password(true)
+                    .interact()?,
+            };
+            let poly = repo_from.open_with_password(&pass)?.config().poly()?;
+            return init_with_poly(repo, &self.key_opts, &config_opts, poly);
+        }
+
+        _ = init(repo, &self.key_opts, &config_opts)?;
+        Ok(())
     }
 }
 
+/// Initialize a new repository using the given chunker polynomial
+fn init_with_poly<P, S>(
+    repo: Repository<P, S>,
+    key_opts: &KeyOptions,
+    config_opts: &ConfigOptions,
+    poly: u64,
+) -> Result<()> {
+    let mut config = ConfigFile::new(2, Id::random(), poly);
+    config_opts.apply(&mut config)?;
+    let pass = new_password(&repo, key_opts, config_opts)?;
+    repo.init_with_config(&pass, key_opts, config)?
+        .save_password(&pass)?;
+    Ok(())
+}
+
+/// Initialize a new repository and return it opened
 pub(crate) fn init<P, S>(
     repo: Repository<P, S>,
     key_opts: &KeyOptions,
     config_opts: &ConfigOptions,
+) -> Result<Repository<P, OpenStatus>> {
+    let pass = new_password(&repo, key_opts, config_opts)?;
+    let repo = repo.init_with_password(&pass, key_opts, config_opts)?;
+    repo.save_password(&pass)?;
+
+    Ok(repo)
+}
+
+/// Use no cipher if `--no-encryption` is given
+///
+/// This creates an unencrypted repository which only contains hashes to detect corrupted data.
+pub(crate) fn apply_no_encryption(
+    no_encryption: bool,
+    config_opts: &mut ConfigOptions,
 ) -> Result<()> {
-    let pass = repo.password()?.unwrap_or_else(|| {
-        match Password::new()
-            .with_prompt("enter password for new key")
-            .allow_empty_password(true)
-            .with_confirmation("confirm password", "passwords do not match")
-            .interact()
+    if no_encryption {
+        if config_opts
+            .set_cipher
+            .map_or(false, |cipher| cipher != Cipher::None)
         {
+            bail!("--no-encryption can't be used together with --set-cipher.");
+        }
+        config_opts.set_cipher = Some(Cipher::None);
+    }
+    Ok(())
+}
+
+/// Get the password for the new key, prompting for it if it is not given
+///
+/// Keys wrapped by a KMS and unencrypted repositories don't need a password.
+fn new_password<P, S>(
+    repo: &Repository<P, S>,
+    key_opts: &KeyOptions,
+    config_opts: &ConfigOptions,
+) -> Result<String> {
+    if key_opts.kms.is_some() || config_opts.set_cipher == Some(Cipher::None) {
+        return Ok(String::new());
+    }
+    Ok(repo
+        .password()?
+        .unwrap_or_else(|| match prompt_new_password(key_opts) {
             Ok(it) => it,
             Err(err) => {
                 status_err!("{}", err);
                 RUSTIC_APP.shutdown(Shutdown::Crash);
             }
-        }
-    });
-
-    let _ = repo.init_with_password(&pass, key_opts, config_opts)?;
-
-    Ok(())
+        }))
 }
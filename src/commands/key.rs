@@ -2,16 +2,32 @@
 
 /// App-local prelude includes `app_reader()`/`app_writer()`/`app_config()`
 /// accessors along with logging macros. Customize as you see fit.
-use crate::{commands::open_repository, status_err, Application, RUSTIC_APP};
+use crate::{
+    commands::open_repository, helpers::table_with_titles, status_err, Application, RUSTIC_APP,
+};
 
-use std::path::PathBuf;
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::PathBuf,
+};
 
 use abscissa_core::{Command, Runnable, Shutdown};
-use anyhow::Result;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local};
 use dialoguer::Password;
-use log::info;
+use log::{info, warn};
+use serde::Serialize;
 
-use rustic_core::{KeyOptions, Repository, RepositoryOptions};
+use rustic_core::{
+    repofile::{KeyDerivation, KeyRestriction, KeyShare},
+    Id, KeyOptions, PasswordStrength, Repository, RepositoryOptions, WrappedKey,
+};
+
+mod constants {
+    /// Passwords with a score below this are considered weak, even if no minimum score is required
+    pub(super) const WEAK_SCORE: u8 = 3;
+}
 
 /// `key` subcommand
 #[derive(clap::Parser, Command, Debug)]
@@ -24,6 +40,24 @@ pub(super) struct KeyCmd {
 enum KeySubCmd {
     /// Add a new key to the repository
     Add(AddCmd),
+
+    /// Replace the key used to open the repository by a key with a new password
+    Rotate(RotateCmd),
+
+    /// List all keys of the repository
+    List(ListCmd),
+
+    /// Split the master key into shares, such that a threshold of them is needed to open the repository
+    Split(SplitCmd),
+
+    /// Remove the key cached by using --key-cache-ttl
+    Lock(LockCmd),
+
+    /// Export a key file, e.g. to back it up out-of-band
+    Export(ExportCmd),
+
+    /// Import a key file, e.g. exported from another repository using the same master key
+    Import(ImportCmd),
 }
 
 #[derive(clap::Parser, Debug)]
@@ -36,6 +70,66 @@ pub(crate) struct AddCmd {
     pub(crate) key_opts: KeyOptions,
 }
 
+#[derive(clap::Parser, Debug)]
+pub(crate) struct RotateCmd {
+    /// File from which to read the new password
+    #[clap(long)]
+    pub(crate) new_password_file: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub(crate) key_opts: KeyOptions,
+}
+
+#[derive(clap::Parser, Debug)]
+pub(crate) struct SplitCmd {
+    /// Number of shares needed to open the repository
+    #[clap(long, value_name = "K")]
+    threshold: u8,
+
+    /// Number of shares to create, each protected by its own password
+    #[clap(long, value_name = "N")]
+    shares: u8,
+
+    /// Remove all keys containing the whole master key, such that the repository can only be opened using shares
+    #[clap(long)]
+    remove_keys: bool,
+
+    #[clap(flatten)]
+    key_opts: KeyOptions,
+}
+
+#[derive(clap::Parser, Debug)]
+pub(crate) struct LockCmd {}
+
+#[derive(clap::Parser, Debug)]
+pub(crate) struct ExportCmd {
+    /// Id of the key file to export
+    #[clap(value_name = "ID")]
+    id: String,
+
+    /// File to write the key file to [default: stdout]
+    #[clap(long, short, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+#[derive(clap::Parser, Debug)]
+pub(crate) struct ImportCmd {
+    /// Key file to import, use "-" to read from stdin
+    #[clap(value_name = "FILE")]
+    file: PathBuf,
+
+    /// File from which to read the password of the imported key file
+    #[clap(long)]
+    key_password_file: Option<PathBuf>,
+}
+
+#[derive(clap::Parser, Debug)]
+pub(crate) struct ListCmd {
+    /// Show keys in json format
+    #[clap(long)]
+    json: bool,
+}
+
 impl Runnable for KeyCmd {
     fn run(&self) {
         self.cmd.run();
@@ -57,29 +151,368 @@ impl AddCmd {
 
         let repo = open_repository(&config)?;
 
-        // create new "artificial" repo using the given password options
-        let repo_opts = RepositoryOptions {
-            password_file: self.new_password_file.clone(),
-            repository: Some(String::new()), // fake repository to make Repository::new() not bail
-            ..Default::default()
+        // keys wrapped by a KMS don't need a password
+        let pass = match self.key_opts.kms {
+            Some(_) => String::new(),
+            None => new_password(self.new_password_file.as_ref(), &self.key_opts)?,
         };
-        let repo_newpass = Repository::new(&repo_opts)?;
-
-        let pass = repo_newpass
-            .password()
-            .map_err(|err| err.into())
-            .transpose()
-            .unwrap_or_else(|| -> Result<_> {
-                Ok(Password::new()
-                    .with_prompt("enter password for new key")
-                    .allow_empty_password(true)
-                    .with_confirmation("confirm password", "passwords do not match")
-                    .interact()?)
-            })?;
-
         let id = repo.add_key(&pass, &self.key_opts)?;
         info!("key {id} successfully added.");
 
         Ok(())
     }
 }
+
+impl Runnable for RotateCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl RotateCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+
+        // the current password is needed to identify the key to replace
+        let repo =
+            Repository::new_with_progress(&config.repository, config.global.progress_options)?;
+        let old_pass = match repo.password()? {
+            Some(pass) => pass,
+            None => Password::new()
+                .with_prompt("enter current repository password")
+                .allow_empty_password(true)
+                .interact()?,
+        };
+        let repo = repo.open_with_password(&old_pass)?;
+
+        let pass = new_password(self.new_password_file.as_ref(), &self.key_opts)?;
+        let (old_id, new_id) = repo.rotate_key(&old_pass, &pass, &self.key_opts)?;
+        repo.save_password(&pass)?;
+        info!("key {old_id} successfully replaced by key {new_id}.");
+
+        Ok(())
+    }
+}
+
+impl Runnable for SplitCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl SplitCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+
+        if self.threshold < 2 || self.threshold > self.shares {
+            bail!("the threshold must be at least 2 and not larger than the number of shares.");
+        }
+
+        let repo = open_repository(&config)?;
+
+        let passwords = (1..=self.shares)
+            .map(|i| {
+                Password::new()
+                    .with_prompt(format!("enter password for share {i}"))
+                    .with_confirmation("confirm password", "passwords do not match")
+                    .interact()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let ids = repo.split_key(&passwords, self.threshold, self.remove_keys, &self.key_opts)?;
+        for id in ids {
+            info!("share key {id} successfully added.");
+        }
+        if self.remove_keys {
+            info!(
+                "removed all other keys, {} of the shares are now needed to open the repository.",
+                self.threshold
+            );
+        } else {
+            warn!(
+                "other keys still contain the whole master key, use --remove-keys to remove them."
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl Runnable for ListCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+/// Public information about a key
+#[serde_with::apply(Option => #[serde(default, skip_serializing_if = "Option::is_none")])]
+#[derive(Serialize)]
+struct KeyInfo {
+    id: Id,
+    hostname: Option<String>,
+    username: Option<String>,
+    created: Option<DateTime<Local>>,
+    label: Option<String>,
+    kdf: KeyDerivation,
+    share: Option<KeyShare>,
+    wrapped: Option<WrappedKey>,
+    restriction: Option<KeyRestriction>,
+}
+
+impl ListCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+
+        // listing keys only needs the public key information, so don't ask for a password
+        let repo =
+            Repository::new_with_progress(&config.repository, config.global.progress_options)?;
+        let mut keys: Vec<_> = repo
+            .get_keys()?
+            .into_iter()
+            .map(|(id, key)| KeyInfo {
+                id,
+                hostname: key.hostname,
+                username: key.username,
+                created: key.created,
+                label: key.label,
+                kdf: key.kdf,
+                share: key.share,
+                wrapped: key.wrapped,
+                restriction: key.restriction,
+            })
+            .collect();
+        keys.sort_by_key(|key| key.created);
+
+        if self.json {
+            let mut stdout = std::io::stdout();
+            serde_json::to_writer_pretty(&mut stdout, &keys)?;
+            return Ok(());
+        }
+
+        let mut table = table_with_titles([
+            "ID",
+            "Host",
+            "User",
+            "Created",
+            "Label",
+            "KDF",
+            "Share",
+            "Restriction",
+        ]);
+        for key in keys {
+            // keys wrapped by a KMS don't use a key derivation function
+            let kdf = match (&key.wrapped, key.kdf) {
+                (Some(wrapped), _) => wrapped.kms.as_str(),
+                (None, KeyDerivation::Scrypt) => "scrypt",
+                (None, KeyDerivation::Argon2id) => "argon2id",
+            };
+            _ = table.add_row([
+                config.global.format_id(&key.id),
+                key.hostname.unwrap_or_default(),
+                key.username.unwrap_or_default(),
+                key.created
+                    .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+                    .unwrap_or_default(),
+                key.label.unwrap_or_default(),
+                kdf.to_string(),
+                key.share
+                    .map(|share| format!("{} of {}", share.index, share.threshold))
+                    .unwrap_or_default(),
+                key.restriction
+                    .map(|restriction| restriction.to_string())
+                    .unwrap_or_default(),
+            ]);
+        }
+        println!("{table}");
+
+        Ok(())
+    }
+}
+
+impl Runnable for LockCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl LockCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+
+        // removing the cached key doesn't need to access the repository
+        let repo =
+            Repository::new_with_progress(&config.repository, config.global.progress_options)?;
+        if repo.remove_cached_key()? {
+            info!("removed cached key.");
+        } else {
+            info!("no cached key found.");
+        }
+
+        Ok(())
+    }
+}
+
+impl Runnable for ExportCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl ExportCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+
+        // key files are exported as-is, so no password is needed
+        let repo =
+            Repository::new_with_progress(&config.repository, config.global.progress_options)?;
+        let (id, data) = repo.export_key(&self.id)?;
+        match &self.output {
+            Some(file) => {
+                fs::write(file, &data)?;
+                info!("key {id} successfully exported to {file:?}.");
+            }
+            None => io::stdout().write_all(&data)?,
+        }
+
+        Ok(())
+    }
+}
+
+impl Runnable for ImportCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl ImportCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+
+        let data = if self.file.as_os_str() == "-" {
+            let mut data = Vec::new();
+            _ = io::stdin().read_to_end(&mut data)?;
+            data
+        } else {
+            fs::read(&self.file)?
+        };
+
+        let repo = open_repository(&config)?;
+        // the imported key file is validated by decrypting it with its own password
+        let pass = match &self.key_password_file {
+            Some(file) => fs::read_to_string(file)?
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            None => Password::new()
+                .with_prompt("enter password of the imported key")
+                .allow_empty_password(true)
+                .interact()?,
+        };
+
+        if config.global.dry_run {
+            println!("would have imported key from {:?}", self.file);
+            return Ok(());
+        }
+
+        let id = repo.import_key(&data, &pass)?;
+        info!("key {id} successfully imported.");
+
+        Ok(())
+    }
+}
+
+/// Get the password for a new key from the given file or prompt for it
+///
+/// # Arguments
+///
+/// * `file` - The file to read the password from
+/// * `key_opts` - The options of the new key, used to check the strength of a prompted password
+pub(crate) fn new_password(file: Option<&PathBuf>, key_opts: &KeyOptions) -> Result<String> {
+    // create new "artificial" repo using the given password options
+    let repo_opts = RepositoryOptions {
+        password_file: file.cloned(),
+        repository: Some(String::new()), // fake repository to make Repository::new() not bail
+        ..Default::default()
+    };
+    let repo_newpass = Repository::new(&repo_opts)?;
+
+    repo_newpass
+        .password()
+        .map_err(|err| err.into())
+        .transpose()
+        .unwrap_or_else(|| prompt_new_password(key_opts))
+}
+
+/// Prompt for the password of a new key.
+///
+/// Passwords below the minimum score of the key options are refused and prompted again.
+/// If no minimum score is given, weak passwords are accepted with a warning.
+///
+/// # Arguments
+///
+/// * `key_opts` - The options of the new key
+pub(crate) fn prompt_new_password(key_opts: &KeyOptions) -> Result<String> {
+    loop {
+        let pass = Password::new()
+            .with_prompt("enter password for new key")
+            .allow_empty_password(true)
+            .with_confirmation("confirm password", "passwords do not match")
+            .interact()?;
+        let Some(strength) = key_opts.password_strength(&pass) else {
+            // without estimating the strength, only a minimum score is an error
+            key_opts.check_password(&pass)?;
+            return Ok(pass);
+        };
+        match key_opts.min_password_score {
+            Some(min) if strength.score < min => {
+                warn_weak_password(&strength);
+                warn!("a score of at least {min} is required, please enter another password.");
+            }
+            _ => {
+                if strength.score < constants::WEAK_SCORE {
+                    warn_weak_password(&strength);
+                }
+                return Ok(pass);
+            }
+        }
+    }
+}
+
+/// Warn about a weak password and show how to improve it.
+///
+/// # Arguments
+///
+/// * `strength` - The estimated strength of the password
+fn warn_weak_password(strength: &PasswordStrength) {
+    warn!(
+        "the password is weak (score {} of 4).{}",
+        strength.score,
+        strength
+            .warning
+            .as_ref()
+            .map(|warning| format!(" {warning}"))
+            .unwrap_or_default()
+    );
+    for suggestion in &strength.suggestions {
+        info!("{suggestion}");
+    }
+}
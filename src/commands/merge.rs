@@ -58,7 +58,10 @@ impl MergeCmd {
             let mut stdout = std::io::stdout();
             serde_json::to_writer_pretty(&mut stdout, &snap)?;
         }
-        info!("saved new snapshot as {}.", snap.id);
+        info!(
+            "saved new snapshot as {}.",
+            config.global.format_id(&snap.id)
+        );
 
         if self.delete {
             let now = Local::now();
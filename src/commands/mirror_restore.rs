@@ -0,0 +1,146 @@
+//! `mirror-restore` subcommand
+
+/// App-local prelude includes `app_reader()`/`app_writer()`/`app_config()`
+/// accessors along with logging macros. Customize as you see fit.
+use crate::{
+    commands::{control::listen, open_repository},
+    status_err, Application, RUSTIC_APP,
+};
+
+use std::{path::PathBuf, thread::sleep};
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::Result;
+use log::{error, info};
+
+use rustic_core::{
+    repofile::SnapshotFile, Id, LocalDestination, LsOptions, OpenStatus, Repository, RestoreOptions,
+};
+
+use crate::config::progress_options::ProgressOptions;
+
+/// `mirror-restore` subcommand
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct MirrorRestoreCmd {
+    /// Directory to keep synchronized with the latest snapshot
+    #[clap(long, value_name = "DIR")]
+    target: String,
+
+    /// Synchronize repeatedly with this interval (e.g. 1h). If not given, synchronize once
+    #[clap(long, value_name = "DURATION")]
+    interval: Option<humantime::Duration>,
+
+    /// Path within the snapshot to restore
+    #[clap(long, value_name = "PATH", default_value = "")]
+    path: String,
+
+    /// Use numeric ids instead of user/group when restoring uid/gui
+    #[clap(long)]
+    numeric_id: bool,
+
+    /// Don't restore ownership (user/group)
+    #[clap(long, conflicts_with = "numeric_id")]
+    no_ownership: bool,
+
+    /// Listen on this socket to change the log level using `rustic control log-level`
+    #[clap(long, value_name = "SOCKET", env = "RUSTIC_CONTROL_SOCKET")]
+    control_socket: Option<PathBuf>,
+}
+
+impl Runnable for MirrorRestoreCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl MirrorRestoreCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let repo = open_repository(&config)?;
+
+        let Some(interval) = self.interval else {
+            _ = self.sync(&repo, None)?;
+            return Ok(());
+        };
+
+        let _listener = self
+            .control_socket
+            .as_ref()
+            .map(|path| listen(path, None))
+            .transpose()?;
+        let mut last = None;
+        loop {
+            // errors are reported but don't stop the synchronization, e.g. if the repository is temporarily not accessible
+            match self.sync(&repo, last) {
+                Ok(id) => last = Some(id),
+                Err(err) => error!("synchronizing {} failed: {err}", self.target),
+            }
+            info!("next synchronization in {interval}.");
+            sleep(*interval);
+        }
+    }
+
+    /// Synchronize the target with the latest snapshot, unless it already has been restored.
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The repository to restore from
+    /// * `last` - The snapshot which has been restored by the last synchronization
+    ///
+    /// # Returns
+    ///
+    /// The id of the restored snapshot
+    fn sync(&self, repo: &Repository<ProgressOptions, OpenStatus>, last: Option<Id>) -> Result<Id> {
+        let config = RUSTIC_APP.config();
+        let snap = repo.get_snapshot_from_str("latest", |sn| config.snapshot_filter.matches(sn))?;
+        if last == Some(snap.id) {
+            info!("{} is up to date with snapshot {}.", self.target, snap.id);
+            return Ok(snap.id);
+        }
+        info!("synchronizing {} with snapshot {}...", self.target, snap.id);
+        self.restore(repo, &snap, config.global.dry_run)?;
+        Ok(snap.id)
+    }
+
+    /// Restore the snapshot to the target, removing all other files and directories.
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The repository to restore from
+    /// * `snap` - The snapshot to restore
+    /// * `dry_run` - Only show what would be done
+    fn restore(
+        &self,
+        repo: &Repository<ProgressOptions, OpenStatus>,
+        snap: &SnapshotFile,
+        dry_run: bool,
+    ) -> Result<()> {
+        // the index is read for each synchronization, as new snapshots come with new packs
+        let repo = repo.clone().to_indexed()?;
+        let node = repo.node_from_snapshot_and_path(snap, &self.path)?;
+        let ls = repo.ls(&node, &LsOptions::default().recursive(true))?;
+
+        let opts = RestoreOptions::default()
+            .delete(true)
+            .numeric_id(self.numeric_id)
+            .no_ownership(self.no_ownership);
+        let dest = LocalDestination::new(&self.target, true, !node.is_dir())?;
+        let restore_infos = repo.prepare_restore(&opts, ls.clone(), &dest, dry_run)?;
+
+        let (files, dirs) = (restore_infos.stats.files, restore_infos.stats.dirs);
+        info!(
+            "files: {} to restore, {} unchanged, {} to modify, {} to remove; dirs: {} to restore, {} to remove",
+            files.restore, files.unchanged, files.modify, files.additional, dirs.restore, dirs.additional
+        );
+        if dry_run {
+            repo.warm_up(restore_infos.to_packs().into_iter())?;
+        } else {
+            repo.restore(restore_infos, &opts, ls, &dest)?;
+            info!("{} synchronized with snapshot {}.", self.target, snap.id);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+//! `note` subcommand
+
+/// App-local prelude includes `app_reader()`/`app_writer()`/`app_config()`
+/// accessors along with logging macros. Customize as you see fit.
+use crate::{
+    commands::open_repository, helpers::table_with_titles, status_err, Application, RUSTIC_APP,
+};
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::Result;
+use log::info;
+use serde::Serialize;
+
+use rustic_core::{
+    repofile::{FileType, NoteFile},
+    Id,
+};
+
+/// `note` subcommand
+#[derive(clap::Parser, Command, Debug)]
+pub(super) struct NoteCmd {
+    #[clap(subcommand)]
+    cmd: NoteSubCmd,
+}
+
+#[derive(clap::Subcommand, Debug, Runnable)]
+enum NoteSubCmd {
+    /// Attach a note to a snapshot, pack or key
+    Add(AddCmd),
+
+    /// List all notes
+    List(ListCmd),
+}
+
+/// Types of repository files notes can be attached to
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+enum NoteTarget {
+    /// Snapshot
+    Snapshot,
+    /// Pack
+    Pack,
+    /// Key
+    Key,
+}
+
+impl From<NoteTarget> for FileType {
+    fn from(target: NoteTarget) -> Self {
+        match target {
+            NoteTarget::Snapshot => Self::Snapshot,
+            NoteTarget::Pack => Self::Pack,
+            NoteTarget::Key => Self::Key,
+        }
+    }
+}
+
+#[derive(clap::Parser, Debug)]
+pub(crate) struct AddCmd {
+    /// Id of the snapshot, pack or key to attach the note to
+    #[clap(value_name = "ID")]
+    target: String,
+
+    /// Text of the note
+    #[clap(value_name = "TEXT")]
+    text: String,
+
+    /// Type of the file to attach the note to [default: search snapshots, keys and packs]
+    #[clap(long = "type", value_name = "TYPE")]
+    tpe: Option<NoteTarget>,
+}
+
+#[derive(clap::Parser, Debug)]
+pub(crate) struct ListCmd {
+    /// Only list notes attached to this id
+    #[clap(value_name = "ID")]
+    target: Option<String>,
+
+    /// Show notes in json format
+    #[clap(long)]
+    json: bool,
+}
+
+impl Runnable for NoteCmd {
+    fn run(&self) {
+        self.cmd.run();
+    }
+}
+
+impl Runnable for AddCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl AddCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let repo = open_repository(&config)?;
+
+        if config.global.dry_run {
+            println!("would have added note to {}: {}", self.target, self.text);
+            return Ok(());
+        }
+
+        let id = repo.add_note(self.tpe.map(FileType::from), &self.target, &self.text)?;
+        info!("note {id} successfully added.");
+
+        Ok(())
+    }
+}
+
+impl Runnable for ListCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+/// A note together with its id
+#[derive(Serialize)]
+struct NoteInfo {
+    id: Id,
+    #[serde(flatten)]
+    note: NoteFile,
+}
+
+impl ListCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let repo = open_repository(&config)?;
+
+        let notes: Vec<_> = repo
+            .get_notes()?
+            .into_iter()
+            .filter(|(_, note)| {
+                self.target.as_ref().map_or(true, |target| {
+                    note.target.to_hex().starts_with(target.as_str())
+                })
+            })
+            .map(|(id, note)| NoteInfo { id, note })
+            .collect();
+
+        if self.json {
+            let mut stdout = std::io::stdout();
+            serde_json::to_writer_pretty(&mut stdout, &notes)?;
+            return Ok(());
+        }
+
+        let mut table = table_with_titles(["ID", "Time", "Host", "Type", "Target", "Text"]);
+        for NoteInfo { id, note } in notes {
+            _ = table.add_row([
+                config.global.format_id(&id),
+                note.time.format("%Y-%m-%d %H:%M:%S").to_string(),
+                note.hostname.unwrap_or_default(),
+                format!("{:?}", note.tpe).to_lowercase(),
+                config.global.format_id(&note.target),
+                note.text,
+            ]);
+        }
+        println!("{table}");
+
+        Ok(())
+    }
+}
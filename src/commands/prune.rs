@@ -3,7 +3,8 @@
 /// App-local prelude includes `app_reader()`/`app_writer()`/`app_config()`
 /// accessors along with logging macros. Customize as you see fit.
 use crate::{
-    commands::open_repository, helpers::bytes_size_to_string, status_err, Application, RUSTIC_APP,
+    commands::open_repository, helpers::bytes_size_to_string, keyboard, status_err, Application,
+    RUSTIC_APP,
 };
 use abscissa_core::{Command, Runnable, Shutdown};
 use log::debug;
@@ -34,6 +35,11 @@ impl PruneCmd {
         let config = RUSTIC_APP.config();
         let repo = open_repository(&config)?;
 
+        if !config.global.dry_run {
+            repo.complete_interrupted_removals()?;
+        }
+
+        let _keyboard = keyboard::listen("prune", None);
         let pruner = repo.prune_plan(&self.opts)?;
 
         print_stats(&pruner.stats);
@@ -134,6 +140,13 @@ fn print_stats(stats: &PruneStats) {
         bytes_size_to_string(stats.size_to_delete.recover),
     );
 
+    if stats.trash_snapshots + stats.trash_expired > 0 {
+        println!(
+            "snapshots in trash:        {:>10} kept, {:>10} expired to remove",
+            stats.trash_snapshots, stats.trash_expired
+        );
+    }
+
     debug!(
         "index files to rebuild: {} / {}",
         stats.index_files_rebuild, stats.index_files
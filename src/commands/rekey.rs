@@ -0,0 +1,72 @@
+//! `rekey` subcommand
+
+/// App-local prelude includes `app_reader()`/`app_writer()`/`app_config()`
+/// accessors along with logging macros. Customize as you see fit.
+use crate::{
+    commands::{key::new_password, open_repository},
+    status_err, Application, RUSTIC_APP,
+};
+
+use std::path::PathBuf;
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::{bail, Result};
+use log::{info, warn};
+
+use rustic_core::KeyOptions;
+
+/// `rekey` subcommand
+///
+/// Generates a new master key, e.g. after a suspected key compromise.
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct RekeyCmd {
+    /// Re-encrypt all data of the repository using the new master key. This rewrites the whole repository!
+    #[clap(long)]
+    all_data: bool,
+
+    /// File from which to read the password for the new key
+    #[clap(long)]
+    new_password_file: Option<PathBuf>,
+
+    #[clap(flatten)]
+    key_opts: KeyOptions,
+}
+
+impl Runnable for RekeyCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl RekeyCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+
+        // The master key also encrypts all pack files, so it can only be changed by rewriting all data.
+        if !self.all_data {
+            bail!("rekeying needs --all-data. To only change the password, use `key rotate`.");
+        }
+
+        // aborting while the data is re-encrypted would leave the repository without a usable key
+        if config.global.timeout.is_some() {
+            bail!("rekey can't be used with --timeout, as it must not be interrupted.");
+        }
+
+        let repo = open_repository(&config)?;
+        if config.global.dry_run {
+            info!("would re-encrypt all data with a new master key and remove all existing keys.");
+            return Ok(());
+        }
+
+        let pass = new_password(self.new_password_file.as_ref(), &self.key_opts)?;
+        warn!("all existing keys will be removed. Do not use the repository until rekeying is finished!");
+        let id = repo.rekey_all_data(&pass, &self.key_opts)?;
+        repo.save_password(&pass)?;
+        info!("repository successfully re-encrypted, the only valid key is now {id}.");
+
+        Ok(())
+    }
+}
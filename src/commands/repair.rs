@@ -5,9 +5,9 @@
 use crate::{commands::open_repository, status_err, Application, RUSTIC_APP};
 use abscissa_core::{Command, Runnable, Shutdown};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 
-use rustic_core::{RepairIndexOptions, RepairSnapshotsOptions};
+use rustic_core::{RepairIndexOptions, RepairSnapshotsOptions, Repository};
 
 /// `repair` subcommand
 #[derive(clap::Parser, Command, Debug)]
@@ -22,6 +22,8 @@ enum RepairSubCmd {
     Index(IndexSubCmd),
     /// Repair snapshots
     Snapshots(SnapSubCmd),
+    /// Synchronize the mirrors given by --repo-mirror with the repository
+    Mirrors(MirrorsSubCmd),
 }
 
 #[derive(Default, Debug, clap::Parser, Command)]
@@ -40,6 +42,9 @@ struct SnapSubCmd {
     ids: Vec<String>,
 }
 
+#[derive(Default, Debug, clap::Parser, Command)]
+struct MirrorsSubCmd {}
+
 impl Runnable for RepairCmd {
     fn run(&self) {
         self.cmd.run();
@@ -86,3 +91,26 @@ impl SnapSubCmd {
         Ok(())
     }
 }
+
+impl Runnable for MirrorsSubCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl MirrorsSubCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        if config.repository.repo_mirror.is_empty() {
+            bail!("no mirrors given, use --repo-mirror to specify them.");
+        }
+        // files are copied as they are, so the repository doesn't need to be opened
+        let repo =
+            Repository::new_with_progress(&config.repository, config.global.progress_options)?;
+        _ = repo.repair_mirrors(config.global.dry_run)?;
+        Ok(())
+    }
+}
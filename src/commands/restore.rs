@@ -3,14 +3,27 @@
 /// App-local prelude includes `app_reader()`/`app_writer()`/`app_config()`
 /// accessors along with logging macros. Customize as you see fit.
 use crate::{
-    commands::open_repository, helpers::bytes_size_to_string, status_err, Application, RUSTIC_APP,
+    commands::open_repository,
+    helpers::{bytes_size_to_string, table_with_titles},
+    status_err, Application, RUSTIC_APP,
+};
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
 };
 
 use abscissa_core::{Command, Runnable, Shutdown};
-use anyhow::Result;
-use log::info;
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+use serde::Serialize;
 
-use rustic_core::{LocalDestination, LsOptions, RestoreOptions};
+use rustic_core::{
+    repofile::Node, LocalDestination, LsOptions, PathNormalizationOptions, PathTranslationOptions,
+    RestoreAction, RestoreCheck, RestoreEntry, RestoreOptions, RestorePlan, RestoreStats,
+    RusticResult,
+};
 
 use crate::filtering::SnapshotFilter;
 
@@ -32,6 +45,28 @@ pub(crate) struct RestoreCmd {
     #[clap(flatten)]
     ls_opts: LsOptions,
 
+    #[clap(flatten, next_help_heading = "Path normalization options")]
+    normalize_opts: PathNormalizationOptions,
+
+    #[clap(flatten, next_help_heading = "Path translation options")]
+    translate_opts: PathTranslationOptions,
+
+    /// Don't check free space, path lengths and permissions of the destination before restoring
+    #[clap(long)]
+    no_preflight: bool,
+
+    /// File containing paths (relative to the destination, one per line) to restore before all other contents
+    #[clap(long, value_name = "FILE")]
+    priority_file: Option<PathBuf>,
+
+    /// List every entry which would be created, overwritten, skipped or removed (only with --dry-run)
+    #[clap(long)]
+    verbose: bool,
+
+    /// Show the restore plan including every entry in json format (only with --dry-run)
+    #[clap(long)]
+    json: bool,
+
     #[clap(
         flatten,
         next_help_heading = "Snapshot filter options (when using latest)"
@@ -51,6 +86,9 @@ impl RestoreCmd {
     fn inner_run(&self) -> Result<()> {
         let config = RUSTIC_APP.config();
         let dry_run = config.global.dry_run;
+        if (self.verbose || self.json) && !dry_run {
+            bail!("--verbose and --json can only be used together with --dry-run.");
+        }
         let repo = open_repository(&config)?.to_indexed()?;
 
         let node =
@@ -60,21 +98,70 @@ impl RestoreCmd {
         let mut ls_opts = self.ls_opts.clone();
         ls_opts.recursive = true;
         let ls = repo.ls(&node, &ls_opts)?;
+        let nodes = if self.normalize_opts.is_active() || self.translate_opts.is_active() {
+            Some(normalize_nodes(
+                ls.clone(),
+                &self.normalize_opts,
+                &self.translate_opts,
+            )?)
+        } else {
+            None
+        };
+        let node_streamer = || -> Box<dyn Iterator<Item = RusticResult<(PathBuf, Node)>>> {
+            match &nodes {
+                Some(nodes) => Box::new(nodes.clone().into_iter().map(Ok)),
+                None => Box::new(ls.clone()),
+            }
+        };
 
         let dest = LocalDestination::new(&self.dest, true, !node.is_dir())?;
 
-        let restore_infos = repo.prepare_restore(&self.opts, ls.clone(), &dest, dry_run)?;
+        let check = if self.no_preflight {
+            None
+        } else {
+            let check = repo.check_restore(&self.opts, node_streamer(), &dest)?;
+            report_check(&check);
+            // only problems which make the restore fail midway abort it; others are warnings
+            if !check.limits.writable && !dry_run {
+                bail!("destination is not writable, aborting restore. Use --no-preflight to restore anyway.");
+            }
+            if !check.has_space_for_all() && !dry_run {
+                // existing contents may be used, so the needed space is determined without
+                // modifying the destination before anything is created
+                let plan = repo.prepare_restore(&self.opts, node_streamer(), &dest, true)?;
+                if let Some(msg) = space_error(&check, &plan) {
+                    bail!("{msg} Use --no-preflight to restore anyway.");
+                }
+            }
+            Some(check)
+        };
 
-        let fs = restore_infos.stats.files;
-        println!(
-            "Files:  {} to restore, {} unchanged, {} verified, {} to modify, {} additional",
-            fs.restore, fs.unchanged, fs.verified, fs.modify, fs.additional
-        );
-        let ds = restore_infos.stats.dirs;
-        println!(
-            "Dirs:   {} to restore, {} to modify, {} additional",
-            ds.restore, ds.modify, ds.additional
-        );
+        let mut restore_infos =
+            repo.prepare_restore(&self.opts, node_streamer(), &dest, dry_run)?;
+
+        if let Some(file) = &self.priority_file {
+            let paths = read_priority_file(file)?;
+            let prioritized = restore_infos.prioritize(&paths);
+            info!("restoring contents of {prioritized} prioritized files first.");
+        }
+
+        if self.json {
+            print_plan_json(&restore_infos)?;
+        } else {
+            if self.verbose {
+                print_entries(&restore_infos.entries);
+            }
+            let fs = restore_infos.stats.files;
+            println!(
+                "Files:  {} to restore, {} unchanged, {} verified, {} to modify, {} additional",
+                fs.restore, fs.unchanged, fs.verified, fs.modify, fs.additional
+            );
+            let ds = restore_infos.stats.dirs;
+            println!(
+                "Dirs:   {} to restore, {} to modify, {} additional",
+                ds.restore, ds.modify, ds.additional
+            );
+        }
 
         info!(
             "total restore size: {}",
@@ -90,13 +177,180 @@ impl RestoreCmd {
             info!("all file contents are fine.");
         }
 
+        if let Some(msg) = check
+            .as_ref()
+            .and_then(|check| space_error(check, &restore_infos))
+        {
+            warn!("{msg}");
+        }
+
         if dry_run {
             repo.warm_up(restore_infos.to_packs().into_iter())?;
         } else {
-            repo.restore(restore_infos, &self.opts, ls, &dest)?;
+            repo.restore(restore_infos, &self.opts, node_streamer(), &dest)?;
             println!("restore done.");
         }
 
         Ok(())
     }
 }
+
+/// The restore plan as shown by `--json`
+#[derive(Serialize)]
+struct PlanInfo<'a> {
+    stats: RestoreStats,
+    restore_size: u64,
+    matched_size: u64,
+    entries: &'a [RestoreEntry],
+}
+
+/// Print the restore plan including all entries in json format.
+///
+/// # Arguments
+///
+/// * `plan` - The restore plan to print
+fn print_plan_json(plan: &RestorePlan) -> Result<()> {
+    let info = PlanInfo {
+        stats: plan.stats,
+        restore_size: plan.restore_size,
+        matched_size: plan.matched_size,
+        entries: &plan.entries,
+    };
+    let mut stdout = std::io::stdout();
+    serde_json::to_writer_pretty(&mut stdout, &info)?;
+    Ok(())
+}
+
+/// Print all entries of the restore plan as a table.
+///
+/// # Arguments
+///
+/// * `entries` - The entries to print
+fn print_entries(entries: &[RestoreEntry]) {
+    let mut table = table_with_titles(["Action", "Type", "Size", "Reason", "Path"]);
+    for entry in entries {
+        let action = match entry.action {
+            RestoreAction::Create => "create",
+            RestoreAction::Overwrite => "overwrite",
+            RestoreAction::Skip => "skip",
+            RestoreAction::Remove => "remove",
+        };
+        _ = table.add_row([
+            action.to_string(),
+            if entry.is_dir { "dir" } else { "file" }.to_string(),
+            bytes_size_to_string(entry.size),
+            entry.reason.to_string(),
+            entry.path.display().to_string(),
+        ]);
+    }
+    println!("{table}");
+}
+
+/// Read the paths to prioritize from a file.
+///
+/// Empty lines and lines starting with `#` are ignored.
+///
+/// # Arguments
+///
+/// * `file` - The file to read the paths from
+fn read_priority_file(file: &Path) -> Result<Vec<PathBuf>> {
+    let content = fs::read_to_string(file)
+        .with_context(|| format!("error reading priority file {file:?}"))?;
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// Print the problems found by the pre-flight checks.
+///
+/// # Arguments
+///
+/// * `check` - The result of the pre-flight checks
+fn report_check(check: &RestoreCheck) {
+    if !check.limits.writable {
+        warn!("destination is not writable.");
+    }
+    for path in &check.too_long {
+        warn!("{path:?}: name or path is too long for the destination filesystem.");
+    }
+    if check.ownership > 0 {
+        warn!(
+            "owner of {} entries cannot be restored without root privileges, use --no-ownership to not restore owners.",
+            check.ownership
+        );
+    }
+    if check.special > 0 {
+        warn!(
+            "{} device files cannot be created without root privileges.",
+            check.special
+        );
+    }
+}
+
+/// Check the free space of the destination against a restore plan.
+///
+/// # Arguments
+///
+/// * `check` - The result of the pre-flight checks
+/// * `plan` - The restore plan
+///
+/// # Returns
+///
+/// The error message if there is not enough free space
+fn space_error(check: &RestoreCheck, plan: &RestorePlan) -> Option<String> {
+    (!check.has_space_for(plan)).then(|| {
+        format!(
+            "not enough free space in destination: {} needed, {} available.",
+            bytes_size_to_string(plan.restore_size),
+            bytes_size_to_string(check.limits.free_space.unwrap_or_default())
+        )
+    })
+}
+
+/// Normalize and translate the paths of the nodes to restore.
+///
+/// The unicode normalization and path translation are applied to the restored paths. Entries
+/// which collide with a previous entry after full normalization (including case-folding) are
+/// skipped together with all their contents. The result is sorted by the restored paths.
+///
+/// # Arguments
+///
+/// * `node_streamer` - The nodes to restore
+/// * `opts` - The normalization options to use
+/// * `translate_opts` - The path translation options to use
+fn normalize_nodes(
+    node_streamer: impl Iterator<Item = RusticResult<(PathBuf, Node)>>,
+    opts: &PathNormalizationOptions,
+    translate_opts: &PathTranslationOptions,
+) -> Result<Vec<(PathBuf, Node)>> {
+    let mut seen = BTreeMap::new();
+    let mut skipped_dir: Option<PathBuf> = None;
+    let mut nodes = Vec::new();
+
+    for item in node_streamer {
+        let (path, node) = item?;
+        if matches!(&skipped_dir, Some(dir) if path.starts_with(dir)) {
+            continue;
+        }
+        let restore_path = opts
+            .normalize_unicode
+            .map_or_else(|| path.clone(), |form| form.normalize_path(&path));
+        let restore_path = translate_opts.translate_path(&restore_path);
+        let key = opts.normalize_path(&restore_path);
+        if let Some(other) = seen.get(&key) {
+            warn!("{path:?} collides with {other:?} after normalization, skipping it.");
+            if node.is_dir() {
+                skipped_dir = Some(path);
+            }
+            continue;
+        }
+        _ = seen.insert(key, path);
+        nodes.push((restore_path, node));
+    }
+
+    nodes.sort_by(|(path1, _), (path2, _)| path1.cmp(path2));
+    Ok(nodes)
+}
@@ -0,0 +1,142 @@
+//! `run` subcommand
+
+/// App-local prelude includes `app_reader()`/`app_writer()`/`app_config()`
+/// accessors along with logging macros. Customize as you see fit.
+use crate::{status_err, Application, RusticConfig, RUSTIC_APP};
+
+use std::process::Command as ProcessCommand;
+
+use abscissa_core::{config::Override, Command, FrameworkError, Runnable, Shutdown};
+use anyhow::{anyhow, bail, Result};
+use log::{error, info, warn};
+use merge::Merge;
+use serde::Deserialize;
+
+/// `run` subcommand
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct RunCmd {
+    /// Profile whose `[run]` section defines the steps to run
+    #[clap(value_name = "PROFILE")]
+    profile: String,
+}
+
+/// Steps run by the `run` command
+#[derive(Clone, Default, Debug, Deserialize, Merge)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct RunOptions {
+    /// The steps, run in the given order
+    #[merge(strategy = merge::vec::overwrite_empty)]
+    steps: Vec<RunStep>,
+}
+
+/// A single step of the `run` command
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct RunStep {
+    /// The rustic command to run, e.g. "backup"
+    command: String,
+
+    /// Additional command line arguments of the command
+    #[serde(default)]
+    args: Vec<String>,
+
+    /// What to do if the command fails
+    #[serde(default)]
+    on_failure: FailurePolicy,
+}
+
+/// Policy what to do if a step of the `run` command fails
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FailurePolicy {
+    /// Don't run the remaining steps
+    #[default]
+    Abort,
+    /// Run the remaining steps, but let the `run` command fail at the end
+    Continue,
+    /// Run the remaining steps and ignore the failure
+    Ignore,
+}
+
+impl Override<RusticConfig> for RunCmd {
+    // Use the given profile for all steps
+    fn override_config(&self, mut config: RusticConfig) -> Result<RusticConfig, FrameworkError> {
+        if !config.global.use_profile.contains(&self.profile) {
+            config.merge_profile(&self.profile)?;
+            config.global.use_profile.push(self.profile.clone());
+        }
+        Ok(config)
+    }
+}
+
+impl Runnable for RunCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl RunCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let steps = &config.run.steps;
+        if steps.is_empty() {
+            bail!("profile {} defines no steps to run.", self.profile);
+        }
+        if let Some(step) = steps.iter().find(|step| step.command == "run") {
+            bail!("step {} can't call the run command.", step.command);
+        }
+
+        // The steps are run as separate processes, so each command reads the config of the
+        // profiles as if called directly
+        let exe = std::env::current_exe()?;
+        let mut profile_args = Vec::new();
+        for profile in &config.global.use_profile {
+            profile_args.extend(["--use-profile".to_string(), profile.clone()]);
+        }
+        if config.global.dry_run {
+            profile_args.push("--dry-run".to_string());
+        }
+
+        let mut failed = Vec::new();
+        for (i, step) in steps.iter().enumerate() {
+            info!(
+                "step {}/{}: running {} {}",
+                i + 1,
+                steps.len(),
+                step.command,
+                step.args.join(" ")
+            );
+            let result = ProcessCommand::new(&exe)
+                .args(&profile_args)
+                .arg(&step.command)
+                .args(&step.args)
+                .status();
+            let err = match result {
+                Ok(status) if status.success() => continue,
+                Ok(status) => format!("{} failed: {status}", step.command),
+                Err(err) => format!("{} could not be started: {err}", step.command),
+            };
+            match step.on_failure {
+                FailurePolicy::Abort => {
+                    error!("{err}, skipping remaining steps.");
+                    failed.push(step.command.clone());
+                    break;
+                }
+                FailurePolicy::Continue => {
+                    error!("{err}, continuing with next step.");
+                    failed.push(step.command.clone());
+                }
+                FailurePolicy::Ignore => warn!("{err}, ignoring."),
+            }
+        }
+
+        if !failed.is_empty() {
+            return Err(anyhow!("failed steps: {}", failed.join(", ")));
+        }
+        info!("all steps of profile {} finished.", self.profile);
+        Ok(())
+    }
+}
@@ -0,0 +1,178 @@
+//! `selftest` subcommand
+
+/// App-local prelude includes `app_reader()`/`app_writer()`/`app_config()`
+/// accessors along with logging macros. Customize as you see fit.
+use crate::{commands::open_repository, status_err, Application, RUSTIC_APP};
+
+use std::{fs, path::Path};
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::{bail, Result};
+use log::{info, warn};
+use rand::{thread_rng, RngCore};
+use walkdir::WalkDir;
+
+use rustic_core::{
+    repofile::SnapshotFile, BackupOptions, LocalDestination, LsOptions, OpenStatus, PathList,
+    PruneOptions, Repository, RestoreOptions, SnapshotOptions,
+};
+
+use crate::config::progress_options::ProgressOptions;
+
+/// Name of the directory containing the test data within the snapshot
+const TEST_DIR: &str = "rustic-selftest";
+
+/// `selftest` subcommand
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct SelfTestCmd {
+    /// Prune the repository after forgetting the test snapshot. Note that this prunes the whole
+    /// repository, i.e. also removes unused data of other snapshots
+    #[clap(long)]
+    prune: bool,
+
+    #[clap(flatten, next_help_heading = "PRUNE OPTIONS (only with --prune)")]
+    prune_opts: PruneOptions,
+}
+
+impl Runnable for SelfTestCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl SelfTestCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        if config.global.dry_run {
+            bail!("selftest needs to write to the repository and can't be run with --dry-run.");
+        }
+        let repo = open_repository(&config)?;
+
+        let source = tempfile::tempdir()?;
+        let target = tempfile::tempdir()?;
+        let source_dir = source.path().join(TEST_DIR);
+        info!("writing test data to {}...", source_dir.display());
+        write_test_data(&source_dir)?;
+
+        info!("backing up test data...");
+        let backup_opts = BackupOptions::default().as_path(Path::new("/").join(TEST_DIR));
+        let snap_opts = SnapshotOptions::default()
+            .label(TEST_DIR.to_string())
+            .description("test snapshot created by `rustic selftest`".to_string());
+        let paths = PathList::from_strings([source_dir.to_string_lossy()]).sanitize()?;
+        let snap =
+            repo.clone()
+                .to_indexed_ids()?
+                .backup(&backup_opts, paths, snap_opts.to_snapshot()?)?;
+        info!("created snapshot {}.", snap.id);
+
+        // the test snapshot is removed, even if the restore or the verification failed
+        let result = restore_and_verify(&repo, &snap, &source_dir, target.path());
+
+        info!("forgetting test snapshot {}...", snap.id);
+        repo.delete_snapshots(&[snap.id])?;
+        if self.prune {
+            info!("pruning repository...");
+            let pruner = repo.prune_plan(&self.prune_opts)?;
+            pruner.do_prune(&repo, &self.prune_opts)?;
+        } else {
+            warn!("not pruning the repository, the test data remains until the next prune run.");
+        }
+
+        result?;
+        if self.prune {
+            println!("selftest successful: backup, restore, forget and prune work.");
+        } else {
+            println!("selftest successful: backup, restore and forget work.");
+        }
+        Ok(())
+    }
+}
+
+/// Restore the test snapshot and verify the restored files
+///
+/// # Arguments
+///
+/// * `repo` - The repository containing the test snapshot
+/// * `snap` - The test snapshot
+/// * `source_dir` - The directory containing the test data
+/// * `target` - The directory to restore to
+fn restore_and_verify(
+    repo: &Repository<ProgressOptions, OpenStatus>,
+    snap: &SnapshotFile,
+    source_dir: &Path,
+    target: &Path,
+) -> Result<()> {
+    info!("restoring test snapshot to {}...", target.display());
+    // the index must be read again, as it now contains the new packs
+    let repo = repo.clone().to_indexed()?;
+    let node = repo.node_from_snapshot_and_path(snap, "")?;
+    let ls = repo.ls(&node, &LsOptions::default().recursive(true))?;
+    let opts = RestoreOptions::default();
+    let dest = LocalDestination::new(&target.to_string_lossy(), true, false)?;
+    let restore_infos = repo.prepare_restore(&opts, ls.clone(), &dest, false)?;
+    repo.restore(restore_infos, &opts, ls, &dest)?;
+
+    info!("verifying restored files...");
+    verify(source_dir, &target.join(TEST_DIR))
+}
+
+/// Write a small synthetic dataset containing empty, small, large, duplicate and nested files
+///
+/// # Arguments
+///
+/// * `dir` - The directory to write to, which is created
+fn write_test_data(dir: &Path) -> Result<()> {
+    let sub_dir = dir.join("dir").join("subdir");
+    fs::create_dir_all(&sub_dir)?;
+    fs::create_dir_all(dir.join("empty-dir"))?;
+
+    fs::write(dir.join("empty"), b"")?;
+    fs::write(
+        dir.join("small.txt"),
+        "This file has been written by `rustic selftest`.\n",
+    )?;
+    // random data is not compressible and results in several chunks
+    let mut random = vec![0; 3 * 1024 * 1024];
+    thread_rng().fill_bytes(&mut random);
+    fs::write(dir.join("dir").join("random.bin"), &random)?;
+    fs::write(sub_dir.join("duplicate.bin"), &random)?;
+    fs::write(sub_dir.join("zeros.bin"), vec![0_u8; 1024 * 1024])?;
+    Ok(())
+}
+
+/// Verify that the restored directory has the same structure and contents as the source directory
+///
+/// # Arguments
+///
+/// * `source` - The directory containing the test data
+/// * `restored` - The directory the test data has been restored to
+fn verify(source: &Path, restored: &Path) -> Result<()> {
+    let entries = |dir: &Path| -> Result<Vec<_>> {
+        WalkDir::new(dir)
+            .sort_by_file_name()
+            .into_iter()
+            .map(|entry| -> Result<_> {
+                let entry = entry?;
+                Ok((
+                    entry.path().strip_prefix(dir)?.to_path_buf(),
+                    entry.file_type().is_dir(),
+                ))
+            })
+            .collect()
+    };
+    let source_entries = entries(source)?;
+    let restored_entries = entries(restored)?;
+    if source_entries != restored_entries {
+        bail!("restored files differ: expected {source_entries:?}, restored {restored_entries:?}");
+    }
+    for (path, is_dir) in source_entries {
+        if !is_dir && fs::read(source.join(&path))? != fs::read(restored.join(&path))? {
+            bail!("contents of restored file {} differ", path.display());
+        }
+    }
+    Ok(())
+}
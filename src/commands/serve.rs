@@ -0,0 +1,552 @@
+//! `serve` subcommand
+
+/// App-local prelude includes `app_reader()`/`app_writer()`/`app_config()`
+/// accessors along with logging macros. Customize as you see fit.
+use crate::{commands::control::listen, status_err, Application, RUSTIC_APP};
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use log::{debug, info, warn};
+use serde::Serialize;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use tiny_http::{Header, Method, Request, Response, ResponseBox, Server, SslConfig};
+
+mod constants {
+    /// The directories of the file types within a repository. Besides the file types of restic, this
+    /// includes `trash` and `journal` used by rustic, which other REST servers don't support.
+    pub(super) const TYPES: [&str; 7] = [
+        "data",
+        "index",
+        "keys",
+        "locks",
+        "snapshots",
+        "trash",
+        "journal",
+    ];
+    /// The media type of REST API v2 listings, which contain the file sizes
+    pub(super) const API_V2: &str = "application/vnd.x.restic.rest.v2";
+    /// The media type of REST API v1 listings
+    pub(super) const API_V1: &str = "application/vnd.x.restic.rest.v1";
+}
+
+/// `serve` subcommand
+///
+/// Serves local repositories using the REST protocol of restic's rest-server, so they can be
+/// accessed by rustic or restic using a `rest:` repository.
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct ServeCmd {
+    /// Directory containing the repositories to serve
+    #[clap(long, value_name = "DIR")]
+    path: PathBuf,
+
+    /// Address to listen on
+    #[clap(long, value_name = "ADDR", default_value = "localhost:8000")]
+    listen: String,
+
+    /// PEM file containing the TLS certificate (chain); enables TLS
+    #[clap(long, value_name = "FILE", requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM file containing the private key of the TLS certificate
+    #[clap(long, value_name = "FILE", requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// htpasswd file containing the users allowed to access the server (bcrypt or SHA1 hashes) [default: DIR/.htpasswd]
+    #[clap(long, value_name = "FILE")]
+    htpasswd_file: Option<PathBuf>,
+
+    /// Allow access without authentication
+    #[clap(long, conflicts_with_all = ["htpasswd_file", "private_repos"])]
+    no_auth: bool,
+
+    /// Users can only access repositories below a directory named like the user, i.e. DIR/USER/...
+    #[clap(long)]
+    private_repos: bool,
+
+    /// Only allow to add files; existing files can't be deleted or overwritten, except for locks
+    #[clap(long)]
+    append_only: bool,
+
+    /// Listen on this socket to change the log level using `rustic control log-level`
+    #[clap(long, value_name = "SOCKET", env = "RUSTIC_CONTROL_SOCKET")]
+    control_socket: Option<PathBuf>,
+}
+
+impl Runnable for ServeCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl ServeCmd {
+    fn inner_run(&self) -> Result<()> {
+        let users = if self.no_auth {
+            None
+        } else {
+            let file = self
+                .htpasswd_file
+                .clone()
+                .unwrap_or_else(|| self.path.join(".htpasswd"));
+            let users = read_htpasswd(&file).with_context(|| {
+                format!(
+                    "error reading {file:?}. Create it or use --no-auth to disable authentication"
+                )
+            })?;
+            info!("read {} users from {file:?}", users.len());
+            Some(users)
+        };
+
+        let server = match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => {
+                let ssl = SslConfig {
+                    certificate: fs::read(cert)
+                        .with_context(|| format!("error reading {cert:?}"))?,
+                    private_key: fs::read(key).with_context(|| format!("error reading {key:?}"))?,
+                };
+                Server::https(&self.listen, ssl)
+            }
+            _ => Server::http(&self.listen),
+        }
+        .map_err(|err| anyhow!("cannot listen on {}: {err}", self.listen))?;
+        let scheme = if self.tls_cert.is_some() {
+            "https"
+        } else {
+            "http"
+        };
+        info!(
+            "serving repositories in {:?} on {scheme}://{}/",
+            self.path, self.listen
+        );
+
+        let _listener = self
+            .control_socket
+            .as_ref()
+            .map(|path| listen(path, None))
+            .transpose()?;
+
+        let server = Arc::new(server);
+        let handler = Arc::new(Handler {
+            root: self.path.clone(),
+            users,
+            verified: Mutex::new(HashMap::new()),
+            private_repos: self.private_repos,
+            append_only: self.append_only,
+        });
+        let workers: Vec<_> = (0..thread::available_parallelism().map_or(4, usize::from))
+            .map(|_| {
+                let server = server.clone();
+                let handler = handler.clone();
+                thread::spawn(move || {
+                    while let Ok(request) = server.recv() {
+                        handler.handle(request);
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            _ = worker.join();
+        }
+        Ok(())
+    }
+}
+
+/// Read a htpasswd file.
+///
+/// # Arguments
+///
+/// * `path` - The htpasswd file
+///
+/// # Returns
+///
+/// The password hashes by user
+fn read_htpasswd(path: &Path) -> Result<HashMap<String, String>> {
+    let mut users = HashMap::new();
+    for line in fs::read_to_string(path)?.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((user, hash)) = line.split_once(':') else {
+            bail!("invalid line in htpasswd file: {line}");
+        };
+        if !hash.starts_with("$2") && !hash.starts_with("{SHA}") {
+            warn!("unsupported hash for user {user}, only bcrypt and SHA1 are supported. Ignoring user.");
+            continue;
+        }
+        _ = users.insert(user.to_string(), hash.to_string());
+    }
+    Ok(users)
+}
+
+/// Verify a password against a htpasswd hash.
+///
+/// # Arguments
+///
+/// * `password` - The password to verify
+/// * `hash` - The hash from the htpasswd file
+fn verify_password(password: &str, hash: &str) -> bool {
+    hash.strip_prefix("{SHA}").map_or_else(
+        || bcrypt::verify(password, hash).unwrap_or(false),
+        |sha| STANDARD.encode(Sha1::digest(password.as_bytes())) == sha,
+    )
+}
+
+/// The target of a request
+#[derive(Debug)]
+enum Target {
+    /// A repository; only used to create it
+    Repo(PathBuf),
+    /// The config file of a repository
+    Config(PathBuf),
+    /// The directory of a file type within a repository
+    List(PathBuf, &'static str),
+    /// A file within a repository
+    File(PathBuf, &'static str),
+}
+
+/// An entry of a REST API v2 listing
+#[derive(Serialize)]
+struct ListEntry {
+    /// The name of the file
+    name: String,
+    /// The size of the file
+    size: u64,
+}
+
+/// Handles the requests to the server
+#[derive(Debug)]
+struct Handler {
+    /// Directory containing the repositories
+    root: PathBuf,
+    /// The password hashes by user; `None` if authentication is disabled
+    users: Option<HashMap<String, String>>,
+    /// SHA256 of the last verified password by user, so hashes don't need to be verified again
+    verified: Mutex<HashMap<String, Vec<u8>>>,
+    /// Whether users can only access repositories below their user name
+    private_repos: bool,
+    /// Whether files can only be added
+    append_only: bool,
+}
+
+impl Handler {
+    /// Handle a request and send the response.
+    fn handle(&self, mut request: Request) {
+        let method = request.method().clone();
+        let url = request.url().to_string();
+        debug!("{method} {url}");
+        let response = match self.authenticate(&request) {
+            Err(response) => response,
+            Ok(user) => match self.target(&url, user.as_deref()) {
+                None => status(400),
+                Some(target) => self
+                    .respond(&mut request, &method, &url, target)
+                    .unwrap_or_else(|err| match err.kind() {
+                        ErrorKind::NotFound => status(404),
+                        _ => {
+                            warn!("error handling {method} {url}: {err}");
+                            status(500)
+                        }
+                    }),
+            },
+        };
+        if let Err(err) = request.respond(response) {
+            debug!("error sending response to {method} {url}: {err}");
+        }
+    }
+
+    /// Authenticate the user of a request using basic auth.
+    ///
+    /// # Returns
+    ///
+    /// The user or `None` if authentication is disabled
+    ///
+    /// # Errors
+    ///
+    /// The response to send if the authentication failed
+    fn authenticate(&self, request: &Request) -> Result<Option<String>, ResponseBox> {
+        let Some(users) = &self.users else {
+            return Ok(None);
+        };
+        let unauthorized = || {
+            status(401).with_header(
+                Header::from_bytes("WWW-Authenticate", "Basic realm=\"rustic\"")
+                    .expect("header should be valid"),
+            )
+        };
+        let credentials = header(request, "Authorization")
+            .and_then(|auth| auth.strip_prefix("Basic "))
+            .and_then(|auth| STANDARD.decode(auth.trim()).ok())
+            .and_then(|auth| String::from_utf8(auth).ok());
+        let Some((user, password)) = credentials
+            .as_deref()
+            .and_then(|credentials| credentials.split_once(':'))
+        else {
+            return Err(unauthorized());
+        };
+        let Some(hash) = users.get(user) else {
+            return Err(unauthorized());
+        };
+
+        let password_sha = Sha256::digest(password.as_bytes()).to_vec();
+        let mut verified = self.verified.lock().unwrap();
+        if verified.get(user) != Some(&password_sha) {
+            if !verify_password(password, hash) {
+                warn!("authentication of user {user} failed");
+                return Err(unauthorized());
+            }
+            _ = verified.insert(user.to_string(), password_sha);
+        }
+        Ok(Some(user.to_string()))
+    }
+
+    /// Determine the target of a request.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The url of the request
+    /// * `user` - The authenticated user
+    ///
+    /// # Returns
+    ///
+    /// The target or `None` if the url is invalid or the user is not allowed to access it
+    fn target(&self, url: &str, user: Option<&str>) -> Option<Target> {
+        let path = url.split_once('?').map_or(url, |(path, _)| path);
+        let segments: Vec<_> = path.split('/').filter(|s| !s.is_empty()).collect();
+        if segments
+            .iter()
+            .any(|s| *s == "." || *s == ".." || s.starts_with('.') || s.contains(['\\', ':']))
+        {
+            return None;
+        }
+        if self.private_repos && segments.first().copied() != user {
+            return None;
+        }
+
+        let repo = |n: usize| -> PathBuf {
+            segments[..n]
+                .iter()
+                .fold(self.root.clone(), |path, s| path.join(s))
+        };
+        let tpe = |name: &str| constants::TYPES.into_iter().find(|t| *t == name);
+        let n = segments.len();
+        Some(match segments.last() {
+            Some(&"config") => Target::Config(repo(n - 1)),
+            Some(name) if tpe(name).is_some() => Target::List(repo(n - 1), tpe(name)?),
+            Some(name)
+                if n >= 2
+                    && tpe(segments[n - 2]).is_some()
+                    && name.len() == 64
+                    && name.chars().all(|c| c.is_ascii_hexdigit()) =>
+            {
+                Target::File(repo(n - 2), tpe(segments[n - 2])?)
+            }
+            _ => Target::Repo(repo(n)),
+        })
+    }
+
+    /// Handle a request to the given target.
+    fn respond(
+        &self,
+        request: &mut Request,
+        method: &Method,
+        url: &str,
+        target: Target,
+    ) -> io::Result<ResponseBox> {
+        Ok(match (method, target) {
+            (Method::Post, Target::Repo(repo)) if url.ends_with("?create=true") => {
+                for tpe in constants::TYPES {
+                    fs::create_dir_all(repo.join(tpe))?;
+                }
+                for i in 0..=255_u8 {
+                    fs::create_dir_all(repo.join("data").join(format!("{i:02x}")))?;
+                }
+                info!("created repository {repo:?}");
+                status(200)
+            }
+            (Method::Get | Method::Head, Target::Config(repo)) => {
+                send_file(request, &repo.join("config"))?
+            }
+            (Method::Post, Target::Config(repo)) => {
+                self.save_file(request, &repo.join("config"), "config")?
+            }
+            (Method::Delete, Target::Config(repo)) => {
+                self.delete_file(&repo.join("config"), "config")?
+            }
+            (Method::Get | Method::Head, Target::List(repo, tpe)) => list(request, &repo, tpe)?,
+            (Method::Get | Method::Head, Target::File(repo, tpe)) => {
+                send_file(request, &file_path(&repo, tpe, url))?
+            }
+            (Method::Post, Target::File(repo, tpe)) => {
+                self.save_file(request, &file_path(&repo, tpe, url), tpe)?
+            }
+            (Method::Delete, Target::File(repo, tpe)) => {
+                self.delete_file(&file_path(&repo, tpe, url), tpe)?
+            }
+            _ => status(405),
+        })
+    }
+
+    /// Save the body of a request as file.
+    ///
+    /// The file is first written to a temporary file which is then renamed.
+    fn save_file(&self, request: &mut Request, path: &Path, tpe: &str) -> io::Result<ResponseBox> {
+        if self.append_only && tpe != "locks" && path.exists() {
+            return Ok(status(403));
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(".tmp");
+        let tmp_path = path.with_file_name(tmp_name);
+        if let Err(err) = write_file(request.as_reader(), &tmp_path, path) {
+            _ = fs::remove_file(&tmp_path);
+            return Err(err);
+        }
+        Ok(status(200))
+    }
+
+    /// Delete a file.
+    fn delete_file(&self, path: &Path, tpe: &str) -> io::Result<ResponseBox> {
+        if self.append_only && tpe != "locks" {
+            return Ok(status(403));
+        }
+        fs::remove_file(path)?;
+        Ok(status(200))
+    }
+}
+
+/// Write the data of `reader` to `tmp_path`, sync it and rename it to `path`.
+fn write_file(reader: &mut dyn Read, tmp_path: &Path, path: &Path) -> io::Result<()> {
+    let mut file = File::create(tmp_path)?;
+    _ = io::copy(reader, &mut file)?;
+    file.flush()?;
+    file.sync_all()?;
+    fs::rename(tmp_path, path)
+}
+
+/// Returns the path of the file given by the last segment of the url.
+fn file_path(repo: &Path, tpe: &str, url: &str) -> PathBuf {
+    let path = url.split_once('?').map_or(url, |(path, _)| path);
+    let name = path
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or_default();
+    let dir = repo.join(tpe);
+    if tpe == "data" {
+        dir.join(&name[..2]).join(name)
+    } else {
+        dir.join(name)
+    }
+}
+
+/// Send a file, or the part of it requested by a `Range` header.
+fn send_file(request: &Request, path: &Path) -> io::Result<ResponseBox> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let range = header(request, "Range")
+        .and_then(|range| range.strip_prefix("bytes="))
+        .and_then(|range| range.split_once('-'))
+        .and_then(|(start, end)| {
+            let start: u64 = start.parse().ok()?;
+            let end = if end.is_empty() {
+                size.checked_sub(1)?
+            } else {
+                end.parse::<u64>().ok()?.min(size.checked_sub(1)?)
+            };
+            (start <= end).then_some((start, end))
+        });
+    Ok(match range {
+        Some((start, end)) => {
+            _ = file.seek(SeekFrom::Start(start))?;
+            let mut data = Vec::new();
+            _ = file.take(end - start + 1).read_to_end(&mut data)?;
+            Response::from_data(data)
+                .with_status_code(206)
+                .with_header(
+                    Header::from_bytes("Content-Range", format!("bytes {start}-{end}/{size}"))
+                        .expect("header should be valid"),
+                )
+                .boxed()
+        }
+        None => Response::from_file(file).boxed(),
+    })
+}
+
+/// List the files of a type directory.
+fn list(request: &Request, repo: &Path, tpe: &str) -> io::Result<ResponseBox> {
+    let dir = repo.join(tpe);
+    let mut entries = Vec::new();
+    let mut add_dir = |dir: &Path| -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let meta = entry.metadata()?;
+            if meta.is_file() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if !name.ends_with(".tmp") {
+                    entries.push(ListEntry {
+                        name,
+                        size: meta.len(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    };
+    match fs::read_dir(&dir) {
+        Ok(subdirs) if tpe == "data" => {
+            for subdir in subdirs {
+                let subdir = subdir?;
+                if subdir.file_type()?.is_dir() {
+                    add_dir(&subdir.path())?;
+                }
+            }
+        }
+        Ok(_) => add_dir(&dir)?,
+        // missing directories are listed as empty
+        Err(err) if err.kind() == ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+
+    let v2 = header(request, "Accept").map_or(false, |accept| accept.contains(constants::API_V2));
+    let (data, media_type) = if v2 {
+        (serde_json::to_vec(&entries)?, constants::API_V2)
+    } else {
+        let names: Vec<_> = entries.into_iter().map(|entry| entry.name).collect();
+        (serde_json::to_vec(&names)?, constants::API_V1)
+    };
+    Ok(Response::from_data(data)
+        .with_header(
+            Header::from_bytes("Content-Type", media_type).expect("header should be valid"),
+        )
+        .boxed())
+}
+
+/// Returns the value of a header of the request.
+fn header<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str())
+}
+
+/// Returns an empty response with the given status code.
+fn status(code: u16) -> ResponseBox {
+    Response::empty(code).boxed()
+}
@@ -4,19 +4,25 @@
 /// accessors along with logging macros. Customize as you see fit.
 use crate::{
     commands::open_repository,
-    helpers::{bold_cell, bytes_size_to_string, table, table_right_from},
+    helpers::{bold_cell, bytes_size_to_string, resources_to_string, table, table_right_from},
     status_err, Application, RUSTIC_APP,
 };
 
+use std::cmp::Ordering;
+
 use abscissa_core::{Command, Runnable, Shutdown};
+#[cfg(not(feature = "qrcode"))]
+use anyhow::bail;
 use anyhow::Result;
 use comfy_table::Cell;
 use humantime::format_duration;
 use itertools::Itertools;
+#[cfg(feature = "qrcode")]
+use qrcode::{render::unicode, QrCode};
 
 use rustic_core::{
     repofile::{DeleteOption, SnapshotFile},
-    SnapshotGroupCriterion,
+    Id, SnapshotGroupCriterion,
 };
 
 /// `snapshot` subcommand
@@ -43,10 +49,44 @@ pub(crate) struct SnapshotCmd {
     #[clap(long, conflicts_with = "long")]
     json: bool,
 
-    /// Show all snapshots instead of summarizing identical follow-up snapshots
+    /// Show all snapshots in CSV format with full IDs and sizes in bytes
     #[clap(long, conflicts_with_all = &["long", "json"])]
+    csv: bool,
+
+    /// Show all snapshots instead of summarizing identical follow-up snapshots
+    #[clap(long, alias = "no-collapse", conflicts_with_all = &["long", "json", "csv"])]
     all: bool,
+
+    /// Sort the snapshots of each group by this criterion
+    #[clap(long, value_name = "SORT", value_enum, default_value = "time")]
+    sort: SnapshotSort,
+
+    /// Reverse the sort order, e.g. to show the newest or largest snapshots first
+    #[clap(long)]
+    reverse: bool,
+
+    /// Show a short code and a visual fingerprint of the repository and snapshot IDs, e.g. to compare them over the phone
+    #[clap(long, conflicts_with_all = &["long", "json", "csv"])]
+    fingerprint: bool,
+
+    /// Also show the IDs as QR codes (needs the feature `qrcode`)
+    #[clap(long, requires = "fingerprint")]
+    qr: bool,
+}
+
+/// Criteria to sort snapshots by
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub(super) enum SnapshotSort {
+    /// Snapshot time
+    Time,
+    /// Total size of the backed up files
+    Size,
+    /// Hostname, then time
+    Host,
+    /// Backed up paths, then time
+    Paths,
 }
+
 impl Runnable for SnapshotCmd {
     fn run(&self) {
         if let Err(err) = self.inner_run() {
@@ -61,16 +101,41 @@ impl SnapshotCmd {
         let config = RUSTIC_APP.config();
         let repo = open_repository(&config)?;
 
-        let groups = repo.get_snapshot_group(&self.ids, self.group_by, |sn| {
+        let mut groups = repo.get_snapshot_group(&self.ids, self.group_by, |sn| {
             config.snapshot_filter.matches(sn)
         })?;
 
         if self.json {
+            for (_, snapshots) in &mut groups {
+                snapshots.sort_unstable_by(|sn1, sn2| self.compare(sn1, sn2));
+            }
             let mut stdout = std::io::stdout();
             serde_json::to_writer_pretty(&mut stdout, &groups)?;
             return Ok(());
         }
 
+        if self.csv {
+            let mut snapshots: Vec<_> = groups.into_iter().flat_map(|(_, snaps)| snaps).collect();
+            snapshots.sort_unstable_by(|sn1, sn2| self.compare(sn1, sn2));
+            return print_csv(&snapshots);
+        }
+
+        if self.fingerprint {
+            print_fingerprint("repository", &repo.config().id, self.qr)?;
+            let mut snapshots: Vec<_> = groups.into_iter().flat_map(|(_, snaps)| snaps).collect();
+            snapshots.sort_unstable_by(|sn1, sn2| self.compare(sn1, sn2));
+            for sn in snapshots {
+                println!(
+                    "\n{} from {} at {}",
+                    sn.hostname,
+                    sn.paths,
+                    sn.time.format("%Y-%m-%d %H:%M:%S")
+                );
+                print_fingerprint("snapshot", &sn.id, self.qr)?;
+            }
+            return Ok(());
+        }
+
         let mut total_count = 0;
         for (group, mut snapshots) in groups {
             if !group.is_empty() {
@@ -80,6 +145,7 @@ impl SnapshotCmd {
             let count = snapshots.len();
 
             if self.long {
+                snapshots.sort_unstable_by(|sn1, sn2| self.compare(sn1, sn2));
                 for snap in snapshots {
                     snap.print_table();
                 }
@@ -99,8 +165,8 @@ impl SnapshotCmd {
                         },
                     );
                     let id = match count {
-                        0 => format!("{}", sn.id),
-                        count => format!("{} (+{})", sn.id, count),
+                        0 => config.global.format_id(&sn.id),
+                        count => format!("{} (+{})", config.global.format_id(&sn.id), count),
                     };
                     [
                         id,
@@ -122,13 +188,15 @@ impl SnapshotCmd {
                     ],
                 );
 
-                let snapshots: Vec<_> = snapshots
+                // identical follow-up snapshots are collapsed in time order before sorting
+                let mut snapshots: Vec<_> = snapshots
                     .into_iter()
                     .group_by(|sn| if self.all { sn.id } else { sn.tree })
                     .into_iter()
                     .map(|(_, mut g)| (g.next().unwrap(), g.count()))
-                    .map(snap_to_table)
                     .collect();
+                snapshots.sort_by(|(sn1, _), (sn2, _)| self.compare(sn1, sn2));
+                let snapshots: Vec<_> = snapshots.into_iter().map(snap_to_table).collect();
                 _ = table.add_rows(snapshots);
                 println!("{table}");
             }
@@ -140,6 +208,178 @@ impl SnapshotCmd {
 
         Ok(())
     }
+
+    /// Compare two snapshots using the selected sort order
+    fn compare(&self, sn1: &SnapshotFile, sn2: &SnapshotFile) -> Ordering {
+        let size = |sn: &SnapshotFile| {
+            sn.summary
+                .as_ref()
+                .map_or(0, |summary| summary.total_bytes_processed)
+        };
+        let ordering = match self.sort {
+            SnapshotSort::Time => sn1.cmp(sn2),
+            SnapshotSort::Size => size(sn1).cmp(&size(sn2)).then_with(|| sn1.cmp(sn2)),
+            SnapshotSort::Host => sn1.hostname.cmp(&sn2.hostname).then_with(|| sn1.cmp(sn2)),
+            SnapshotSort::Paths => sn1
+                .paths
+                .to_string()
+                .cmp(&sn2.paths.to_string())
+                .then_with(|| sn1.cmp(sn2)),
+        };
+        if self.reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
+
+/// Columns of the CSV output
+const CSV_TITLES: [&str; 14] = [
+    "id",
+    "time",
+    "hostname",
+    "label",
+    "tags",
+    "paths",
+    "files_new",
+    "files_changed",
+    "files_unmodified",
+    "total_files_processed",
+    "total_dirs_processed",
+    "total_bytes_processed",
+    "data_added",
+    "data_added_packed",
+];
+
+fn print_csv(snapshots: &[SnapshotFile]) -> Result<()> {
+    let mut wtr = csv::Writer::from_writer(std::io::stdout());
+    wtr.write_record(CSV_TITLES)?;
+    for sn in snapshots {
+        let mut record = vec![
+            sn.id.to_hex().to_string(),
+            sn.time.to_rfc3339(),
+            sn.hostname.clone(),
+            sn.label.clone(),
+            sn.tags.to_string(),
+            sn.paths.to_string(),
+        ];
+        // snapshots without summary have empty summary columns
+        let missing = CSV_TITLES.len() - record.len();
+        record.extend(sn.summary.as_ref().map_or_else(
+            || vec![String::new(); missing],
+            |s| {
+                [
+                    s.files_new,
+                    s.files_changed,
+                    s.files_unmodified,
+                    s.total_files_processed,
+                    s.total_dirs_processed,
+                    s.total_bytes_processed,
+                    s.data_added,
+                    s.data_added_packed,
+                ]
+                .map(|n| n.to_string())
+                .to_vec()
+            },
+        ));
+        wtr.write_record(record)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Print the ID with a short code, its random art and optionally a QR code
+///
+/// # Arguments
+///
+/// * `title` - The kind of the ID, e.g. `snapshot`
+/// * `id` - The ID to print
+/// * `qr` - Whether to print a QR code of the ID
+fn print_fingerprint(title: &str, id: &Id, qr: bool) -> Result<()> {
+    let hex = id.to_hex();
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+        .collect::<Result<Vec<_>, _>>()?;
+    // a short code which can be read aloud; the random art allows to compare the full ID at a glance
+    let code = hex[..16]
+        .as_bytes()
+        .chunks(4)
+        .map(|chunk| String::from_utf8_lossy(chunk))
+        .join("-");
+
+    println!("{title} {}", hex.as_str());
+    println!("fingerprint: {code}");
+    println!("{}", random_art(&bytes, title));
+    if qr {
+        #[cfg(feature = "qrcode")]
+        {
+            let qr_code = QrCode::new(hex.as_bytes())?;
+            println!(
+                "{}",
+                qr_code
+                    .render::<unicode::Dense1x2>()
+                    .quiet_zone(true)
+                    .build()
+            );
+        }
+        #[cfg(not(feature = "qrcode"))]
+        bail!("QR codes can't be shown, as rustic was compiled without the feature `qrcode`");
+    }
+    Ok(())
+}
+
+/// Draw the random art ("drunken bishop") of the given bytes, as known from OpenSSH key fingerprints
+///
+/// # Arguments
+///
+/// * `bytes` - The bytes to draw
+/// * `label` - The label shown in the top border
+fn random_art(bytes: &[u8], label: &str) -> String {
+    const WIDTH: usize = 17;
+    const HEIGHT: usize = 9;
+    const SYMBOLS: &[u8] = b" .o+=*BOX@%&#/^";
+
+    let mut field = [[0_usize; WIDTH]; HEIGHT];
+    let start = (WIDTH / 2, HEIGHT / 2);
+    let (mut x, mut y) = start;
+    for byte in bytes {
+        // each byte gives four moves, starting with the lowest bits
+        for step in 0..4 {
+            let bits = byte >> (2 * step);
+            x = if bits & 1 == 0 {
+                x.saturating_sub(1)
+            } else {
+                (x + 1).min(WIDTH - 1)
+            };
+            y = if bits & 2 == 0 {
+                y.saturating_sub(1)
+            } else {
+                (y + 1).min(HEIGHT - 1)
+            };
+            field[y][x] += 1;
+        }
+    }
+
+    let mut art = format!("+{:-^width$}+\n", format!("[{label}]"), width = WIDTH);
+    for (row, counts) in field.iter().enumerate() {
+        art.push('|');
+        for (col, count) in counts.iter().enumerate() {
+            art.push(if (col, row) == start {
+                'S'
+            } else if (col, row) == (x, y) {
+                'E'
+            } else {
+                char::from(SYMBOLS[(*count).min(SYMBOLS.len() - 1)])
+            });
+        }
+        art.push_str("|\n");
+    }
+    art.push('+');
+    art.push_str(&"-".repeat(WIDTH));
+    art.push('+');
+    art
 }
 
 trait PrintTable {
@@ -162,6 +402,9 @@ impl PrintTable for SnapshotFile {
         add_entry("Time", self.time.format("%Y-%m-%d %H:%M:%S").to_string());
         add_entry("Generated by", self.program_version.clone());
         add_entry("Host", self.hostname.clone());
+        if !self.platform.is_empty() {
+            add_entry("Platform", self.platform.clone());
+        }
         add_entry("Label", self.label.clone());
         add_entry("Tags", self.tags.formatln());
         let delete = match self.delete {
@@ -189,11 +432,24 @@ impl PrintTable for SnapshotFile {
             add_entry("Source", source);
             add_entry("", String::new());
 
-            let files = format!(
+            let mut files = format!(
                 "new: {:>10} / changed: {:>10} / unchanged: {:>10}",
                 summary.files_new, summary.files_changed, summary.files_unmodified,
             );
+            if summary.files_unstable > 0 {
+                files.push_str(&format!(" / unstable: {:>10}", summary.files_unstable));
+            }
             add_entry("Files", files);
+            if summary.partial {
+                add_entry(
+                    "Partial",
+                    format!(
+                        "failed files: {} / source errors: {}",
+                        summary.failed_paths.len(),
+                        summary.source_errors
+                    ),
+                );
+            }
 
             let trees = format!(
                 "new: {:>10} / changed: {:>10} / unchanged: {:>10}",
@@ -227,6 +483,9 @@ impl PrintTable for SnapshotFile {
                 format_duration(std::time::Duration::from_secs_f64(summary.total_duration))
             );
             add_entry("Duration", duration);
+            if let Some(resources) = &summary.resources {
+                add_entry("Resources", resources_to_string(resources));
+            }
         }
         if let Some(ref description) = self.description {
             add_entry("Description", description.clone());
@@ -0,0 +1,339 @@
+//! `stats` subcommand
+
+/// App-local prelude includes `app_reader()`/`app_writer()`/`app_config()`
+/// accessors along with logging macros. Customize as you see fit.
+use crate::{
+    commands::open_repository,
+    helpers::{bytes_size_to_string, table_right_from},
+    status_err, Application, RUSTIC_APP,
+};
+
+use std::collections::BTreeMap;
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::Result;
+use bytesize::ByteSize;
+use chrono::{Duration, Local};
+use log::warn;
+
+use rustic_core::{
+    repofile::{BlobType, SnapshotFile},
+    PackUsage, PruneOptions,
+};
+
+/// Upper bounds (in MiB) of the pack size classes
+const SIZE_CLASSES: [u64; 6] = [1, 4, 16, 64, 256, u64::MAX];
+
+/// Upper bounds (in percent) of the unused ratio classes
+const UNUSED_CLASSES: [u64; 6] = [0, 10, 25, 50, 75, 100];
+
+/// Column titles of the statistics tables
+const TITLES: [&str; 4] = ["Packs", "Size", "Unused", "Unused %"];
+
+/// Columns of the CSV output
+const CSV_TITLES: [&str; 6] = ["category", "name", "packs", "size", "used", "unused"];
+
+/// `stats` subcommand
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct StatsCmd {
+    /// Show detailed statistics about packs: size distribution, unused space and fragmentation over time
+    #[clap(long)]
+    packs: bool,
+
+    /// Forecast the repository size for the given duration (e.g. 90d) using the growth within the same
+    /// duration in the past
+    #[clap(long, value_name = "DURATION")]
+    forecast: Option<humantime::Duration>,
+
+    /// Warn if the forecasted repository size exceeds this size, e.g. a quota or the disk capacity
+    #[clap(long, value_name = "SIZE", requires = "forecast")]
+    quota: Option<ByteSize>,
+
+    /// Show statistics in CSV format with sizes in bytes
+    #[clap(long, conflicts_with = "forecast")]
+    csv: bool,
+}
+
+impl Runnable for StatsCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl StatsCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let repo = open_repository(&config)?;
+
+        let plan = repo.prune_plan(&PruneOptions::default())?;
+        let packs = plan.pack_usage();
+
+        let mut sections = vec![("Blob type", summary(packs))];
+        if self.packs {
+            sections.push(("Pack size", size_distribution(packs)));
+            sections.push(("Unused in pack", unused_distribution(packs)));
+            sections.push(("Packs created", trend(packs)));
+        }
+
+        if self.csv {
+            return print_csv(&sections);
+        }
+
+        for (i, (title, rows)) in sections.into_iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            print_table(title, rows);
+        }
+        if let Some(duration) = self.forecast {
+            let current = packs.iter().map(|pack| u64::from(pack.size)).sum();
+            let snaps = repo.get_all_snapshots()?;
+            println!();
+            print_forecast(&snaps, current, Duration::from_std(*duration)?, self.quota)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Accumulated statistics about a set of packs
+#[derive(Default, Clone, Copy)]
+struct PackSum {
+    count: u64,
+    size: u64,
+    used: u64,
+    unused: u64,
+}
+
+impl PackSum {
+    fn add(&mut self, pack: &PackUsage) {
+        self.count += 1;
+        self.size += u64::from(pack.size);
+        self.used += u64::from(pack.used_size);
+        self.unused += u64::from(pack.unused_size);
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn unused_percent(&self) -> String {
+        if self.unused == 0 {
+            "0.0%".to_string()
+        } else {
+            format!(
+                "{:.1}%",
+                self.unused as f64 / (self.used + self.unused) as f64 * 100.0
+            )
+        }
+    }
+
+    fn row(&self, title: impl ToString) -> Vec<String> {
+        vec![
+            title.to_string(),
+            self.count.to_string(),
+            bytes_size_to_string(self.size),
+            bytes_size_to_string(self.unused),
+            self.unused_percent(),
+        ]
+    }
+}
+
+fn print_table(title: &str, rows: Vec<(String, PackSum)>) {
+    let mut table = table_right_from(1, [title].into_iter().chain(TITLES));
+    for (name, sum) in rows {
+        _ = table.add_row(sum.row(name));
+    }
+    println!("{table}");
+}
+
+fn print_csv(sections: &[(&str, Vec<(String, PackSum)>)]) -> Result<()> {
+    let mut wtr = csv::Writer::from_writer(std::io::stdout());
+    wtr.write_record(CSV_TITLES)?;
+    for (title, rows) in sections {
+        let category = title.to_lowercase().replace(' ', "_");
+        for (name, sum) in rows {
+            wtr.write_record([
+                category.clone(),
+                name.clone(),
+                sum.count.to_string(),
+                sum.size.to_string(),
+                sum.used.to_string(),
+                sum.unused.to_string(),
+            ])?;
+        }
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+fn summary(packs: &[PackUsage]) -> Vec<(String, PackSum)> {
+    let mut sums = BTreeMap::new();
+    let mut total = PackSum::default();
+    for pack in packs {
+        sums.entry(pack.blob_type)
+            .or_insert_with(PackSum::default)
+            .add(pack);
+        total.add(pack);
+    }
+
+    let mut rows: Vec<_> = sums
+        .into_iter()
+        .map(|(tpe, sum)| {
+            let tpe = match tpe {
+                BlobType::Tree => "Tree",
+                BlobType::Data => "Data",
+            };
+            (tpe.to_string(), sum)
+        })
+        .collect();
+    rows.push(("Total".to_string(), total));
+    rows
+}
+
+fn size_distribution(packs: &[PackUsage]) -> Vec<(String, PackSum)> {
+    let mut sums = [PackSum::default(); SIZE_CLASSES.len()];
+    for pack in packs {
+        let size_mib = u64::from(pack.size) / (1024 * 1024);
+        let class = SIZE_CLASSES
+            .iter()
+            .position(|limit| size_mib < *limit)
+            .unwrap_or(SIZE_CLASSES.len() - 1);
+        sums[class].add(pack);
+    }
+
+    let mut rows = Vec::new();
+    let mut lower = 0;
+    for (limit, sum) in SIZE_CLASSES.iter().zip(sums) {
+        let title = if *limit == u64::MAX {
+            format!(">= {lower} MiB")
+        } else {
+            format!("{lower} - {limit} MiB")
+        };
+        rows.push((title, sum));
+        lower = *limit;
+    }
+    rows
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn unused_distribution(packs: &[PackUsage]) -> Vec<(String, PackSum)> {
+    let mut sums = [PackSum::default(); UNUSED_CLASSES.len()];
+    for pack in packs {
+        let unused = pack.unused_ratio() * 100.0;
+        let class = UNUSED_CLASSES
+            .iter()
+            .position(|limit| unused <= *limit as f64)
+            .unwrap_or(UNUSED_CLASSES.len() - 1);
+        sums[class].add(pack);
+    }
+
+    let mut rows = Vec::new();
+    let mut lower = 0;
+    for (limit, sum) in UNUSED_CLASSES.iter().zip(sums) {
+        let title = if *limit == 0 {
+            "0%".to_string()
+        } else {
+            format!("{lower} - {limit}%")
+        };
+        rows.push((title, sum));
+        lower = *limit;
+    }
+    rows
+}
+
+fn trend(packs: &[PackUsage]) -> Vec<(String, PackSum)> {
+    let mut sums = BTreeMap::new();
+    for pack in packs {
+        let month = pack
+            .time
+            .map_or_else(|| "unknown".to_string(), |t| t.format("%Y-%m").to_string());
+        sums.entry(month).or_insert_with(PackSum::default).add(pack);
+    }
+    sums.into_iter().collect()
+}
+
+/// Estimate the growth rate in bytes per second from the data added by the given snapshots.
+///
+/// Uses a least-squares fit of the accumulated added data over time.
+/// Returns `None` if there are not enough snapshots to fit.
+#[allow(clippy::cast_precision_loss)]
+fn growth_rate(snaps: &[&SnapshotFile]) -> Option<f64> {
+    let mut added = 0;
+    let points: Vec<_> = snaps
+        .iter()
+        .filter_map(|sn| {
+            let summary = sn.summary.as_ref()?;
+            added += summary.data_added_packed;
+            Some((sn.time.timestamp() as f64, added as f64))
+        })
+        .collect();
+    if points.len() < 2 {
+        return None;
+    }
+
+    let n = points.len() as f64;
+    let mean_t = points.iter().map(|(t, _)| t).sum::<f64>() / n;
+    let mean_s = points.iter().map(|(_, s)| s).sum::<f64>() / n;
+    let (cov, var) = points.iter().fold((0.0, 0.0), |(cov, var), (t, s)| {
+        (
+            cov + (t - mean_t) * (s - mean_s),
+            var + (t - mean_t).powi(2),
+        )
+    });
+    (var > 0.0).then_some(cov / var)
+}
+
+#[allow(
+    clippy::cast_precision_loss,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+fn print_forecast(
+    snaps: &[SnapshotFile],
+    current: u64,
+    duration: Duration,
+    quota: Option<ByteSize>,
+) -> Result<()> {
+    let now = Local::now();
+    let mut recent: Vec<_> = snaps.iter().filter(|sn| sn.time > now - duration).collect();
+    recent.sort_unstable();
+
+    let Some(rate) = growth_rate(&recent) else {
+        warn!("not enough snapshots with summary in the given duration to compute a forecast.");
+        return Ok(());
+    };
+    let rate = rate.max(0.0);
+    let seconds = duration.num_seconds() as f64;
+    let forecast = current + (rate * seconds) as u64;
+
+    let mut table = table_right_from(1, ["Forecast", "Size"]);
+    _ = table.add_row(["current size".to_string(), bytes_size_to_string(current)]);
+    _ = table.add_row([
+        "growth per day".to_string(),
+        bytes_size_to_string((rate * 86400.0) as u64),
+    ]);
+    _ = table.add_row([
+        format!("in {}", humantime::format_duration(duration.to_std()?)),
+        bytes_size_to_string(forecast),
+    ]);
+    let quota = quota.map(|quota| quota.as_u64());
+    if let Some(quota) = quota {
+        _ = table.add_row(["quota".to_string(), bytes_size_to_string(quota)]);
+    }
+    println!("{table}");
+
+    if let Some(quota) = quota {
+        if current >= quota {
+            warn!("the repository size already exceeds the quota!");
+        } else if forecast >= quota {
+            let days_left = (quota - current) as f64 / rate / 86400.0;
+            let date = now + Duration::days(days_left as i64);
+            warn!(
+                "the repository size will exceed the quota in about {days_left:.0} days ({}).",
+                date.format("%Y-%m-%d")
+            );
+        }
+    }
+    Ok(())
+}
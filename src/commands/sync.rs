@@ -0,0 +1,92 @@
+//! `sync` subcommand
+
+/// App-local prelude includes `app_reader()`/`app_writer()`/`app_config()`
+/// accessors along with logging macros. Customize as you see fit.
+use crate::{
+    commands::open_repository, helpers::bytes_size_to_string, status_err, Application, RUSTIC_APP,
+};
+
+use std::{fs, path::PathBuf};
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use log::info;
+
+use rustic_core::{Repository, RepositoryOptions};
+
+/// `sync` subcommand
+///
+/// Mirrors the repository by transferring the encrypted files as they are. Only files missing in
+/// the mirror are transferred; with a sync mark, the packs of the mirror don't need to be listed.
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct SyncCmd {
+    /// Repository to sync to. It is initialized as mirror if it doesn't exist.
+    #[clap(long, value_name = "REPO")]
+    to: String,
+
+    /// Mark of the last sync (RFC 3339 timestamp); the packs of the mirror are then not listed, but all packs of missing index files are transferred
+    #[clap(long, value_name = "MARK", value_parser = parse_mark, conflicts_with = "mark_file")]
+    since: Option<DateTime<Local>>,
+
+    /// Read the last sync mark from this file and save the new sync mark to it
+    #[clap(long, value_name = "FILE")]
+    mark_file: Option<PathBuf>,
+}
+
+fn parse_mark(s: &str) -> Result<DateTime<Local>> {
+    Ok(DateTime::parse_from_rfc3339(s.trim())?.with_timezone(&Local))
+}
+
+impl Runnable for SyncCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl SyncCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let repo = open_repository(&config)?;
+        let target = Repository::new(&RepositoryOptions::default().repository(&self.to))?;
+
+        let since = match (&self.since, &self.mark_file) {
+            (Some(since), _) => Some(*since),
+            (None, Some(file)) if file.exists() => Some(parse_mark(&fs::read_to_string(file)?)?),
+            _ => None,
+        };
+        if let Some(since) = since {
+            info!("last sync at {since}, not listing the packs of the mirror...");
+        }
+
+        let stats = repo.sync_to(&target, since, config.global.dry_run)?;
+
+        let action = if config.global.dry_run {
+            "would transfer"
+        } else {
+            "transferred"
+        };
+        println!(
+            "{action} {} packs ({}), {} index files, {} snapshots, {} keys",
+            stats.packs,
+            bytes_size_to_string(stats.packs_size),
+            stats.index_files,
+            stats.snapshots,
+            stats.keys
+        );
+
+        if let Some(mark) = stats.mark {
+            println!("sync mark: {}", mark.to_rfc3339());
+            if let Some(file) = &self.mark_file {
+                if !config.global.dry_run {
+                    fs::write(file, mark.to_rfc3339())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
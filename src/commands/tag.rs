@@ -57,8 +57,14 @@ pub(crate) struct TagCmd {
     )]
     set_delete_never: bool,
 
-    /// Mark snapshot to be deleted after given duration (e.g. 10d)
-    #[clap(long, value_name = "DURATION", help_heading = "Delete mark options")]
+    /// Mark snapshot to be deleted after given duration (e.g. 10d). Until then, `forget` keeps the
+    /// snapshot, afterwards it removes the snapshot regardless of the keep options.
+    #[clap(
+        long,
+        value_name = "DURATION",
+        visible_alias = "set-expire-after",
+        help_heading = "Delete mark options"
+    )]
     set_delete_after: Option<humantime::Duration>,
 }
 
@@ -0,0 +1,78 @@
+//! `undelete` subcommand
+
+/// App-local prelude includes `app_reader()`/`app_writer()`/`app_config()`
+/// accessors along with logging macros. Customize as you see fit.
+use crate::{
+    commands::open_repository, helpers::table_with_titles, status_err, Application, RUSTIC_APP,
+};
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::Result;
+use chrono::Local;
+
+/// `undelete` subcommand
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct UndeleteCmd {
+    /// Snapshots to restore from the trash. If none is given, list the snapshots in the trash
+    #[clap(value_name = "ID")]
+    ids: Vec<String>,
+}
+
+impl Runnable for UndeleteCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl UndeleteCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let repo = open_repository(&config)?;
+
+        if self.ids.is_empty() {
+            let now = Local::now();
+            let mut table =
+                table_with_titles(["ID", "Time", "Host", "Label", "Paths", "Trashed", "Expires"]);
+            for trash in repo.get_trash()? {
+                let sn = &trash.snapshot;
+                let expires = if trash.is_expired(now) {
+                    "expired".to_string()
+                } else {
+                    trash.expires.format("%Y-%m-%d %H:%M:%S").to_string()
+                };
+                _ = table.add_row([
+                    &config.global.format_id(&sn.id),
+                    &sn.time.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    &sn.hostname,
+                    &sn.label,
+                    &sn.paths.formatln(),
+                    &trash.time.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    &expires,
+                ]);
+            }
+            println!("{table}");
+            return Ok(());
+        }
+
+        if config.global.dry_run {
+            println!(
+                "would have undeleted the following snapshots:\n {:?}",
+                self.ids
+            );
+            return Ok(());
+        }
+
+        for snap in repo.undelete_snapshots(&self.ids)? {
+            println!(
+                "undeleted snapshot {} (from {})",
+                config.global.format_id(&snap.id),
+                snap.time
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -17,11 +17,12 @@ use abscissa_core::path::AbsPathBuf;
 use abscissa_core::FrameworkError;
 use clap::Parser;
 use itertools::Itertools;
-use rustic_core::RepositoryOptions;
+use rustic_core::{Id, RepositoryOptions};
 use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
 
 use crate::{
-    commands::{backup::BackupCmd, copy::Targets, forget::ForgetOptions},
+    commands::{backup::BackupCmd, copy::Targets, forget::ForgetOptions, run::RunOptions},
     config::progress_options::ProgressOptions,
     filtering::SnapshotFilter,
 };
@@ -47,6 +48,9 @@ pub struct RusticConfig {
 
     #[clap(skip)]
     pub forget: ForgetOptions,
+
+    #[clap(skip)]
+    pub run: RunOptions,
 }
 
 impl RusticConfig {
@@ -77,6 +81,7 @@ impl RusticConfig {
     }
 }
 
+#[serde_as]
 #[derive(Default, Debug, Parser, Clone, Deserialize, Serialize, Merge)]
 #[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
 pub struct GlobalOptions {
@@ -106,12 +111,87 @@ pub struct GlobalOptions {
     #[clap(long, global = true, env = "RUSTIC_LOG_FILE", value_name = "LOGFILE")]
     pub log_file: Option<PathBuf>,
 
+    /// Show full IDs instead of abbreviated ones
+    #[clap(long, global = true, conflicts_with = "short_id")]
+    #[merge(strategy = merge::bool::overwrite_false)]
+    pub long_id: bool,
+
+    /// Number of hex characters to show for abbreviated IDs [default: 8]
+    #[clap(long, global = true, value_name = "N")]
+    pub short_id: Option<usize>,
+
+    /// Abort the run if it takes longer than this duration, e.g. "6h". Running backups are stopped
+    /// first and save a partial snapshot. Can't be used for `rekey`
+    #[clap(long, global = true, value_name = "DURATION", env = "RUSTIC_TIMEOUT")]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub timeout: Option<humantime::Duration>,
+
     /// Settings to customize progress bars
     #[clap(flatten)]
     #[serde(flatten)]
     pub progress_options: ProgressOptions,
 }
 
+impl GlobalOptions {
+    /// Format an ID for output, abbreviated as configured.
+    ///
+    /// Note that JSON output always contains full IDs.
+    #[must_use]
+    pub fn format_id(&self, id: &Id) -> String {
+        let hex = id.to_hex();
+        let len = if self.long_id {
+            hex.len()
+        } else {
+            self.short_id.unwrap_or(8).clamp(1, hex.len())
+        };
+        hex[..len].to_string()
+    }
+}
+
+/// Get the names of all profiles in the config directories matching `pattern`.
+///
+/// `pattern` may contain the wildcards `*` and `?`.
+pub(crate) fn find_profiles(pattern: &str) -> Vec<String> {
+    get_config_paths("")
+        .iter()
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            if path.extension()? != "toml" {
+                return None;
+            }
+            let name = path.file_stem()?.to_str()?.to_string();
+            wildcard_match(pattern, &name).then_some(name)
+        })
+        .sorted()
+        .dedup()
+        .collect()
+}
+
+/// Check if `name` matches `pattern` which may contain the wildcards `*` and `?`
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<_> = pattern.chars().collect();
+    let name: Vec<_> = name.chars().collect();
+    // matches[j] is true if the pattern processed so far matches the first j chars of name
+    let mut matches = vec![false; name.len() + 1];
+    matches[0] = true;
+    for p in pattern {
+        if p == '*' {
+            for j in 1..=name.len() {
+                matches[j] = matches[j] || matches[j - 1];
+            }
+        } else {
+            for j in (1..=name.len()).rev() {
+                matches[j] = matches[j - 1] && (p == '?' || p == name[j - 1]);
+            }
+            matches[0] = false;
+        }
+    }
+    matches[name.len()]
+}
+
 fn get_config_paths(filename: &str) -> Vec<PathBuf> {
     [
         ProjectDirs::from("", "", "rustic")
@@ -147,3 +227,18 @@ fn get_global_config_path() -> Option<PathBuf> {
 fn get_global_config_path() -> Option<PathBuf> {
     Some(PathBuf::from("/etc/rustic"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("*daily*", "home-daily"));
+        assert!(wildcard_match("*daily*", "daily"));
+        assert!(wildcard_match("db-?", "db-1"));
+        assert!(!wildcard_match("db-?", "db-12"));
+        assert!(!wildcard_match("*daily", "daily-home"));
+        assert!(wildcard_match("*", ""));
+    }
+}
@@ -2,6 +2,7 @@ use bytesize::ByteSize;
 use comfy_table::{
     presets::ASCII_MARKDOWN, Attribute, Cell, CellAlignment, ContentArrangement, Table,
 };
+use rustic_core::repofile::ResourceUsage;
 
 /// Helpers for table output
 
@@ -39,3 +40,21 @@ pub fn table_right_from<I: IntoIterator<Item = T>, T: ToString>(start: usize, ti
 pub fn bytes_size_to_string(b: u64) -> String {
     ByteSize(b).to_string_as(true)
 }
+
+#[must_use]
+pub fn resources_to_string(resources: &ResourceUsage) -> String {
+    let peak_memory = resources
+        .peak_memory
+        .map_or_else(|| "-".to_string(), bytes_size_to_string);
+    let cpu_time = resources
+        .cpu_time
+        .map_or_else(|| "-".to_string(), |secs| format!("{secs:.1}s"));
+    format!(
+        "peak memory: {peak_memory} / CPU time: {cpu_time}\n\
+        scan: {:.1}s / chunk: {:.1}s / upload: {:.1}s / index: {:.1}s",
+        resources.scan_duration,
+        resources.chunk_duration,
+        resources.upload_duration,
+        resources.index_duration
+    )
+}
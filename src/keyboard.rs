@@ -0,0 +1,225 @@
+//! Keyboard control of long running commands attached to a terminal
+//!
+//! While listening, these keys are handled:
+//! * `s` - print the status of the command
+//! * `p` - pause or resume reading the backup source (only backup)
+//! * `q` - stop the backup and save a partial snapshot (only backup)
+//!
+//! The terminal settings are restored when listening stops, including when the run is aborted by
+//! `--timeout` or terminated by SIGINT or SIGTERM.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Instant,
+};
+
+use humantime::format_duration;
+use log::info;
+#[cfg(unix)]
+use once_cell::sync::OnceCell;
+
+use rustic_core::{BackupControl, ResourceMeter};
+
+use crate::helpers::resources_to_string;
+
+/// Whether a listener is active; only one command at a time can be controlled by the keyboard
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// The terminal settings before the first listener changed them
+#[cfg(unix)]
+static TERMIOS: OnceCell<libc::termios> = OnceCell::new();
+
+/// Listener for keypresses; stops listening and restores the terminal settings when dropped.
+#[derive(Debug)]
+pub(crate) struct KeyboardListener {
+    /// Set to stop listening
+    stop: Arc<AtomicBool>,
+    /// The thread listening for keypresses
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Drop for KeyboardListener {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(handle) = self.handle.take() {
+            _ = handle.join();
+        }
+        restore_terminal();
+        ACTIVE.store(false, Ordering::Release);
+    }
+}
+
+/// Restore the terminal settings if a listener is active.
+///
+/// The listener restores them when dropped; this is needed if the run is aborted without running
+/// destructors.
+///
+/// Note: This doesn't allocate or lock, so it is safe to call from a signal handler.
+pub fn restore_terminal() {
+    #[cfg(unix)]
+    if ACTIVE.load(Ordering::Acquire) {
+        use nix::sys::termios::{tcsetattr, SetArg, Termios};
+        if let Some(termios) = TERMIOS.get() {
+            _ = tcsetattr(
+                libc::STDIN_FILENO,
+                SetArg::TCSANOW,
+                &Termios::from(*termios),
+            );
+        }
+    }
+}
+
+/// Listen for keypresses if stdin is a terminal.
+///
+/// # Arguments
+///
+/// * `command` - The name of the running command, used in the status
+/// * `control` - The control of a running backup; if not given, only the status can be shown
+///
+/// # Returns
+///
+/// The listener, or `None` if stdin is no terminal, another command is already listening
+/// or keyboard control is not supported on this platform.
+#[cfg(unix)]
+pub(crate) fn listen(
+    command: &'static str,
+    control: Option<BackupControl>,
+) -> Option<KeyboardListener> {
+    use nix::{
+        poll::{poll, PollFd, PollFlags},
+        sys::termios::{tcgetattr, tcsetattr, LocalFlags, SetArg, Termios},
+        unistd::{isatty, read},
+    };
+
+    /// Time after which the listener checks whether it should stop
+    const POLL_TIMEOUT_MS: i32 = 200;
+
+    let fd = libc::STDIN_FILENO;
+    if !isatty(fd).unwrap_or(false)
+        || ACTIVE
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+    {
+        return None;
+    }
+    let Ok(termios) = tcgetattr(fd) else {
+        ACTIVE.store(false, Ordering::Release);
+        return None;
+    };
+    let termios = *TERMIOS.get_or_init(|| termios.into());
+    // read single keypresses without echoing them
+    let mut raw = Termios::from(termios);
+    raw.local_flags
+        .remove(LocalFlags::ICANON | LocalFlags::ECHO);
+    if tcsetattr(fd, SetArg::TCSANOW, &raw).is_err() {
+        ACTIVE.store(false, Ordering::Release);
+        return None;
+    }
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let stop_thread = stop.clone();
+    if control.is_some() {
+        info!("press 's' for status, 'p' to pause/resume and 'q' to stop.");
+    } else {
+        info!("press 's' for status.");
+    }
+    let status = Status::new(command, control);
+    let handle = thread::spawn(move || {
+        let mut key = [0_u8];
+        while !stop_thread.load(Ordering::Acquire) {
+            let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
+            match poll(&mut fds, POLL_TIMEOUT_MS) {
+                Ok(0) => continue,
+                Ok(_) => {}
+                Err(_) => break,
+            }
+            // read directly from the file descriptor, as buffering would delay the following keypresses
+            match read(fd, &mut key) {
+                Ok(1) => status.handle_key(key[0]),
+                _ => break,
+            }
+        }
+    });
+
+    Some(KeyboardListener {
+        stop,
+        handle: Some(handle),
+    })
+}
+
+#[cfg(not(unix))]
+pub(crate) fn listen(
+    _command: &'static str,
+    _control: Option<BackupControl>,
+) -> Option<KeyboardListener> {
+    None
+}
+
+/// State of a command needed to handle keypresses
+struct Status {
+    /// The name of the running command
+    command: &'static str,
+    /// The control of a running backup
+    control: Option<BackupControl>,
+    /// The start of listening
+    start: Instant,
+    /// Measures the resources used by the command
+    meter: ResourceMeter,
+}
+
+impl Status {
+    fn new(command: &'static str, control: Option<BackupControl>) -> Self {
+        Self {
+            command,
+            control,
+            start: Instant::now(),
+            meter: ResourceMeter::start(),
+        }
+    }
+
+    /// Handle a single keypress.
+    fn handle_key(&self, key: u8) {
+        match (key, &self.control) {
+            (b's', _) => self.print(),
+            (b'p', Some(control)) if control.is_paused() => {
+                control.resume();
+                info!("{} resumed.", self.command);
+            }
+            (b'p', Some(control)) => {
+                control.pause();
+                info!("{} paused, press 'p' to resume.", self.command);
+            }
+            (b'q', Some(control)) if !control.is_stopped() => {
+                control.stop();
+                info!("stopping {}, saving partial snapshot...", self.command);
+            }
+            (b'p' | b'q', None) => {
+                info!("pausing and stopping is only supported for backups.");
+            }
+            _ => {}
+        }
+    }
+
+    /// Print the status of the command.
+    fn print(&self) {
+        let state = match &self.control {
+            Some(control) if control.is_stopped() => "stopping",
+            Some(control) if control.is_paused() => "paused",
+            _ => "running",
+        };
+        // round to seconds
+        let elapsed = std::time::Duration::from_secs(self.start.elapsed().as_secs());
+        info!(
+            "{}: {state}, elapsed: {}",
+            self.command,
+            format_duration(elapsed)
+        );
+        for line in resources_to_string(&self.meter.finish()).lines() {
+            info!("{line}");
+        }
+    }
+}
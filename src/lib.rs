@@ -59,12 +59,16 @@ Application based on the [Abscissa] framework.
     clippy::missing_const_for_fn
 )]
 
+pub(crate) mod apfs;
 pub mod application;
 pub(crate) mod commands;
 pub(crate) mod config;
 pub(crate) mod error;
 pub(crate) mod filtering;
 pub(crate) mod helpers;
+pub mod keyboard;
+pub(crate) mod state;
+pub(crate) mod timeout;
 
 // rustic_cli Public API
 
@@ -0,0 +1,90 @@
+//! Local state kept between runs of rustic
+
+use std::{fs, io::ErrorKind, path::PathBuf};
+
+use anyhow::{Context, Result};
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+
+use rustic_core::Id;
+
+/// The files which failed to be backed up in the last backup of a source
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct FailedFiles {
+    /// The paths of the snapshot, identifying the backup source
+    pub(crate) paths: String,
+    /// The partial snapshot
+    pub(crate) snapshot: Id,
+    /// The files which could not be backed up
+    pub(crate) failed: Vec<PathBuf>,
+}
+
+/// Returns the directory to keep the local state in.
+///
+/// This is the state dir on Linux and the local data dir on other platforms.
+fn state_dir() -> Option<PathBuf> {
+    ProjectDirs::from("", "", "rustic").map(|dirs| {
+        dirs.state_dir()
+            .unwrap_or_else(|| dirs.data_local_dir())
+            .to_path_buf()
+    })
+}
+
+/// Returns the file containing the failed files of backups into the given repository.
+///
+/// # Arguments
+///
+/// * `repo_id` - The id of the repository
+fn failed_files_path(repo_id: &Id) -> Option<PathBuf> {
+    state_dir().map(|dir| {
+        dir.join("failed")
+            .join(format!("{}.json", repo_id.to_hex()))
+    })
+}
+
+/// Load the failed files of the last backups into the given repository.
+///
+/// # Arguments
+///
+/// * `repo_id` - The id of the repository
+///
+/// # Returns
+///
+/// The failed files per backup source; empty if none are saved.
+pub(crate) fn load_failed_files(repo_id: &Id) -> Result<Vec<FailedFiles>> {
+    let Some(path) = failed_files_path(repo_id) else {
+        return Ok(Vec::new());
+    };
+    match fs::read(&path) {
+        Ok(data) => serde_json::from_slice(&data)
+            .with_context(|| format!("error reading failed files from {}", path.display())),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err).with_context(|| format!("error reading {}", path.display())),
+    }
+}
+
+/// Save the failed files of backups into the given repository, replacing the saved ones.
+///
+/// # Arguments
+///
+/// * `repo_id` - The id of the repository
+/// * `failed` - The failed files per backup source; if empty, the saved file is removed.
+pub(crate) fn save_failed_files(repo_id: &Id, failed: &[FailedFiles]) -> Result<()> {
+    let Some(path) = failed_files_path(repo_id) else {
+        return Ok(());
+    };
+    if failed.is_empty() {
+        return match fs::remove_file(&path) {
+            Err(err) if err.kind() != ErrorKind::NotFound => {
+                Err(err).with_context(|| format!("error removing {}", path.display()))
+            }
+            _ => Ok(()),
+        };
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("error creating state dir {}", dir.display()))?;
+    }
+    fs::write(&path, serde_json::to_vec_pretty(failed)?)
+        .with_context(|| format!("error writing failed files to {}", path.display()))
+}
@@ -0,0 +1,71 @@
+//! Overall deadline of a rustic run, see `--timeout`
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use abscissa_core::{Application, Shutdown};
+use log::warn;
+
+use rustic_core::BackupControl;
+
+use crate::{keyboard, status_err, RUSTIC_APP};
+
+pub(crate) mod constants {
+    use std::time::Duration;
+
+    /// Time given to stopped backups to save their partial snapshots before the run is aborted
+    pub(crate) const GRACE_PERIOD: Duration = Duration::from_secs(10 * 60);
+}
+
+/// Controls of the backups which are stopped when the timeout is exceeded
+static BACKUPS: Mutex<Vec<BackupControl>> = Mutex::new(Vec::new());
+
+/// Whether the timeout has been exceeded
+static EXCEEDED: AtomicBool = AtomicBool::new(false);
+
+/// Start a watchdog which ends the run after `timeout`.
+///
+/// When the timeout is exceeded, running backups are stopped and save a partial snapshot. If they
+/// don't finish within [`constants::GRACE_PERIOD`] or if no backup is running, the run is aborted.
+/// This also aborts runs which hang in backend operations, e.g. on an unresponsive network mount.
+/// Commands which must not be interrupted (i.e. `rekey`) refuse to run if a timeout is set.
+pub(crate) fn start(timeout: Duration) {
+    _ = thread::spawn(move || {
+        thread::sleep(timeout);
+        let timeout = humantime::format_duration(timeout);
+        let backups = {
+            let mut backups = BACKUPS.lock().unwrap();
+            EXCEEDED.store(true, Ordering::Release);
+            std::mem::take(&mut *backups)
+        };
+        if !backups.is_empty() {
+            warn!("timeout of {timeout} exceeded, stopping backup...");
+            for control in backups {
+                control.stop();
+            }
+            thread::sleep(constants::GRACE_PERIOD);
+        }
+        status_err!("timeout of {timeout} exceeded, aborting.");
+        // the run is aborted without running destructors, so restore the terminal here
+        keyboard::restore_terminal();
+        RUSTIC_APP.shutdown(Shutdown::Crash);
+    });
+}
+
+/// Register a backup to be stopped when the timeout is exceeded.
+///
+/// If the timeout has already been exceeded, the backup is stopped immediately.
+pub(crate) fn register(control: &BackupControl) {
+    let mut backups = BACKUPS.lock().unwrap();
+    if EXCEEDED.load(Ordering::Acquire) {
+        control.stop();
+    } else {
+        backups.push(control.clone());
+    }
+}